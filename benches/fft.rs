@@ -0,0 +1,75 @@
+use bellperson::domain::{serial_fft, Group, Scalar};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use ff::{Field, PrimeField};
+use paired::bls12_381::{Bls12, Fr};
+
+// The radix-2 FFT `serial_fft` used before the radix-4 rounds and their cache blocking, to
+// compare against.
+fn radix2_fft(a: &mut [Scalar<Bls12>], omega: &Fr, log_n: u32) {
+    fn bitreverse(mut n: u32, l: u32) -> u32 {
+        let mut r = 0;
+        for _ in 0..l {
+            r = (r << 1) | (n & 1);
+            n >>= 1;
+        }
+        r
+    }
+
+    let n = a.len() as u32;
+    for k in 0..n {
+        let rk = bitreverse(k, log_n);
+        if k < rk {
+            a.swap(rk as usize, k as usize);
+        }
+    }
+
+    let mut m = 1;
+    for _ in 0..log_n {
+        let w_m = omega.pow(&[u64::from(n / (2 * m))]);
+
+        let mut k = 0;
+        while k < n {
+            let mut w = Fr::one();
+            for j in 0..m {
+                let mut t = a[(k + j + m) as usize];
+                t.group_mul_assign(&w);
+                let mut tmp = a[(k + j) as usize];
+                tmp.group_sub_assign(&t);
+                a[(k + j + m) as usize] = tmp;
+                a[(k + j) as usize].group_add_assign(&t);
+                w.mul_assign(&w_m);
+            }
+
+            k += 2 * m;
+        }
+
+        m *= 2;
+    }
+}
+
+fn fft_benchmark(c: &mut Criterion) {
+    let rng = &mut rand::thread_rng();
+
+    let mut group = c.benchmark_group("serial fft");
+    group.sample_size(10);
+    for &log_n in &[10u32, 14, 18] {
+        let mut omega = Fr::root_of_unity();
+        for _ in log_n..Fr::S {
+            omega.square();
+        }
+        let a = (0..1 << log_n)
+            .map(|_| Scalar::<Bls12>(Fr::random(rng)))
+            .collect::<Vec<_>>();
+
+        group.bench_with_input(BenchmarkId::new("radix-2", log_n), &a, |b, a| {
+            b.iter(|| radix2_fft(&mut a.clone(), &omega, log_n))
+        });
+        group.bench_with_input(BenchmarkId::new("radix-4", log_n), &a, |b, a| {
+            b.iter(|| serial_fft::<Bls12, _>(&mut a.clone(), &omega, log_n))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, fft_benchmark);
+criterion_main!(benches);
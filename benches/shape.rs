@@ -0,0 +1,79 @@
+use bellperson::groth16::{
+    create_proof, create_proof_from_assignments, create_proof_with_shape,
+    generate_random_parameters, CircuitConstraints, WitnessCS,
+};
+use bellperson::{Circuit, ConstraintSystem, SynthesisError};
+use criterion::{criterion_group, criterion_main, Criterion};
+use ff::Field;
+use paired::bls12_381::{Bls12, Fr};
+
+const NUM_VARS: usize = 1024;
+const LC_LEN: usize = 64;
+
+// Constraints with long linear combinations, whose synthesis is a large part of the proof. The
+// witness doesn't satisfy them, which the prover doesn't check.
+#[derive(Clone)]
+struct Dense(Vec<Fr>);
+
+impl Circuit<Bls12> for Dense {
+    fn synthesize<CS: ConstraintSystem<Bls12>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let vars = self
+            .0
+            .iter()
+            .enumerate()
+            .map(|(i, &value)| cs.alloc(|| format!("var {}", i), || Ok(value)))
+            .collect::<Result<Vec<_>, _>>()?;
+        for i in 0..NUM_VARS {
+            cs.enforce(
+                || format!("constraint {}", i),
+                |mut lc| {
+                    for j in 0..LC_LEN {
+                        lc = lc + (self.0[j], vars[(i + j) % NUM_VARS]);
+                    }
+                    lc
+                },
+                |lc| lc + vars[i],
+                |lc| lc + vars[(i + 1) % NUM_VARS],
+            );
+        }
+
+        Ok(())
+    }
+}
+
+fn shape_benchmark(c: &mut Criterion) {
+    let rng = &mut rand::thread_rng();
+    let circuit = Dense((0..NUM_VARS).map(|_| Fr::random(rng)).collect());
+    let params = generate_random_parameters::<Bls12, _, _>(circuit.clone(), rng).unwrap();
+    let shape = CircuitConstraints::from_circuit(circuit.clone()).unwrap();
+    let mut cs = WitnessCS::new();
+    circuit.clone().synthesize(&mut cs).unwrap();
+    let (input_assignment, aux_assignment) = cs.into_assignments();
+    let (r, s) = (Fr::random(rng), Fr::random(rng));
+
+    let mut group = c.benchmark_group("dense circuit");
+    group.sample_size(10);
+    group.bench_function("create_proof", |b| {
+        b.iter(|| create_proof(circuit.clone(), &params, r, s).unwrap())
+    });
+    group.bench_function("create_proof_with_shape", |b| {
+        b.iter(|| create_proof_with_shape(&shape, circuit.clone(), &params, r, s).unwrap())
+    });
+    group.bench_function("create_proof_from_assignments", |b| {
+        b.iter(|| {
+            create_proof_from_assignments(
+                &shape,
+                &params,
+                r,
+                s,
+                input_assignment.clone(),
+                aux_assignment.clone(),
+            )
+            .unwrap()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, shape_benchmark);
+criterion_main!(benches);
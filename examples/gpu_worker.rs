@@ -0,0 +1,23 @@
+//! Standalone GPU worker serving FFT and multiexp jobs to provers on other machines.
+//!
+//! ```bash
+//! RUST_LOG=info cargo run --release --features gpu,remote-gpu --example gpu_worker -- 0.0.0.0:7878
+//! ```
+
+use bellperson::gpu::Priority;
+use bellperson::remote::serve_tcp;
+use paired::bls12_381::Bls12;
+use std::env;
+
+fn main() {
+    env_logger::init();
+
+    let addr = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "127.0.0.1:7878".to_string());
+    // Local proofs with a high priority can still take the GPUs over
+    if let Err(e) = serve_tcp::<Bls12, _>(addr.as_str(), Priority::Low) {
+        eprintln!("GPU worker failed: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,57 @@
+//! Cancellation of long running computations.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::SynthesisError;
+
+/// A token shared by a computation and the code which may cancel it. Cloning a token gives a
+/// handle to the same token.
+///
+/// The prover checks its token (See `groth16::ProverConfig::cancel`) after the synthesis,
+/// between FFTs and multiexps, and the GPU kernels check it between FFT rounds and multiexp
+/// chunks, after which they release their buffers and their GPU locks.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Fails with `SynthesisError::Cancelled` if the token was cancelled.
+    pub fn check(&self) -> Result<(), SynthesisError> {
+        if self.is_cancelled() {
+            return Err(SynthesisError::Cancelled);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancellation_token() {
+        let token = CancellationToken::new();
+        let handle = token.clone();
+        assert!(!token.is_cancelled());
+        assert!(token.check().is_ok());
+
+        handle.cancel();
+        assert!(token.is_cancelled());
+        match token.check() {
+            Err(SynthesisError::Cancelled) => (),
+            _ => panic!("the token should be cancelled"),
+        }
+    }
+}
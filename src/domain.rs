@@ -11,7 +11,7 @@
 //! [`EvaluationDomain`]: crate::domain::EvaluationDomain
 //! [Groth16]: https://eprint.iacr.org/2016/260
 
-use ff::{Field, PrimeField, ScalarEngine};
+use ff::{Field, PrimeField, PrimeFieldRepr, ScalarEngine};
 use groupy::CurveProjective;
 use paired::Engine;
 
@@ -19,12 +19,24 @@ use super::multicore::Worker;
 use super::SynthesisError;
 
 use crate::gpu;
+use crate::secret;
 
 use log::{info, warn};
+use std::any::{Any, TypeId};
+use std::cmp;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tracing::info_span;
+
+// Smaller batch inversions and polynomial multiplications are done on the CPU by
+// `batch_invert` and `polynomial_mul`, creating a GPU kernel would take longer.
+const GPU_MIN_LOG_N: u32 = 16;
 
 pub struct EvaluationDomain<E: ScalarEngine, G: Group<E>> {
     coeffs: Vec<G>,
+    // The size of the domain is 2^exp * 3^radix3, radix3 is only non-zero for mixed radix domains
     exp: u32,
+    radix3: u32,
     omega: E::Fr,
     omegainv: E::Fr,
     geninv: E::Fr,
@@ -74,6 +86,55 @@ impl<E: Engine, G: Group<E>> EvaluationDomain<E, G> {
         Ok(EvaluationDomain {
             coeffs,
             exp,
+            radix3: 0,
+            omega,
+            omegainv: omega.inverse().unwrap(),
+            geninv: E::Fr::multiplicative_generator().inverse().unwrap(),
+            minv: E::Fr::from_str(&format!("{}", m))
+                .unwrap()
+                .inverse()
+                .unwrap(),
+        })
+    }
+
+    /// Same as `from_coeffs`, but the domain is padded to the smallest size of the form
+    /// 2^a * 3^b (Instead of 2^a) which is supported by the field, wasting less memory and FFT
+    /// time when the number of coefficients is just above a power of two.
+    /// NOTE: The domain size has to match between parameter generation and proving, so this can't
+    /// be used with parameters generated for radix-2 domains.
+    pub fn from_coeffs_mixed_radix(
+        mut coeffs: Vec<G>,
+    ) -> Result<EvaluationDomain<E, G>, SynthesisError> {
+        let max_radix3 = three_adicity::<E::Fr>();
+        let mut best: Option<(u64, u32, u32)> = None;
+        for radix3 in 0..=max_radix3 {
+            let pow3 = 3u64.pow(radix3);
+            let mut exp = 0;
+            while pow3 << exp < coeffs.len() as u64 {
+                exp += 1;
+            }
+            let m = pow3 << exp;
+            if exp < E::Fr::S && best.map_or(true, |(best_m, _, _)| m < best_m) {
+                best = Some((m, exp, radix3));
+            }
+        }
+        let (m, exp, radix3) = best.ok_or(SynthesisError::PolynomialDegreeTooLarge)?;
+
+        // Compute omega, a primitive (2^exp * 3^radix3)-th root of unity
+        let mut omega = E::Fr::root_of_unity();
+        for _ in exp..E::Fr::S {
+            omega.square();
+        }
+        if radix3 > 0 {
+            omega.mul_assign(&root_of_unity_3::<E::Fr>(radix3).unwrap());
+        }
+
+        coeffs.resize(m as usize, G::group_zero());
+
+        Ok(EvaluationDomain {
+            coeffs,
+            exp,
+            radix3,
             omega,
             omegainv: omega.inverse().unwrap(),
             geninv: E::Fr::multiplicative_generator().inverse().unwrap(),
@@ -89,8 +150,22 @@ impl<E: Engine, G: Group<E>> EvaluationDomain<E, G> {
         worker: &Worker,
         kern: &mut Option<gpu::LockedFFTKernel<E>>,
     ) -> gpu::GPUResult<()> {
-        best_fft(kern, &mut self.coeffs, worker, &self.omega, self.exp)?;
-        Ok(())
+        let omega = self.omega;
+        self.transform(worker, kern, &omega)
+    }
+
+    /// FFT with the given root of unity, mixed radix domains are supported too.
+    fn transform(
+        &mut self,
+        worker: &Worker,
+        kern: &mut Option<gpu::LockedFFTKernel<E>>,
+        omega: &E::Fr,
+    ) -> gpu::GPUResult<()> {
+        if self.radix3 > 0 {
+            best_mixed_radix_fft(kern, &mut self.coeffs, worker, omega, self.exp, self.radix3)
+        } else {
+            best_fft(kern, &mut self.coeffs, worker, omega, self.exp)
+        }
     }
 
     pub fn ifft(
@@ -98,43 +173,155 @@ impl<E: Engine, G: Group<E>> EvaluationDomain<E, G> {
         worker: &Worker,
         kern: &mut Option<gpu::LockedFFTKernel<E>>,
     ) -> gpu::GPUResult<()> {
-        best_fft(kern, &mut self.coeffs, worker, &self.omegainv, self.exp)?;
+        let omegainv = self.omegainv;
+        self.transform(worker, kern, &omegainv)?;
 
-        worker.scope(self.coeffs.len(), |scope, chunk| {
-            let minv = self.minv;
+        let minv = self.minv;
+        self.mul_all(worker, minv);
 
-            for v in self.coeffs.chunks_mut(chunk) {
-                scope.spawn(move |_| {
-                    for v in v {
-                        v.group_mul_assign(&minv);
-                    }
-                });
+        Ok(())
+    }
+
+    /// Performs FFT on all of `domains`, which should all have the same size. Unlike calling
+    /// `fft` on each of them, the GPU buffers and twiddle factors are only set up once.
+    pub fn fft_many(
+        domains: &mut [&mut EvaluationDomain<E, G>],
+        worker: &Worker,
+        kern: &mut Option<gpu::LockedFFTKernel<E>>,
+    ) -> gpu::GPUResult<()> {
+        if domains.is_empty() {
+            return Ok(());
+        }
+        let (omega, exp, len) = (domains[0].omega, domains[0].exp, domains[0].coeffs.len());
+        if domains.iter().any(|d| d.coeffs.len() != len) {
+            return Err(gpu::GPUError::Simple(
+                "All domains of a batched FFT should have the same size!",
+            ));
+        }
+        if domains[0].radix3 > 0 {
+            for d in domains.iter_mut() {
+                d.fft(worker, kern)?;
             }
-        });
+            return Ok(());
+        }
+        let mut coeffs = domains
+            .iter_mut()
+            .map(|d| &mut d.coeffs[..])
+            .collect::<Vec<_>>();
+        best_fft_many(kern, &mut coeffs, worker, &omega, exp)?;
+        Ok(())
+    }
+
+    /// Batched version of `ifft`, see `fft_many`.
+    pub fn ifft_many(
+        domains: &mut [&mut EvaluationDomain<E, G>],
+        worker: &Worker,
+        kern: &mut Option<gpu::LockedFFTKernel<E>>,
+    ) -> gpu::GPUResult<()> {
+        if domains.is_empty() {
+            return Ok(());
+        }
+        let (omegainv, exp, len) = (domains[0].omegainv, domains[0].exp, domains[0].coeffs.len());
+        if domains.iter().any(|d| d.coeffs.len() != len) {
+            return Err(gpu::GPUError::Simple(
+                "All domains of a batched FFT should have the same size!",
+            ));
+        }
+        if domains[0].radix3 > 0 {
+            for d in domains.iter_mut() {
+                d.ifft(worker, kern)?;
+            }
+            return Ok(());
+        }
+        {
+            let mut coeffs = domains
+                .iter_mut()
+                .map(|d| &mut d.coeffs[..])
+                .collect::<Vec<_>>();
+            best_fft_many(kern, &mut coeffs, worker, &omegainv, exp)?;
+        }
+
+        for d in domains.iter_mut() {
+            let minv = d.minv;
+            d.mul_all(worker, minv);
+        }
 
         Ok(())
     }
 
-    pub fn distribute_powers(&mut self, worker: &Worker, g: E::Fr) {
+    /// Batched version of `coset_fft`. On the GPU, the coset shift of the next domain is
+    /// computed on the CPU while the FFT of the current one is running.
+    pub fn coset_fft_many(
+        domains: &mut [&mut EvaluationDomain<E, G>],
+        worker: &Worker,
+        kern: &mut Option<gpu::LockedFFTKernel<E>>,
+    ) -> gpu::GPUResult<()> {
+        if domains.is_empty() {
+            return Ok(());
+        }
+        let (omega, exp, len) = (domains[0].omega, domains[0].exp, domains[0].coeffs.len());
+        if domains.iter().any(|d| d.coeffs.len() != len) {
+            return Err(gpu::GPUError::Simple(
+                "All domains of a batched FFT should have the same size!",
+            ));
+        }
+        if domains[0].radix3 > 0 {
+            for d in domains.iter_mut() {
+                d.coset_fft(worker, kern)?;
+            }
+            return Ok(());
+        }
+        let mut coeffs = domains
+            .iter_mut()
+            .map(|d| &mut d.coeffs[..])
+            .collect::<Vec<_>>();
+        best_coset_fft_many(
+            kern,
+            &mut coeffs,
+            worker,
+            E::Fr::multiplicative_generator(),
+            &omega,
+            exp,
+        )?;
+        Ok(())
+    }
+
+    fn mul_all(&mut self, worker: &Worker, by: E::Fr) {
         worker.scope(self.coeffs.len(), |scope, chunk| {
-            for (i, v) in self.coeffs.chunks_mut(chunk).enumerate() {
+            for v in self.coeffs.chunks_mut(chunk) {
                 scope.spawn(move |_| {
-                    let mut u = g.pow(&[(i * chunk) as u64]);
-                    for v in v.iter_mut() {
-                        v.group_mul_assign(&u);
-                        u.mul_assign(&g);
+                    for v in v {
+                        v.group_mul_assign(&by);
                     }
                 });
             }
         });
     }
 
+    pub fn distribute_powers(&mut self, worker: &Worker, g: E::Fr) {
+        distribute_powers(&mut self.coeffs, worker, g);
+    }
+
     pub fn coset_fft(
         &mut self,
         worker: &Worker,
         kern: &mut Option<gpu::LockedFFTKernel<E>>,
     ) -> gpu::GPUResult<()> {
-        self.distribute_powers(worker, E::Fr::multiplicative_generator());
+        let g = E::Fr::multiplicative_generator();
+        // The coset shift is fused into the GPU FFT, saving a host round trip.
+        if self.radix3 == 0
+            && try_gpu_fft(kern, |k| {
+                gpu_coset_fft(k, &mut self.coeffs, &self.omega, &g, self.exp)
+            })?
+            .is_some()
+        {
+            return Ok(());
+        }
+
+        // Mixed radix domains only use the GPU for the FFT itself
+        let mut cpu = None;
+        let kern = if self.radix3 > 0 { kern } else { &mut cpu };
+        self.distribute_powers(worker, g);
         self.fft(worker, kern)?;
         Ok(())
     }
@@ -145,6 +332,25 @@ impl<E: Engine, G: Group<E>> EvaluationDomain<E, G> {
         kern: &mut Option<gpu::LockedFFTKernel<E>>,
     ) -> gpu::GPUResult<()> {
         let geninv = self.geninv;
+        // Scaling by `minv` and the coset shift are fused into the GPU FFT.
+        if self.radix3 == 0
+            && try_gpu_fft(kern, |k| {
+                gpu_icoset_fft(
+                    k,
+                    &mut self.coeffs,
+                    &self.omegainv,
+                    &geninv,
+                    &self.minv,
+                    self.exp,
+                )
+            })?
+            .is_some()
+        {
+            return Ok(());
+        }
+
+        let mut cpu = None;
+        let kern = if self.radix3 > 0 { kern } else { &mut cpu };
         self.ifft(worker, kern)?;
         self.distribute_powers(worker, geninv);
         Ok(())
@@ -218,6 +424,168 @@ impl<E: Engine, G: Group<E>> EvaluationDomain<E, G> {
     }
 }
 
+impl<E: Engine> EvaluationDomain<E, Scalar<E>> {
+    /// Given the evaluations of `self` (`a`), `b` and `c` on this domain, computes the
+    /// coefficients of `(a * b - c) / Z` (Where `Z` is the vanishing polynomial of the domain)
+    /// into `self`, by evaluating on a coset. `b` and `c` are left in an unspecified state.
+    ///
+    /// On the GPU, the intermediate values are never copied back to the host.
+    pub fn quotient(
+        &mut self,
+        worker: &Worker,
+        b: &mut EvaluationDomain<E, Scalar<E>>,
+        c: &mut EvaluationDomain<E, Scalar<E>>,
+        kern: &mut Option<gpu::LockedFFTKernel<E>>,
+    ) -> gpu::GPUResult<()> {
+        let g = E::Fr::multiplicative_generator();
+        // Split FFTs have to go through the host anyway, they are done step by step below, as
+        // well as mixed radix ones.
+        let res = try_gpu_fft(kern, |k| {
+            if self.radix3 > 0 || k.is_split(self.exp) {
+                return Ok(false);
+            }
+            gpu_quotient(
+                k,
+                &mut self.coeffs,
+                &b.coeffs,
+                &c.coeffs,
+                &self.omega,
+                &g,
+                self.exp,
+            )?;
+            Ok(true)
+        })?;
+        if let Some(true) = res {
+            return Ok(());
+        }
+
+        EvaluationDomain::ifft_many(&mut [&mut *self, &mut *b, &mut *c], worker, kern)?;
+        EvaluationDomain::coset_fft_many(&mut [&mut *self, &mut *b, &mut *c], worker, kern)?;
+
+        self.mul_assign(worker, b);
+        self.sub_assign(worker, c);
+        self.divide_by_z_on_coset(worker);
+        self.icoset_fft(worker, kern)?;
+
+        Ok(())
+    }
+
+    /// Uploads the coefficients to the GPU of `session` and returns a domain which stays there,
+    /// see `GPUEvaluationDomain`. The size of the domain (`coeffs.len()` rounded up to a power of
+    /// two) has to match the size of the session.
+    pub fn gpu(
+        coeffs: Vec<Scalar<E>>,
+        session: &mut gpu::FFTSession<E::Fr>,
+    ) -> Result<GPUEvaluationDomain<E>, SynthesisError> {
+        let domain = EvaluationDomain::from_coeffs(coeffs)?;
+        if domain.exp != session.lgn() {
+            return Err(gpu::GPUError::Simple("Domain size doesn't match the FFT session!").into());
+        }
+        // See `gpu_fft` for why this is safe.
+        let values = unsafe { std::mem::transmute::<&[Scalar<E>], &[E::Fr]>(&domain.coeffs) };
+        Ok(GPUEvaluationDomain {
+            values: session.create_buffer(values)?,
+            exp: domain.exp,
+            omega: domain.omega,
+            omegainv: domain.omegainv,
+            geninv: domain.geninv,
+            minv: domain.minv,
+        })
+    }
+
+    /// Replaces every non-zero value of the domain with its inverse, see `best_batch_invert`.
+    pub fn batch_invert(
+        &mut self,
+        worker: &Worker,
+        kern: &mut Option<gpu::LockedFFTKernel<E>>,
+    ) -> gpu::GPUResult<()> {
+        // See `gpu_fft` for why this is safe.
+        let a = unsafe { std::mem::transmute::<&mut [Scalar<E>], &mut [E::Fr]>(&mut self.coeffs) };
+        best_batch_invert(kern, a, worker)
+    }
+}
+
+/// An `EvaluationDomain` whose values are kept on the GPU of an `FFTSession` (Created with
+/// `EvaluationDomain::gpu`). The operations are only enqueued on the GPU, nothing is copied back
+/// to the host until `into_coeffs` is called. All of the domains used together have to belong to
+/// the same session, which is passed to each operation.
+pub struct GPUEvaluationDomain<E: Engine> {
+    values: gpu::FFTBuffer<E::Fr>,
+    exp: u32,
+    omega: E::Fr,
+    omegainv: E::Fr,
+    geninv: E::Fr,
+    minv: E::Fr,
+}
+
+impl<E: Engine> GPUEvaluationDomain<E> {
+    /// Waits for the pending operations and copies the values back to the host
+    pub fn into_coeffs(self) -> gpu::GPUResult<Vec<Scalar<E>>> {
+        let mut coeffs = vec![E::Fr::zero(); 1 << self.exp];
+        self.values.download(&mut coeffs)?;
+        Ok(coeffs.into_iter().map(Scalar).collect())
+    }
+
+    pub fn fft(&mut self, session: &mut gpu::FFTSession<E::Fr>) -> gpu::GPUResult<()> {
+        let omega = self.omega;
+        session.with_buffer(&mut self.values, |s| s.transform(&omega))
+    }
+
+    pub fn ifft(&mut self, session: &mut gpu::FFTSession<E::Fr>) -> gpu::GPUResult<()> {
+        let (omegainv, minv) = (self.omegainv, self.minv);
+        session.with_buffer(&mut self.values, |s| {
+            s.transform(&omegainv)?;
+            s.mul_by_powers(&E::Fr::one(), &minv)
+        })
+    }
+
+    pub fn coset_fft(&mut self, session: &mut gpu::FFTSession<E::Fr>) -> gpu::GPUResult<()> {
+        let omega = self.omega;
+        session.with_buffer(&mut self.values, |s| {
+            s.mul_by_powers(&E::Fr::multiplicative_generator(), &E::Fr::one())?;
+            s.transform(&omega)
+        })
+    }
+
+    pub fn icoset_fft(&mut self, session: &mut gpu::FFTSession<E::Fr>) -> gpu::GPUResult<()> {
+        let (omegainv, geninv, minv) = (self.omegainv, self.geninv, self.minv);
+        session.with_buffer(&mut self.values, |s| {
+            s.transform(&omegainv)?;
+            s.mul_by_powers(&geninv, &minv)
+        })
+    }
+
+    /// Divides the values by the vanishing polynomial of the domain, which is constant on the
+    /// coset, see `EvaluationDomain::divide_by_z_on_coset`.
+    pub fn divide_by_z_on_coset(
+        &mut self,
+        session: &mut gpu::FFTSession<E::Fr>,
+    ) -> gpu::GPUResult<()> {
+        let mut z = E::Fr::multiplicative_generator().pow(&[1u64 << self.exp]);
+        z.sub_assign(&E::Fr::one());
+        let zinv = z.inverse().unwrap();
+        session.with_buffer(&mut self.values, |s| s.mul_by_powers(&E::Fr::one(), &zinv))
+    }
+
+    /// Perform O(n) multiplication of two polynomials in the domain.
+    pub fn mul_assign(
+        &mut self,
+        session: &mut gpu::FFTSession<E::Fr>,
+        other: &GPUEvaluationDomain<E>,
+    ) -> gpu::GPUResult<()> {
+        session.with_buffer(&mut self.values, |s| s.mul_assign_buffer(&other.values))
+    }
+
+    /// Perform O(n) subtraction of one polynomial from another in the domain.
+    pub fn sub_assign(
+        &mut self,
+        session: &mut gpu::FFTSession<E::Fr>,
+        other: &GPUEvaluationDomain<E>,
+    ) -> gpu::GPUResult<()> {
+        session.with_buffer(&mut self.values, |s| s.sub_assign_buffer(&other.values))
+    }
+}
+
 pub trait Group<E: ScalarEngine>: Sized + Copy + Clone + Send + Sync {
     fn group_zero() -> Self;
     fn group_mul_assign(&mut self, by: &E::Fr);
@@ -287,48 +655,647 @@ impl<E: ScalarEngine> Group<E> for Scalar<E> {
     }
 }
 
-fn best_fft<E: Engine, T: Group<E>>(
+/// Runs `f` with the GPU kernel if there is one. Returns `None` if the work should be done on
+/// the CPU instead, i.e. there is no kernel or it failed with something else than the GPU being
+/// taken by a higher priority process (Which is handled by `LockedFFTKernel`).
+fn try_gpu_fft<E, F, R>(
+    kern: &mut Option<gpu::LockedFFTKernel<E>>,
+    f: F,
+) -> gpu::GPUResult<Option<R>>
+where
+    E: Engine,
+    F: FnMut(&mut gpu::FFTKernel<E::Fr>) -> gpu::GPUResult<R>,
+{
+    let kern = match kern.as_mut() {
+        Some(kern) => kern,
+        None => return Ok(None),
+    };
+    match kern.with(f) {
+        Ok(res) => Ok(Some(res)),
+        // A cancelled computation isn't resumed on the CPU
+        Err(gpu::GPUError::Cancelled) => Err(gpu::GPUError::Cancelled),
+        Err(e) => {
+            info!("FFT: Falling back to CPU... Reason: {}", e);
+            Ok(None)
+        }
+    }
+}
+
+/// Performs FFT on `a`, on the GPU if `kern` is available and on the CPU otherwise (Or if the
+/// GPU fails for any reason), so it never fails because of OpenCL errors.
+pub fn best_fft<E: Engine, T: Group<E>>(
     kern: &mut Option<gpu::LockedFFTKernel<E>>,
     a: &mut [T],
     worker: &Worker,
     omega: &E::Fr,
     log_n: u32,
 ) -> gpu::GPUResult<()> {
-    if let Some(ref mut kern) = kern {
-        if kern
-            .with(|k: &mut gpu::FFTKernel<E>| gpu_fft(k, a, omega, log_n))
-            .is_ok()
-        {
-            return Ok(());
+    let span = info_span!("fft", lgn = log_n);
+    let _enter = span.enter();
+
+    // Kept across retries, so that an FFT interrupted by a high priority process resumes from
+    // its last completed round.
+    let mut checkpoint = None;
+    if try_gpu_fft(kern, |k| {
+        gpu_fft_resume(k, a, omega, log_n, &mut checkpoint)
+    })?
+    .is_some()
+    {
+        return Ok(());
+    }
+
+    cpu_fft(a, worker, omega, log_n);
+
+    Ok(())
+}
+
+fn best_mixed_radix_fft<E: Engine, T: Group<E>>(
+    kern: &mut Option<gpu::LockedFFTKernel<E>>,
+    a: &mut [T],
+    worker: &Worker,
+    omega: &E::Fr,
+    log_n: u32,
+    radix3: u32,
+) -> gpu::GPUResult<()> {
+    if try_gpu_fft(kern, |k| gpu_mixed_radix_fft(k, a, omega, log_n, radix3))?.is_some() {
+        return Ok(());
+    }
+
+    mixed_radix_fft(a, worker, omega, log_n, radix3);
+
+    Ok(())
+}
+
+/// Replaces every non-zero element of `a` with its inverse, on the GPU if `kern` is available
+/// and on the CPU otherwise (Or if the GPU fails for any reason). Both use Montgomery's trick, so
+/// only a few field inversions are done in total.
+pub fn best_batch_invert<E: Engine>(
+    kern: &mut Option<gpu::LockedFFTKernel<E>>,
+    a: &mut [E::Fr],
+    worker: &Worker,
+) -> gpu::GPUResult<()> {
+    // The parts the GPU inverted before failing are left out of the CPU inversion, as inverting
+    // them again would put back their original values
+    let mut done = 0;
+    if try_gpu_fft(kern, |k| k.batch_invert_from(a, &mut done))?.is_some() {
+        return Ok(());
+    }
+
+    let a = &mut a[done..];
+    worker.scope(a.len(), |scope, chunk| {
+        for a in a.chunks_mut(chunk) {
+            scope.spawn(move |_| serial_batch_invert(a));
+        }
+    });
+
+    Ok(())
+}
+
+/// Replaces every non-zero element of `a` with its inverse, choosing between the GPU and the CPU
+/// automatically: the GPU is only used for at least 2^`GPU_MIN_LOG_N` elements.
+pub fn batch_invert<E: Engine>(a: &mut [E::Fr]) {
+    let log_d = a.len().next_power_of_two().trailing_zeros();
+    let mut kern = if cfg!(feature = "gpu") && log_d >= GPU_MIN_LOG_N {
+        Some(gpu::LockedFFTKernel::<E>::new(
+            log_d as usize,
+            gpu::Priority::Low,
+        ))
+    } else {
+        None
+    };
+    // Never fails, as it falls back to the CPU
+    best_batch_invert(&mut kern, a, &Worker::new()).unwrap();
+}
+
+/// Computes the coefficients of the product of the polynomials of coefficients `a` and `b`. On
+/// the GPU (If `kern` is available), the inputs are padded, transformed, multiplied and
+/// interpolated back with a single upload of each of them and a single download of the result.
+/// Otherwise the same is done with `EvaluationDomain`.
+pub fn best_polynomial_mul<E: Engine>(
+    kern: &mut Option<gpu::LockedFFTKernel<E>>,
+    a: &[E::Fr],
+    b: &[E::Fr],
+    worker: &Worker,
+) -> Result<Vec<E::Fr>, SynthesisError> {
+    if a.is_empty() || b.is_empty() {
+        return Ok(Vec::new());
+    }
+    let len = a.len() + b.len() - 1;
+    let exp = len.next_power_of_two().trailing_zeros();
+    if exp >= E::Fr::S {
+        return Err(SynthesisError::PolynomialDegreeTooLarge);
+    }
+    let mut omega = E::Fr::root_of_unity();
+    for _ in exp..E::Fr::S {
+        omega.square();
+    }
+
+    let mut result = vec![E::Fr::zero(); len];
+    if try_gpu_fft(kern, |k| k.polynomial_mul(a, b, &mut result, &omega, exp))?.is_some() {
+        return Ok(result);
+    }
+
+    let domain = |p: &[E::Fr]| {
+        let mut coeffs = p.iter().map(|&x| Scalar::<E>(x)).collect::<Vec<_>>();
+        coeffs.resize(len, Scalar::<E>(E::Fr::zero()));
+        EvaluationDomain::from_coeffs(coeffs)
+    };
+    let mut a = domain(a)?;
+    let mut b = domain(b)?;
+    a.fft(worker, kern)?;
+    b.fft(worker, kern)?;
+    a.mul_assign(worker, &b);
+    a.ifft(worker, kern)?;
+
+    let mut result = a.into_coeffs().into_iter().map(|s| s.0).collect::<Vec<_>>();
+    result.truncate(len);
+    Ok(result)
+}
+
+/// Computes the coefficients of the product of the polynomials of coefficients `a` and `b`,
+/// choosing between the GPU and the CPU automatically: the GPU is only used for products of at
+/// least 2^`GPU_MIN_LOG_N` coefficients.
+pub fn polynomial_mul<E: Engine>(a: &[E::Fr], b: &[E::Fr]) -> Result<Vec<E::Fr>, SynthesisError> {
+    let len = (a.len() + b.len()).saturating_sub(1);
+    let log_d = len.next_power_of_two().trailing_zeros();
+    let mut kern = if cfg!(feature = "gpu") && log_d >= GPU_MIN_LOG_N {
+        Some(gpu::LockedFFTKernel::<E>::new(
+            log_d as usize,
+            gpu::Priority::Low,
+        ))
+    } else {
+        None
+    };
+    best_polynomial_mul(&mut kern, a, b, &Worker::new())
+}
+
+/// Inverts `a[*done..]` with `invert`, `capacity` elements at a time, and moves `done` past every
+/// part once it's inverted.
+#[cfg(any(feature = "gpu", test))]
+pub(crate) fn batch_invert_parts<T, I>(
+    a: &mut [T],
+    capacity: usize,
+    done: &mut usize,
+    mut invert: I,
+) -> gpu::GPUResult<()>
+where
+    I: FnMut(&mut [T]) -> gpu::GPUResult<()>,
+{
+    while *done < a.len() {
+        let end = cmp::min(*done + capacity, a.len());
+        invert(&mut a[*done..end])?;
+        *done = end;
+    }
+    Ok(())
+}
+
+/// Montgomery's trick on a single thread: replaces every non-zero element of `a` with its
+/// inverse, with a single field inversion.
+pub fn serial_batch_invert<F: Field>(a: &mut [F]) {
+    let mut prefix = Vec::with_capacity(a.len());
+    let mut acc = F::one();
+    for x in a.iter().filter(|x| !x.is_zero()) {
+        prefix.push(acc);
+        acc.mul_assign(x);
+    }
+
+    // `acc` is a product of non-zero elements
+    let mut inv = acc.inverse().unwrap();
+    for (x, p) in a
+        .iter_mut()
+        .rev()
+        .filter(|x| !x.is_zero())
+        .zip(prefix.into_iter().rev())
+    {
+        let mut tmp = inv;
+        tmp.mul_assign(&p);
+        inv.mul_assign(x);
+        *x = tmp;
+    }
+}
+
+/// FFT of 2^`log_n` * 3^`radix3` elements on the CPU. Each radix-3 step splits `a` into three
+/// interleaved sub-FFTs which are merged with the twiddle factors `omega^k` and `omega^2k`.
+fn mixed_radix_fft<E: Engine, T: Group<E>>(
+    a: &mut [T],
+    worker: &Worker,
+    omega: &E::Fr,
+    log_n: u32,
+    radix3: u32,
+) {
+    if radix3 == 0 {
+        cpu_fft(a, worker, omega, log_n);
+        return;
+    }
+
+    let m = a.len() / 3;
+    let new_omega = omega.pow(&[3u64]);
+    let mut subs = vec![vec![T::group_zero(); m]; 3];
+    for (r, sub) in subs.iter_mut().enumerate() {
+        for (i, v) in sub.iter_mut().enumerate() {
+            *v = a[3 * i + r];
+        }
+        mixed_radix_fft(sub, worker, &new_omega, log_n, radix3 - 1);
+    }
+
+    let subs = &subs;
+    worker.scope(a.len(), |scope, chunk| {
+        for (c, a) in a.chunks_mut(chunk).enumerate() {
+            scope.spawn(move |_| {
+                let mut w = omega.pow(&[(c * chunk) as u64]);
+                for (k, a) in a.iter_mut().enumerate() {
+                    let i = (c * chunk + k) % m;
+                    let mut w2 = w;
+                    w2.square();
+                    let mut t1 = subs[1][i];
+                    t1.group_mul_assign(&w);
+                    let mut t2 = subs[2][i];
+                    t2.group_mul_assign(&w2);
+                    *a = subs[0][i];
+                    a.group_add_assign(&t1);
+                    a.group_add_assign(&t2);
+                    w.mul_assign(omega);
+                }
+            });
+        }
+    });
+}
+
+/// Largest `b` such that 3^`b` divides the order of the multiplicative group of `F`
+fn three_adicity<F: PrimeField>() -> u32 {
+    let mut order = F::char();
+    order.sub_noborrow(&F::Repr::from(1));
+    let mut b = 0;
+    while div_repr(&mut order, 3) == 0 {
+        b += 1;
+    }
+    b
+}
+
+/// A primitive 3^`b`-th root of unity, if the field has one
+fn root_of_unity_3<F: PrimeField>(b: u32) -> Option<F> {
+    if b > three_adicity::<F>() {
+        return None;
+    }
+    let mut exp = F::char();
+    exp.sub_noborrow(&F::Repr::from(1));
+    for _ in 0..b {
+        div_repr(&mut exp, 3);
+    }
+    Some(F::multiplicative_generator().pow(exp))
+}
+
+/// Divides `repr` by a small number in place, returning the remainder. `repr` is left unchanged
+/// if the remainder isn't zero.
+fn div_repr<R: ff::PrimeFieldRepr>(repr: &mut R, by: u64) -> u64 {
+    let mut res = *repr;
+    let mut rem = 0u128;
+    for limb in res.as_mut().iter_mut().rev() {
+        let cur = (rem << 64) | (*limb as u128);
+        *limb = (cur / by as u128) as u64;
+        rem = cur % by as u128;
+    }
+    if rem == 0 {
+        *repr = res;
+    }
+    rem as u64
+}
+
+fn best_fft_many<E: Engine, T: Group<E>>(
+    kern: &mut Option<gpu::LockedFFTKernel<E>>,
+    a: &mut [&mut [T]],
+    worker: &Worker,
+    omega: &E::Fr,
+    log_n: u32,
+) -> gpu::GPUResult<()> {
+    // Number of polynomials already transformed on the GPU, so that they are not transformed
+    // again when the kernel is re-created or when falling back to the CPU.
+    let mut done = 0;
+    if try_gpu_fft(kern, |k| gpu_fft_many(k, a, omega, log_n, &mut done))?.is_some() {
+        return Ok(());
+    }
+
+    for a in a[done..].iter_mut() {
+        cpu_fft(a, worker, omega, log_n);
+    }
+
+    Ok(())
+}
+
+fn best_coset_fft_many<E: Engine, T: Group<E>>(
+    kern: &mut Option<gpu::LockedFFTKernel<E>>,
+    a: &mut [&mut [T]],
+    worker: &Worker,
+    g: E::Fr,
+    omega: &E::Fr,
+    log_n: u32,
+) -> gpu::GPUResult<()> {
+    // Progress is tracked so that nothing is shifted or transformed twice when the kernel is
+    // re-created or when falling back to the CPU.
+    let mut distributed = 0;
+    let mut done = 0;
+    if try_gpu_fft(kern, |k| {
+        gpu_coset_fft_many(k, a, worker, g, omega, log_n, &mut distributed, &mut done)
+    })?
+    .is_some()
+    {
+        return Ok(());
+    }
+
+    for a in a[distributed..].iter_mut() {
+        distribute_powers(a, worker, g);
+    }
+    for a in a[done..].iter_mut() {
+        cpu_fft(a, worker, omega, log_n);
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn gpu_coset_fft_many<E: Engine, T: Group<E>>(
+    kern: &mut gpu::FFTKernel<E::Fr>,
+    a: &mut [&mut [T]],
+    worker: &Worker,
+    g: E::Fr,
+    omega: &E::Fr,
+    log_n: u32,
+    distributed: &mut usize,
+    done: &mut usize,
+) -> gpu::GPUResult<()> {
+    while *done < a.len() {
+        if *distributed == *done {
+            distribute_powers(a[*done], worker, g);
+            *distributed += 1;
+        }
+        let (cur, rest) = a[*done..].split_at_mut(1);
+        // See `gpu_fft` for why this is safe.
+        let cur = unsafe { std::mem::transmute::<&mut [T], &mut [E::Fr]>(&mut *cur[0]) };
+        let handle = kern.radix_fft_async(cur, omega, log_n)?;
+        if let Some(next) = rest.first_mut() {
+            distribute_powers(next, worker, g);
+            *distributed += 1;
+        }
+        handle.wait()?;
+        *done += 1;
+    }
+    Ok(())
+}
+
+fn distribute_powers<E: Engine, T: Group<E>>(a: &mut [T], worker: &Worker, g: E::Fr) {
+    worker.scope(a.len(), |scope, chunk| {
+        for (i, v) in a.chunks_mut(chunk).enumerate() {
+            scope.spawn(move |_| {
+                let mut u = g.pow(&[(i * chunk) as u64]);
+                for v in v.iter_mut() {
+                    v.group_mul_assign(&u);
+                    u.mul_assign(&g);
+                }
+            });
+        }
+    });
+}
+
+fn cpu_fft<E: Engine, T: Group<E>>(a: &mut [T], worker: &Worker, omega: &E::Fr, log_n: u32) {
+    let log_cpus = worker.log_num_cpus();
+    if log_n <= log_cpus {
+        serial_fft(a, omega, log_n);
+    } else {
+        parallel_fft(a, worker, omega, log_n, log_cpus);
+    }
+}
+
+pub fn gpu_fft<E: Engine, T: Group<E>>(
+    kern: &mut gpu::FFTKernel<E::Fr>,
+    a: &mut [T],
+    omega: &E::Fr,
+    log_n: u32,
+) -> gpu::GPUResult<()> {
+    // EvaluationDomain module is supposed to work only with E::Fr elements, and not CurveProjective
+    // points. The Bellman authors have implemented an unnecessarry abstraction called Group<E>
+    // which is implemented for both PrimeField and CurveProjective elements. As nowhere in the code
+    // is the CurveProjective version used, T and E::Fr are guaranteed to be equal and thus have same
+    // size.
+    // For compatibility/performance reasons we decided to transmute the array to the desired type
+    // as it seems safe and needs less modifications in the current structure of Bellman library.
+    let a = unsafe { std::mem::transmute::<&mut [T], &mut [E::Fr]>(a) };
+    kern.radix_fft(a, omega, log_n)?;
+    Ok(())
+}
+
+pub fn gpu_fft_resume<E: Engine, T: Group<E>>(
+    kern: &mut gpu::FFTKernel<E::Fr>,
+    a: &mut [T],
+    omega: &E::Fr,
+    log_n: u32,
+    checkpoint: &mut Option<gpu::FFTCheckpoint<E::Fr>>,
+) -> gpu::GPUResult<()> {
+    // See `gpu_fft` for why this is safe.
+    let a = unsafe { std::mem::transmute::<&mut [T], &mut [E::Fr]>(a) };
+    kern.radix_fft_resume(a, omega, log_n, checkpoint)?;
+    Ok(())
+}
+
+pub fn gpu_coset_fft<E: Engine, T: Group<E>>(
+    kern: &mut gpu::FFTKernel<E::Fr>,
+    a: &mut [T],
+    omega: &E::Fr,
+    g: &E::Fr,
+    log_n: u32,
+) -> gpu::GPUResult<()> {
+    // See `gpu_fft` for why this is safe.
+    let a = unsafe { std::mem::transmute::<&mut [T], &mut [E::Fr]>(a) };
+    kern.coset_fft(a, omega, g, log_n)?;
+    Ok(())
+}
+
+pub fn gpu_icoset_fft<E: Engine, T: Group<E>>(
+    kern: &mut gpu::FFTKernel<E::Fr>,
+    a: &mut [T],
+    omegainv: &E::Fr,
+    geninv: &E::Fr,
+    minv: &E::Fr,
+    log_n: u32,
+) -> gpu::GPUResult<()> {
+    // See `gpu_fft` for why this is safe.
+    let a = unsafe { std::mem::transmute::<&mut [T], &mut [E::Fr]>(a) };
+    kern.icoset_fft(a, omegainv, geninv, minv, log_n)?;
+    Ok(())
+}
+
+pub fn gpu_quotient<E: Engine, T: Group<E>>(
+    kern: &mut gpu::FFTKernel<E::Fr>,
+    a: &mut [T],
+    b: &[T],
+    c: &[T],
+    omega: &E::Fr,
+    g: &E::Fr,
+    log_n: u32,
+) -> gpu::GPUResult<()> {
+    // See `gpu_fft` for why this is safe.
+    let a = unsafe { std::mem::transmute::<&mut [T], &mut [E::Fr]>(a) };
+    let b = unsafe { std::mem::transmute::<&[T], &[E::Fr]>(b) };
+    let c = unsafe { std::mem::transmute::<&[T], &[E::Fr]>(c) };
+    kern.quotient(a, b, c, omega, g, log_n)?;
+    Ok(())
+}
+
+pub fn gpu_mixed_radix_fft<E: Engine, T: Group<E>>(
+    kern: &mut gpu::FFTKernel<E::Fr>,
+    a: &mut [T],
+    omega: &E::Fr,
+    log_n: u32,
+    radix3: u32,
+) -> gpu::GPUResult<()> {
+    // See `gpu_fft` for why this is safe.
+    let a = unsafe { std::mem::transmute::<&mut [T], &mut [E::Fr]>(a) };
+    kern.mixed_radix_fft(a, omega, log_n, radix3)?;
+    Ok(())
+}
+
+pub fn gpu_fft_many<E: Engine, T: Group<E>>(
+    kern: &mut gpu::FFTKernel<E::Fr>,
+    a: &mut [&mut [T]],
+    omega: &E::Fr,
+    log_n: u32,
+    done: &mut usize,
+) -> gpu::GPUResult<()> {
+    // See `gpu_fft` for why this is safe.
+    let a = unsafe { std::mem::transmute::<&mut [&mut [T]], &mut [&mut [E::Fr]]>(a) };
+    kern.radix_fft_many_from(a, omega, log_n, done)?;
+    Ok(())
+}
+
+// Default bound of the twiddle tables kept by the global `TwiddleCache`, in MiB
+const DEFAULT_TWIDDLE_CACHE_SIZE: usize = 256;
+
+fn twiddle_cache_size() -> usize {
+    match std::env::var("BELLMAN_TWIDDLE_CACHE_SIZE") {
+        Ok(size) => size.parse().unwrap_or_else(|_| {
+            warn!(
+                "Invalid BELLMAN_TWIDDLE_CACHE_SIZE! Defaulting to {}...",
+                DEFAULT_TWIDDLE_CACHE_SIZE
+            );
+            DEFAULT_TWIDDLE_CACHE_SIZE
+        }),
+        Err(_) => DEFAULT_TWIDDLE_CACHE_SIZE,
+    }
+}
+
+type TwiddleKey = (TypeId, u32, Vec<u64>);
+
+struct TwiddleTable {
+    table: Arc<dyn Any + Send + Sync>,
+    bytes: usize,
+    last_use: u64,
+}
+
+#[derive(Default)]
+struct TwiddleTables {
+    tables: HashMap<TwiddleKey, TwiddleTable>,
+    bytes: usize,
+    uses: u64,
+}
+
+impl TwiddleTables {
+    fn get<F: PrimeField>(&mut self, key: &TwiddleKey) -> Option<Arc<Vec<F>>> {
+        self.uses += 1;
+        let uses = self.uses;
+        self.tables.get_mut(key).and_then(|t| {
+            t.last_use = uses;
+            t.table.clone().downcast::<Vec<F>>().ok()
+        })
+    }
+
+    /// Inserts `table`, evicting the least recently used tables until it fits in `max_bytes`.
+    fn insert<F: PrimeField>(&mut self, key: TwiddleKey, table: Arc<Vec<F>>, max_bytes: usize) {
+        let bytes = table.len() * std::mem::size_of::<F>();
+        while self.bytes + bytes > max_bytes {
+            let lru = match self.tables.iter().min_by_key(|(_, t)| t.last_use) {
+                Some((key, _)) => key.clone(),
+                None => break,
+            };
+            let evicted = self.tables.remove(&lru).unwrap();
+            self.bytes -= evicted.bytes;
+        }
+        self.uses += 1;
+        self.bytes += bytes;
+        self.tables.insert(
+            key,
+            TwiddleTable {
+                table,
+                bytes,
+                last_use: self.uses,
+            },
+        );
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref TWIDDLES: TwiddleCache = TwiddleCache::new(twiddle_cache_size() << 20);
+}
+
+/// Cache of the twiddle factors of `serial_fft`, keyed by the size of the FFT and its root of
+/// unity. The FFTs of the same size (E.g. the three domains of every proof of a circuit, or the
+/// sub-FFTs of `parallel_fft`) share a single table instead of recomputing it. The tables take
+/// at most `max_bytes`, the least recently used ones being evicted to make room for new ones.
+pub struct TwiddleCache {
+    max_bytes: usize,
+    tables: Mutex<TwiddleTables>,
+}
+
+impl TwiddleCache {
+    pub fn new(max_bytes: usize) -> Self {
+        TwiddleCache {
+            max_bytes,
+            tables: Mutex::new(TwiddleTables::default()),
         }
     }
 
-    let log_cpus = worker.log_num_cpus();
-    if log_n <= log_cpus {
-        serial_fft(a, omega, log_n);
-    } else {
-        parallel_fft(a, worker, omega, log_n, log_cpus);
+    /// The cache used by `serial_fft`, bounded by `BELLMAN_TWIDDLE_CACHE_SIZE` (In MiB).
+    pub fn global() -> &'static TwiddleCache {
+        &TWIDDLES
     }
 
-    Ok(())
-}
+    /// Returns `[omega^0, omega^1, ..., omega^(2^log_n / 4 - 1)]`, the twiddle factors of the
+    /// last radix-4 round of an FFT of 2^`log_n` elements. The other rounds use a stride of it.
+    pub fn get<F: PrimeField>(&self, omega: &F, log_n: u32) -> Arc<Vec<F>> {
+        let key = (
+            TypeId::of::<F>(),
+            log_n,
+            omega.into_repr().as_ref().to_vec(),
+        );
+        if let Some(table) = self.tables.lock().unwrap().get(&key) {
+            return table;
+        }
 
-pub fn gpu_fft<E: Engine, T: Group<E>>(
-    kern: &mut gpu::FFTKernel<E>,
-    a: &mut [T],
-    omega: &E::Fr,
-    log_n: u32,
-) -> gpu::GPUResult<()> {
-    // EvaluationDomain module is supposed to work only with E::Fr elements, and not CurveProjective
-    // points. The Bellman authors have implemented an unnecessarry abstraction called Group<E>
-    // which is implemented for both PrimeField and CurveProjective elements. As nowhere in the code
-    // is the CurveProjective version used, T and E::Fr are guaranteed to be equal and thus have same
-    // size.
-    // For compatibility/performance reasons we decided to transmute the array to the desired type
-    // as it seems safe and needs less modifications in the current structure of Bellman library.
-    let a = unsafe { std::mem::transmute::<&mut [T], &mut [E::Fr]>(a) };
-    kern.radix_fft(a, omega, log_n)?;
-    Ok(())
+        // The table is computed without holding the lock, so that it doesn't block the FFTs of
+        // other sizes. If another thread inserted it meanwhile, its table is used instead.
+        let mut table = Vec::with_capacity((1 << log_n) / 4);
+        let mut w = F::one();
+        for _ in 0..(1 << log_n) / 4 {
+            table.push(w);
+            w.mul_assign(omega);
+        }
+        let table = Arc::new(table);
+        if table.len() * std::mem::size_of::<F>() > self.max_bytes {
+            return table;
+        }
+
+        let mut tables = self.tables.lock().unwrap();
+        if let Some(table) = tables.get(&key) {
+            return table;
+        }
+        tables.insert(key, table.clone(), self.max_bytes);
+        table
+    }
+
+    /// Frees all of the cached tables
+    pub fn clear(&self) {
+        let mut tables = self.tables.lock().unwrap();
+        tables.tables.clear();
+        tables.bytes = 0;
+    }
 }
 
 pub fn serial_fft<E: ScalarEngine, T: Group<E>>(a: &mut [T], omega: &E::Fr, log_n: u32) {
@@ -351,27 +1318,98 @@ pub fn serial_fft<E: ScalarEngine, T: Group<E>>(a: &mut [T], omega: &E::Fr, log_
         }
     }
 
+    let n = n as usize;
     let mut m = 1;
-    for _ in 0..log_n {
-        let w_m = omega.pow(&[u64::from(n / (2 * m))]);
-
-        let mut k = 0;
-        while k < n {
-            let mut w = E::Fr::one();
-            for j in 0..m {
-                let mut t = a[(k + j + m) as usize];
-                t.group_mul_assign(&w);
-                let mut tmp = a[(k + j) as usize];
-                tmp.group_sub_assign(&t);
-                a[(k + j + m) as usize] = tmp;
-                a[(k + j) as usize].group_add_assign(&t);
-                w.mul_assign(&w_m);
-            }
+    if log_n % 2 == 1 {
+        // A single radix-2 round, whose twiddle factors are all 1
+        for k in (0..n).step_by(2) {
+            let t = a[k + 1];
+            let mut tmp = a[k];
+            tmp.group_sub_assign(&t);
+            a[k + 1] = tmp;
+            a[k].group_add_assign(&t);
+        }
+        m = 2;
+    }
+
+    // The remaining rounds are radix-4, each one merging two radix-2 rounds into a single pass
+    // over `a`, with half as many multiplications. The twiddle factors of a round are used for
+    // all of its blocks at once.
+    let omega_4 = omega.pow(&[(n / 4) as u64]); // Primitive 4th root of unity
+    let twiddles = if m < n {
+        TwiddleCache::global().get(omega, log_n)
+    } else {
+        Arc::new(Vec::new())
+    };
+
+    // The rounds whose butterflies stay within `FFT_BLOCK_SIZE` elements are done one block at a
+    // time, all of them on a block before moving to the next one, so that the block stays in the
+    // cache. The larger rounds are done over all of `a`.
+    let block = cmp::min(n, FFT_BLOCK_SIZE);
+    let mut blocked_m = m;
+    while blocked_m < n && 4 * blocked_m <= block {
+        blocked_m *= 4;
+    }
+    for a in a.chunks_mut(block) {
+        radix4_rounds::<E, T>(a, m, blocked_m, n, &twiddles, &omega_4);
+    }
+    radix4_rounds::<E, T>(a, blocked_m, n, n, &twiddles, &omega_4);
+}
+
+// Number of elements the radix-4 rounds of `serial_fft` are cache-blocked by
+const FFT_BLOCK_SIZE: usize = 1 << 10;
 
-            k += 2 * m;
+/// Runs the radix-4 rounds of an FFT of `n` elements, from the one of half-size `m` up to (And
+/// excluding) `end_m`, on `a`, which is `n` elements or an aligned block of them.
+fn radix4_rounds<E: ScalarEngine, T: Group<E>>(
+    a: &mut [T],
+    mut m: usize,
+    end_m: usize,
+    n: usize,
+    twiddles: &[E::Fr],
+    omega_4: &E::Fr,
+) {
+    let len = a.len();
+    while m < end_m {
+        let stride = n / (4 * m);
+
+        for j in 0..m {
+            let w1 = twiddles[j * stride];
+            let mut w2 = w1;
+            w2.square();
+            let mut w3 = w2;
+            w3.mul_assign(&w1);
+
+            for k in (j..len).step_by(4 * m) {
+                let mut t1 = a[k + m];
+                t1.group_mul_assign(&w2);
+                let mut t2 = a[k + 2 * m];
+                t2.group_mul_assign(&w1);
+                let mut t3 = a[k + 3 * m];
+                t3.group_mul_assign(&w3);
+
+                let mut b0 = a[k];
+                b0.group_add_assign(&t1);
+                let mut b1 = a[k];
+                b1.group_sub_assign(&t1);
+                let mut c0 = t2;
+                c0.group_add_assign(&t3);
+                let mut c1 = t2;
+                c1.group_sub_assign(&t3);
+                c1.group_mul_assign(omega_4);
+
+                a[k] = b0;
+                a[k].group_add_assign(&c0);
+                a[k + m] = b1;
+                a[k + m].group_add_assign(&c1);
+                a[k + 2 * m] = b0;
+                a[k + 2 * m].group_sub_assign(&c0);
+                a[k + 3 * m] = b1;
+                a[k + 3 * m].group_sub_assign(&c1);
+            }
         }
 
-        m *= 2;
+        m *= 4;
     }
 }
 
@@ -431,6 +1469,9 @@ fn parallel_fft<E: ScalarEngine, T: Group<E>>(
             });
         }
     });
+    for tmp in tmp.iter_mut() {
+        secret::zeroize(tmp);
+    }
 }
 
 // Test multiplying various (low degree) polynomials together and
@@ -524,6 +1565,154 @@ fn fft_composition() {
     test_comp::<Bls12, _>(rng);
 }
 
+#[test]
+fn mixed_radix_fft_composition() {
+    use paired::bls12_381::Bls12;
+    use rand_core::RngCore;
+
+    fn test_comp<E: Engine, R: RngCore>(rng: &mut R) {
+        let worker = Worker::new();
+
+        for len in &[3, 5, 7, 12, 13, 24, 25, 100, 384, 385, 1000] {
+            let mut v = vec![];
+            for _ in 0..*len {
+                v.push(Scalar::<E>(E::Fr::random(rng)));
+            }
+
+            let mut domain = EvaluationDomain::from_coeffs_mixed_radix(v.clone()).unwrap();
+            assert!(domain.coeffs.len() >= *len && domain.coeffs.len() < 2 * len);
+            domain.ifft(&worker, &mut None).unwrap();
+            domain.fft(&worker, &mut None).unwrap();
+            assert!(v[..] == domain.coeffs[..*len]);
+            domain.fft(&worker, &mut None).unwrap();
+            domain.ifft(&worker, &mut None).unwrap();
+            assert!(v[..] == domain.coeffs[..*len]);
+            domain.icoset_fft(&worker, &mut None).unwrap();
+            domain.coset_fft(&worker, &mut None).unwrap();
+            assert!(v[..] == domain.coeffs[..*len]);
+        }
+    }
+
+    let rng = &mut rand::thread_rng();
+
+    test_comp::<Bls12, _>(rng);
+}
+
+#[test]
+fn twiddle_cache_is_shared() {
+    use paired::bls12_381::Fr;
+
+    let mut omega = Fr::root_of_unity();
+    for _ in 10..Fr::S {
+        omega.square();
+    }
+    let cache = TwiddleCache::new(1 << 20);
+    let t1 = cache.get(&omega, 10);
+    let t2 = cache.get(&omega, 10);
+    assert!(Arc::ptr_eq(&t1, &t2));
+    assert_eq!(t1.len(), 1 << 8);
+    assert_eq!(t1[3], omega.pow(&[3]));
+
+    let mut other = omega;
+    other.square();
+    assert!(!Arc::ptr_eq(&t1, &cache.get(&other, 10)));
+}
+
+#[test]
+fn twiddle_cache_evicts_least_recently_used() {
+    use paired::bls12_381::Fr;
+
+    // Room for two tables of 2^8 elements
+    let cache = TwiddleCache::new(2 * (1 << 8) * std::mem::size_of::<Fr>());
+    let omegas = (2..5).map(|i| Fr::from_str(&i.to_string()).unwrap());
+    let omegas = omegas.collect::<Vec<_>>();
+    let t0 = cache.get(&omegas[0], 10);
+    let t1 = cache.get(&omegas[1], 10);
+    cache.get(&omegas[0], 10);
+    cache.get(&omegas[2], 10);
+    assert!(Arc::ptr_eq(&t0, &cache.get(&omegas[0], 10)));
+    assert!(!Arc::ptr_eq(&t1, &cache.get(&omegas[1], 10)));
+
+    // Tables larger than the cache aren't kept
+    let cache = TwiddleCache::new(0);
+    assert!(!Arc::ptr_eq(
+        &cache.get(&omegas[0], 10),
+        &cache.get(&omegas[0], 10)
+    ));
+}
+
+#[test]
+fn serial_fft_matches_dft() {
+    use paired::bls12_381::{Bls12, Fr};
+
+    let rng = &mut rand::thread_rng();
+
+    // Both odd and even sizes, i.e. with and without the initial radix-2 round, and larger than
+    // the cache blocks. Only some of the values of the larger FFTs are checked.
+    for log_d in 0..13 {
+        let d = 1 << log_d;
+        let v = (0..d)
+            .map(|_| Scalar::<Bls12>(Fr::random(rng)))
+            .collect::<Vec<_>>();
+        let mut domain = EvaluationDomain::from_coeffs(v.clone()).unwrap();
+        serial_fft(&mut domain.coeffs, &domain.omega, log_d);
+
+        for (k, e) in domain
+            .coeffs
+            .iter()
+            .enumerate()
+            .step_by(cmp::max(1, d >> 8))
+        {
+            let x = domain.omega.pow(&[k as u64]);
+            let mut expected = Fr::zero();
+            for c in v.iter().rev() {
+                expected.mul_assign(&x);
+                expected.add_assign(&c.0);
+            }
+            assert_eq!(e.0, expected);
+        }
+    }
+}
+
+#[test]
+fn batch_invert_parts_resumes() {
+    use paired::bls12_381::Fr;
+
+    let rng = &mut rand::thread_rng();
+    let orig = (0..10).map(|_| Fr::random(rng)).collect::<Vec<_>>();
+    let mut expected = orig.clone();
+    serial_batch_invert(&mut expected);
+
+    // Inverts the parts, failing on the second one
+    let invert = |fail: bool| {
+        let mut parts = 0;
+        move |part: &mut [Fr]| {
+            parts += 1;
+            if fail && parts == 2 {
+                return Err(gpu::GPUError::Simple("second part failed"));
+            }
+            serial_batch_invert(part);
+            Ok(())
+        }
+    };
+
+    // Retried
+    let mut a = orig.clone();
+    let mut done = 0;
+    assert!(batch_invert_parts(&mut a, 4, &mut done, invert(true)).is_err());
+    assert_eq!(done, 4);
+    batch_invert_parts(&mut a, 4, &mut done, invert(false)).unwrap();
+    assert_eq!(done, 10);
+    assert_eq!(a, expected);
+
+    // Finished on the CPU, as `best_batch_invert` does
+    let mut a = orig.clone();
+    let mut done = 0;
+    assert!(batch_invert_parts(&mut a, 4, &mut done, invert(true)).is_err());
+    serial_batch_invert(&mut a[done..]);
+    assert_eq!(a, expected);
+}
+
 #[cfg(feature = "pairing")]
 #[test]
 fn parallel_fft_consistency() {
@@ -559,7 +1748,7 @@ fn parallel_fft_consistency() {
     test_consistency::<Bls12, _>(rng);
 }
 
-pub fn create_fft_kernel<E>(log_d: usize, priority: bool) -> Option<gpu::FFTKernel<E>>
+pub fn create_fft_kernel<E>(log_d: usize, priority: gpu::Priority) -> Option<gpu::FFTKernel<E::Fr>>
 where
     E: Engine,
 {
@@ -578,7 +1767,10 @@ where
 #[cfg(feature = "gpu")]
 #[cfg(test)]
 mod tests {
-    use crate::domain::{gpu_fft, parallel_fft, serial_fft, EvaluationDomain, Scalar};
+    use crate::domain::{
+        best_batch_invert, best_polynomial_mul, gpu_fft, gpu_fft_many, gpu_fft_resume,
+        parallel_fft, serial_fft, EvaluationDomain, Scalar,
+    };
     use crate::gpu;
     use crate::multicore::Worker;
     use ff::Field;
@@ -594,7 +1786,8 @@ mod tests {
 
         let worker = Worker::new();
         let log_cpus = worker.log_num_cpus();
-        let mut kern = gpu::FFTKernel::create(1 << 24, false).expect("Cannot initialize kernel!");
+        let mut kern =
+            gpu::FFTKernel::create(1 << 24, gpu::Priority::Low).expect("Cannot initialize kernel!");
 
         for log_d in 1..25 {
             let d = 1 << log_d;
@@ -629,4 +1822,384 @@ mod tests {
             println!("============================");
         }
     }
+
+    #[test]
+    pub fn gpu_fft_resume_consistency() {
+        let _ = env_logger::try_init();
+
+        use paired::bls12_381::{Bls12, Fr};
+        let rng = &mut rand::thread_rng();
+
+        let worker = Worker::new();
+        let log_cpus = worker.log_num_cpus();
+        let mut kern =
+            gpu::FFTKernel::create(1 << 20, gpu::Priority::Low).expect("Cannot initialize kernel!");
+
+        for log_d in 1..21 {
+            let d = 1 << log_d;
+
+            let elems = (0..d)
+                .map(|_| Scalar::<Bls12>(Fr::random(rng)))
+                .collect::<Vec<_>>();
+            let mut v1 = EvaluationDomain::from_coeffs(elems.clone()).unwrap();
+            let mut v2 = EvaluationDomain::from_coeffs(elems.clone()).unwrap();
+
+            let mut checkpoint = None;
+            gpu_fft_resume(&mut kern, &mut v1.coeffs, &v1.omega, log_d, &mut checkpoint)
+                .expect("GPU FFT failed!");
+            assert!(checkpoint.is_none());
+
+            if log_d <= log_cpus {
+                serial_fft(&mut v2.coeffs, &v2.omega, log_d);
+            } else {
+                parallel_fft(&mut v2.coeffs, &worker, &v2.omega, log_d, log_cpus);
+            }
+
+            assert!(v1.coeffs == v2.coeffs);
+        }
+    }
+
+    #[test]
+    pub fn gpu_fft_many_consistency() {
+        let _ = env_logger::try_init();
+
+        use paired::bls12_381::{Bls12, Fr};
+        let rng = &mut rand::thread_rng();
+
+        let worker = Worker::new();
+        let log_cpus = worker.log_num_cpus();
+        let mut kern =
+            gpu::FFTKernel::create(1 << 20, gpu::Priority::Low).expect("Cannot initialize kernel!");
+
+        for log_d in 1..21 {
+            let d = 1 << log_d;
+
+            let mut gpu_polys = (0..3)
+                .map(|_| {
+                    (0..d)
+                        .map(|_| Scalar::<Bls12>(Fr::random(rng)))
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+            let mut cpu_polys = gpu_polys.clone();
+            let omega = EvaluationDomain::from_coeffs(gpu_polys[0].clone())
+                .unwrap()
+                .omega;
+
+            println!("Testing batched FFT for 3 x {} elements...", d);
+
+            let mut done = 0;
+            let mut refs = gpu_polys.iter_mut().map(|p| &mut p[..]).collect::<Vec<_>>();
+            gpu_fft_many(&mut kern, &mut refs, &omega, log_d, &mut done).expect("GPU FFT failed!");
+            assert_eq!(done, 3);
+
+            for p in cpu_polys.iter_mut() {
+                if log_d <= log_cpus {
+                    serial_fft(p, &omega, log_d);
+                } else {
+                    parallel_fft(p, &worker, &omega, log_d, log_cpus);
+                }
+            }
+
+            assert!(gpu_polys == cpu_polys);
+        }
+    }
+
+    #[test]
+    pub fn gpu_coset_fft_consistency() {
+        let _ = env_logger::try_init();
+
+        use paired::bls12_381::{Bls12, Fr};
+        let rng = &mut rand::thread_rng();
+
+        let worker = Worker::new();
+        let mut kern = Some(gpu::LockedFFTKernel::<Bls12>::new(20, gpu::Priority::Low));
+
+        for log_d in 1..21 {
+            let d = 1 << log_d;
+
+            let elems = (0..d)
+                .map(|_| Scalar::<Bls12>(Fr::random(rng)))
+                .collect::<Vec<_>>();
+            let mut v1 = EvaluationDomain::from_coeffs(elems.clone()).unwrap();
+            let mut v2 = EvaluationDomain::from_coeffs(elems.clone()).unwrap();
+
+            println!("Testing coset FFT for {} elements...", d);
+
+            v1.coset_fft(&worker, &mut kern).expect("GPU FFT failed!");
+            v2.coset_fft(&worker, &mut None).expect("CPU FFT failed!");
+            assert!(v1.coeffs == v2.coeffs);
+
+            v1.icoset_fft(&worker, &mut kern).expect("GPU FFT failed!");
+            v2.icoset_fft(&worker, &mut None).expect("CPU FFT failed!");
+            assert!(v1.coeffs == v2.coeffs);
+            assert!(v1.coeffs == elems);
+        }
+    }
+
+    #[test]
+    pub fn gpu_quotient_consistency() {
+        let _ = env_logger::try_init();
+
+        use paired::bls12_381::{Bls12, Fr};
+        let rng = &mut rand::thread_rng();
+
+        let worker = Worker::new();
+        let mut kern = Some(gpu::LockedFFTKernel::<Bls12>::new(20, gpu::Priority::Low));
+
+        for log_d in 1..21 {
+            let d = 1 << log_d;
+
+            let mut polys = (0..3)
+                .map(|_| {
+                    let elems = (0..d)
+                        .map(|_| Scalar::<Bls12>(Fr::random(rng)))
+                        .collect::<Vec<_>>();
+                    EvaluationDomain::from_coeffs(elems).unwrap()
+                })
+                .collect::<Vec<_>>();
+            let mut c1 = polys.pop().unwrap();
+            let mut b1 = polys.pop().unwrap();
+            let mut a1 = polys.pop().unwrap();
+            let mut a2 = EvaluationDomain::from_coeffs(a1.coeffs.clone()).unwrap();
+            let mut b2 = EvaluationDomain::from_coeffs(b1.coeffs.clone()).unwrap();
+            let mut c2 = EvaluationDomain::from_coeffs(c1.coeffs.clone()).unwrap();
+
+            println!("Testing quotient for {} elements...", d);
+
+            a1.quotient(&worker, &mut b1, &mut c1, &mut kern)
+                .expect("GPU quotient failed!");
+            a2.quotient(&worker, &mut b2, &mut c2, &mut None)
+                .expect("CPU quotient failed!");
+            assert!(a1.coeffs == a2.coeffs);
+        }
+    }
+
+    #[test]
+    pub fn gpu_batch_invert_consistency() {
+        let _ = env_logger::try_init();
+
+        use paired::bls12_381::{Bls12, Fr};
+        let rng = &mut rand::thread_rng();
+
+        let worker = Worker::new();
+        let mut kern = Some(gpu::LockedFFTKernel::<Bls12>::new(16, gpu::Priority::Low));
+
+        // Not a multiple of the chunks of the kernel, and larger than its buffers
+        for d in &[1, 100, 1000, (1 << 16) + 3, 1 << 17] {
+            let mut v1 = (0..*d).map(|_| Fr::random(rng)).collect::<Vec<_>>();
+            v1[0] = Fr::zero();
+            let orig = v1.clone();
+            let mut v2 = v1.clone();
+
+            println!("Testing batch inversion for {} elements...", d);
+
+            best_batch_invert(&mut kern, &mut v1, &worker).expect("GPU batch inversion failed!");
+            best_batch_invert::<Bls12>(&mut None, &mut v2, &worker)
+                .expect("CPU batch inversion failed!");
+            assert!(v1 == v2);
+            assert!(v1[0].is_zero());
+            for (x, inv) in orig.iter().zip(v1.iter()).skip(1) {
+                let mut one = *x;
+                one.mul_assign(inv);
+                assert_eq!(one, Fr::one());
+            }
+        }
+    }
+
+    #[test]
+    pub fn gpu_polynomial_mul_consistency() {
+        let _ = env_logger::try_init();
+
+        use paired::bls12_381::{Bls12, Fr};
+        let rng = &mut rand::thread_rng();
+
+        let worker = Worker::new();
+        let mut kern = Some(gpu::LockedFFTKernel::<Bls12>::new(16, gpu::Priority::Low));
+
+        for &(d1, d2) in &[
+            (1, 1),
+            (3, 5),
+            (100, 29),
+            (1 << 10, 1 << 10),
+            (40000, 25000),
+        ] {
+            let a = (0..d1).map(|_| Fr::random(rng)).collect::<Vec<_>>();
+            let b = (0..d2).map(|_| Fr::random(rng)).collect::<Vec<_>>();
+
+            println!(
+                "Testing polynomial multiplication for {}x{} elements...",
+                d1, d2
+            );
+
+            let gpu = best_polynomial_mul(&mut kern, &a, &b, &worker).unwrap();
+            let cpu = best_polynomial_mul::<Bls12>(&mut None, &a, &b, &worker).unwrap();
+            assert_eq!(gpu.len(), d1 + d2 - 1);
+            assert!(gpu == cpu);
+
+            // Compare with the evaluations at a random point
+            let x = Fr::random(rng);
+            let eval = |p: &[Fr]| {
+                p.iter().rev().fold(Fr::zero(), |mut acc, c| {
+                    acc.mul_assign(&x);
+                    acc.add_assign(c);
+                    acc
+                })
+            };
+            let mut expected = eval(&a);
+            expected.mul_assign(&eval(&b));
+            assert_eq!(eval(&gpu), expected);
+        }
+    }
+
+    #[test]
+    pub fn gpu_evaluation_domain_consistency() {
+        let _ = env_logger::try_init();
+
+        use paired::bls12_381::{Bls12, Fr};
+        let rng = &mut rand::thread_rng();
+
+        const LOG_D: u32 = 12;
+        let worker = Worker::new();
+        let mut session = gpu::FFTSession::<Fr>::create(LOG_D, gpu::Priority::Low).unwrap();
+
+        let polys = (0..3)
+            .map(|_| {
+                (0..1 << LOG_D)
+                    .map(|_| Scalar::<Bls12>(Fr::random(rng)))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut gpu_polys = polys
+            .iter()
+            .map(|p| EvaluationDomain::gpu(p.clone(), &mut session).unwrap())
+            .collect::<Vec<_>>();
+        for p in gpu_polys.iter_mut() {
+            p.ifft(&mut session).unwrap();
+            p.coset_fft(&mut session).unwrap();
+        }
+        let c = gpu_polys.pop().unwrap();
+        let b = gpu_polys.pop().unwrap();
+        let mut a = gpu_polys.pop().unwrap();
+        a.mul_assign(&mut session, &b).unwrap();
+        a.sub_assign(&mut session, &c).unwrap();
+        a.divide_by_z_on_coset(&mut session).unwrap();
+        a.icoset_fft(&mut session).unwrap();
+        a.fft(&mut session).unwrap();
+
+        let mut cpu_polys = polys
+            .into_iter()
+            .map(|p| EvaluationDomain::from_coeffs(p).unwrap())
+            .collect::<Vec<_>>();
+        for p in cpu_polys.iter_mut() {
+            p.ifft(&worker, &mut None).unwrap();
+            p.coset_fft(&worker, &mut None).unwrap();
+        }
+        let c = cpu_polys.pop().unwrap();
+        let b = cpu_polys.pop().unwrap();
+        let mut expected = cpu_polys.pop().unwrap();
+        expected.mul_assign(&worker, &b);
+        expected.sub_assign(&worker, &c);
+        expected.divide_by_z_on_coset(&worker);
+        expected.icoset_fft(&worker, &mut None).unwrap();
+        expected.fft(&worker, &mut None).unwrap();
+
+        assert!(a.into_coeffs().unwrap() == expected.into_coeffs());
+    }
+
+    #[test]
+    pub fn gpu_fft_session_consistency() {
+        let _ = env_logger::try_init();
+
+        use paired::bls12_381::{Bls12, Fr};
+        let rng = &mut rand::thread_rng();
+
+        let log_d = 16;
+        let d = 1 << log_d;
+        let mut session = gpu::FFTSession::<Fr>::create(log_d, gpu::Priority::Low)
+            .expect("Cannot initialize session!");
+
+        for _ in 0..10 {
+            let elems = (0..d).map(|_| Fr::random(rng)).collect::<Vec<_>>();
+            let domain =
+                EvaluationDomain::from_coeffs(elems.iter().map(|e| Scalar::<Bls12>(*e)).collect())
+                    .unwrap();
+
+            // FFT followed by an inverse FFT, without leaving the GPU
+            let mut result = vec![Fr::zero(); d];
+            session.upload(&elems).expect("Upload failed!");
+            session.transform(&domain.omega).expect("FFT failed!");
+            session.transform(&domain.omegainv).expect("FFT failed!");
+            session
+                .mul_by_powers(&Fr::one(), &domain.minv)
+                .expect("Scaling failed!");
+            session.download(&mut result).expect("Download failed!");
+
+            assert!(result == elems);
+        }
+    }
+
+    #[test]
+    pub fn gpu_bailey_fft_consistency() {
+        let _ = env_logger::try_init();
+
+        use paired::bls12_381::{Bls12, Fr};
+        let rng = &mut rand::thread_rng();
+
+        let worker = Worker::new();
+        let log_cpus = worker.log_num_cpus();
+        // Buffers of 2^10 elements, so that larger FFTs are streamed through the GPU
+        let mut kern = gpu::FFTKernel::<Fr>::create_with_memory_limit(
+            1 << 20,
+            gpu::Priority::Low,
+            Some(gpu::required_memory::<Fr>(10)),
+        )
+        .expect("Cannot initialize kernel!");
+
+        for log_d in 11..21 {
+            let d = 1 << log_d;
+
+            let elems = (0..d)
+                .map(|_| Scalar::<Bls12>(Fr::random(rng)))
+                .collect::<Vec<_>>();
+            let mut v1 = EvaluationDomain::from_coeffs(elems.clone()).unwrap();
+            let mut v2 = EvaluationDomain::from_coeffs(elems.clone()).unwrap();
+
+            println!("Testing four-step FFT for {} elements...", d);
+
+            gpu_fft(&mut kern, &mut v1.coeffs, &v1.omega, log_d).expect("GPU FFT failed!");
+            parallel_fft(&mut v2.coeffs, &worker, &v2.omega, log_d, log_cpus);
+
+            assert!(v1.coeffs == v2.coeffs);
+        }
+    }
+
+    #[test]
+    pub fn gpu_mixed_radix_fft_consistency() {
+        let _ = env_logger::try_init();
+
+        use paired::bls12_381::{Bls12, Fr};
+        let rng = &mut rand::thread_rng();
+
+        let worker = Worker::new();
+        let mut kern = Some(gpu::LockedFFTKernel::<Bls12>::new(20, gpu::Priority::Low));
+
+        for log_d in 1..19 {
+            let d = 3 << log_d;
+
+            let elems = (0..d)
+                .map(|_| Scalar::<Bls12>(Fr::random(rng)))
+                .collect::<Vec<_>>();
+            let mut v1 = EvaluationDomain::from_coeffs_mixed_radix(elems.clone()).unwrap();
+            let mut v2 = EvaluationDomain::from_coeffs_mixed_radix(elems.clone()).unwrap();
+            assert_eq!(v1.radix3, 1);
+
+            println!("Testing mixed radix FFT for {} elements...", d);
+
+            v1.fft(&worker, &mut kern).expect("GPU FFT failed!");
+            v2.fft(&worker, &mut None).expect("CPU FFT failed!");
+            assert!(v1.coeffs == v2.coeffs);
+        }
+    }
 }
@@ -4,12 +4,17 @@ pub mod test;
 
 pub mod blake2s;
 pub mod boolean;
+pub mod ecc;
 pub mod lookup;
+pub mod merkle;
 pub mod multieq;
 pub mod multipack;
 pub mod num;
+pub mod poseidon;
+pub mod public_inputs;
 pub mod sha256;
 pub mod uint32;
+pub mod uint64;
 
 use crate::SynthesisError;
 
@@ -0,0 +1,392 @@
+//! Points of the Jubjub twisted Edwards curve `-x^2 + y^2 = 1 + d x^2 y^2`, defined over the
+//! scalar field of BLS12-381 (With `d = -(10240/10241)`), and the fixed-base scalar
+//! multiplication of signature verification circuits.
+//!
+//! `fixed_base_multiplication` looks up the multiples of the base in 3-bit windows, which costs
+//! about 3 constraints per bit instead of 12 for a double-and-add. The `FixedBaseTable` of a
+//! base is computed once, at setup, and can be stored next to the parameters of the circuit
+//! with `FixedBaseTable::write` (It only depends on the base and the number of bits).
+
+use blake2b_simd::Params as Blake2bParams;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use ff::{Field, PrimeField, PrimeFieldRepr, ScalarEngine, SqrtField};
+use rand_chacha::ChaChaRng;
+use rand_core::SeedableRng;
+
+use std::io::{self, Read, Write};
+
+use super::boolean::Boolean;
+use super::lookup::lookup3_xy;
+use super::num::AllocatedNum;
+use crate::{ConstraintSystem, SynthesisError};
+
+/// Domain separation tag of `EdwardsPoint::hash_to_subgroup`.
+pub const HASH_TO_SUBGROUP_DOMAIN: &[u8] = b"bellperson/jubjub/hash-to-subgroup";
+
+/// The `d` parameter of the curve.
+fn edwards_d<F: PrimeField>() -> F {
+    let mut d = F::from_str("10240").unwrap();
+    d.mul_assign(&F::from_str("10241").unwrap().inverse().unwrap());
+    d.negate();
+    d
+}
+
+/// A point of the curve, in affine coordinates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EdwardsPoint<F: PrimeField> {
+    pub x: F,
+    pub y: F,
+}
+
+impl<F: PrimeField + SqrtField> EdwardsPoint<F> {
+    pub fn identity() -> Self {
+        EdwardsPoint {
+            x: F::zero(),
+            y: F::one(),
+        }
+    }
+
+    /// The point of coordinate `y` with an even `x`, if there is one.
+    pub fn from_y(y: F) -> Option<Self> {
+        // x^2 = (y^2 - 1) / (d y^2 + 1)
+        let mut y2 = y;
+        y2.square();
+        let mut num = y2;
+        num.sub_assign(&F::one());
+        let mut den = y2;
+        den.mul_assign(&edwards_d());
+        den.add_assign(&F::one());
+
+        let mut x2 = num;
+        x2.mul_assign(&den.inverse()?);
+        let mut x = x2.sqrt()?;
+        if x.into_repr().is_odd() {
+            x.negate();
+        }
+        Some(EdwardsPoint { x, y })
+    }
+
+    /// A point of the prime order subgroup derived from `tag`, whose discrete logarithm to any
+    /// other base is unknown, e.g. for the generators of signatures.
+    pub fn hash_to_subgroup(tag: &[u8]) -> Self {
+        let mut seed = [0u8; 32];
+        seed.copy_from_slice(
+            Blake2bParams::new()
+                .hash_length(32)
+                .to_state()
+                .update(HASH_TO_SUBGROUP_DOMAIN)
+                .update(tag)
+                .finalize()
+                .as_bytes(),
+        );
+        let mut rng = ChaChaRng::from_seed(seed);
+
+        loop {
+            if let Some(p) = Self::from_y(F::random(&mut rng)) {
+                let p = p.mul_by_cofactor();
+                if p != Self::identity() {
+                    return p;
+                }
+            }
+        }
+    }
+
+    pub fn mul_by_cofactor(&self) -> Self {
+        self.double().double().double()
+    }
+
+    pub fn add(&self, other: &Self) -> Self {
+        let d = edwards_d::<F>();
+        // x3 = (x1 y2 + y1 x2) / (1 + d x1 x2 y1 y2), y3 = (y1 y2 + x1 x2) / (1 - d x1 x2 y1 y2)
+        let mut a = self.x;
+        a.mul_assign(&other.y);
+        let mut b = self.y;
+        b.mul_assign(&other.x);
+        let mut c = a;
+        c.mul_assign(&b);
+        c.mul_assign(&d);
+
+        let mut x_num = a;
+        x_num.add_assign(&b);
+        let mut x_den = F::one();
+        x_den.add_assign(&c);
+
+        let mut y_num = self.y;
+        y_num.mul_assign(&other.y);
+        let mut xx = self.x;
+        xx.mul_assign(&other.x);
+        y_num.add_assign(&xx);
+        let mut y_den = F::one();
+        y_den.sub_assign(&c);
+
+        // The addition is complete, the denominators are never zero
+        x_num.mul_assign(&x_den.inverse().unwrap());
+        y_num.mul_assign(&y_den.inverse().unwrap());
+        EdwardsPoint { x: x_num, y: y_num }
+    }
+
+    pub fn double(&self) -> Self {
+        self.add(self)
+    }
+
+    /// The point multiplied by the scalar of `bits`, least significant first.
+    pub fn mul_by_bits(&self, bits: &[bool]) -> Self {
+        let mut acc = Self::identity();
+        for bit in bits.iter().rev() {
+            acc = acc.double();
+            if *bit {
+                acc = acc.add(self);
+            }
+        }
+        acc
+    }
+}
+
+/// A point allocated in a circuit. Its coordinates aren't constrained to be on the curve.
+#[derive(Clone)]
+pub struct AllocatedPoint<E: ScalarEngine> {
+    pub x: AllocatedNum<E>,
+    pub y: AllocatedNum<E>,
+}
+
+impl<E: ScalarEngine> AllocatedPoint<E> {
+    pub fn get_value(&self) -> Option<EdwardsPoint<E::Fr>> {
+        Some(EdwardsPoint {
+            x: self.x.get_value()?,
+            y: self.y.get_value()?,
+        })
+    }
+
+    /// The sum of two points, in 6 constraints.
+    pub fn add<CS>(&self, mut cs: CS, other: &Self) -> Result<Self, SynthesisError>
+    where
+        CS: ConstraintSystem<E>,
+    {
+        let d = edwards_d::<E::Fr>();
+
+        // u = (x1 + y1) (x2 + y2), a = x1 y2, b = y1 x2, c = d a b
+        let u = AllocatedNum::alloc(cs.namespace(|| "u"), || {
+            let mut t0 = self
+                .x
+                .get_value()
+                .ok_or(SynthesisError::AssignmentMissing)?;
+            t0.add_assign(
+                &self
+                    .y
+                    .get_value()
+                    .ok_or(SynthesisError::AssignmentMissing)?,
+            );
+            let mut t1 = other
+                .x
+                .get_value()
+                .ok_or(SynthesisError::AssignmentMissing)?;
+            t1.add_assign(
+                &other
+                    .y
+                    .get_value()
+                    .ok_or(SynthesisError::AssignmentMissing)?,
+            );
+            t0.mul_assign(&t1);
+            Ok(t0)
+        })?;
+        cs.enforce(
+            || "u computation",
+            |lc| lc + self.x.get_variable() + self.y.get_variable(),
+            |lc| lc + other.x.get_variable() + other.y.get_variable(),
+            |lc| lc + u.get_variable(),
+        );
+        let a = self.x.mul(cs.namespace(|| "a computation"), &other.y)?;
+        let b = self.y.mul(cs.namespace(|| "b computation"), &other.x)?;
+        let c = AllocatedNum::alloc(cs.namespace(|| "c"), || {
+            let mut t = a.get_value().ok_or(SynthesisError::AssignmentMissing)?;
+            t.mul_assign(&b.get_value().ok_or(SynthesisError::AssignmentMissing)?);
+            t.mul_assign(&d);
+            Ok(t)
+        })?;
+        cs.enforce(
+            || "c computation",
+            |lc| lc + (d, a.get_variable()),
+            |lc| lc + b.get_variable(),
+            |lc| lc + c.get_variable(),
+        );
+
+        let sum =
+            || -> Option<EdwardsPoint<E::Fr>> { Some(self.get_value()?.add(&other.get_value()?)) };
+        let x = AllocatedNum::alloc(cs.namespace(|| "x"), || {
+            sum().map(|p| p.x).ok_or(SynthesisError::AssignmentMissing)
+        })?;
+        cs.enforce(
+            || "x computation",
+            |lc| lc + CS::one() + c.get_variable(),
+            |lc| lc + x.get_variable(),
+            |lc| lc + a.get_variable() + b.get_variable(),
+        );
+        let y = AllocatedNum::alloc(cs.namespace(|| "y"), || {
+            sum().map(|p| p.y).ok_or(SynthesisError::AssignmentMissing)
+        })?;
+        cs.enforce(
+            || "y computation",
+            |lc| lc + CS::one() - c.get_variable(),
+            |lc| lc + y.get_variable(),
+            |lc| lc + u.get_variable() - a.get_variable() - b.get_variable(),
+        );
+
+        Ok(AllocatedPoint { x, y })
+    }
+}
+
+/// The multiples `j * 8^i * base` of a fixed base, for `j` in `0..8` and each 3-bit window `i` of
+/// the scalars.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FixedBaseTable<F: PrimeField> {
+    windows: Vec<Vec<(F, F)>>,
+}
+
+impl<F: PrimeField + SqrtField> FixedBaseTable<F> {
+    /// The table of `base`, for scalars of up to `num_bits` bits.
+    pub fn new(base: &EdwardsPoint<F>, num_bits: usize) -> Self {
+        let mut window_base = *base;
+        let windows = (0..(num_bits + 2) / 3)
+            .map(|_| {
+                let mut multiple = EdwardsPoint::identity();
+                let window = (0..8)
+                    .map(|_| {
+                        let coords = (multiple.x, multiple.y);
+                        multiple = multiple.add(&window_base);
+                        coords
+                    })
+                    .collect();
+                window_base = multiple;
+                window
+            })
+            .collect();
+
+        FixedBaseTable { windows }
+    }
+
+    /// The largest number of bits of the scalars.
+    pub fn num_bits(&self) -> usize {
+        3 * self.windows.len()
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_u32::<BigEndian>(self.windows.len() as u32)?;
+        for (x, y) in self.windows.iter().flatten() {
+            x.into_repr().write_be(&mut writer)?;
+            y.into_repr().write_be(&mut writer)?;
+        }
+        Ok(())
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> io::Result<Self> {
+        let read_field = |reader: &mut R| -> io::Result<F> {
+            let mut repr = F::Repr::default();
+            repr.read_be(reader)?;
+            F::from_repr(repr).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        };
+
+        let len = reader.read_u32::<BigEndian>()? as usize;
+        let mut windows = Vec::with_capacity(len);
+        for _ in 0..len {
+            let window = (0..8)
+                .map(|_| -> io::Result<(F, F)> {
+                    Ok((read_field(&mut reader)?, read_field(&mut reader)?))
+                })
+                .collect::<io::Result<_>>()?;
+            windows.push(window);
+        }
+        Ok(FixedBaseTable { windows })
+    }
+}
+
+/// The fixed base of `table` multiplied by the scalar of `bits`, least significant first.
+pub fn fixed_base_multiplication<E, CS>(
+    mut cs: CS,
+    table: &FixedBaseTable<E::Fr>,
+    bits: &[Boolean],
+) -> Result<AllocatedPoint<E>, SynthesisError>
+where
+    E: ScalarEngine,
+    CS: ConstraintSystem<E>,
+{
+    assert!(
+        bits.len() <= table.num_bits(),
+        "the scalar has more bits than the table"
+    );
+
+    let mut acc: Option<AllocatedPoint<E>> = None;
+    for (i, (window, coords)) in bits.chunks(3).zip(&table.windows).enumerate() {
+        let mut window = window.to_vec();
+        window.resize(3, Boolean::constant(false));
+        let (x, y) = lookup3_xy(cs.namespace(|| format!("window {}", i)), &window, coords)?;
+        let point = AllocatedPoint { x, y };
+
+        acc = Some(match acc {
+            None => point,
+            Some(acc) => acc.add(cs.namespace(|| format!("addition {}", i)), &point)?,
+        });
+    }
+
+    match acc {
+        Some(acc) => Ok(acc),
+        None => {
+            let identity = EdwardsPoint::<E::Fr>::identity();
+            Ok(AllocatedPoint {
+                x: AllocatedNum::alloc(cs.namespace(|| "x"), || Ok(identity.x))?,
+                y: AllocatedNum::alloc(cs.namespace(|| "y"), || Ok(identity.y))?,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gadgets::boolean::AllocatedBit;
+    use crate::gadgets::test::TestConstraintSystem;
+    use paired::bls12_381::{Bls12, Fr};
+    use rand_core::RngCore;
+
+    #[test]
+    fn test_edwards_arithmetic() {
+        let p = EdwardsPoint::<Fr>::hash_to_subgroup(b"p");
+        let q = EdwardsPoint::<Fr>::hash_to_subgroup(b"q");
+        assert!(p != q);
+        assert_eq!(p.add(&q), q.add(&p));
+        assert_eq!(p.add(&EdwardsPoint::identity()), p);
+        assert_eq!(p.mul_by_bits(&[true, true]), p.double().add(&p));
+        assert_eq!(EdwardsPoint::from_y(p.y).map(|r| r.y), Some(p.y));
+    }
+
+    #[test]
+    fn test_fixed_base_multiplication() {
+        let rng = &mut rand::thread_rng();
+        let base = EdwardsPoint::<Fr>::hash_to_subgroup(b"base");
+        let table = FixedBaseTable::new(&base, 251);
+        assert_eq!(table.num_bits(), 252);
+
+        let mut encoded = vec![];
+        table.write(&mut encoded).unwrap();
+        assert!(FixedBaseTable::read(&encoded[..]).unwrap() == table);
+
+        let bits = (0..251)
+            .map(|_| rng.next_u32() % 2 == 0)
+            .collect::<Vec<_>>();
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let allocated = bits
+            .iter()
+            .enumerate()
+            .map(|(i, b)| {
+                let cs = cs.namespace(|| format!("bit {}", i));
+                Boolean::from(AllocatedBit::alloc(cs, Some(*b)).unwrap())
+            })
+            .collect::<Vec<_>>();
+        let point = fixed_base_multiplication(cs.namespace(|| "mul"), &table, &allocated).unwrap();
+
+        assert!(cs.is_satisfied());
+        assert_eq!(point.get_value(), Some(base.mul_by_bits(&bits)));
+        // The bits, 3 constraints per window lookup (But the last one, whose highest bit is a
+        // constant) and 6 per addition
+        assert_eq!(cs.num_constraints(), 251 + 84 * 3 - 1 + 83 * 6);
+    }
+}
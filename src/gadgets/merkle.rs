@@ -0,0 +1,285 @@
+//! Verification of the paths of [`crate::merkle::MerkleTree`]s, of any power of two arity.
+
+use ff::{Field, ScalarEngine};
+
+use super::boolean::Boolean;
+use super::num::AllocatedNum;
+use super::poseidon::poseidon_hash;
+use crate::merkle::MerkleHasher;
+use crate::poseidon::PoseidonParams;
+use crate::{ConstraintSystem, LinearCombination, SynthesisError};
+
+/// A `MerkleHasher` which can also hash in a circuit.
+pub trait MerkleHasherGadget<E: ScalarEngine>: MerkleHasher<E::Fr> {
+    fn hash_gadget<CS: ConstraintSystem<E>>(
+        &self,
+        cs: CS,
+        children: &[AllocatedNum<E>],
+    ) -> Result<AllocatedNum<E>, SynthesisError>;
+}
+
+impl<E: ScalarEngine> MerkleHasherGadget<E> for PoseidonParams<E::Fr> {
+    fn hash_gadget<CS: ConstraintSystem<E>>(
+        &self,
+        cs: CS,
+        children: &[AllocatedNum<E>],
+    ) -> Result<AllocatedNum<E>, SynthesisError> {
+        poseidon_hash(cs, self, children)
+    }
+}
+
+// The one-hot decoding of the position `bits` (Least significant first): the j-th selector is
+// set if the position is j
+fn selectors<E, CS>(mut cs: CS, bits: &[Boolean]) -> Result<Vec<Boolean>, SynthesisError>
+where
+    E: ScalarEngine,
+    CS: ConstraintSystem<E>,
+{
+    let mut selectors = vec![Boolean::constant(true)];
+    for (i, bit) in bits.iter().enumerate() {
+        let mut next = Vec::with_capacity(2 * selectors.len());
+        for (j, s) in selectors.iter().enumerate() {
+            next.push(Boolean::and(
+                cs.namespace(|| format!("selector {} without bit {}", j, i)),
+                s,
+                &bit.not(),
+            )?);
+        }
+        for (j, s) in selectors.iter().enumerate() {
+            next.push(Boolean::and(
+                cs.namespace(|| format!("selector {} with bit {}", j, i)),
+                s,
+                bit,
+            )?);
+        }
+        selectors = next;
+    }
+    Ok(selectors)
+}
+
+// The children of a node: `siblings`, with `node` inserted at the position of `selectors`.
+//
+// With s_k the selector of k and L_k the sum of the selectors before k, the k-th child is
+// sib[k] + s_k (node - sib[k]) + L_k (sib[k - 1] - sib[k]), which is node if the position is k,
+// sib[k - 1] if it is before k and sib[k] if it is after k. The first and last children only
+// have one of the siblings, and don't need the L_k product.
+fn insert<E, CS>(
+    mut cs: CS,
+    node: &AllocatedNum<E>,
+    siblings: &[AllocatedNum<E>],
+    selectors: &[Boolean],
+) -> Result<Vec<AllocatedNum<E>>, SynthesisError>
+where
+    E: ScalarEngine,
+    CS: ConstraintSystem<E>,
+{
+    let arity = selectors.len();
+    let values = (|| {
+        let position = selectors
+            .iter()
+            .map(|s| s.get_value())
+            .collect::<Option<Vec<_>>>()?
+            .iter()
+            .position(|s| *s)?;
+        let mut values = siblings
+            .iter()
+            .map(|s| s.get_value())
+            .collect::<Option<Vec<_>>>()?;
+        values.insert(position, node.get_value()?);
+        Some(values)
+    })();
+
+    let one = E::Fr::one();
+    let mut before = LinearCombination::<E>::zero();
+    let mut children = Vec::with_capacity(arity);
+    for k in 0..arity {
+        let mut cs = cs.namespace(|| format!("child {}", k));
+        let base = if k < arity - 1 {
+            &siblings[k]
+        } else {
+            &siblings[k - 1]
+        };
+
+        let mut t = None;
+        if 0 < k && k < arity - 1 {
+            let (left, right) = (&siblings[k - 1], &siblings[k]);
+            let before_value = selectors[..k]
+                .iter()
+                .map(|s| s.get_value())
+                .collect::<Option<Vec<_>>>()
+                .map(|s| s.iter().any(|s| *s));
+            let value = match (before_value, left.get_value(), right.get_value()) {
+                (Some(false), _, _) => Some(E::Fr::zero()),
+                (Some(true), Some(mut l), Some(r)) => {
+                    l.sub_assign(&r);
+                    Some(l)
+                }
+                _ => None,
+            };
+            let product = AllocatedNum::alloc(cs.namespace(|| "shift"), || {
+                value.ok_or(SynthesisError::AssignmentMissing)
+            })?;
+            cs.enforce(
+                || "shift constraint",
+                |lc| lc + &before,
+                |lc| lc + left.get_variable() - right.get_variable(),
+                |lc| lc + product.get_variable(),
+            );
+            t = Some(product);
+        }
+
+        let child = AllocatedNum::alloc(cs.namespace(|| "child"), || {
+            values
+                .as_ref()
+                .map(|v| v[k])
+                .ok_or(SynthesisError::AssignmentMissing)
+        })?;
+        cs.enforce(
+            || "child constraint",
+            |lc| lc + &selectors[k].lc(CS::one(), one),
+            |lc| lc + node.get_variable() - base.get_variable(),
+            |lc| {
+                let lc = lc + child.get_variable() - base.get_variable();
+                match &t {
+                    Some(t) => lc - t.get_variable(),
+                    None => lc,
+                }
+            },
+        );
+        children.push(child);
+
+        before = before + &selectors[k].lc(CS::one(), one);
+    }
+
+    Ok(children)
+}
+
+/// The root of the tree of `leaf`, from its path (`MerklePath::siblings`) and the bits of its
+/// index (`MerklePath::index_bits`).
+pub fn merkle_root<E, CS, H>(
+    mut cs: CS,
+    hasher: &H,
+    leaf: &AllocatedNum<E>,
+    index_bits: &[Boolean],
+    path: &[Vec<AllocatedNum<E>>],
+) -> Result<AllocatedNum<E>, SynthesisError>
+where
+    E: ScalarEngine,
+    CS: ConstraintSystem<E>,
+    H: MerkleHasherGadget<E>,
+{
+    let arity = hasher.arity();
+    assert!(
+        arity >= 2 && arity.is_power_of_two(),
+        "the arity must be a power of two"
+    );
+    let bits_per_level = arity.trailing_zeros() as usize;
+    assert_eq!(index_bits.len(), path.len() * bits_per_level);
+
+    let mut node = leaf.clone();
+    for (level, (siblings, bits)) in path
+        .iter()
+        .zip(index_bits.chunks(bits_per_level))
+        .enumerate()
+    {
+        assert_eq!(siblings.len(), arity - 1);
+        let mut cs = cs.namespace(|| format!("level {}", level));
+        let selectors = selectors(cs.namespace(|| "position"), bits)?;
+        let children = insert(cs.namespace(|| "children"), &node, siblings, &selectors)?;
+        node = hasher.hash_gadget(cs.namespace(|| "hash"), &children)?;
+    }
+
+    Ok(node)
+}
+
+/// Enforces that `leaf` is in the tree of `root`, at the index of `index_bits`.
+pub fn verify_merkle_path<E, CS, H>(
+    mut cs: CS,
+    hasher: &H,
+    root: &AllocatedNum<E>,
+    leaf: &AllocatedNum<E>,
+    index_bits: &[Boolean],
+    path: &[Vec<AllocatedNum<E>>],
+) -> Result<(), SynthesisError>
+where
+    E: ScalarEngine,
+    CS: ConstraintSystem<E>,
+    H: MerkleHasherGadget<E>,
+{
+    let computed = merkle_root(cs.namespace(|| "path"), hasher, leaf, index_bits, path)?;
+    cs.enforce(
+        || "root",
+        |lc| lc + computed.get_variable(),
+        |lc| lc + CS::one(),
+        |lc| lc + root.get_variable(),
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gadgets::boolean::AllocatedBit;
+    use crate::gadgets::test::TestConstraintSystem;
+    use crate::merkle::MerkleTree;
+    use paired::bls12_381::{Bls12, Fr};
+
+    #[test]
+    fn test_merkle_path() {
+        let rng = &mut rand::thread_rng();
+        for &(width, leaves) in &[(3, 16), (5, 64), (9, 64)] {
+            let params = PoseidonParams::<Fr>::new(width, 8, 57);
+            let arity = params.arity();
+            let leaves = (0..leaves).map(|_| Fr::random(rng)).collect::<Vec<_>>();
+            let tree = MerkleTree::new(&params, leaves.clone());
+
+            for &index in &[0, 5, leaves.len() - 1] {
+                let path = tree.path(index);
+                for &wrong in &[false, true] {
+                    let mut cs = TestConstraintSystem::<Bls12>::new();
+                    let alloc = |cs: &mut TestConstraintSystem<Bls12>, name: String, v: Fr| {
+                        AllocatedNum::alloc(cs.namespace(|| name), || Ok(v)).unwrap()
+                    };
+                    let root = alloc(&mut cs, "root".into(), tree.root());
+                    let leaf = alloc(&mut cs, "leaf".into(), leaves[index]);
+                    let mut index_bits = path.index_bits(arity);
+                    if wrong {
+                        index_bits[0] = !index_bits[0];
+                    }
+                    let index_bits = index_bits
+                        .iter()
+                        .enumerate()
+                        .map(|(i, b)| {
+                            let cs = cs.namespace(|| format!("bit {}", i));
+                            Boolean::from(AllocatedBit::alloc(cs, Some(*b)).unwrap())
+                        })
+                        .collect::<Vec<_>>();
+                    let siblings = path
+                        .siblings
+                        .iter()
+                        .enumerate()
+                        .map(|(l, level)| {
+                            level
+                                .iter()
+                                .enumerate()
+                                .map(|(i, s)| alloc(&mut cs, format!("sibling {} {}", l, i), *s))
+                                .collect()
+                        })
+                        .collect::<Vec<_>>();
+
+                    verify_merkle_path(
+                        cs.namespace(|| "verify"),
+                        &params,
+                        &root,
+                        &leaf,
+                        &index_bits,
+                        &siblings,
+                    )
+                    .unwrap();
+                    assert_eq!(cs.is_satisfied(), !wrong);
+                }
+            }
+        }
+    }
+}
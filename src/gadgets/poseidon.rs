@@ -0,0 +1,181 @@
+//! Circuit of the Poseidon hash of [`crate::poseidon`].
+
+use ff::{Field, ScalarEngine};
+
+use std::mem;
+
+use super::num::AllocatedNum;
+use crate::poseidon::PoseidonParams;
+use crate::{ConstraintSystem, LinearCombination, SynthesisError};
+
+// An element of the state, the linear combinations are only turned into variables by the S-boxes
+struct Elt<E: ScalarEngine> {
+    lc: LinearCombination<E>,
+    value: Option<E::Fr>,
+}
+
+// x^5, in 3 constraints
+fn sbox<E, CS>(mut cs: CS, x: &Elt<E>) -> Result<Elt<E>, SynthesisError>
+where
+    E: ScalarEngine,
+    CS: ConstraintSystem<E>,
+{
+    let x2_value = x.value.map(|mut v| {
+        v.square();
+        v
+    });
+    let x2 = cs.alloc(
+        || "x^2",
+        || x2_value.ok_or(SynthesisError::AssignmentMissing),
+    )?;
+    cs.enforce(
+        || "x * x = x^2",
+        |lc| lc + &x.lc,
+        |lc| lc + &x.lc,
+        |lc| lc + x2,
+    );
+
+    let x4_value = x2_value.map(|mut v| {
+        v.square();
+        v
+    });
+    let x4 = cs.alloc(
+        || "x^4",
+        || x4_value.ok_or(SynthesisError::AssignmentMissing),
+    )?;
+    cs.enforce(
+        || "x^2 * x^2 = x^4",
+        |lc| lc + x2,
+        |lc| lc + x2,
+        |lc| lc + x4,
+    );
+
+    let x5_value = x4_value.and_then(|mut v| {
+        v.mul_assign(&x.value?);
+        Some(v)
+    });
+    let x5 = cs.alloc(
+        || "x^5",
+        || x5_value.ok_or(SynthesisError::AssignmentMissing),
+    )?;
+    cs.enforce(
+        || "x^4 * x = x^5",
+        |lc| lc + x4,
+        |lc| lc + &x.lc,
+        |lc| lc + x5,
+    );
+
+    Ok(Elt {
+        lc: LinearCombination::zero() + x5,
+        value: x5_value,
+    })
+}
+
+/// Hashes the `arity` elements of `inputs`, as `PoseidonParams::hash` does.
+pub fn poseidon_hash<E, CS>(
+    mut cs: CS,
+    params: &PoseidonParams<E::Fr>,
+    inputs: &[AllocatedNum<E>],
+) -> Result<AllocatedNum<E>, SynthesisError>
+where
+    E: ScalarEngine,
+    CS: ConstraintSystem<E>,
+{
+    assert_eq!(inputs.len(), params.arity());
+    let width = params.width();
+
+    let tag = params.domain_tag();
+    let mut state: Vec<Elt<E>> = vec![Elt {
+        lc: LinearCombination::zero() + (tag, CS::one()),
+        value: Some(tag),
+    }];
+    state.extend(inputs.iter().map(|input| Elt {
+        lc: LinearCombination::zero() + input.get_variable(),
+        value: input.get_value(),
+    }));
+
+    for round in 0..params.full_rounds() + params.partial_rounds() {
+        let constants = &params.round_constants()[round * width..][..width];
+        for (s, c) in state.iter_mut().zip(constants) {
+            let lc = mem::replace(&mut s.lc, LinearCombination::zero());
+            s.lc = lc + (*c, CS::one());
+            if let Some(v) = s.value.as_mut() {
+                v.add_assign(c);
+            }
+        }
+
+        let sboxes = if params.is_full_round(round) {
+            width
+        } else {
+            1
+        };
+        for i in 0..sboxes {
+            state[i] = sbox(
+                cs.namespace(|| format!("round {} sbox {}", round, i)),
+                &state[i],
+            )?;
+        }
+
+        state = (0..width)
+            .map(|i| {
+                let mut lc = LinearCombination::zero();
+                let mut value = Some(E::Fr::zero());
+                for (s, c) in state.iter().zip(&params.mds()[i * width..][..width]) {
+                    lc = lc + (*c, &s.lc);
+                    value = value.and_then(|mut v| {
+                        let mut tmp = s.value?;
+                        tmp.mul_assign(c);
+                        v.add_assign(&tmp);
+                        Some(v)
+                    });
+                }
+                Elt { lc, value }
+            })
+            .collect();
+    }
+
+    let output = &state[1];
+    let hash = AllocatedNum::alloc(cs.namespace(|| "hash"), || {
+        output.value.ok_or(SynthesisError::AssignmentMissing)
+    })?;
+    cs.enforce(
+        || "hash = state[1]",
+        |lc| lc + &output.lc,
+        |lc| lc + CS::one(),
+        |lc| lc + hash.get_variable(),
+    );
+
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gadgets::test::TestConstraintSystem;
+    use paired::bls12_381::{Bls12, Fr};
+
+    #[test]
+    fn test_poseidon_hash() {
+        let rng = &mut rand::thread_rng();
+        let params = PoseidonParams::<Fr>::new(3, 8, 57);
+        let values = [Fr::random(rng), Fr::random(rng)];
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let inputs = values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| AllocatedNum::alloc(cs.namespace(|| format!("input {}", i)), || Ok(*v)))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let hash = poseidon_hash(cs.namespace(|| "poseidon"), &params, &inputs).unwrap();
+
+        assert!(cs.is_satisfied());
+        assert_eq!(hash.get_value(), Some(params.hash(&values)));
+        assert_eq!(cs.num_constraints(), 3 * (3 * 8 + 57) + 1);
+
+        let mut wrong = params.hash(&values);
+        wrong.add_assign(&Fr::one());
+        cs.set("poseidon/hash/num", wrong);
+        assert!(!cs.is_satisfied());
+    }
+}
@@ -0,0 +1,239 @@
+//! Public inputs built from byte strings, with the same packing on both sides of a proof.
+//!
+//! `PublicInputsGadget` exposes byte strings as public inputs in a circuit, and
+//! `PublicInputs` computes the same field elements for the verifier. Each input is bound to the
+//! domain of the application and to a tag naming it, so that inputs of different applications
+//! or of different fields can't be confused:
+//!
+//! * `pack_bytes` exposes a header element, the BLAKE2s hash of the domain, the tag and the
+//!   length, followed by the bits of the bytes packed into field elements (See
+//!   `multipack::pack_into_inputs`).
+//! * `hash_bytes` exposes a single element, the BLAKE2s hash of the header and of the bytes,
+//!   truncated to the capacity of the field, which is cheaper to verify for long inputs.
+//!
+//! The bytes are given to the gadget as their bits, least significant first in each byte (See
+//! `multipack::bytes_to_bits_le`).
+
+use blake2s_simd::Params as Blake2sParams;
+use byteorder::{LittleEndian, WriteBytesExt};
+use ff::{PrimeField, ScalarEngine};
+
+use super::blake2s::blake2s;
+use super::boolean::Boolean;
+use super::multipack::{bytes_to_bits_le, compute_multipacking, pack_into_inputs};
+use crate::{ConstraintSystem, SynthesisError};
+
+/// BLAKE2s personalization of the headers of the packed inputs.
+pub const HEADER_PERSONALIZATION: &[u8; 8] = b"BellPIhd";
+/// BLAKE2s personalization of the hashed inputs.
+pub const HASH_PERSONALIZATION: &[u8; 8] = b"BellPIhs";
+
+const KIND_PACKED: u8 = 0;
+const KIND_HASHED: u8 = 1;
+
+// The length prefixed domain and tag, the kind of the input and the length of its bytes
+fn header(domain: &[u8], tag: &[u8], kind: u8, len: usize) -> Vec<u8> {
+    let mut header = vec![];
+    header
+        .write_u32::<LittleEndian>(domain.len() as u32)
+        .unwrap();
+    header.extend_from_slice(domain);
+    header.write_u32::<LittleEndian>(tag.len() as u32).unwrap();
+    header.extend_from_slice(tag);
+    header.push(kind);
+    header.write_u64::<LittleEndian>(len as u64).unwrap();
+    header
+}
+
+// The first `CAPACITY` bits of a hash, as a field element
+fn truncate<E: ScalarEngine>(hash: &[u8]) -> E::Fr {
+    let bits = bytes_to_bits_le(hash);
+    compute_multipacking::<E>(&bits[..E::Fr::CAPACITY as usize])[0]
+}
+
+fn header_element<E: ScalarEngine>(domain: &[u8], tag: &[u8], len: usize) -> E::Fr {
+    let hash = Blake2sParams::new()
+        .hash_length(32)
+        .personal(HEADER_PERSONALIZATION)
+        .hash(&header(domain, tag, KIND_PACKED, len));
+    truncate::<E>(hash.as_bytes())
+}
+
+/// The public inputs of a proof, for the verifier.
+pub struct PublicInputs<E: ScalarEngine> {
+    domain: Vec<u8>,
+    inputs: Vec<E::Fr>,
+}
+
+impl<E: ScalarEngine> PublicInputs<E> {
+    /// Inputs of the application `domain`, e.g. `b"myapp/transfer/v1"`.
+    pub fn new(domain: &[u8]) -> Self {
+        PublicInputs {
+            domain: domain.to_vec(),
+            inputs: vec![],
+        }
+    }
+
+    /// Adds the inputs of `PublicInputsGadget::pack_bytes`.
+    pub fn pack_bytes(&mut self, tag: &[u8], bytes: &[u8]) -> &mut Self {
+        self.inputs
+            .push(header_element::<E>(&self.domain, tag, bytes.len()));
+        self.inputs
+            .extend(compute_multipacking::<E>(&bytes_to_bits_le(bytes)));
+        self
+    }
+
+    /// Adds the input of `PublicInputsGadget::hash_bytes`.
+    pub fn hash_bytes(&mut self, tag: &[u8], bytes: &[u8]) -> &mut Self {
+        let hash = Blake2sParams::new()
+            .hash_length(32)
+            .personal(HASH_PERSONALIZATION)
+            .to_state()
+            .update(&header(&self.domain, tag, KIND_HASHED, bytes.len()))
+            .update(bytes)
+            .finalize();
+        self.inputs.push(truncate::<E>(hash.as_bytes()));
+        self
+    }
+
+    pub fn as_slice(&self) -> &[E::Fr] {
+        &self.inputs
+    }
+
+    pub fn into_inputs(self) -> Vec<E::Fr> {
+        self.inputs
+    }
+}
+
+/// Exposes the public inputs of a circuit, in the order the verifier adds them to its
+/// `PublicInputs`.
+pub struct PublicInputsGadget {
+    domain: Vec<u8>,
+    count: usize,
+}
+
+impl PublicInputsGadget {
+    pub fn new(domain: &[u8]) -> Self {
+        PublicInputsGadget {
+            domain: domain.to_vec(),
+            count: 0,
+        }
+    }
+
+    /// Exposes the bytes of `bits` as a header and their packing.
+    pub fn pack_bytes<E, CS>(
+        &mut self,
+        mut cs: CS,
+        tag: &[u8],
+        bits: &[Boolean],
+    ) -> Result<(), SynthesisError>
+    where
+        E: ScalarEngine,
+        CS: ConstraintSystem<E>,
+    {
+        assert_eq!(bits.len() % 8, 0, "the bits must be whole bytes");
+        let mut cs = cs.namespace(|| format!("packed input {}", self.count));
+        self.count += 1;
+
+        let header = header_element::<E>(&self.domain, tag, bits.len() / 8);
+        let input = cs.alloc_input(|| "header", || Ok(header))?;
+        cs.enforce(
+            || "header constraint",
+            |lc| lc + input,
+            |lc| lc + CS::one(),
+            |lc| lc + (header, CS::one()),
+        );
+
+        pack_into_inputs(cs.namespace(|| "bytes"), bits)
+    }
+
+    /// Exposes the truncated hash of the bytes of `bits`.
+    pub fn hash_bytes<E, CS>(
+        &mut self,
+        mut cs: CS,
+        tag: &[u8],
+        bits: &[Boolean],
+    ) -> Result<(), SynthesisError>
+    where
+        E: ScalarEngine,
+        CS: ConstraintSystem<E>,
+    {
+        assert_eq!(bits.len() % 8, 0, "the bits must be whole bytes");
+        let mut cs = cs.namespace(|| format!("hashed input {}", self.count));
+        self.count += 1;
+
+        let header = header(&self.domain, tag, KIND_HASHED, bits.len() / 8);
+        let mut message = bytes_to_bits_le(&header)
+            .into_iter()
+            .map(Boolean::constant)
+            .collect::<Vec<_>>();
+        message.extend_from_slice(bits);
+
+        let hash = blake2s(cs.namespace(|| "hash"), &message, HASH_PERSONALIZATION)?;
+        pack_into_inputs(
+            cs.namespace(|| "truncated hash"),
+            &hash[..E::Fr::CAPACITY as usize],
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gadgets::boolean::AllocatedBit;
+    use crate::gadgets::test::TestConstraintSystem;
+    use paired::bls12_381::Bls12;
+
+    #[test]
+    fn test_public_inputs() {
+        let short = b"recipient".to_vec();
+        let long = (0..100u8).collect::<Vec<_>>();
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let mut alloc_bytes = |name: &str, bytes: &[u8]| {
+            bytes_to_bits_le(bytes)
+                .into_iter()
+                .enumerate()
+                .map(|(i, b)| {
+                    let cs = cs.namespace(|| format!("{} bit {}", name, i));
+                    Boolean::from(AllocatedBit::alloc(cs, Some(b)).unwrap())
+                })
+                .collect::<Vec<_>>()
+        };
+        let short_bits = alloc_bytes("short", &short);
+        let long_bits = alloc_bytes("long", &long);
+
+        let mut gadget = PublicInputsGadget::new(b"test/v1");
+        gadget
+            .pack_bytes(cs.namespace(|| "to"), b"to", &short_bits)
+            .unwrap();
+        gadget
+            .hash_bytes(cs.namespace(|| "memo"), b"memo", &long_bits)
+            .unwrap();
+        assert!(cs.is_satisfied());
+
+        let mut inputs = PublicInputs::<Bls12>::new(b"test/v1");
+        inputs.pack_bytes(b"to", &short).hash_bytes(b"memo", &long);
+        // The header and the 72 bits of the short input, and the hash of the long one
+        assert_eq!(inputs.as_slice().len(), 3);
+        assert!(cs.verify(inputs.as_slice()));
+
+        let mut other_domain = PublicInputs::<Bls12>::new(b"test/v2");
+        other_domain
+            .pack_bytes(b"to", &short)
+            .hash_bytes(b"memo", &long);
+        assert!(!cs.verify(other_domain.as_slice()));
+
+        let mut other_tag = PublicInputs::<Bls12>::new(b"test/v1");
+        other_tag
+            .pack_bytes(b"from", &short)
+            .hash_bytes(b"memo", &long);
+        assert!(!cs.verify(other_tag.as_slice()));
+
+        let mut other_bytes = PublicInputs::<Bls12>::new(b"test/v1");
+        other_bytes
+            .pack_bytes(b"to", &short)
+            .hash_bytes(b"memo", &long[1..]);
+        assert!(!cs.verify(other_bytes.as_slice()));
+    }
+}
@@ -0,0 +1,81 @@
+use ff::PrimeField;
+use log::{info, warn};
+use ocl::Device;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Work sizes of the FFT kernel, found by timing the kernel on the device the first time it's
+/// used (See `SingleFFTKernel::autotune`) and cached on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FFTTuning {
+    /// Maximum degree of a radix round (1=>radix2, 2=>radix4, ...)
+    pub max_radix_degree: u32,
+    /// log2 of the maximum local work size of a radix round
+    pub max_local_work_size_degree: u32,
+    /// log2 of the maximum number of work-items of `mul_by_powers` and `radix3_combine`, which
+    /// sets the number of elements (Chunk size) each work-item handles
+    pub log_max_powers_workers: u32,
+}
+
+/// Autotuning is done unless `BELLMAN_NO_AUTOTUNE` is set
+pub fn is_autotune_enabled() -> bool {
+    env::var("BELLMAN_NO_AUTOTUNE").is_err()
+}
+
+/// Path of the tuning cache, which may be set with `BELLMAN_TUNING_CACHE`
+fn cache_path() -> PathBuf {
+    match env::var("BELLMAN_TUNING_CACHE") {
+        Ok(path) => PathBuf::from(path),
+        Err(_) => {
+            let mut p = env::temp_dir();
+            p.push("bellman.tuning.toml");
+            p
+        }
+    }
+}
+
+/// Tunings are only valid for the same device with the same driver, and for fields of the same
+/// size, as the kernels of fields with more limbs use more registers
+pub fn tuning_key<F: PrimeField>(d: Device) -> String {
+    let driver = match d.info(ocl::enums::DeviceInfo::DriverVersion) {
+        Ok(ocl::enums::DeviceInfoResult::DriverVersion(v)) => v,
+        _ => String::new(),
+    };
+    format!(
+        "{} ({}, {}-bit field)",
+        d.name().unwrap_or_default(),
+        driver,
+        F::NUM_BITS
+    )
+}
+
+fn read_cache() -> HashMap<String, FFTTuning> {
+    fs::read_to_string(cache_path())
+        .ok()
+        .and_then(|cache| toml::from_str(&cache).ok())
+        .unwrap_or_default()
+}
+
+pub fn get_cached_tuning(key: &str) -> Option<FFTTuning> {
+    read_cache().get(key).cloned()
+}
+
+pub fn cache_tuning(key: &str, tuning: FFTTuning) {
+    let path = cache_path();
+    let mut cache = read_cache();
+    cache.insert(key.to_string(), tuning);
+
+    // Written to a temporary file first, so that other processes never read a partial cache
+    let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+    match toml::to_string(&cache)
+        .map_err(|e| e.to_string())
+        .and_then(|cache| fs::write(&tmp, cache).map_err(|e| e.to_string()))
+        .and_then(|_| fs::rename(&tmp, &path).map_err(|e| e.to_string()))
+    {
+        Ok(_) => info!("Tuning of {} cached in {:?}.", key, path),
+        Err(e) => warn!("Cannot write the tuning cache to {:?}! Error: {}", path, e),
+    }
+}
@@ -2,6 +2,8 @@
 pub enum GPUError {
     #[error("GPUError: {0}")]
     Simple(&'static str),
+    #[error("GPU computation cancelled!")]
+    Cancelled,
     #[cfg(feature = "gpu")]
     #[error("Ocl Error: {0}")]
     Ocl(ocl::Error),
@@ -11,6 +13,15 @@ pub enum GPUError {
     #[cfg(feature = "gpu")]
     #[error("No kernel is initialized!")]
     KernelUninitialized,
+    #[cfg(feature = "gpu")]
+    #[error("GPU result of {0} doesn't match the CPU!")]
+    ResultMismatch(&'static str),
+    #[cfg(feature = "gpu")]
+    #[error("GPU commands timed out!")]
+    Timeout,
+    #[cfg(feature = "gpu")]
+    #[error("GPU device is lost! Ocl Error: {0}")]
+    DeviceLost(ocl::Error),
 }
 
 pub type GPUResult<T> = std::result::Result<T, GPUError>;
@@ -18,7 +29,28 @@ pub type GPUResult<T> = std::result::Result<T, GPUError>;
 #[cfg(feature = "gpu")]
 impl From<ocl::Error> for GPUError {
     fn from(error: ocl::Error) -> Self {
-        GPUError::Ocl(error)
+        if is_device_lost(&error) {
+            GPUError::DeviceLost(error)
+        } else {
+            GPUError::Ocl(error)
+        }
+    }
+}
+
+/// Whether `error` is returned by drivers for devices that fell off the bus or were reset, in
+/// which case the device (Or its context) can't be used anymore.
+#[cfg(feature = "gpu")]
+fn is_device_lost(error: &ocl::Error) -> bool {
+    use ocl::core::Status;
+    match error.api_status() {
+        Some(Status::CL_DEVICE_NOT_FOUND)
+        | Some(Status::CL_DEVICE_NOT_AVAILABLE)
+        | Some(Status::CL_OUT_OF_RESOURCES)
+        | Some(Status::CL_EXEC_STATUS_ERROR_FOR_EVENTS_IN_WAIT_LIST)
+        | Some(Status::CL_INVALID_DEVICE)
+        | Some(Status::CL_INVALID_CONTEXT)
+        | Some(Status::CL_INVALID_COMMAND_QUEUE) => true,
+        _ => false,
     }
 }
 
@@ -1,58 +1,89 @@
-use crate::gpu::get_platform;
+use crate::cancellation::CancellationToken;
+use crate::domain;
+use crate::gpu::{
+    build_proque, cache_tuning, get_assigned_devices, get_cached_tuning, get_memory_limit,
+    get_work_group_size, is_autotune_enabled, tuning_key, FFTTuning, KernelKind, Priority,
+};
 use crate::gpu::{
     error::{GPUError, GPUResult},
-    get_devices, locks, sources, structs,
+    locks,
+    metrics::{self, is_metrics_enabled, Stage},
+    paranoid,
+    pinned::PinnedBuffer,
+    sources,
+    structs::{self, GpuRepr},
+    watchdog,
 };
-use ff::Field;
-use log::info;
-use ocl::{Buffer, MemFlags, ProQue};
-use paired::Engine;
+use crate::secret;
+use crossbeam::thread;
+use ff::PrimeField;
+use log::{error, info, warn};
+use ocl::{Buffer, Device, MemFlags, ProQue};
+use rayon::prelude::*;
 use std::cmp;
-
-// NOTE: Please read `structs.rs` for an explanation for unsafe transmutes of this code!
+use std::time::{Duration, Instant};
+use tracing::{info_span, Span};
 
 const LOG2_MAX_ELEMENTS: usize = 32; // At most 2^32 elements is supported.
 const MAX_RADIX_DEGREE: u32 = 8; // Radix256
 const MAX_LOCAL_WORK_SIZE_DEGREE: u32 = 7; // 128
 
-pub struct FFTKernel<E>
+// Each work-item of `mul_by_powers` handles at least 2^(lgn - LOG_MAX_POWERS_WORKERS) elements,
+// so that the initial exponentiation is amortized.
+const LOG_MAX_POWERS_WORKERS: u32 = 16;
+
+// The kernels are autotuned on 2^TUNING_LOG_N elements, each candidate being timed over
+// TUNING_RUNS runs.
+const TUNING_LOG_N: u32 = 16;
+const TUNING_RUNS: usize = 4;
+
+// Number of elements (log2) moved to the GPU at once by the four-step FFT
+const BAILEY_LOG_BLOCK_SIZE: u32 = 22;
+
+// FFTs with at least 2^MULTI_GPU_MIN_LOG_N elements are split across all available GPUs.
+// Smaller ones run on a single device as the host-side shuffling would dominate.
+const MULTI_GPU_MIN_LOG_N: u32 = 24;
+
+// Elements handled by each work-item of the batch inversion kernels
+const BATCH_INVERT_CHUNK: u32 = 64;
+
+// FFT kernel for a single GPU
+pub struct SingleFFTKernel<F>
 where
-    E: Engine,
+    F: PrimeField,
 {
     proque: ProQue,
-    fft_src_buffer: Buffer<structs::PrimeFieldStruct<E::Fr>>,
-    fft_dst_buffer: Buffer<structs::PrimeFieldStruct<E::Fr>>,
-    fft_pq_buffer: Buffer<structs::PrimeFieldStruct<E::Fr>>,
-    fft_omg_buffer: Buffer<structs::PrimeFieldStruct<E::Fr>>,
-    _lock: locks::GPULock, // RFC 1857: struct fields are dropped in the same order as they are declared.
-    priority: bool,
+    fft_src_buffer: Buffer<structs::PrimeFieldStruct<F>>,
+    fft_dst_buffer: Buffer<structs::PrimeFieldStruct<F>>,
+    fft_pq_buffer: Buffer<structs::PrimeFieldStruct<F>>,
+    fft_omg_buffer: Buffer<structs::PrimeFieldStruct<F>>,
+    // The omega, size and radix degree the pq and omega buffers were set up for
+    pq_key: Option<(F, usize, u32)>,
+    // The tables set up before the current ones, as the FFTs alternate between omega and its
+    // inverse, e.g. in `quotient`. Only allocated once the tables change.
+    pq_spare: Option<PqTables<F>>,
+    // Only allocated for batched FFTs (Next polynomial is uploaded here while the current one is
+    // being transformed) and for `quotient` (Holds the accumulated result).
+    fft_tmp_buffer: Option<Buffer<structs::PrimeFieldStruct<F>>>,
+    // Host memory the inputs and results are copied through
+    staging: PinnedBuffer<structs::PrimeFieldStruct<F>>,
+    n: u32,
+    tuning: FFTTuning,
+    // Work-group size set by the GPU config, caps the tuned local work size
+    max_lwsd: u32,
+    priority: Priority,
+    // Checked between the rounds, see `FFTKernel::set_cancellation`
+    cancel: Option<CancellationToken>,
 }
 
-impl<E> FFTKernel<E>
+impl<F> SingleFFTKernel<F>
 where
-    E: Engine,
+    F: PrimeField,
 {
-    pub fn create(n: u32, priority: bool) -> GPUResult<FFTKernel<E>> {
-        let lock = locks::GPULock::lock();
-        let src = sources::kernel::<E>();
-
-        let platform = get_platform(None)?;
-        info!("Platform selected: {}", platform.name()?);
-
-        let devices = get_devices(&platform).unwrap_or_default();
-        if devices.is_empty() {
-            return Err(GPUError::Simple("No working GPUs found!"));
-        }
+    pub fn create(d: Device, n: u32, priority: Priority) -> GPUResult<SingleFFTKernel<F>> {
+        let src = sources::fft_kernel::<F>();
 
-        // Select the first device for FFT
-        let device = devices[0];
-
-        let pq = ProQue::builder()
-            .platform(platform)
-            .device(device)
-            .src(src)
-            .dims(n)
-            .build()?;
+        let pq = build_proque(d, &src, n as usize)?;
 
         let srcbuff = Buffer::builder()
             .queue(pq.queue().clone())
@@ -64,29 +95,144 @@ where
             .flags(MemFlags::new().read_write())
             .len(n)
             .build()?;
-        let pqbuff = Buffer::builder()
-            .queue(pq.queue().clone())
-            .flags(MemFlags::new().read_write())
-            .len(1 << MAX_RADIX_DEGREE >> 1)
-            .build()?;
-        let omgbuff = Buffer::builder()
-            .queue(pq.queue().clone())
-            .flags(MemFlags::new().read_write())
-            .len(LOG2_MAX_ELEMENTS)
-            .build()?;
+        let (pqbuff, omgbuff) = pq_buffers(&pq)?;
 
-        info!("FFT: 1 working device(s) selected.");
-        info!("FFT: Device 0: {}", pq.device().name()?);
-
-        Ok(FFTKernel {
+        let max_lwsd = get_work_group_size(d)
+            .map(|wgs| cmp::min(log2_floor(wgs), MAX_LOCAL_WORK_SIZE_DEGREE))
+            .unwrap_or(MAX_LOCAL_WORK_SIZE_DEGREE);
+        let mut kern = SingleFFTKernel {
             proque: pq,
             fft_src_buffer: srcbuff,
             fft_dst_buffer: dstbuff,
             fft_pq_buffer: pqbuff,
             fft_omg_buffer: omgbuff,
-            _lock: lock,
+            pq_key: None,
+            pq_spare: None,
+            fft_tmp_buffer: None,
+            staging: PinnedBuffer::new(),
+            n,
+            tuning: FFTTuning::default(),
+            max_lwsd,
             priority,
-        })
+            cancel: None,
+        };
+        kern.tune(d);
+        Ok(kern)
+    }
+
+    // Span of an FFT of 2^`lgn` elements running on this device
+    fn span(&self, lgn: u32) -> Span {
+        info_span!(
+            "fft_device",
+            device = %self.proque.device().name().unwrap_or_default(),
+            lgn,
+            elements = 1u64 << lgn
+        )
+    }
+
+    /// Uses the cached tuning of `d`, or runs `autotune` and caches its result if there is none.
+    /// The defaults are used if autotuning is disabled, fails, or the buffers are too small.
+    fn tune(&mut self, d: Device) {
+        let mut tuning = FFTTuning::default();
+        if is_autotune_enabled() {
+            let key = tuning_key::<F>(d);
+            if let Some(t) = get_cached_tuning(&key) {
+                tuning = t;
+            } else if self.n >= 1 << TUNING_LOG_N {
+                info!("Autotuning FFT kernel for {}...", key);
+                match self.autotune(TUNING_LOG_N) {
+                    Ok(t) => {
+                        info!("FFT tuning for {}: {:?}", key, t);
+                        cache_tuning(&key, t);
+                        tuning = t;
+                    }
+                    Err(e) => warn!("FFT autotuning failed! Using defaults... Error: {}", e),
+                }
+            }
+        }
+        self.set_tuning(tuning);
+    }
+
+    /// The cache may have been edited by hand, so the tuning is clamped to valid values
+    fn set_tuning(&mut self, tuning: FFTTuning) {
+        self.tuning = FFTTuning {
+            max_radix_degree: cmp::max(1, cmp::min(tuning.max_radix_degree, MAX_RADIX_DEGREE)),
+            max_local_work_size_degree: cmp::min(tuning.max_local_work_size_degree, self.max_lwsd),
+            log_max_powers_workers: cmp::min(
+                tuning.log_max_powers_workers,
+                LOG2_MAX_ELEMENTS as u32 - 1,
+            ),
+        };
+    }
+
+    /// Finds the fastest work sizes of this device by timing the kernels on 2^`lgn` random
+    /// elements, one parameter at a time.
+    fn autotune(&mut self, lgn: u32) -> GPUResult<FFTTuning> {
+        let rng = &mut rand::thread_rng();
+        let values = (0..1 << lgn)
+            .map(|_| structs::PrimeFieldStruct::<F>(F::random(rng)))
+            .collect::<Vec<_>>();
+        self.fft_src_buffer.write(&values).enq()?;
+        let mut omega = F::root_of_unity();
+        for _ in lgn..F::S {
+            omega.square();
+        }
+
+        let mut best = FFTTuning::default();
+        self.set_tuning(best);
+        let mut best_time = self.time_fft(lgn, &omega)?;
+        for deg in 1..=MAX_RADIX_DEGREE {
+            for lwsd in 0..=cmp::min(deg - 1, self.max_lwsd) {
+                self.set_tuning(FFTTuning {
+                    max_radix_degree: deg,
+                    max_local_work_size_degree: lwsd,
+                    ..best
+                });
+                let time = self.time_fft(lgn, &omega)?;
+                if time < best_time {
+                    best = self.tuning;
+                    best_time = time;
+                }
+            }
+        }
+
+        self.set_tuning(best);
+        let mut best_time = self.time_mul_by_powers(lgn, &omega)?;
+        for log_workers in 1..=lgn {
+            self.set_tuning(FFTTuning {
+                log_max_powers_workers: log_workers,
+                ..best
+            });
+            let time = self.time_mul_by_powers(lgn, &omega)?;
+            if time < best_time {
+                best = self.tuning;
+                best_time = time;
+            }
+        }
+
+        Ok(best)
+    }
+
+    fn time_fft(&mut self, lgn: u32, omega: &F) -> GPUResult<Duration> {
+        let max_deg = cmp::min(self.tuning.max_radix_degree, lgn);
+        self.setup_pq(omega, 1 << lgn, max_deg)?;
+        watchdog::finish(self.proque.queue())?;
+        let now = Instant::now();
+        for _ in 0..TUNING_RUNS {
+            self.radix_fft_rounds(lgn, max_deg)?;
+        }
+        watchdog::finish(self.proque.queue())?;
+        Ok(now.elapsed())
+    }
+
+    fn time_mul_by_powers(&mut self, lgn: u32, g: &F) -> GPUResult<Duration> {
+        watchdog::finish(self.proque.queue())?;
+        let now = Instant::now();
+        for _ in 0..TUNING_RUNS {
+            self.mul_by_powers(lgn, true, g, g)?;
+        }
+        watchdog::finish(self.proque.queue())?;
+        Ok(now.elapsed())
     }
 
     /// Peforms a FFT round
@@ -102,12 +248,15 @@ where
         max_deg: u32,
         in_src: bool,
     ) -> GPUResult<()> {
+        if self.cancel.as_ref().map_or(false, |c| c.is_cancelled()) {
+            return Err(GPUError::Cancelled);
+        }
         if locks::PriorityLock::should_break(self.priority) {
             return Err(GPUError::GPUTaken);
         }
 
         let n = 1u32 << lgn;
-        let lwsd = cmp::min(deg - 1, MAX_LOCAL_WORK_SIZE_DEGREE);
+        let lwsd = cmp::min(deg - 1, self.tuning.max_local_work_size_degree);
         let kernel = self
             .proque
             .kernel_builder("radix_fft")
@@ -125,7 +274,7 @@ where
             })
             .arg(&self.fft_pq_buffer)
             .arg(&self.fft_omg_buffer)
-            .arg_local::<structs::PrimeFieldStruct<E::Fr>>(1 << deg)
+            .arg_local::<structs::PrimeFieldStruct<F>>(1 << deg)
             .arg(n)
             .arg(lgp)
             .arg(deg)
@@ -137,16 +286,37 @@ where
         Ok(())
     }
 
-    /// Share some precalculated values between threads to boost the performance
-    fn setup_pq(&mut self, omega: &E::Fr, n: usize, max_deg: u32) -> ocl::Result<()> {
+    /// Share some precalculated values between threads to boost the performance. The values
+    /// of the last two setups are kept, so that they are only computed once for FFTs of the
+    /// same size, e.g. for the proofs of a batch.
+    fn setup_pq(&mut self, omega: &F, n: usize, max_deg: u32) -> ocl::Result<()> {
+        let key = Some((*omega, n, max_deg));
+        if self.pq_key == key {
+            return Ok(());
+        }
+        if self.pq_key.is_some() {
+            if self.pq_spare.is_none() {
+                let (pq, omg) = pq_buffers(&self.proque)?;
+                self.pq_spare = Some(PqTables { key: None, pq, omg });
+            }
+            if let Some(ref mut spare) = self.pq_spare {
+                std::mem::swap(&mut spare.pq, &mut self.fft_pq_buffer);
+                std::mem::swap(&mut spare.omg, &mut self.fft_omg_buffer);
+                std::mem::swap(&mut spare.key, &mut self.pq_key);
+            }
+            if self.pq_key == key {
+                return Ok(());
+            }
+        }
+        // Cleared until the buffers are written, which are stale if writing them fails
+        self.pq_key = None;
+
         // Precalculate:
         // [omega^(0/(2^(deg-1))), omega^(1/(2^(deg-1))), ..., omega^((2^(deg-1)-1)/(2^(deg-1)))]
-        let mut tpq = vec![structs::PrimeFieldStruct::<E::Fr>::default(); 1 << max_deg >> 1];
-        let pq = unsafe {
-            std::mem::transmute::<&mut [structs::PrimeFieldStruct<E::Fr>], &mut [E::Fr]>(&mut tpq)
-        };
+        let mut tpq = vec![structs::PrimeFieldStruct::<F>::default(); 1 << max_deg >> 1];
+        let pq = structs::PrimeFieldStruct::<F>::from_repr_slice_mut(&mut tpq);
         let tw = omega.pow([(n >> max_deg) as u64]);
-        pq[0] = E::Fr::one();
+        pq[0] = F::one();
         if max_deg > 1 {
             pq[1] = tw;
             for i in 2..(1 << max_deg >> 1) {
@@ -157,48 +327,1505 @@ where
         self.fft_pq_buffer.write(&tpq).enq()?;
 
         // Precalculate [omega, omega^2, omega^4, omega^8, ..., omega^(2^31)]
-        let mut tom = vec![structs::PrimeFieldStruct::<E::Fr>::default(); 32];
-        let om = unsafe {
-            std::mem::transmute::<&mut [structs::PrimeFieldStruct<E::Fr>], &mut [E::Fr]>(&mut tom)
-        };
+        let mut tom = vec![structs::PrimeFieldStruct::<F>::default(); 32];
+        let om = structs::PrimeFieldStruct::<F>::from_repr_slice_mut(&mut tom);
         om[0] = *omega;
         for i in 1..LOG2_MAX_ELEMENTS {
             om[i] = om[i - 1].pow([2u64]);
         }
         self.fft_omg_buffer.write(&tom).enq()?;
+        self.pq_key = key;
+
+        Ok(())
+    }
 
+    /// Multiplies the i-th element of the source (Or destination) buffer by `c * g^i`
+    fn mul_by_powers(&mut self, lgn: u32, in_src: bool, g: &F, c: &F) -> GPUResult<()> {
+        let n = 1u32 << lgn;
+        let lg_chunk = lgn.saturating_sub(self.tuning.log_max_powers_workers);
+        let kernel = self
+            .proque
+            .kernel_builder("mul_by_powers")
+            .global_work_size([n >> lg_chunk])
+            .arg(if in_src {
+                &self.fft_src_buffer
+            } else {
+                &self.fft_dst_buffer
+            })
+            .arg(n)
+            .arg(1u32 << lg_chunk)
+            .arg(structs::PrimeFieldStruct::<F>(*g))
+            .arg(structs::PrimeFieldStruct::<F>(*c))
+            .build()?;
+        unsafe {
+            kernel.enq()?;
+        } // Running a GPU kernel is unsafe!
+        Ok(())
+    }
+
+    fn ensure_tmp_buffer(&mut self) -> GPUResult<()> {
+        if self.fft_tmp_buffer.is_none() {
+            self.fft_tmp_buffer = Some(
+                Buffer::builder()
+                    .queue(self.proque.queue().clone())
+                    .flags(MemFlags::new().read_write())
+                    .len(self.n)
+                    .build()?,
+            );
+        }
+        Ok(())
+    }
+
+    /// Runs one of the element-wise kernels of `polynomial.cl` with `a` as the destination
+    fn poly_op(
+        &self,
+        name: &str,
+        a: &Buffer<structs::PrimeFieldStruct<F>>,
+        b: &Buffer<structs::PrimeFieldStruct<F>>,
+        n: u32,
+    ) -> GPUResult<()> {
+        let kernel = self
+            .proque
+            .kernel_builder(name)
+            .global_work_size([n])
+            .arg(a)
+            .arg(b)
+            .arg(n)
+            .build()?;
+        unsafe {
+            kernel.enq()?;
+        } // Running a GPU kernel is unsafe!
+        Ok(())
+    }
+
+    /// Runs all radix rounds on the values already uploaded to the source buffer,
+    /// returns true if the result ended up in the source buffer.
+    fn radix_fft_rounds(&mut self, lgn: u32, max_deg: u32) -> GPUResult<bool> {
+        let mut in_src = true;
+        let mut lgp = 0u32;
+        self.radix_fft_rounds_from(lgn, max_deg, &mut lgp, &mut in_src)?;
+        Ok(in_src)
+    }
+
+    /// Runs the radix rounds starting at `lgp`, on the values in the source buffer if `in_src`
+    /// and in the destination buffer otherwise. `lgp` and `in_src` are kept up to date after
+    /// each round, so after an error they point to the values of the last completed round.
+    fn radix_fft_rounds_from(
+        &mut self,
+        lgn: u32,
+        max_deg: u32,
+        lgp: &mut u32,
+        in_src: &mut bool,
+    ) -> GPUResult<()> {
+        while *lgp < lgn {
+            let deg = cmp::min(max_deg, lgn - *lgp);
+            if is_metrics_enabled() {
+                let now = Instant::now();
+                self.radix_fft_round(lgn, *lgp, deg, max_deg, *in_src)?;
+                watchdog::finish(self.proque.queue())?;
+                metrics::record(Stage::FFTRound, now.elapsed(), 0);
+            } else {
+                self.radix_fft_round(lgn, *lgp, deg, max_deg, *in_src)?;
+            }
+            *lgp += deg;
+            *in_src = !*in_src; // Destination of this FFT round is source of the next round.
+        }
         Ok(())
     }
 
+    /// Like `radix_fft`, but if the GPU is taken by a high priority process in the middle of
+    /// the FFT, the values of the last completed round are saved into `checkpoint` before
+    /// returning `GPUError::GPUTaken`. Calling it again with the same `checkpoint` resumes the
+    /// FFT from that round instead of restarting it. `a` is only written once the FFT is done.
+    pub fn radix_fft_resume(
+        &mut self,
+        a: &mut [F],
+        omega: &F,
+        lgn: u32,
+        checkpoint: &mut Option<FFTCheckpoint<F>>,
+    ) -> GPUResult<()> {
+        let span = self.span(lgn);
+        let _enter = span.enter();
+
+        let n = 1 << lgn;
+        if n > self.n as usize {
+            return Err(GPUError::Simple(
+                "FFT is larger than the allocated GPU buffers!",
+            ));
+        }
+
+        let max_deg = cmp::min(self.tuning.max_radix_degree, lgn);
+        self.setup_pq(omega, n, max_deg)?;
+
+        let mut lgp = 0u32;
+        let mut in_src = true;
+        match checkpoint.take() {
+            // The checkpoint is only valid for the very same FFT
+            Some(c) if c.lgn == lgn && c.omega == *omega => {
+                self.fft_src_buffer.write(c.values()).enq()?;
+                lgp = c.lgp;
+            }
+            _ => {
+                let ta = structs::PrimeFieldStruct::<F>::to_repr_slice(&*a);
+                self.staging
+                    .write(ta, &self.fft_src_buffer, self.proque.queue())?;
+            }
+        }
+
+        match self.radix_fft_rounds_from(lgn, max_deg, &mut lgp, &mut in_src) {
+            Err(GPUError::GPUTaken) if lgp > 0 => {
+                let mut c = FFTCheckpoint {
+                    lgn,
+                    lgp,
+                    omega: *omega,
+                    values: vec![F::zero(); n],
+                };
+                watchdog::finish(self.proque.queue())?;
+                if in_src {
+                    self.fft_src_buffer.read(c.values_mut()).enq()?;
+                } else {
+                    self.fft_dst_buffer.read(c.values_mut()).enq()?;
+                }
+                info!(
+                    "FFT of 2^{} elements interrupted after {} of {} rounds, saved a checkpoint.",
+                    lgn, lgp, lgn
+                );
+                *checkpoint = Some(c);
+                return Err(GPUError::GPUTaken);
+            }
+            res => res?,
+        }
+
+        FFTHandle {
+            kernel: Some(self),
+            a: structs::PrimeFieldStruct::<F>::to_repr_slice_mut(a),
+            in_src,
+        }
+        .wait()
+    }
+
     /// Performs FFT on `a`
     /// * `omega` - Special value `omega` is used for FFT over finite-fields
     /// * `lgn` - Specifies log2 of number of elements
-    pub fn radix_fft(&mut self, a: &mut [E::Fr], omega: &E::Fr, lgn: u32) -> GPUResult<()> {
+    ///
+    /// FFTs larger than the buffers of the kernel are done with `bailey_fft`.
+    pub fn radix_fft(&mut self, a: &mut [F], omega: &F, lgn: u32) -> GPUResult<()> {
+        if 1 << lgn > self.n as usize {
+            return self.bailey_fft(a, omega, lgn);
+        }
+        self.radix_fft_async(a, omega, lgn)?.wait()
+    }
+
+    /// Four-step (Bailey) FFT for domains that don't fit in the GPU buffers. With 2^`lgn` = n1 * n2,
+    /// `a` is seen as a matrix of n2 rows and n1 columns, then:
+    /// 1. An FFT of size n2 is done on each column, multiplying the results by the twiddle
+    ///    factors `omega^(column * row)`.
+    /// 2. An FFT of size n1 is done on each row.
+    /// 3. The matrix is transposed into `a`.
+    /// Only blocks of whole rows/columns are streamed through the GPU, so both n1 and n2 should
+    /// fit in the buffers. `a` is only written at the very end, so that a failed FFT can be
+    /// retried.
+    fn bailey_fft(&mut self, a: &mut [F], omega: &F, lgn: u32) -> GPUResult<()> {
+        let log_cap = log2_floor(self.n as usize);
+        let lgn1 = lgn / 2;
+        let lgn2 = lgn - lgn1;
+        if lgn2 > log_cap {
+            return Err(GPUError::Simple(
+                "FFT is larger than the allocated GPU buffers!",
+            ));
+        }
+        let (n1, n2) = (1usize << lgn1, 1usize << lgn2);
+        info!(
+            "FFT: Doing a 2^{} FFT as 2^{} x 2^{} on a single device",
+            lgn, lgn1, lgn2
+        );
+
+        let mut tmp = vec![F::zero(); 1 << lgn];
+
+        // Step 1: Column FFTs, a few columns at a time
+        let omega_col = omega.pow([n1 as u64]);
+        let cols_per_block = cmp::max(1, (1 << BAILEY_LOG_BLOCK_SIZE) >> lgn2);
+        let mut block = vec![F::zero(); cmp::min(cols_per_block, n1) * n2];
+        for c0 in (0..n1).step_by(cols_per_block) {
+            block.par_chunks_mut(n2).enumerate().for_each(|(b, col)| {
+                for (j2, v) in col.iter_mut().enumerate() {
+                    *v = a[c0 + b + n1 * j2];
+                }
+            });
+            {
+                let mut cols = block.chunks_mut(n2).collect::<Vec<_>>();
+                self.radix_fft_many(&mut cols, &omega_col, lgn2)?;
+            }
+            // Scatter back with the twiddle factors applied
+            let block = &block;
+            tmp.par_chunks_mut(n1).enumerate().for_each(|(k2, row)| {
+                let step = omega.pow([k2 as u64]);
+                let mut w = step.pow([c0 as u64]);
+                for (b, v) in row[c0..].iter_mut().take(block.len() / n2).enumerate() {
+                    *v = block[b * n2 + k2];
+                    v.mul_assign(&w);
+                    w.mul_assign(&step);
+                }
+            });
+        }
+
+        // Step 2: Row FFTs
+        {
+            let omega_row = omega.pow([n2 as u64]);
+            let mut rows = tmp.chunks_mut(n1).collect::<Vec<_>>();
+            self.radix_fft_many(&mut rows, &omega_row, lgn1)?;
+        }
+
+        // Step 3: Transpose
+        {
+            let tmp = &tmp;
+            a.par_chunks_mut(n2).enumerate().for_each(|(k1, row)| {
+                for (k2, v) in row.iter_mut().enumerate() {
+                    *v = tmp[k1 + n1 * k2];
+                }
+            });
+        }
+        secret::zeroize(&mut tmp);
+        secret::zeroize(&mut block);
+
+        Ok(())
+    }
+
+    /// Performs FFT of `a` on the coset `g * <omega>`, i.e. the i-th element is multiplied by
+    /// `g^i` on the GPU before the transform.
+    pub fn coset_fft(&mut self, a: &mut [F], omega: &F, g: &F, lgn: u32) -> GPUResult<()> {
+        self.radix_fft_scaled(a, omega, lgn, Some(g), None)
+    }
+
+    /// Performs inverse FFT of `a` from the coset `g * <omega>`, where `omegainv`, `geninv` and
+    /// `minv` are the inverses of `omega`, `g` and the number of elements. The scaling by
+    /// `minv * geninv^i` is done on the GPU right after the transform.
+    pub fn icoset_fft(
+        &mut self,
+        a: &mut [F],
+        omegainv: &F,
+        geninv: &F,
+        minv: &F,
+        lgn: u32,
+    ) -> GPUResult<()> {
+        self.radix_fft_scaled(a, omegainv, lgn, None, Some((geninv, minv)))
+    }
+
+    /// Performs FFT on `a`, multiplying the i-th element by `pre^i` before and by `c * g^i`
+    /// after the transform, where `post` is `(g, c)`.
+    fn radix_fft_scaled(
+        &mut self,
+        a: &mut [F],
+        omega: &F,
+        lgn: u32,
+        pre: Option<&F>,
+        post: Option<(&F, &F)>,
+    ) -> GPUResult<()> {
+        let span = self.span(lgn);
+        let _enter = span.enter();
+
         let n = 1 << lgn;
+        if n > self.n as usize {
+            return Err(GPUError::Simple(
+                "FFT is larger than the allocated GPU buffers!",
+            ));
+        }
 
-        let ta = unsafe {
-            std::mem::transmute::<&mut [E::Fr], &mut [structs::PrimeFieldStruct<E::Fr>]>(a)
-        };
+        let ta = structs::PrimeFieldStruct::<F>::to_repr_slice_mut(a);
 
-        let max_deg = cmp::min(MAX_RADIX_DEGREE, lgn);
+        let max_deg = cmp::min(self.tuning.max_radix_degree, lgn);
         self.setup_pq(omega, n, max_deg)?;
 
-        self.fft_src_buffer.write(&*ta).enq()?;
-        let mut in_src = true;
-        let mut lgp = 0u32;
-        while lgp < lgn {
-            let deg = cmp::min(max_deg, lgn - lgp);
-            self.radix_fft_round(lgn, lgp, deg, max_deg, in_src)?;
-            lgp += deg;
-            in_src = !in_src; // Destination of this FFT round is source of the next round.
+        self.staging
+            .write(&*ta, &self.fft_src_buffer, self.proque.queue())?;
+        if let Some(g) = pre {
+            self.mul_by_powers(lgn, true, g, &F::one())?;
+        }
+        let in_src = self.radix_fft_rounds(lgn, max_deg)?;
+        if let Some((g, c)) = post {
+            self.mul_by_powers(lgn, in_src, g, c)?;
         }
         if in_src {
-            self.fft_src_buffer.read(ta).enq()?;
+            self.staging
+                .read(&self.fft_src_buffer, ta, self.proque.queue())?;
         } else {
-            self.fft_dst_buffer.read(ta).enq()?;
+            self.staging
+                .read(&self.fft_dst_buffer, ta, self.proque.queue())?;
+        }
+        watchdog::finish(self.proque.queue())?; // Wait for all commands in the queue (Including read command)
+
+        Ok(())
+    }
+
+    /// Given the evaluations of `a`, `b` and `c` on the domain `<omega>`, computes the
+    /// coefficients of `(a * b - c) / Z` where `Z` is the vanishing polynomial of the domain, by
+    /// evaluating on the coset `g * <omega>`. The result is stored in `a`.
+    /// All of the intermediate values stay on the GPU, only the inputs are uploaded and only the
+    /// final result is read back.
+    pub fn quotient(
+        &mut self,
+        a: &mut [F],
+        b: &[F],
+        c: &[F],
+        omega: &F,
+        g: &F,
+        lgn: u32,
+    ) -> GPUResult<()> {
+        let n = 1 << lgn;
+        if n > self.n as usize {
+            return Err(GPUError::Simple(
+                "FFT is larger than the allocated GPU buffers!",
+            ));
+        }
+        if a.len() != n || b.len() != n || c.len() != n {
+            return Err(GPUError::Simple(
+                "All polynomials of a batched FFT should have the same size!",
+            ));
+        }
+        self.ensure_tmp_buffer()?;
+
+        let omegainv = omega.inverse().unwrap();
+        let geninv = g.inverse().unwrap();
+        let minv = F::from_str(&format!("{}", n)).unwrap().inverse().unwrap();
+        // Z(x) = x^n - 1 is constant on the coset
+        let mut zinv = g.pow([n as u64]);
+        zinv.sub_assign(&F::one());
+        let zinv = zinv.inverse().unwrap();
+
+        let max_deg = cmp::min(self.tuning.max_radix_degree, lgn);
+        for (i, x) in [&*a, b, c].iter().enumerate() {
+            let tx = structs::PrimeFieldStruct::<F>::to_repr_slice(*x);
+            self.fft_src_buffer.write(tx).enq()?;
+
+            // Interpolate, scale by `minv` and shift to the coset at once, then evaluate
+            self.setup_pq(&omegainv, n, max_deg)?;
+            self.result_to_src(lgn, max_deg)?;
+            self.mul_by_powers(lgn, true, g, &minv)?;
+            self.setup_pq(omega, n, max_deg)?;
+            self.result_to_src(lgn, max_deg)?;
+
+            if i == 0 {
+                if let Some(ref mut tmp) = self.fft_tmp_buffer {
+                    std::mem::swap(&mut self.fft_src_buffer, tmp);
+                }
+            } else if let Some(ref tmp) = self.fft_tmp_buffer {
+                let op = if i == 1 {
+                    "poly_mul_assign"
+                } else {
+                    "poly_sub_assign"
+                };
+                self.poly_op(op, tmp, &self.fft_src_buffer, n as u32)?;
+            }
+        }
+        if let Some(ref mut tmp) = self.fft_tmp_buffer {
+            std::mem::swap(&mut self.fft_src_buffer, tmp);
+        }
+        let kernel = self
+            .proque
+            .kernel_builder("mul_by_field")
+            .global_work_size([n])
+            .arg(&self.fft_src_buffer)
+            .arg(n as u32)
+            .arg(structs::PrimeFieldStruct::<F>(zinv))
+            .build()?;
+        unsafe {
+            kernel.enq()?;
+        } // Running a GPU kernel is unsafe!
+
+        self.setup_pq(&omegainv, n, max_deg)?;
+        self.result_to_src(lgn, max_deg)?;
+        self.mul_by_powers(lgn, true, &geninv, &minv)?;
+
+        let ta = structs::PrimeFieldStruct::<F>::to_repr_slice_mut(a);
+        watchdog::finish(self.proque.queue())?; // Reads block until the kernels are done
+        self.fft_src_buffer.read(ta).enq()?;
+
+        Ok(())
+    }
+
+    /// Computes the coefficients of the product of the polynomials of coefficients `a` and `b`
+    /// into `result`, through FFTs of 2^`lgn` elements (Which must be at least `result.len()`,
+    /// i.e. `a.len() + b.len() - 1`). The inputs are padded with zeros on the GPU, and all of
+    /// the intermediate values stay there.
+    pub fn polynomial_mul(
+        &mut self,
+        a: &[F],
+        b: &[F],
+        result: &mut [F],
+        omega: &F,
+        lgn: u32,
+    ) -> GPUResult<()> {
+        let n = 1 << lgn;
+        if n > self.n as usize {
+            return Err(GPUError::Simple(
+                "FFT is larger than the allocated GPU buffers!",
+            ));
+        }
+        if a.len() + b.len() != result.len() + 1 || result.len() > n {
+            return Err(GPUError::Simple(
+                "Polynomial sizes don't match the product!",
+            ));
+        }
+        self.ensure_tmp_buffer()?;
+
+        let omegainv = omega.inverse().unwrap();
+        let minv = F::from_str(&format!("{}", n)).unwrap().inverse().unwrap();
+
+        let max_deg = cmp::min(self.tuning.max_radix_degree, lgn);
+        self.setup_pq(omega, n, max_deg)?;
+        for (i, x) in [a, b].iter().enumerate() {
+            let tx = structs::PrimeFieldStruct::<F>::to_repr_slice(*x);
+            self.fft_src_buffer.write(tx).enq()?;
+            if x.len() < n {
+                self.fft_src_buffer
+                    .cmd()
+                    .offset(x.len())
+                    .fill(structs::PrimeFieldStruct::<F>(F::zero()), Some(n - x.len()))
+                    .enq()?;
+            }
+            self.result_to_src(lgn, max_deg)?;
+
+            // The evaluations of `a` are kept in the temporary buffer, then multiplied by the
+            // ones of `b` and swapped back into the source buffer.
+            if i == 1 {
+                if let Some(ref tmp) = self.fft_tmp_buffer {
+                    self.poly_op("poly_mul_assign", tmp, &self.fft_src_buffer, n as u32)?;
+                }
+            }
+            if let Some(ref mut tmp) = self.fft_tmp_buffer {
+                std::mem::swap(&mut self.fft_src_buffer, tmp);
+            }
         }
-        self.proque.finish()?; // Wait for all commands in the queue (Including read command)
 
+        self.setup_pq(&omegainv, n, max_deg)?;
+        self.result_to_src(lgn, max_deg)?;
+        self.mul_by_powers(lgn, true, &F::one(), &minv)?;
+
+        let tr = structs::PrimeFieldStruct::<F>::to_repr_slice_mut(result);
+        watchdog::finish(self.proque.queue())?; // Reads block until the kernels are done
+        self.fft_src_buffer.read(tr).enq()?;
+
+        Ok(())
+    }
+
+    /// Replaces every non-zero element of `a` with its inverse, with Montgomery's trick. Each
+    /// work-item of the `poly_batch_invert_*` kernels handles BATCH_INVERT_CHUNK elements, only
+    /// the inversion of the products of the chunks is done on the CPU. Slices larger than the
+    /// buffers are inverted one part at a time.
+    pub fn batch_invert(&mut self, a: &mut [F]) -> GPUResult<()> {
+        let mut done = 0;
+        self.batch_invert_from(a, &mut done)
+    }
+
+    /// Same as `batch_invert`, but starts from `a[*done]` and moves `done` past every part once
+    /// it's inverted, so that a failed inversion can be continued without inverting any of the
+    /// elements twice.
+    pub fn batch_invert_from(&mut self, a: &mut [F], done: &mut usize) -> GPUResult<()> {
+        let capacity = self.n as usize;
+        domain::batch_invert_parts(a, capacity, done, |part| self.batch_invert_part(part))
+    }
+
+    fn batch_invert_part(&mut self, a: &mut [F]) -> GPUResult<()> {
+        let n = a.len() as u32;
+        let workers = (n + BATCH_INVERT_CHUNK - 1) / BATCH_INVERT_CHUNK;
+        let totals_buffer = Buffer::builder()
+            .queue(self.proque.queue().clone())
+            .flags(MemFlags::new().read_write())
+            .len(workers)
+            .build()?;
+        let mut totals = vec![F::zero(); workers as usize];
+
+        let ta = structs::PrimeFieldStruct::<F>::to_repr_slice_mut(a);
+        self.fft_src_buffer.write(&*ta).enq()?;
+        self.batch_invert_step("poly_batch_invert_prefix", &totals_buffer, n, workers)?;
+
+        let tt = structs::PrimeFieldStruct::<F>::to_repr_slice_mut(&mut totals);
+        watchdog::finish(self.proque.queue())?; // Reads block until the kernels are done
+        totals_buffer.read(&mut *tt).enq()?;
+        totals
+            .par_chunks_mut(BATCH_INVERT_CHUNK as usize)
+            .for_each(domain::serial_batch_invert::<F>);
+        let tt = structs::PrimeFieldStruct::<F>::to_repr_slice(&totals);
+        totals_buffer.write(tt).enq()?;
+
+        self.batch_invert_step("poly_batch_invert_finish", &totals_buffer, n, workers)?;
+        watchdog::finish(self.proque.queue())?; // Reads block until the kernels are done
+        self.fft_src_buffer.read(ta).enq()?;
+        Ok(())
+    }
+
+    fn batch_invert_step(
+        &self,
+        name: &str,
+        totals: &Buffer<structs::PrimeFieldStruct<F>>,
+        n: u32,
+        workers: u32,
+    ) -> GPUResult<()> {
+        let kernel = self
+            .proque
+            .kernel_builder(name)
+            .global_work_size([workers])
+            .arg(&self.fft_src_buffer)
+            .arg(&self.fft_dst_buffer)
+            .arg(totals)
+            .arg(n)
+            .arg(BATCH_INVERT_CHUNK)
+            .build()?;
+        unsafe {
+            kernel.enq()?;
+        } // Running a GPU kernel is unsafe!
+        Ok(())
+    }
+
+    /// Runs all radix rounds on the source buffer and makes sure the result ends up there too
+    fn result_to_src(&mut self, lgn: u32, max_deg: u32) -> GPUResult<()> {
+        if !self.radix_fft_rounds(lgn, max_deg)? {
+            std::mem::swap(&mut self.fft_src_buffer, &mut self.fft_dst_buffer);
+        }
         Ok(())
     }
+
+    /// Uploads `a` and enqueues the FFT rounds without waiting for them to finish. The result
+    /// is only copied back to `a` once `wait` is called on the returned handle.
+    pub fn radix_fft_async<'a>(
+        &'a mut self,
+        a: &'a mut [F],
+        omega: &F,
+        lgn: u32,
+    ) -> GPUResult<FFTHandle<'a, F>> {
+        let span = self.span(lgn);
+        let _enter = span.enter();
+
+        let n = 1 << lgn;
+        if n > self.n as usize {
+            return Err(GPUError::Simple(
+                "FFT is larger than the allocated GPU buffers!",
+            ));
+        }
+
+        let ta = structs::PrimeFieldStruct::<F>::to_repr_slice_mut(a);
+
+        let max_deg = cmp::min(self.tuning.max_radix_degree, lgn);
+        self.setup_pq(omega, n, max_deg)?;
+
+        self.staging
+            .write(&*ta, &self.fft_src_buffer, self.proque.queue())?;
+        let in_src = self.radix_fft_rounds(lgn, max_deg)?;
+
+        Ok(FFTHandle {
+            kernel: Some(self),
+            a: ta,
+            in_src,
+        })
+    }
+
+    /// Merges the FFTs of the three interleaved sub-sequences of `a` (See `radix3_combine` in
+    /// `fft.cl`) into `a`
+    fn radix3_combine(&mut self, subs: &[Vec<F>], a: &mut [F], omega: &F) -> GPUResult<()> {
+        let m = a.len() / 3;
+        if a.len() > self.n as usize {
+            return Err(GPUError::Simple(
+                "FFT is larger than the allocated GPU buffers!",
+            ));
+        }
+
+        for (r, sub) in subs.iter().enumerate() {
+            let ts = structs::PrimeFieldStruct::<F>::to_repr_slice(&sub[..]);
+            self.fft_src_buffer.write(ts).offset(r * m).enq()?;
+        }
+        // Only the `omegas` table is needed
+        self.setup_pq(omega, a.len(), 1)?;
+
+        let lg_chunk = log2_floor(m).saturating_sub(self.tuning.log_max_powers_workers);
+        let kernel = self
+            .proque
+            .kernel_builder("radix3_combine")
+            .global_work_size([a.len() >> lg_chunk])
+            .arg(&self.fft_src_buffer)
+            .arg(&self.fft_dst_buffer)
+            .arg(&self.fft_omg_buffer)
+            .arg(m as u32)
+            .arg(1u32 << lg_chunk)
+            .build()?;
+        unsafe {
+            kernel.enq()?;
+        } // Running a GPU kernel is unsafe!
+
+        let ta = structs::PrimeFieldStruct::<F>::to_repr_slice_mut(a);
+        watchdog::finish(self.proque.queue())?; // Reads block until the kernels are done
+        self.fft_dst_buffer.read(ta).enq()?;
+
+        Ok(())
+    }
+
+    /// Performs FFT on each of `polys`, all of them having 2^`lgn` elements
+    /// The twiddle factors are only calculated once and the next polynomial is uploaded through
+    /// a separate queue while the current one is being transformed.
+    pub fn radix_fft_many(&mut self, polys: &mut [&mut [F]], omega: &F, lgn: u32) -> GPUResult<()> {
+        let mut done = 0;
+        self.radix_fft_many_from(polys, omega, lgn, &mut done)
+    }
+
+    /// Same as `radix_fft_many`, but starts from `polys[*done]` and increments `done` whenever a
+    /// polynomial is transformed, so that a failed batch can be continued without transforming
+    /// any of the polynomials twice.
+    pub fn radix_fft_many_from(
+        &mut self,
+        polys: &mut [&mut [F]],
+        omega: &F,
+        lgn: u32,
+        done: &mut usize,
+    ) -> GPUResult<()> {
+        let span = self.span(lgn);
+        let _enter = span.enter();
+
+        let n = 1 << lgn;
+        if n > self.n as usize {
+            return Err(GPUError::Simple(
+                "FFT is larger than the allocated GPU buffers!",
+            ));
+        }
+        if polys.iter().any(|p| p.len() != n) {
+            return Err(GPUError::Simple(
+                "All polynomials of a batched FFT should have the same size!",
+            ));
+        }
+        let polys = &mut polys[*done..];
+        if polys.is_empty() {
+            return Ok(());
+        }
+
+        if polys.len() > 1 {
+            self.ensure_tmp_buffer()?;
+        }
+        let transfer_queue = ocl::Queue::new(self.proque.context(), self.proque.device(), None)?;
+
+        let max_deg = cmp::min(self.tuning.max_radix_degree, lgn);
+        self.setup_pq(omega, n, max_deg)?;
+
+        let first = structs::PrimeFieldStruct::<F>::to_repr_slice(&*polys[0]);
+        self.fft_src_buffer.write(first).enq()?;
+        for i in 0..polys.len() {
+            // Kernels are enqueued without blocking, so the (blocking) upload of the next
+            // polynomial runs on the device while the current one is being transformed.
+            let in_src = self.radix_fft_rounds(lgn, max_deg)?;
+            if i + 1 < polys.len() {
+                let next = structs::PrimeFieldStruct::<F>::to_repr_slice(&*polys[i + 1]);
+                if let Some(ref tmp) = self.fft_tmp_buffer {
+                    tmp.write(next).queue(&transfer_queue).enq()?;
+                }
+            }
+
+            let ta = structs::PrimeFieldStruct::<F>::to_repr_slice_mut(&mut *polys[i]);
+            watchdog::finish(self.proque.queue())?; // Reads block until the kernels are done
+            if in_src {
+                self.fft_src_buffer.read(ta).enq()?;
+            } else {
+                self.fft_dst_buffer.read(ta).enq()?;
+            }
+            *done += 1;
+
+            // The freshly uploaded polynomial becomes the source of the next FFT.
+            if let Some(ref mut tmp) = self.fft_tmp_buffer {
+                std::mem::swap(&mut self.fft_src_buffer, tmp);
+            }
+        }
+        watchdog::finish(self.proque.queue())?;
+        watchdog::finish(&transfer_queue)?;
+
+        Ok(())
+    }
+}
+
+/// State of an FFT interrupted by a high priority process, see `SingleFFTKernel::radix_fft_resume`.
+/// It holds a host copy of the working buffer after the last completed radix round.
+pub struct FFTCheckpoint<F>
+where
+    F: PrimeField,
+{
+    lgn: u32,
+    // log2 of `p` of the next round to run (See `radix_fft_round`)
+    lgp: u32,
+    omega: F,
+    values: Vec<F>,
+}
+
+impl<F> FFTCheckpoint<F>
+where
+    F: PrimeField,
+{
+    fn values(&self) -> &[structs::PrimeFieldStruct<F>] {
+        structs::PrimeFieldStruct::<F>::to_repr_slice(&self.values)
+    }
+
+    fn values_mut(&mut self) -> &mut [structs::PrimeFieldStruct<F>] {
+        structs::PrimeFieldStruct::<F>::to_repr_slice_mut(&mut self.values)
+    }
+}
+
+impl Default for FFTTuning {
+    fn default() -> Self {
+        FFTTuning {
+            max_radix_degree: MAX_RADIX_DEGREE,
+            max_local_work_size_degree: MAX_LOCAL_WORK_SIZE_DEGREE,
+            log_max_powers_workers: LOG_MAX_POWERS_WORKERS,
+        }
+    }
+}
+
+/// An FFT that has been enqueued on a GPU but whose result has not been read back yet.
+/// Dropping the handle without calling `wait` leaves the input slice in an unspecified state.
+pub struct FFTHandle<'a, F>
+where
+    F: PrimeField,
+{
+    // `None` when the FFT was already finished synchronously (E.g. it was split across GPUs)
+    kernel: Option<&'a mut SingleFFTKernel<F>>,
+    a: &'a mut [structs::PrimeFieldStruct<F>],
+    in_src: bool,
+}
+
+impl<'a, F> FFTHandle<'a, F>
+where
+    F: PrimeField,
+{
+    /// Blocks until the FFT is finished and its result is copied back to the input slice.
+    pub fn wait(self) -> GPUResult<()> {
+        if let Some(k) = self.kernel {
+            if self.in_src {
+                k.staging
+                    .read(&k.fft_src_buffer, self.a, k.proque.queue())?;
+            } else {
+                k.staging
+                    .read(&k.fft_dst_buffer, self.a, k.proque.queue())?;
+            }
+            watchdog::finish(k.proque.queue())?; // Wait for all commands in the queue (Including read command)
+        }
+        Ok(())
+    }
+}
+
+/// Device memory needed (In bytes) by a `SingleFFTKernel` of 2^`lgn` elements: the source,
+/// destination and temporary buffers of 2^`lgn` elements plus the twiddle factor tables.
+pub fn required_memory<F: PrimeField>(lgn: u32) -> u64 {
+    let elem = std::mem::size_of::<F>() as u64;
+    (3u64 << lgn) * elem + ((1 << MAX_RADIX_DEGREE >> 1) + LOG2_MAX_ELEMENTS as u64) * elem
+}
+
+/// Largest `lgn` such that a `SingleFFTKernel` of 2^`lgn` elements fits in `memory` bytes (E.g.
+/// from `get_memory_limit`), `None` if not even the smallest one fits.
+pub fn max_fft_lgn<F: PrimeField>(memory: u64) -> Option<u32> {
+    (0..LOG2_MAX_ELEMENTS as u32)
+        .rev()
+        .find(|&lgn| required_memory::<F>(lgn) <= memory)
+}
+
+// In paranoid mode, spot-checks the result of running `f` on `a` on the CPU, see
+// `paranoid::spot_check_fft`. `a` is restored on a mismatch, so that the FFT can be redone.
+fn checked_fft<F, R>(
+    a: &mut [F],
+    omega: &F,
+    pre: Option<&F>,
+    post: Option<(&F, &F)>,
+    f: R,
+) -> GPUResult<()>
+where
+    F: PrimeField,
+    R: FnOnce(&mut [F]) -> GPUResult<()>,
+{
+    if !paranoid::is_paranoid() {
+        return f(a);
+    }
+    let input = a.to_vec();
+    f(a)?;
+    if let Err(e) = paranoid::spot_check_fft(&input, a, omega, pre, post) {
+        error!("GPU FFT result doesn't match the CPU!");
+        a.copy_from_slice(&input);
+        return Err(e);
+    }
+    Ok(())
+}
+
+// The pq and omega buffers of an FFT, see `SingleFFTKernel::setup_pq`
+struct PqTables<F: PrimeField> {
+    key: Option<(F, usize, u32)>,
+    pq: Buffer<structs::PrimeFieldStruct<F>>,
+    omg: Buffer<structs::PrimeFieldStruct<F>>,
+}
+
+#[allow(clippy::type_complexity)]
+fn pq_buffers<F: PrimeField>(
+    pq: &ProQue,
+) -> ocl::Result<(
+    Buffer<structs::PrimeFieldStruct<F>>,
+    Buffer<structs::PrimeFieldStruct<F>>,
+)> {
+    let pqbuff = Buffer::builder()
+        .queue(pq.queue().clone())
+        .flags(MemFlags::new().read_write())
+        .len(1 << MAX_RADIX_DEGREE >> 1)
+        .build()?;
+    let omgbuff = Buffer::builder()
+        .queue(pq.queue().clone())
+        .flags(MemFlags::new().read_write())
+        .len(LOG2_MAX_ELEMENTS)
+        .build()?;
+    Ok((pqbuff, omgbuff))
+}
+
+// A struct that containts several FFT kernels for different devices
+pub struct FFTKernel<F>
+where
+    F: PrimeField,
+{
+    kernels: Vec<SingleFFTKernel<F>>,
+    _locks: Vec<locks::GPULock>, // RFC 1857: struct fields are dropped in the same order as they are declared.
+}
+
+impl<F> FFTKernel<F>
+where
+    F: PrimeField,
+{
+    pub fn create(n: u32, priority: Priority) -> GPUResult<FFTKernel<F>> {
+        Self::create_with_memory_limit(n, priority, None)
+    }
+
+    /// Same as `create`, but at most `memory_limit` bytes are allocated on each device (Instead of
+    /// the memory size reported by the device or `BELLMAN_GPU_MEMORY_LIMIT`). Devices that don't
+    /// have enough memory for 2^`n` elements get smaller buffers, so that they can still be used
+    /// for the smaller FFTs.
+    pub fn create_with_memory_limit(
+        n: u32,
+        priority: Priority,
+        memory_limit: Option<u64>,
+    ) -> GPUResult<FFTKernel<F>> {
+        let devices = get_assigned_devices(KernelKind::FFT).unwrap_or_default();
+        Self::create_on_devices(&devices, n, priority, memory_limit)
+    }
+
+    /// Creates a kernel running on `device` only, regardless of `BELLMAN_GPU_INDEX`
+    pub fn create_on(device: &Device, n: u32, priority: Priority) -> GPUResult<FFTKernel<F>> {
+        Self::create_on_devices(&[*device], n, priority, None)
+    }
+
+    fn create_on_devices(
+        devices: &[Device],
+        n: u32,
+        priority: Priority,
+        memory_limit: Option<u64>,
+    ) -> GPUResult<FFTKernel<F>> {
+        if devices.is_empty() {
+            return Err(GPUError::Simple("No working GPUs found!"));
+        }
+
+        // Only small FFTs are ever done on a single device when the work is split, so the
+        // first device needs enough memory for those as well as for its share of the big ones.
+        let log_parts = if n >= 1 << MULTI_GPU_MIN_LOG_N {
+            log2_floor(devices.len())
+        } else {
+            0
+        };
+        let part_n = n >> log_parts;
+        let first_n = cmp::max(part_n, cmp::min(n, 1 << (MULTI_GPU_MIN_LOG_N - 1)));
+
+        let devices = &devices[..cmp::min(devices.len(), 1 << log_parts)];
+        let locks = locks::GPULock::lock_all(devices);
+
+        let create = |i: usize, d: &Device, wanted: u32| -> GPUResult<SingleFFTKernel<F>> {
+            let memory = match memory_limit {
+                Some(limit) => limit,
+                None => get_memory_limit(*d)?,
+            };
+            let capacity = max_fft_lgn::<F>(memory)
+                .map(|lgn| 1u32 << lgn)
+                .ok_or(GPUError::Simple("Not enough GPU memory for FFT!"))?;
+            if capacity < wanted {
+                info!(
+                    "FFT: Device {} can only fit FFTs of up to {} elements.",
+                    i, capacity
+                );
+            }
+            SingleFFTKernel::<F>::create(*d, cmp::min(wanted, capacity), priority)
+        };
+        // `kernels[0]` does the FFTs which aren't split, so the `first_n` buffers go to the first
+        // device whose kernel builds, not to the first device
+        let mut kernels = Vec::with_capacity(devices.len());
+        for (i, d) in devices.iter().enumerate() {
+            let wanted = if kernels.is_empty() { first_n } else { part_n };
+            match create(i, d, wanted) {
+                Ok(kern) => kernels.push(kern),
+                Err(e) => warn!("FFT: Cannot use device {}! Error: {}", i, e),
+            }
+        }
+
+        if kernels.is_empty() {
+            return Err(GPUError::Simple("No working GPUs found!"));
+        }
+        info!("FFT: {} working device(s) selected.", kernels.len());
+        for (i, k) in kernels.iter().enumerate() {
+            info!("FFT: Device {}: {}", i, k.proque.device().name()?);
+        }
+
+        Ok(FFTKernel {
+            kernels,
+            _locks: locks,
+        })
+    }
+
+    /// Makes the FFTs fail with `GPUError::Cancelled` before their next round once `cancel` is
+    /// cancelled.
+    pub fn set_cancellation(&mut self, cancel: Option<CancellationToken>) {
+        for k in self.kernels.iter_mut() {
+            k.cancel = cancel.clone();
+        }
+    }
+
+    /// Performs FFT on `a`
+    /// * `omega` - Special value `omega` is used for FFT over finite-fields
+    /// * `lgn` - Specifies log2 of number of elements
+    ///
+    /// Large FFTs are split into 2^k independent sub-FFTs (Where 2^k is the number of working
+    /// devices) which are computed in parallel, one per GPU.
+    pub fn radix_fft(&mut self, a: &mut [F], omega: &F, lgn: u32) -> GPUResult<()> {
+        checked_fft(a, omega, None, None, |a| {
+            self.radix_fft_unchecked(a, omega, lgn)
+        })
+    }
+
+    fn radix_fft_unchecked(&mut self, a: &mut [F], omega: &F, lgn: u32) -> GPUResult<()> {
+        let log_parts = cmp::min(log2_floor(self.kernels.len()), lgn);
+        if lgn < MULTI_GPU_MIN_LOG_N || log_parts == 0 {
+            return self.kernels[0].radix_fft(a, omega, lgn);
+        }
+        self.multi_radix_fft(a, omega, lgn, log_parts)
+    }
+
+    /// Resumable version of `radix_fft`, see `SingleFFTKernel::radix_fft_resume`. FFTs which
+    /// are split across several GPUs or don't fit in the buffers are restarted from scratch
+    /// and never save a checkpoint.
+    pub fn radix_fft_resume(
+        &mut self,
+        a: &mut [F],
+        omega: &F,
+        lgn: u32,
+        checkpoint: &mut Option<FFTCheckpoint<F>>,
+    ) -> GPUResult<()> {
+        checked_fft(a, omega, None, None, |a| {
+            if self.is_split(lgn) || 1 << lgn > self.kernels[0].n as usize {
+                return self.radix_fft_unchecked(a, omega, lgn);
+            }
+            self.kernels[0].radix_fft_resume(a, omega, lgn, checkpoint)
+        })
+    }
+
+    /// Performs FFT of `a` on the coset `g * <omega>`, see `SingleFFTKernel::coset_fft`.
+    pub fn coset_fft(&mut self, a: &mut [F], omega: &F, g: &F, lgn: u32) -> GPUResult<()> {
+        checked_fft(a, omega, Some(g), None, |a| {
+            self.coset_fft_unchecked(a, omega, g, lgn)
+        })
+    }
+
+    fn coset_fft_unchecked(&mut self, a: &mut [F], omega: &F, g: &F, lgn: u32) -> GPUResult<()> {
+        let log_parts = cmp::min(log2_floor(self.kernels.len()), lgn);
+        if lgn < MULTI_GPU_MIN_LOG_N || log_parts == 0 {
+            return self.kernels[0].coset_fft(a, omega, g, lgn);
+        }
+        // Split FFTs are shuffled on the host anyway, so the coset shift is done there too.
+        mul_by_powers::<F>(a, g, &F::one());
+        if let Err(e) = self.multi_radix_fft(a, omega, lgn, log_parts) {
+            // `a` is untouched by a failed FFT, undo the shift so that it can be retried.
+            mul_by_powers::<F>(a, &g.inverse().unwrap(), &F::one());
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Performs inverse FFT of `a` from the coset `g * <omega>`, see
+    /// `SingleFFTKernel::icoset_fft`.
+    pub fn icoset_fft(
+        &mut self,
+        a: &mut [F],
+        omegainv: &F,
+        geninv: &F,
+        minv: &F,
+        lgn: u32,
+    ) -> GPUResult<()> {
+        checked_fft(a, omegainv, None, Some((geninv, minv)), |a| {
+            self.icoset_fft_unchecked(a, omegainv, geninv, minv, lgn)
+        })
+    }
+
+    fn icoset_fft_unchecked(
+        &mut self,
+        a: &mut [F],
+        omegainv: &F,
+        geninv: &F,
+        minv: &F,
+        lgn: u32,
+    ) -> GPUResult<()> {
+        let log_parts = cmp::min(log2_floor(self.kernels.len()), lgn);
+        if lgn < MULTI_GPU_MIN_LOG_N || log_parts == 0 {
+            return self.kernels[0].icoset_fft(a, omegainv, geninv, minv, lgn);
+        }
+        self.multi_radix_fft(a, omegainv, lgn, log_parts)?;
+        mul_by_powers::<F>(a, geninv, minv);
+        Ok(())
+    }
+
+    /// Computes the coefficients of `(a * b - c) / Z` given the evaluations of `a`, `b` and `c`,
+    /// see `SingleFFTKernel::quotient`. Not supported for FFTs that are split across GPUs (See
+    /// `is_split`), as their intermediate values can't stay on a single device.
+    pub fn quotient(
+        &mut self,
+        a: &mut [F],
+        b: &[F],
+        c: &[F],
+        omega: &F,
+        g: &F,
+        lgn: u32,
+    ) -> GPUResult<()> {
+        if self.is_split(lgn) {
+            return Err(GPUError::Simple(
+                "Quotient is not supported for FFTs split across GPUs!",
+            ));
+        }
+        self.kernels[0].quotient(a, b, c, omega, g, lgn)
+    }
+
+    /// Computes the coefficients of the product of `a` and `b` into `result`, see
+    /// `SingleFFTKernel::polynomial_mul`. Not supported for FFTs that are split across GPUs (See
+    /// `is_split`), as their intermediate values can't stay on a single device.
+    pub fn polynomial_mul(
+        &mut self,
+        a: &[F],
+        b: &[F],
+        result: &mut [F],
+        omega: &F,
+        lgn: u32,
+    ) -> GPUResult<()> {
+        if self.is_split(lgn) {
+            return Err(GPUError::Simple(
+                "Polynomial multiplication is not supported for FFTs split across GPUs!",
+            ));
+        }
+        self.kernels[0].polynomial_mul(a, b, result, omega, lgn)
+    }
+
+    /// Replaces every non-zero element of `a` with its inverse, see
+    /// `SingleFFTKernel::batch_invert`. This runs on the first device only.
+    pub fn batch_invert(&mut self, a: &mut [F]) -> GPUResult<()> {
+        self.kernels[0].batch_invert(a)
+    }
+
+    /// Same as `batch_invert`, but starts from `a[*done]`, see
+    /// `SingleFFTKernel::batch_invert_from`.
+    pub fn batch_invert_from(&mut self, a: &mut [F], done: &mut usize) -> GPUResult<()> {
+        self.kernels[0].batch_invert_from(a, done)
+    }
+
+    /// Returns true if FFTs of 2^`lgn` elements are split across several GPUs
+    pub fn is_split(&self, lgn: u32) -> bool {
+        lgn >= MULTI_GPU_MIN_LOG_N && log2_floor(self.kernels.len()) > 0
+    }
+
+    /// Performs FFT on `a` of 2^`lgn` * 3^`radix3` elements. Each radix-3 step splits `a` into
+    /// three interleaved sub-FFTs (Batched on the GPU) which are merged by the `radix3_combine`
+    /// kernel, or on the host if they don't fit in the buffers.
+    pub fn mixed_radix_fft(
+        &mut self,
+        a: &mut [F],
+        omega: &F,
+        lgn: u32,
+        radix3: u32,
+    ) -> GPUResult<()> {
+        if radix3 == 0 {
+            return self.radix_fft(a, omega, lgn);
+        }
+
+        let m = a.len() / 3;
+        let new_omega = omega.pow([3u64]);
+        let mut subs = (0..3)
+            .map(|r| (0..m).map(|i| a[3 * i + r]).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        if radix3 == 1 {
+            let mut refs = subs.iter_mut().map(|s| &mut s[..]).collect::<Vec<_>>();
+            self.radix_fft_many(&mut refs, &new_omega, lgn)?;
+        } else {
+            for sub in subs.iter_mut() {
+                self.mixed_radix_fft(sub, &new_omega, lgn, radix3 - 1)?;
+            }
+        }
+
+        if a.len() <= self.kernels[0].n as usize {
+            return self.kernels[0].radix3_combine(&subs, a, omega);
+        }
+        let chunk_size = cmp::max(1, a.len() / rayon::current_num_threads());
+        a.par_chunks_mut(chunk_size)
+            .enumerate()
+            .for_each(|(c, chunk)| {
+                let mut w = omega.pow([(c * chunk_size) as u64]);
+                for (k, v) in chunk.iter_mut().enumerate() {
+                    let i = (c * chunk_size + k) % m;
+                    let mut w2 = w;
+                    w2.square();
+                    let mut t1 = subs[1][i];
+                    t1.mul_assign(&w);
+                    let mut t2 = subs[2][i];
+                    t2.mul_assign(&w2);
+                    *v = subs[0][i];
+                    v.add_assign(&t1);
+                    v.add_assign(&t2);
+                    w.mul_assign(omega);
+                }
+            });
+
+        Ok(())
+    }
+
+    /// Non-blocking version of `radix_fft`, the result is only available in `a` after calling
+    /// `wait` on the returned handle. FFTs that are split across several GPUs are still done
+    /// synchronously.
+    pub fn radix_fft_async<'a>(
+        &'a mut self,
+        a: &'a mut [F],
+        omega: &F,
+        lgn: u32,
+    ) -> GPUResult<FFTHandle<'a, F>> {
+        let log_parts = cmp::min(log2_floor(self.kernels.len()), lgn);
+        if lgn < MULTI_GPU_MIN_LOG_N || log_parts == 0 {
+            return self.kernels[0].radix_fft_async(a, omega, lgn);
+        }
+        self.multi_radix_fft(a, omega, lgn, log_parts)?;
+        Ok(FFTHandle {
+            kernel: None,
+            a: structs::PrimeFieldStruct::<F>::to_repr_slice_mut(a),
+            in_src: false,
+        })
+    }
+
+    /// Same decomposition as `parallel_fft` in `domain.rs`, with each sub-FFT running on its own
+    /// device. The shuffling of the elements into the sub-FFTs and back is done in host memory.
+    fn multi_radix_fft(
+        &mut self,
+        a: &mut [F],
+        omega: &F,
+        lgn: u32,
+        log_parts: u32,
+    ) -> GPUResult<()> {
+        let parts = 1 << log_parts;
+        let log_new_n = lgn - log_parts;
+        let new_n = 1 << log_new_n;
+        let new_omega = omega.pow([parts as u64]);
+        let mut tmp = vec![vec![F::zero(); new_n]; parts];
+
+        match thread::scope(|s| -> GPUResult<()> {
+            let a = &*a;
+            let mut threads = Vec::new();
+            for ((j, tmp), kern) in tmp.iter_mut().enumerate().zip(self.kernels.iter_mut()) {
+                threads.push(s.spawn(move |_| -> GPUResult<()> {
+                    // Shuffle into a sub-FFT
+                    let omega_j = omega.pow([j as u64]);
+                    let omega_step = omega.pow([(j as u64) << log_new_n]);
+                    let chunk_size = cmp::max(new_n / rayon::current_num_threads(), 1);
+                    tmp.par_chunks_mut(chunk_size)
+                        .enumerate()
+                        .for_each(|(c, tmp)| {
+                            let mut elt = omega_j.pow([(c * chunk_size) as u64]);
+                            for (i, tmp) in tmp.iter_mut().enumerate() {
+                                let i = c * chunk_size + i;
+                                for s in 0..parts {
+                                    let mut t = a[i + (s << log_new_n)];
+                                    t.mul_assign(&elt);
+                                    tmp.add_assign(&t);
+                                    elt.mul_assign(&omega_step);
+                                }
+                                elt.mul_assign(&omega_j);
+                            }
+                        });
+
+                    // Perform sub-FFT
+                    kern.radix_fft(tmp, &new_omega, log_new_n)
+                }));
+            }
+
+            for t in threads {
+                t.join()??;
+            }
+            Ok(())
+        }) {
+            Ok(res) => res?,
+            Err(e) => return Err(GPUError::from(e)),
+        }
+
+        let mask = parts - 1;
+        a.par_iter_mut()
+            .enumerate()
+            .for_each(|(idx, a)| *a = tmp[idx & mask][idx >> log_parts]);
+        for tmp in tmp.iter_mut() {
+            secret::zeroize(tmp);
+        }
+
+        Ok(())
+    }
+
+    /// Performs FFT on each of `polys`, all of them having 2^`lgn` elements
+    /// Device buffers and twiddle factors are reused across the polynomials.
+    pub fn radix_fft_many(&mut self, polys: &mut [&mut [F]], omega: &F, lgn: u32) -> GPUResult<()> {
+        let mut done = 0;
+        self.radix_fft_many_from(polys, omega, lgn, &mut done)
+    }
+
+    /// Same as `radix_fft_many`, but starts from `polys[*done]` and increments `done` whenever a
+    /// polynomial is transformed.
+    pub fn radix_fft_many_from(
+        &mut self,
+        polys: &mut [&mut [F]],
+        omega: &F,
+        lgn: u32,
+        done: &mut usize,
+    ) -> GPUResult<()> {
+        if lgn >= MULTI_GPU_MIN_LOG_N && self.kernels.len() > 1 {
+            // Every single FFT is already using all of the devices.
+            while *done < polys.len() {
+                self.radix_fft(polys[*done], omega, lgn)?;
+                *done += 1;
+            }
+            return Ok(());
+        }
+        if !paranoid::is_paranoid() {
+            return self.kernels[0].radix_fft_many_from(polys, omega, lgn, done);
+        }
+
+        // Every transformed polynomial is checked, even if the batch failed midway. The first
+        // mismatching one and the ones after it are restored and counted as not done.
+        let start = *done;
+        let inputs = polys[start..]
+            .iter()
+            .map(|p| p.to_vec())
+            .collect::<Vec<_>>();
+        let res = self.kernels[0].radix_fft_many_from(polys, omega, lgn, done);
+        let mismatch = (start..*done).find_map(|i| {
+            paranoid::spot_check_fft(&inputs[i - start], polys[i], omega, None, None)
+                .err()
+                .map(|e| (i, e))
+        });
+        if let Some((i, e)) = mismatch {
+            error!("GPU FFT result doesn't match the CPU!");
+            for (p, input) in polys[i..*done].iter_mut().zip(&inputs[i - start..]) {
+                p.copy_from_slice(input);
+            }
+            *done = i;
+            return Err(e);
+        }
+        res
+    }
+}
+
+/// An FFT kernel bound to a single size, for callers doing many FFTs in a row (E.g. a pool of
+/// provers). All of the device buffers are allocated once at creation and the upload, transform
+/// and download steps are exposed separately, so that several transforms can be chained without
+/// copying the values back to the host in between.
+/// NOTE: The GPU lock is held for the whole lifetime of the session.
+pub struct FFTSession<F>
+where
+    F: PrimeField,
+{
+    kernel: SingleFFTKernel<F>,
+    lgn: u32,
+    _lock: locks::GPULock, // RFC 1857: struct fields are dropped in the same order as they are declared.
+}
+
+impl<F> FFTSession<F>
+where
+    F: PrimeField,
+{
+    pub fn create(lgn: u32, priority: Priority) -> GPUResult<FFTSession<F>> {
+        let devices = get_assigned_devices(KernelKind::FFT).unwrap_or_default();
+        let device = *devices
+            .first()
+            .ok_or(GPUError::Simple("No working GPUs found!"))?;
+        let lock = locks::GPULock::lock(&device);
+
+        let kernel = SingleFFTKernel::<F>::create(device, 1 << lgn, priority)?;
+        info!(
+            "FFT: Session of 2^{} elements created on {}",
+            lgn,
+            device.name()?
+        );
+
+        Ok(FFTSession {
+            kernel,
+            lgn,
+            _lock: lock,
+        })
+    }
+
+    /// Copies `a` to the GPU, replacing the values of the session
+    pub fn upload(&mut self, a: &[F]) -> GPUResult<()> {
+        if a.len() != 1 << self.lgn {
+            return Err(GPUError::Simple(
+                "Polynomial size doesn't match the FFT session!",
+            ));
+        }
+        let ta = structs::PrimeFieldStruct::<F>::to_repr_slice(a);
+        self.kernel.fft_src_buffer.write(ta).enq()?;
+        Ok(())
+    }
+
+    /// Performs FFT on the values of the session, without waiting for it to finish
+    pub fn transform(&mut self, omega: &F) -> GPUResult<()> {
+        let max_deg = cmp::min(self.kernel.tuning.max_radix_degree, self.lgn);
+        self.kernel.setup_pq(omega, 1 << self.lgn, max_deg)?;
+        self.kernel.result_to_src(self.lgn, max_deg)
+    }
+
+    /// Multiplies the i-th value of the session by `c * g^i`
+    pub fn mul_by_powers(&mut self, g: &F, c: &F) -> GPUResult<()> {
+        self.kernel.mul_by_powers(self.lgn, true, g, c)
+    }
+
+    /// Multiplies the values of the session by the elements of `b`, one by one
+    pub fn mul_assign(&mut self, b: &[F]) -> GPUResult<()> {
+        self.elementwise("poly_mul_assign", b)
+    }
+
+    /// Subtracts the elements of `b` from the values of the session, one by one
+    pub fn sub_assign(&mut self, b: &[F]) -> GPUResult<()> {
+        self.elementwise("poly_sub_assign", b)
+    }
+
+    fn elementwise(&mut self, op: &str, b: &[F]) -> GPUResult<()> {
+        if b.len() != 1 << self.lgn {
+            return Err(GPUError::Simple(
+                "Polynomial size doesn't match the FFT session!",
+            ));
+        }
+        let tb = structs::PrimeFieldStruct::<F>::to_repr_slice(b);
+        self.kernel.fft_dst_buffer.write(tb).enq()?;
+        self.kernel.poly_op(
+            op,
+            &self.kernel.fft_src_buffer,
+            &self.kernel.fft_dst_buffer,
+            1 << self.lgn,
+        )
+    }
+
+    /// Multiplies the values of the session by the values of `b`, one by one
+    pub fn mul_assign_buffer(&mut self, b: &FFTBuffer<F>) -> GPUResult<()> {
+        self.kernel.poly_op(
+            "poly_mul_assign",
+            &self.kernel.fft_src_buffer,
+            &b.buffer,
+            1 << self.lgn,
+        )
+    }
+
+    /// Subtracts the values of `b` from the values of the session, one by one
+    pub fn sub_assign_buffer(&mut self, b: &FFTBuffer<F>) -> GPUResult<()> {
+        self.kernel.poly_op(
+            "poly_sub_assign",
+            &self.kernel.fft_src_buffer,
+            &b.buffer,
+            1 << self.lgn,
+        )
+    }
+
+    /// Copies `a` to a new buffer on the GPU of the session, which can later replace the values
+    /// of the session with `with_buffer`.
+    pub fn create_buffer(&mut self, a: &[F]) -> GPUResult<FFTBuffer<F>> {
+        if a.len() != 1 << self.lgn {
+            return Err(GPUError::Simple(
+                "Polynomial size doesn't match the FFT session!",
+            ));
+        }
+        let buffer = Buffer::builder()
+            .queue(self.kernel.proque.queue().clone())
+            .flags(MemFlags::new().read_write())
+            .len(1 << self.lgn)
+            .build()?;
+        let ta = structs::PrimeFieldStruct::<F>::to_repr_slice(a);
+        buffer.write(ta).enq()?;
+        Ok(FFTBuffer { buffer })
+    }
+
+    /// Runs `f` with the values of `buf` as the values of the session, e.g. for transforming
+    /// them. No data is copied, the buffers are swapped back once `f` returns.
+    pub fn with_buffer<R, T>(&mut self, buf: &mut FFTBuffer<F>, f: T) -> GPUResult<R>
+    where
+        T: FnOnce(&mut FFTSession<F>) -> GPUResult<R>,
+    {
+        std::mem::swap(&mut self.kernel.fft_src_buffer, &mut buf.buffer);
+        let res = f(self);
+        std::mem::swap(&mut self.kernel.fft_src_buffer, &mut buf.buffer);
+        res
+    }
+
+    /// log2 of the number of values of the session
+    pub fn lgn(&self) -> u32 {
+        self.lgn
+    }
+
+    /// Copies the values of the session back to `a`, waiting for all of the pending operations
+    pub fn download(&mut self, a: &mut [F]) -> GPUResult<()> {
+        if a.len() != 1 << self.lgn {
+            return Err(GPUError::Simple(
+                "Polynomial size doesn't match the FFT session!",
+            ));
+        }
+        let ta = structs::PrimeFieldStruct::<F>::to_repr_slice_mut(a);
+        watchdog::finish(self.kernel.proque.queue())?; // Reads block until the kernels are done
+        self.kernel.fft_src_buffer.read(ta).enq()?;
+        Ok(())
+    }
+}
+
+/// Values kept on the GPU of an `FFTSession`, see `FFTSession::create_buffer`
+pub struct FFTBuffer<F>
+where
+    F: PrimeField,
+{
+    buffer: Buffer<structs::PrimeFieldStruct<F>>,
+}
+
+impl<F> FFTBuffer<F>
+where
+    F: PrimeField,
+{
+    /// Copies the values back to `a`, waiting for all of the pending operations of the session
+    pub fn download(&self, a: &mut [F]) -> GPUResult<()> {
+        if a.len() != self.buffer.len() {
+            return Err(GPUError::Simple(
+                "Polynomial size doesn't match the FFT buffer!",
+            ));
+        }
+        let ta = structs::PrimeFieldStruct::<F>::to_repr_slice_mut(a);
+        if let Some(queue) = self.buffer.default_queue() {
+            watchdog::finish(queue)?; // Reads block until the kernels are done
+        }
+        self.buffer.read(ta).enq()?;
+        Ok(())
+    }
+}
+
+/// Multiplies the i-th element of `a` by `c * g^i` on the CPU
+fn mul_by_powers<F: PrimeField>(a: &mut [F], g: &F, c: &F) {
+    let chunk_size = cmp::max(1, a.len() / rayon::current_num_threads());
+    a.par_chunks_mut(chunk_size)
+        .enumerate()
+        .for_each(|(i, chunk)| {
+            let mut tmp = g.pow([(i * chunk_size) as u64]);
+            tmp.mul_assign(c);
+            for v in chunk.iter_mut() {
+                v.mul_assign(&tmp);
+                tmp.mul_assign(g);
+            }
+        });
+}
+
+fn log2_floor(num: usize) -> u32 {
+    let mut pow = 0;
+    while (1 << (pow + 1)) <= num {
+        pow += 1;
+    }
+    pow
 }
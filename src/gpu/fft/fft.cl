@@ -74,3 +74,39 @@ __kernel void mul_by_field(__global FIELD* elements,
   const uint gid = get_global_id(0);
   elements[gid] = FIELD_mul(elements[gid], field);
 }
+
+/// Multiplies the i-th element by `c * g^i`, used for the coset shifts and the final scaling of
+/// an inverse FFT. Each work-item handles `chunk` consecutive elements.
+__kernel void mul_by_powers(__global FIELD* elements,
+                        uint n,
+                        uint chunk,
+                        FIELD g,
+                        FIELD c) {
+  const uint start = get_global_id(0) * chunk;
+  const uint end = min(start + chunk, n);
+  FIELD tmp = FIELD_mul(c, FIELD_pow(g, start));
+  for(uint i = start; i < end; i++) {
+    elements[i] = FIELD_mul(elements[i], tmp);
+    tmp = FIELD_mul(tmp, g);
+  }
+}
+
+/// Last step of a radix-3 FFT of `3 * m` elements: `x` holds the FFTs of the three interleaved
+/// sub-sequences one after another and `y[k] = X0[k % m] + w^k * X1[k % m] + w^2k * X2[k % m]`
+/// where `w` is `omegas[0]`. Each work-item handles `chunk` consecutive elements.
+__kernel void radix3_combine(__global FIELD* x,
+                        __global FIELD* y,
+                        __global FIELD* omegas,
+                        uint m,
+                        uint chunk) {
+  const uint start = get_global_id(0) * chunk;
+  const uint end = min(start + chunk, 3 * m);
+  const FIELD omega = omegas[0];
+  FIELD w = FIELD_pow_lookup(omegas, start);
+  for(uint k = start; k < end; k++) {
+    const uint i = k % m;
+    const FIELD w2 = FIELD_mul(w, w);
+    y[k] = FIELD_add(FIELD_add(x[i], FIELD_mul(w, x[m + i])), FIELD_mul(w2, x[2 * m + i]));
+    w = FIELD_mul(w, omega);
+  }
+}
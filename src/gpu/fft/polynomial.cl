@@ -0,0 +1,59 @@
+/// Element-wise polynomial arithmetic, used for keeping the evaluations of the Groth16 `a`, `b`
+/// and `c` polynomials on the GPU between the FFTs.
+
+/// a[i] *= b[i]
+__kernel void poly_mul_assign(__global FIELD* a,
+                        __global FIELD* b,
+                        uint n) {
+  const uint gid = get_global_id(0);
+  if(gid < n) a[gid] = FIELD_mul(a[gid], b[gid]);
+}
+
+/// a[i] -= b[i]
+__kernel void poly_sub_assign(__global FIELD* a,
+                        __global FIELD* b,
+                        uint n) {
+  const uint gid = get_global_id(0);
+  if(gid < n) a[gid] = FIELD_sub(a[gid], b[gid]);
+}
+
+/// First half of the Montgomery batch inversion, each work-item handling `chunk` elements of
+/// `a`: stores the product of the elements before a[i] (Within its chunk) into prefix[i], and
+/// the product of the whole chunk into totals[gid]. Zeros are skipped.
+__kernel void poly_batch_invert_prefix(__global FIELD* a,
+                        __global FIELD* prefix,
+                        __global FIELD* totals,
+                        uint n,
+                        uint chunk) {
+  const uint gid = get_global_id(0);
+  const uint start = gid * chunk;
+  if(start >= n) return;
+  const uint end = min(start + chunk, n);
+  FIELD acc = FIELD_ONE;
+  for(uint i = start; i < end; i++) {
+    prefix[i] = acc;
+    if(!FIELD_eq(a[i], FIELD_ZERO)) acc = FIELD_mul(acc, a[i]);
+  }
+  totals[gid] = acc;
+}
+
+/// Second half of the Montgomery batch inversion, once `totals` have been inverted: replaces
+/// every non-zero a[i] with its inverse.
+__kernel void poly_batch_invert_finish(__global FIELD* a,
+                        __global FIELD* prefix,
+                        __global FIELD* totals,
+                        uint n,
+                        uint chunk) {
+  const uint gid = get_global_id(0);
+  const uint start = gid * chunk;
+  if(start >= n) return;
+  const uint end = min(start + chunk, n);
+  FIELD inv = totals[gid];
+  for(uint i = end; i > start; i--) {
+    const FIELD x = a[i - 1];
+    if(!FIELD_eq(x, FIELD_ZERO)) {
+      a[i - 1] = FIELD_mul(inv, prefix[i - 1]);
+      inv = FIELD_mul(inv, x);
+    }
+  }
+}
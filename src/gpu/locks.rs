@@ -1,129 +1,346 @@
+use super::metrics::{timed, Stage};
+use super::utils;
+use super::Priority;
 use fs2::FileExt;
 use log::{debug, info, warn};
-use std::fs::File;
+use ocl::Device;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex, Weak};
+use tracing::info_span;
 
-const GPU_LOCK_NAME: &str = "bellman.gpu.lock";
-const PRIORITY_LOCK_NAME: &str = "bellman.priority.lock";
+const GPU_LOCK_NAME: &str = "bellman.gpu";
+const PRIORITY_LOCK_NAME: &str = "bellman.priority";
+const QUEUE_NAME: &str = "bellman.queue";
 fn tmp_path(filename: &str) -> PathBuf {
     let mut p = std::env::temp_dir();
     p.push(filename);
     p
 }
 
-/// `GPULock` prevents two kernel objects to be instantiated simultaneously.
+/// Whether the kernels of this process share the GPU locks (See `GPULock`), set by
+/// `BELLMAN_SHARE_GPU`.
+pub fn is_gpu_sharing_enabled() -> bool {
+    std::env::var("BELLMAN_SHARE_GPU").is_ok()
+}
+
+lazy_static::lazy_static! {
+    // The lock files held by this process, per device, when the GPU locks are shared
+    static ref SHARED_LOCKS: Mutex<HashMap<String, Arc<Mutex<Weak<File>>>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// `GPULock` prevents two kernel objects to be instantiated simultaneously on the same device.
+/// There is one lock file per device, keyed by the PCI bus id of the device (Or its name, when
+/// the bus id is not available), so that different GPUs can be used concurrently.
+///
+/// If `BELLMAN_SHARE_GPU` is set, the kernels of a single process share the lock of a device
+/// (E.g. an FFT kernel and a multiexp kernel may then run on the same GPU at the same time, each
+/// one on its own command queue), while other processes are still locked out.
 #[derive(Debug)]
-pub struct GPULock(File);
+pub struct GPULock(Arc<File>, String);
 impl GPULock {
-    pub fn lock() -> GPULock {
-        debug!("Acquiring GPU lock...");
-        let f = File::create(tmp_path(GPU_LOCK_NAME)).unwrap();
-        f.lock_exclusive().unwrap();
-        debug!("GPU lock acquired!");
-        GPULock(f)
+    pub fn lock(device: &Device) -> GPULock {
+        let id = utils::get_device_lock_id(*device);
+        if !is_gpu_sharing_enabled() {
+            return GPULock(Arc::new(Self::lock_file(&id)), id);
+        }
+
+        let entry = SHARED_LOCKS
+            .lock()
+            .unwrap()
+            .entry(id.clone())
+            .or_insert_with(|| Arc::new(Mutex::new(Weak::new())))
+            .clone();
+        // Kept locked while acquiring the file, so that other threads locking the same device
+        // share the result instead of waiting for the lock of this process.
+        let mut shared = entry.lock().unwrap();
+        if let Some(f) = shared.upgrade() {
+            debug!("GPU lock of {} shared!", id);
+            return GPULock(f, id);
+        }
+        let f = Arc::new(Self::lock_file(&id));
+        *shared = Arc::downgrade(&f);
+        GPULock(f, id)
+    }
+
+    fn lock_file(id: &str) -> File {
+        let span = info_span!("lock_wait", device = %id);
+        let _enter = span.enter();
+        debug!("Acquiring GPU lock of {}...", id);
+        let f = File::create(tmp_path(&format!("{}.{}.lock", GPU_LOCK_NAME, id))).unwrap();
+        timed(Stage::LockWait, 0, || f.lock_exclusive().unwrap());
+        debug!("GPU lock of {} acquired!", id);
+        f
+    }
+
+    /// Locks all of `devices`, always in the same order so that processes locking overlapping
+    /// sets of devices can't deadlock.
+    pub fn lock_all(devices: &[Device]) -> Vec<GPULock> {
+        let mut devices = devices
+            .iter()
+            .map(|d| (utils::get_device_lock_id(*d), d))
+            .collect::<Vec<_>>();
+        devices.sort_by(|a, b| a.0.cmp(&b.0));
+        devices.dedup_by(|a, b| a.0 == b.0);
+        devices.into_iter().map(|(_, d)| GPULock::lock(d)).collect()
+    }
+
+    /// The id of the device this lock holds
+    pub fn device(&self) -> &str {
+        &self.1
     }
 }
 impl Drop for GPULock {
     fn drop(&mut self) {
-        debug!("GPU lock released!");
+        // A shared lock file is only unlocked once its last `GPULock` is dropped
+        if Arc::strong_count(&self.0) == 1 {
+            debug!("GPU lock of {} released!", self.1);
+        }
     }
 }
 
-/// `PrioriyLock` is like a flag. When acquired, it means a high-priority process
-/// needs to acquire the GPU really soon. Acquiring the `PriorityLock` is like
-/// signaling all other processes to release their `GPULock`s.
-/// Only one process can have the `PriorityLock` at a time.
+/// `PrioriyLock` is like a flag. When acquired, it means a process of the given priority
+/// needs to acquire the GPU really soon. Acquiring the `PriorityLock` is like signaling all
+/// other processes of a lower priority to release their `GPULock`s.
+/// There is one lock file per priority level, and only one process can have the
+/// `PriorityLock` of a given level at a time.
 #[derive(Debug)]
-pub struct PriorityLock(File);
+pub struct PriorityLock(File, Priority);
 impl PriorityLock {
-    pub fn lock() -> PriorityLock {
-        debug!("Acquiring priority lock...");
-        let f = File::create(tmp_path(PRIORITY_LOCK_NAME)).unwrap();
-        f.lock_exclusive().unwrap();
-        debug!("Priority lock acquired!");
-        PriorityLock(f)
+    pub fn lock(priority: Priority) -> PriorityLock {
+        debug!("Acquiring {} priority lock...", priority);
+        let f = File::create(priority_path(priority)).unwrap();
+        timed(Stage::LockWait, 0, || f.lock_exclusive().unwrap());
+        debug!("{} priority lock acquired!", priority);
+        PriorityLock(f, priority)
     }
-    pub fn wait(priority: bool) {
-        if !priority {
-            File::create(tmp_path(PRIORITY_LOCK_NAME))
-                .unwrap()
-                .lock_exclusive()
-                .unwrap();
-        }
+    /// Blocks until no process of a higher priority than `priority` needs the GPU
+    pub fn wait(priority: Priority) {
+        let span = info_span!("lock_wait", priority = %priority);
+        let _enter = span.enter();
+        timed(Stage::LockWait, 0, || {
+            for p in priority.higher() {
+                File::create(priority_path(p))
+                    .unwrap()
+                    .lock_exclusive()
+                    .unwrap();
+            }
+        })
     }
-    pub fn should_break(priority: bool) -> bool {
-        !priority
-            && File::create(tmp_path(PRIORITY_LOCK_NAME))
+    /// Returns true if a process of a higher priority than `priority` needs the GPU
+    pub fn should_break(priority: Priority) -> bool {
+        priority.higher().any(|p| {
+            File::create(priority_path(p))
                 .unwrap()
                 .try_lock_exclusive()
                 .is_err()
+        })
     }
 }
 impl Drop for PriorityLock {
     fn drop(&mut self) {
-        debug!("Priority lock released!");
+        debug!("{} priority lock released!", self.1);
+    }
+}
+
+fn priority_path(priority: Priority) -> PathBuf {
+    tmp_path(&format!("{}.{}.lock", PRIORITY_LOCK_NAME, priority))
+}
+
+fn ticket_path(priority: Priority, ticket: u64) -> PathBuf {
+    tmp_path(&format!("{}.{}.{}.lock", QUEUE_NAME, priority, ticket))
+}
+
+/// `QueueTicket` is a place in the FIFO queue of the processes of a given priority waiting for
+/// the GPU (e.g. after being evicted by a higher priority process), so that they get it back in
+/// the order they asked for it, instead of whichever process the OS wakes up first. There is
+/// one queue per priority level.
+///
+/// Each ticket is a lock file which is held while its owner is waiting, and the owner of the
+/// next ticket waits for it to be released. The lock is released by the OS if the process
+/// dies, so a crashed process can't block the queue.
+#[derive(Debug)]
+pub struct QueueTicket(File, Priority, u64);
+impl QueueTicket {
+    /// Joins the end of the queue of `priority` and blocks until every process that joined
+    /// before has left.
+    pub fn join(priority: Priority) -> QueueTicket {
+        let (f, ticket) = {
+            let mut counter = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .open(tmp_path(&format!("{}.{}.lock", QUEUE_NAME, priority)))
+                .unwrap();
+            counter.lock_exclusive().unwrap();
+            let mut last = String::new();
+            counter.read_to_string(&mut last).unwrap();
+            let ticket = last.trim().parse::<u64>().unwrap_or(0) + 1;
+
+            // The ticket has to be locked before it's published, so that the next process
+            // can't get past it.
+            let f = File::create(ticket_path(priority, ticket)).unwrap();
+            f.lock_exclusive().unwrap();
+
+            counter.set_len(0).unwrap();
+            counter.seek(SeekFrom::Start(0)).unwrap();
+            write!(counter, "{}", ticket).unwrap();
+            (f, ticket)
+        };
+
+        let span = info_span!("lock_wait", priority = %priority, ticket);
+        let _enter = span.enter();
+        debug!(
+            "Waiting in the {} priority GPU queue with ticket {}...",
+            priority, ticket
+        );
+        let prev = ticket_path(priority, ticket - 1);
+        if let Ok(p) = OpenOptions::new().write(true).open(&prev) {
+            timed(Stage::LockWait, 0, || p.lock_exclusive().unwrap());
+            // Nobody else is waiting for the previous ticket
+            let _ = fs::remove_file(&prev);
+        }
+        debug!(
+            "Ticket {} is at the head of the {} priority GPU queue!",
+            ticket, priority
+        );
+        QueueTicket(f, priority, ticket)
+    }
+}
+impl Drop for QueueTicket {
+    fn drop(&mut self) {
+        debug!("Ticket {} left the {} priority GPU queue!", self.2, self.1);
     }
 }
 
 use super::error::{GPUError, GPUResult};
 use super::fft::FFTKernel;
 use super::multiexp::MultiexpKernel;
+use crate::cancellation::CancellationToken;
 use crate::domain::create_fft_kernel;
 use crate::multiexp::create_multiexp_kernel;
 use paired::Engine;
 
+// Number of times a kernel is re-created on the remaining devices after losing a GPU, within a
+// single `with` call
+const MAX_DEVICE_LOST_RETRIES: usize = 2;
+
 macro_rules! locked_kernel {
-    ($class:ident, $kern:ident, $func:ident, $name:expr) => {
+    ($class:ident, $kern:ty, $func:ident, $name:expr) => {
         pub struct $class<E>
         where
             E: Engine,
         {
             log_d: usize,
-            priority: bool,
-            kernel: Option<$kern<E>>,
+            priority: Priority,
+            cancel: Option<CancellationToken>,
+            kernel: Option<$kern>,
         }
 
         impl<E> $class<E>
         where
             E: Engine,
         {
-            pub fn new(log_d: usize, priority: bool) -> $class<E> {
+            pub fn new(log_d: usize, priority: Priority) -> $class<E> {
                 $class::<E> {
                     log_d,
                     priority,
+                    cancel: None,
                     kernel: None,
                 }
             }
 
+            /// Cancels the computations run with `with` once `cancel` is cancelled. The kernel
+            /// is then freed, releasing its buffers and the GPU lock.
+            pub fn set_cancellation(&mut self, cancel: Option<CancellationToken>) {
+                if let Some(ref mut k) = self.kernel {
+                    k.set_cancellation(cancel.clone());
+                }
+                self.cancel = cancel;
+            }
+
             fn init(&mut self) {
                 if self.kernel.is_none() {
+                    // Processes that can be evicted re-acquire the GPU in the order they asked
+                    // for it. The ticket is kept until the kernel is created, so that the next
+                    // process in the queue waits for the GPU lock after this one.
+                    let _ticket = if self.priority == Priority::High {
+                        None
+                    } else {
+                        Some(QueueTicket::join(self.priority))
+                    };
                     PriorityLock::wait(self.priority);
                     info!("GPU is available for {}!", $name);
                     self.kernel = $func::<E>(self.log_d, self.priority);
+                    if let Some(ref mut k) = self.kernel {
+                        k.set_cancellation(self.cancel.clone());
+                    }
                 }
             }
 
             fn free(&mut self) {
                 if let Some(_kernel) = self.kernel.take() {
                     warn!(
-                        "GPU acquired by a high priority process! Freeing up {} kernels...",
+                        "GPU acquired by a higher priority process! Freeing up {} kernels...",
                         $name
                     );
                 }
             }
 
+            /// Runs `f` with the kernel. If the GPU is taken by a high priority process in the
+            /// meantime, the kernel is freed, re-created once the GPU is released, and `f` is
+            /// called again, so `f` should keep track of its progress outside of the closure
+            /// in order to resume from the last completed step instead of restarting.
+            ///
+            /// If the GPU times out, the kernel is freed (Releasing the GPU lock) and is only
+            /// re-created and retried once if `utils::is_reinit_on_timeout` is set.
+            ///
+            /// If a GPU is lost (Unplugged or reset by the driver), the kernel is re-created on
+            /// the devices that are still available and `f` is resumed there. Callers fall back
+            /// to the CPU if no device is left.
             pub fn with<F, R>(&mut self, mut f: F) -> GPUResult<R>
             where
-                F: FnMut(&mut $kern<E>) -> GPUResult<R>,
+                F: FnMut(&mut $kern) -> GPUResult<R>,
             {
                 self.init();
 
+                let mut timed_out = false;
+                let mut device_lost_retries = 0;
                 loop {
                     if let Some(ref mut k) = self.kernel {
                         match f(k) {
                             Err(GPUError::GPUTaken) => {
                                 self.free();
                                 self.init();
+                                info!("Resuming {}...", $name);
+                            }
+                            Err(GPUError::Timeout) => {
+                                warn!("GPU {} timed out! Freeing up the kernels...", $name);
+                                self.kernel.take();
+                                if timed_out || !utils::is_reinit_on_timeout() {
+                                    return Err(GPUError::Timeout);
+                                }
+                                timed_out = true;
+                                self.init();
+                                info!("Retrying {} on a re-initialized GPU...", $name);
+                            }
+                            Err(GPUError::Cancelled) => {
+                                info!("{} cancelled! Freeing up the kernels...", $name);
+                                self.kernel.take();
+                                return Err(GPUError::Cancelled);
+                            }
+                            Err(e @ GPUError::DeviceLost(_)) => {
+                                warn!("GPU lost while running {}! Error: {}", $name, e);
+                                self.kernel.take();
+                                if device_lost_retries == MAX_DEVICE_LOST_RETRIES {
+                                    return Err(e);
+                                }
+                                device_lost_retries += 1;
+                                self.init();
+                                info!("Resuming {} on the remaining GPUs...", $name);
                             }
                             Err(e) => {
                                 warn!("GPU {} failed! Falling back to CPU... Error: {}", $name, e);
@@ -140,10 +357,10 @@ macro_rules! locked_kernel {
     };
 }
 
-locked_kernel!(LockedFFTKernel, FFTKernel, create_fft_kernel, "FFT");
+locked_kernel!(LockedFFTKernel, FFTKernel<E::Fr>, create_fft_kernel, "FFT");
 locked_kernel!(
     LockedMultiexpKernel,
-    MultiexpKernel,
+    MultiexpKernel<E>,
     create_multiexp_kernel,
     "Multiexp"
 );
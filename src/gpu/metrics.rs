@@ -0,0 +1,198 @@
+use std::cell::Cell;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// The GPU stages reported to a `MetricsSink`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stage {
+    /// Building (Or loading from the cache) the OpenCL program of a kernel
+    KernelCompile,
+    /// Copying inputs from the host to a GPU
+    Upload,
+    /// A single radix round of an FFT
+    FFTRound,
+    /// A chunk of a multiexp, from the kernel launch to its accumulated result
+    MultiexpChunk,
+    /// Copying results from a GPU to the host
+    Download,
+    /// Waiting for the lock of a GPU or for processes of a higher priority to finish
+    LockWait,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 6] = [
+        Stage::KernelCompile,
+        Stage::Upload,
+        Stage::FFTRound,
+        Stage::MultiexpChunk,
+        Stage::Download,
+        Stage::LockWait,
+    ];
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stage::KernelCompile => write!(f, "kernel_compile"),
+            Stage::Upload => write!(f, "upload"),
+            Stage::FFTRound => write!(f, "fft_round"),
+            Stage::MultiexpChunk => write!(f, "multiexp_chunk"),
+            Stage::Download => write!(f, "download"),
+            Stage::LockWait => write!(f, "lock_wait"),
+        }
+    }
+}
+
+/// Receives the duration of every GPU stage, along with the bytes transferred between host and
+/// device for the `Upload` and `Download` stages (Zero for the other ones).
+///
+/// NOTE: While a sink is registered, FFT rounds wait for the GPU after each round in order to
+/// time them, which makes the FFTs slightly slower.
+pub trait MetricsSink: Send + Sync {
+    fn record(&self, stage: Stage, elapsed: Duration, bytes: u64);
+}
+
+thread_local! {
+    // Time spent by the current thread in the `LockWait` stage, recorded even without a sink
+    static LOCK_WAIT: Cell<Duration> = Cell::new(Duration::from_secs(0));
+}
+
+/// Total time the current thread has spent waiting for the GPU locks.
+pub(crate) fn lock_wait_time() -> Duration {
+    LOCK_WAIT.with(|wait| wait.get())
+}
+
+lazy_static::lazy_static! {
+    static ref METRICS_SINK: RwLock<Option<Arc<dyn MetricsSink>>> = RwLock::new(None);
+}
+
+/// Registers `sink` for all of the GPU stages of this process, replacing the previous one.
+pub fn set_metrics_sink(sink: Arc<dyn MetricsSink>) {
+    *METRICS_SINK.write().unwrap() = Some(sink);
+}
+
+/// Unregisters the sink set with `set_metrics_sink`.
+pub fn clear_metrics_sink() {
+    *METRICS_SINK.write().unwrap() = None;
+}
+
+#[cfg(feature = "gpu")]
+pub(crate) fn is_metrics_enabled() -> bool {
+    METRICS_SINK.read().unwrap().is_some()
+}
+
+#[cfg(feature = "gpu")]
+pub(crate) fn record(stage: Stage, elapsed: Duration, bytes: u64) {
+    if let Some(ref sink) = *METRICS_SINK.read().unwrap() {
+        sink.record(stage, elapsed, bytes);
+    }
+}
+
+/// Runs `f` and records its duration as `stage`.
+#[cfg(feature = "gpu")]
+pub(crate) fn timed<T, F: FnOnce() -> T>(stage: Stage, bytes: u64, f: F) -> T {
+    if !is_metrics_enabled() && stage != Stage::LockWait {
+        return f();
+    }
+    let now = std::time::Instant::now();
+    let res = f();
+    let elapsed = now.elapsed();
+    if stage == Stage::LockWait {
+        LOCK_WAIT.with(|wait| wait.set(wait.get() + elapsed));
+    }
+    record(stage, elapsed, bytes);
+    res
+}
+
+/// A `MetricsSink` accumulating the number of runs, the total duration and the bytes of each
+/// stage, which can be exported in the Prometheus text format.
+#[cfg(feature = "metrics")]
+#[derive(Debug, Default)]
+pub struct PrometheusCounters {
+    counters: std::sync::Mutex<std::collections::HashMap<Stage, (u64, Duration, u64)>>,
+}
+
+#[cfg(feature = "metrics")]
+impl PrometheusCounters {
+    pub fn new() -> PrometheusCounters {
+        Self::default()
+    }
+
+    /// Number of runs, total duration and bytes transferred of `stage` so far
+    pub fn get(&self, stage: Stage) -> (u64, Duration, u64) {
+        self.counters
+            .lock()
+            .unwrap()
+            .get(&stage)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// The counters in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        out.push_str("# TYPE bellman_gpu_stage_total counter\n");
+        for stage in Stage::ALL.iter() {
+            out.push_str(&format!(
+                "bellman_gpu_stage_total{{stage=\"{}\"}} {}\n",
+                stage,
+                self.get(*stage).0
+            ));
+        }
+        out.push_str("# TYPE bellman_gpu_stage_seconds_total counter\n");
+        for stage in Stage::ALL.iter() {
+            out.push_str(&format!(
+                "bellman_gpu_stage_seconds_total{{stage=\"{}\"}} {}\n",
+                stage,
+                self.get(*stage).1.as_secs_f64()
+            ));
+        }
+        out.push_str("# TYPE bellman_gpu_stage_bytes_total counter\n");
+        for stage in [Stage::Upload, Stage::Download].iter() {
+            out.push_str(&format!(
+                "bellman_gpu_stage_bytes_total{{stage=\"{}\"}} {}\n",
+                stage,
+                self.get(*stage).2
+            ));
+        }
+        out
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl MetricsSink for PrometheusCounters {
+    fn record(&self, stage: Stage, elapsed: Duration, bytes: u64) {
+        let mut counters = self.counters.lock().unwrap();
+        let c = counters
+            .entry(stage)
+            .or_insert((0, Duration::from_secs(0), 0));
+        c.0 += 1;
+        c.1 += elapsed;
+        c.2 += bytes;
+    }
+}
+
+#[cfg(feature = "metrics")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prometheus_counters() {
+        let counters = PrometheusCounters::new();
+        counters.record(Stage::Upload, Duration::from_millis(500), 1024);
+        counters.record(Stage::Upload, Duration::from_millis(250), 1024);
+        counters.record(Stage::FFTRound, Duration::from_millis(10), 0);
+
+        assert_eq!(
+            counters.get(Stage::Upload),
+            (2, Duration::from_millis(750), 2048)
+        );
+        let text = counters.render();
+        assert!(text.contains("bellman_gpu_stage_total{stage=\"upload\"} 2\n"));
+        assert!(text.contains("bellman_gpu_stage_seconds_total{stage=\"upload\"} 0.75\n"));
+        assert!(text.contains("bellman_gpu_stage_bytes_total{stage=\"upload\"} 2048\n"));
+        assert!(text.contains("bellman_gpu_stage_total{stage=\"multiexp_chunk\"} 0\n"));
+    }
+}
@@ -2,6 +2,14 @@ mod error;
 
 pub use self::error::*;
 
+mod priority;
+
+pub use self::priority::*;
+
+mod metrics;
+
+pub use self::metrics::*;
+
 #[cfg(feature = "gpu")]
 mod locks;
 
@@ -26,18 +34,68 @@ mod structs;
 #[cfg(feature = "gpu")]
 pub use self::structs::*;
 
+#[cfg(feature = "gpu")]
+mod watchdog;
+
+#[cfg(feature = "gpu")]
+mod paranoid;
+
+#[cfg(feature = "gpu")]
+pub use self::paranoid::{is_paranoid, set_paranoid};
+
+#[cfg(feature = "gpu")]
+mod pinned;
+
+#[cfg(feature = "gpu")]
+mod program;
+
+#[cfg(feature = "gpu")]
+pub use self::program::*;
+
+#[cfg(feature = "gpu")]
+mod autotune;
+
+#[cfg(feature = "gpu")]
+pub use self::autotune::*;
+
 #[cfg(feature = "gpu")]
 mod fft;
 
 #[cfg(feature = "gpu")]
 pub use self::fft::*;
 
+#[cfg(feature = "gpu")]
+mod transpose;
+
+#[cfg(feature = "gpu")]
+pub use self::transpose::*;
+
 #[cfg(feature = "gpu")]
 mod multiexp;
 
 #[cfg(feature = "gpu")]
 pub use self::multiexp::*;
 
+#[cfg(feature = "gpu")]
+mod poseidon;
+
+#[cfg(feature = "gpu")]
+pub use self::poseidon::*;
+
+#[cfg(feature = "gpu")]
+mod scheduler;
+
+#[cfg(feature = "gpu")]
+pub use self::scheduler::*;
+
+#[cfg(feature = "gpu")]
+mod selftest;
+
+#[cfg(feature = "gpu")]
+pub use self::selftest::*;
+
+pub mod ops;
+
 #[cfg(not(feature = "gpu"))]
 mod nogpu;
 
@@ -1,27 +1,36 @@
 use super::error::{GPUError, GPUResult};
 use super::locks;
+use super::metrics::{self, Stage};
+use super::paranoid;
+use super::pinned::PinnedBuffer;
 use super::sources;
-use super::structs;
+use super::structs::{self, GpuRepr};
 use super::utils;
-use crate::gpu::{get_devices, get_platform};
+use super::watchdog;
+use crate::cancellation::CancellationToken;
+use crate::gpu::{build_proque, get_assigned_devices, KernelKind, Priority};
 use crate::multicore::Worker;
 use crate::multiexp::{multiexp as cpu_multiexp, FullDensity};
 use crossbeam::thread;
 use ff::{PrimeField, ScalarEngine};
 use futures::Future;
 use groupy::{CurveAffine, CurveProjective};
-use log::debug;
 use log::{error, info};
-use ocl::{Buffer, Device, MemFlags, ProQue};
+use ocl::traits::OclPrm;
+use ocl::{Buffer, Device, MemFlags, ProQue, Queue, SpatialDims};
 use paired::Engine;
+use rand::Rng;
 use std::sync::Arc;
-
-// NOTE: Please read `structs.rs` for an explanation for unsafe transmutes of this code!
+use std::time::Instant;
+use tracing::{info_span, Span};
 
 const MAX_WINDOW_SIZE: usize = 10;
 const LOCAL_WORK_SIZE: usize = 256;
 const MEMORY_PADDING: f64 = 0.2f64; // Let 20% of GPU memory be free
 
+// Elements of the random window recomputed on the CPU in paranoid mode
+const SPOT_CHECK_SIZE: usize = 1 << 10;
+
 pub fn get_cpu_utilization() -> f64 {
     use std::env;
     env::var("BELLMAN_CPU_UTILIZATION")
@@ -43,21 +52,45 @@ where
     E: Engine,
 {
     proque: ProQue,
+    // Used to upload the next chunk of a streamed multiexp while the kernel is running
+    transfer_queue: Queue,
 
-    g1_base_buffer: Buffer<structs::CurveAffineStruct<E::G1Affine>>,
+    // Bases and exponents are double-buffered, see `multiexp_streamed`
+    g1_base_buffers: [Buffer<structs::CurveAffineStruct<E::G1Affine>>; 2],
     g1_bucket_buffer: Buffer<structs::CurveProjectiveStruct<E::G1>>,
     g1_result_buffer: Buffer<structs::CurveProjectiveStruct<E::G1>>,
 
-    g2_base_buffer: Buffer<structs::CurveAffineStruct<E::G2Affine>>,
+    g2_base_buffers: [Buffer<structs::CurveAffineStruct<E::G2Affine>>; 2],
     g2_bucket_buffer: Buffer<structs::CurveProjectiveStruct<E::G2>>,
     g2_result_buffer: Buffer<structs::CurveProjectiveStruct<E::G2>>,
 
-    exp_buffer: Buffer<structs::PrimeFieldStruct<E::Fr>>,
+    exp_buffers: [Buffer<structs::PrimeFieldReprStruct<<E::Fr as PrimeField>::Repr>>; 2],
+    // Negative windows of the exponents of the current chunk, see `signed_windows`
+    flag_buffer: Option<Buffer<u32>>,
+
+    // Host memory the bases and exponents of each slot are copied through
+    g1_base_staging: [PinnedBuffer<structs::CurveAffineStruct<E::G1Affine>>; 2],
+    g2_base_staging: [PinnedBuffer<structs::CurveAffineStruct<E::G2Affine>>; 2],
+    exp_staging: [PinnedBuffer<structs::PrimeFieldReprStruct<<E::Fr as PrimeField>::Repr>>; 2],
 
     core_count: usize,
     n: usize,
+    // Set by the GPU config, otherwise the work-group size is chosen by the driver
+    work_group_size: Option<usize>,
+    // Set by the GPU config, otherwise chosen by `calc_window_size`
+    window_size: Option<usize>,
+
+    priority: Priority,
+    // Checked before each chunk, see `MultiexpKernel::set_cancellation`
+    cancel: Option<CancellationToken>,
+}
 
-    priority: bool,
+/// Parameters of a multiexp kernel run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct MultiexpParams {
+    window_size: usize,
+    num_windows: usize,
+    num_groups: usize,
 }
 
 fn calc_num_groups(core_count: usize, num_windows: usize) -> usize {
@@ -84,6 +117,26 @@ fn calc_window_size(n: usize, exp_bits: usize, core_count: usize) -> usize {
     MAX_WINDOW_SIZE
 }
 
+fn calc_params(
+    n: usize,
+    exp_bits: usize,
+    core_count: usize,
+    window_size: Option<usize>,
+) -> MultiexpParams {
+    let window_size = window_size
+        .unwrap_or_else(|| calc_window_size(n, exp_bits, core_count))
+        .max(1)
+        .min(MAX_WINDOW_SIZE);
+    let num_windows = ((exp_bits as f64) / (window_size as f64)).ceil() as usize;
+    // With small inputs, threads beyond one per base would have nothing to do
+    let num_groups = calc_num_groups(core_count, num_windows).min(n).max(1);
+    MultiexpParams {
+        window_size,
+        num_windows,
+        num_groups,
+    }
+}
+
 fn calc_best_chunk_size(max_window_size: usize, core_count: usize, exp_bits: usize) -> usize {
     // Best chunk-size (N) can also be calculated using the same logic as calc_window_size:
     // n = e^window_size * window_size * 2 * core_count / exp_bits
@@ -95,103 +148,119 @@ fn calc_best_chunk_size(max_window_size: usize, core_count: usize, exp_bits: usi
         .ceil() as usize
 }
 
-fn calc_chunk_size<E>(mem: u64, core_count: usize) -> usize
+// Device memory of the buckets and results of both groups, which doesn't depend on the chunk size
+fn fixed_memory<E>(core_count: usize) -> usize
+where
+    E: Engine,
+{
+    let proj_size = std::mem::size_of::<E::G1>() + std::mem::size_of::<E::G2>();
+    2 * core_count * ((1 << MAX_WINDOW_SIZE) + 1) * proj_size
+}
+
+// Device memory of a single element of a chunk. Bases and exponents are double-buffered, and the
+// flags of the signed windows take as many bits as an exponent.
+fn element_memory<E>() -> usize
 where
     E: Engine,
 {
     let aff_size = std::mem::size_of::<E::G1Affine>() + std::mem::size_of::<E::G2Affine>();
     let exp_size = std::mem::size_of::<E::Fr>();
-    let proj_size = std::mem::size_of::<E::G1>() + std::mem::size_of::<E::G2>();
-    ((((mem as f64) * (1f64 - MEMORY_PADDING)) as usize)
-        - (2 * core_count * ((1 << MAX_WINDOW_SIZE) + 1) * proj_size))
-        / (aff_size + exp_size)
+    2 * (aff_size + exp_size) + exp_size
+}
+
+// Exponents are recoded into signed windows on the GPU (See `multiexp.cl`), which halves the
+// number of buckets. This requires the most significant bit of the exponents to be zero.
+fn signed_windows<E>() -> bool
+where
+    E: Engine,
+{
+    (<E::Fr as PrimeField>::NUM_BITS as usize) < std::mem::size_of::<E::Fr>() * 8
+}
+
+/// Device memory (In bytes) needed by a `SingleMultiexpKernel` processing chunks of `n` bases on
+/// a device with `core_count` cores (See `get_core_count`), including the share of memory which
+/// is left free. Inputs larger than a chunk are streamed, so this is what callers need to plan
+/// for, regardless of the total number of bases.
+pub fn multiexp_required_memory<E>(n: usize, core_count: usize) -> u64
+where
+    E: Engine,
+{
+    let used = fixed_memory::<E>(core_count) + n * element_memory::<E>();
+    ((used as f64) / (1f64 - MEMORY_PADDING)).ceil() as u64
+}
+
+/// Largest chunk of bases a `SingleMultiexpKernel` can process at once with `mem` bytes of device
+/// memory (E.g. from `get_memory_limit`) and `core_count` cores, `None` if not even a single
+/// base fits.
+pub fn max_multiexp_chunk_size<E>(mem: u64, core_count: usize) -> Option<usize>
+where
+    E: Engine,
+{
+    let usable = ((mem as f64) * (1f64 - MEMORY_PADDING)) as usize;
+    match usable.checked_sub(fixed_memory::<E>(core_count)) {
+        Some(free) if free >= element_memory::<E>() => Some(free / element_memory::<E>()),
+        _ => None,
+    }
+}
+
+fn create_buffer<T>(pq: &ProQue, len: usize) -> GPUResult<Buffer<T>>
+where
+    T: OclPrm,
+{
+    Ok(Buffer::builder()
+        .queue(pq.queue().clone())
+        .flags(MemFlags::new().read_write())
+        .len(len)
+        .build()?)
 }
 
 impl<E> SingleMultiexpKernel<E>
 where
     E: Engine,
 {
-    pub fn create(d: Device, priority: bool) -> GPUResult<SingleMultiexpKernel<E>> {
+    pub fn create(d: Device, priority: Priority) -> GPUResult<SingleMultiexpKernel<E>> {
         let src = sources::kernel::<E>();
 
-        let platform = match d.info(ocl::enums::DeviceInfo::Platform)? {
-            ocl::enums::DeviceInfoResult::Platform(p) => ocl::Platform::new(p),
-            _ => ocl::Platform::default(),
-        };
-
         let exp_bits = std::mem::size_of::<E::Fr>() * 8;
         let core_count = utils::get_core_count(d)?;
-        let mem = utils::get_memory(d)?;
-        let max_n = calc_chunk_size::<E>(mem, core_count);
+        let mem = utils::get_memory_limit(d)?;
+        let max_n = max_multiexp_chunk_size::<E>(mem, core_count)
+            .ok_or(GPUError::Simple("Not enough GPU memory for multiexp!"))?;
         let best_n = calc_best_chunk_size(MAX_WINDOW_SIZE, core_count, exp_bits);
         let n = std::cmp::min(max_n, best_n);
         let max_bucket_len = 1 << MAX_WINDOW_SIZE;
 
-        let pq = ProQue::builder()
-            .platform(platform)
-            .device(d)
-            .src(src)
-            .dims(1)
-            .build()
-            .map_err(|err| {
-                debug!("{:?}", err);
-                err
-            })?;
+        let pq = build_proque(d, &src, 1)?;
+        let transfer_queue = Queue::new(pq.context(), d, None)?;
 
         // Each group will have `num_windows` threads and as there are `num_groups` groups, there will
         // be `num_groups` * `num_windows` threads in total.
         // Each thread will use `num_groups` * `num_windows` * `bucket_len` buckets.
 
-        let g1basebuff = Buffer::builder()
-            .queue(pq.queue().clone())
-            .flags(MemFlags::new().read_write())
-            .len(n)
-            .build()?;
-        let g1buckbuff = Buffer::builder()
-            .queue(pq.queue().clone())
-            .flags(MemFlags::new().read_write())
-            .len(2 * core_count * max_bucket_len)
-            .build()?;
-        let g1resbuff = Buffer::builder()
-            .queue(pq.queue().clone())
-            .flags(MemFlags::new().read_write())
-            .len(2 * core_count)
-            .build()?;
-
-        let g2basebuff = Buffer::builder()
-            .queue(pq.queue().clone())
-            .flags(MemFlags::new().read_write())
-            .len(n)
-            .build()?;
-        let g2buckbuff = Buffer::builder()
-            .queue(pq.queue().clone())
-            .flags(MemFlags::new().read_write())
-            .len(2 * core_count * max_bucket_len)
-            .build()?;
-        let g2resbuff = Buffer::builder()
-            .queue(pq.queue().clone())
-            .flags(MemFlags::new().read_write())
-            .len(2 * core_count)
-            .build()?;
-
-        let expbuff = Buffer::builder()
-            .queue(pq.queue().clone())
-            .flags(MemFlags::new().read_write())
-            .len(n)
-            .build()?;
-
         Ok(SingleMultiexpKernel {
+            g1_base_buffers: [create_buffer(&pq, n)?, create_buffer(&pq, n)?],
+            g1_bucket_buffer: create_buffer(&pq, 2 * core_count * max_bucket_len)?,
+            g1_result_buffer: create_buffer(&pq, 2 * core_count)?,
+            g2_base_buffers: [create_buffer(&pq, n)?, create_buffer(&pq, n)?],
+            g2_bucket_buffer: create_buffer(&pq, 2 * core_count * max_bucket_len)?,
+            g2_result_buffer: create_buffer(&pq, 2 * core_count)?,
+            exp_buffers: [create_buffer(&pq, n)?, create_buffer(&pq, n)?],
+            flag_buffer: if signed_windows::<E>() {
+                Some(create_buffer(&pq, n * exp_bits / 32)?)
+            } else {
+                None
+            },
+            g1_base_staging: [PinnedBuffer::new(), PinnedBuffer::new()],
+            g2_base_staging: [PinnedBuffer::new(), PinnedBuffer::new()],
+            exp_staging: [PinnedBuffer::new(), PinnedBuffer::new()],
             proque: pq,
-            g1_base_buffer: g1basebuff,
-            g1_bucket_buffer: g1buckbuff,
-            g1_result_buffer: g1resbuff,
-            g2_base_buffer: g2basebuff,
-            g2_bucket_buffer: g2buckbuff,
-            g2_result_buffer: g2resbuff,
-            exp_buffer: expbuff,
+            transfer_queue,
             core_count,
             n,
+            work_group_size: utils::get_work_group_size(d),
+            window_size: utils::get_multiexp_window_size(d),
             priority,
+            cancel: None,
         })
     }
 
@@ -204,82 +273,241 @@ where
     where
         G: CurveAffine,
     {
+        let mut progress = (0, <G as CurveAffine>::Projective::zero());
+        self.multiexp_streamed(&bases[..n], &exps[..n], &mut progress)?;
+        Ok(progress.1)
+    }
+
+    // Runs the multiexp of a random window of `bases` both on the GPU and on the CPU, for the
+    // paranoid mode.
+    fn spot_check<G>(
+        &mut self,
+        bases: &[G],
+        exps: &[<<G::Engine as ScalarEngine>::Fr as PrimeField>::Repr],
+    ) -> GPUResult<()>
+    where
+        G: CurveAffine,
+    {
+        let len = SPOT_CHECK_SIZE.min(bases.len());
+        if len == 0 {
+            return Ok(());
+        }
+        let start = rand::thread_rng().gen_range(0, bases.len() - len + 1);
+        let bases = &bases[start..start + len];
+        let exps = &exps[start..start + len];
+
+        let gpu = self.multiexp(bases, exps, len)?;
+        let mut cpu = <G as CurveAffine>::Projective::zero();
+        for (base, exp) in bases.iter().zip(exps.iter()) {
+            cpu.add_assign(&base.mul(*exp));
+        }
+        if gpu != cpu {
+            error!("GPU multiexp result doesn't match the CPU!");
+            return Err(GPUError::ResultMismatch("multiexp"));
+        }
+        Ok(())
+    }
+
+    /// Runs the multiexp on `bases[progress.0..]` in chunks of `self.n` elements, adding the
+    /// result of each chunk to `progress.1` and its length to `progress.0`.
+    ///
+    /// Chunks are double-buffered: the next chunk is uploaded while the kernel is running on the
+    /// current one, so that the inputs don't need to fit in GPU memory and the transfers overlap
+    /// with the computation.
+    pub fn multiexp_streamed<G>(
+        &mut self,
+        bases: &[G],
+        exps: &[<<G::Engine as ScalarEngine>::Fr as PrimeField>::Repr],
+        progress: &mut (usize, <G as CurveAffine>::Projective),
+    ) -> GPUResult<()>
+    where
+        G: CurveAffine,
+    {
+        let (done, acc) = progress;
+        let span = info_span!(
+            "multiexp_device",
+            device = %self.proque.device().name().unwrap_or_default(),
+            elements = bases.len() - *done
+        );
+        let _enter = span.enter();
+
+        let mut chunks = bases[*done..]
+            .chunks(self.n)
+            .zip(exps[*done..].chunks(self.n));
+
+        let mut slot = 0;
+        let mut current = match chunks.next() {
+            Some((bases, exps)) => {
+                self.upload(slot, bases, exps, self.proque.queue().clone())?;
+                bases.len()
+            }
+            None => return Ok(()),
+        };
+        loop {
+            let now = Instant::now();
+            let params = self.launch::<G>(slot, current)?;
+
+            // Kernels are enqueued without blocking, so the (blocking) upload of the next chunk
+            // runs on the device while the current one is being processed.
+            let next = match chunks.next() {
+                Some((bases, exps)) => {
+                    self.upload(1 - slot, bases, exps, self.transfer_queue.clone())?;
+                    Some(bases.len())
+                }
+                None => None,
+            };
+
+            let result = self.result::<G>(params)?;
+            metrics::record(Stage::MultiexpChunk, now.elapsed(), 0);
+            acc.add_assign(&result);
+            *done += current;
+
+            match next {
+                Some(n) => {
+                    slot = 1 - slot;
+                    current = n;
+                    // The kernel must not start before the upload is complete
+                    watchdog::finish(&self.transfer_queue)?;
+                }
+                None => return Ok(()),
+            }
+        }
+    }
+
+    // Writes a chunk of bases and exponents to the buffers of `slot`
+    fn upload<G>(
+        &mut self,
+        slot: usize,
+        bases: &[G],
+        exps: &[<<G::Engine as ScalarEngine>::Fr as PrimeField>::Repr],
+        queue: Queue,
+    ) -> GPUResult<()>
+    where
+        G: CurveAffine,
+    {
+        let texps =
+            structs::PrimeFieldReprStruct::<<E::Fr as PrimeField>::Repr>::to_repr_slice_of(exps)
+                .ok_or(GPUError::Simple("The exponents must be of E::Fr!"))?;
+        self.exp_staging[slot].write(texps, &self.exp_buffers[slot], &queue)?;
+
+        if let Some(tbases) = structs::CurveAffineStruct::<E::G1Affine>::to_repr_slice_of(bases) {
+            self.g1_base_staging[slot].write(tbases, &self.g1_base_buffers[slot], &queue)?;
+        } else if let Some(tbases) =
+            structs::CurveAffineStruct::<E::G2Affine>::to_repr_slice_of(bases)
+        {
+            self.g2_base_staging[slot].write(tbases, &self.g2_base_buffers[slot], &queue)?;
+        } else {
+            return Err(GPUError::Simple("Only E::G1 and E::G2 are supported!"));
+        }
+        Ok(())
+    }
+
+    // Enqueues the kernel on the first `n` elements of the buffers of `slot`
+    fn launch<G>(&self, slot: usize, n: usize) -> GPUResult<MultiexpParams>
+    where
+        G: CurveAffine,
+    {
+        if self.cancel.as_ref().map_or(false, |c| c.is_cancelled()) {
+            return Err(GPUError::Cancelled);
+        }
         if locks::PriorityLock::should_break(self.priority) {
             return Err(GPUError::GPUTaken);
         }
 
         let exp_bits = std::mem::size_of::<E::Fr>() * 8;
-        let window_size = calc_window_size(n as usize, exp_bits, self.core_count);
-        let num_windows = ((exp_bits as f64) / (window_size as f64)).ceil() as usize;
-        let num_groups = calc_num_groups(self.core_count, num_windows);
+        let params = calc_params(n, exp_bits, self.core_count, self.window_size);
 
-        let mut res = vec![<G as CurveAffine>::Projective::zero(); num_groups * num_windows];
-        let texps = unsafe {
-            std::mem::transmute::<
-                &[<<G::Engine as ScalarEngine>::Fr as PrimeField>::Repr],
-                &[structs::PrimeFieldStruct<E::Fr>],
-            >(exps)
+        let sz = std::mem::size_of::<G>(); // Trick, used for dispatching between G1 and G2!
+        let prefix = if sz == std::mem::size_of::<E::G1Affine>() {
+            "G1"
+        } else if sz == std::mem::size_of::<E::G2Affine>() {
+            "G2"
+        } else {
+            return Err(GPUError::Simple("Only E::G1 and E::G2 are supported!"));
         };
-        self.exp_buffer.write(texps).enq()?;
 
-        // Make global work size divisible by `LOCAL_WORK_SIZE`
-        let mut gws = num_windows * num_groups;
-        gws += (LOCAL_WORK_SIZE - (gws % LOCAL_WORK_SIZE)) % LOCAL_WORK_SIZE;
+        // Make global work sizes divisible by the local work size
+        let lws = self.work_group_size.unwrap_or(LOCAL_WORK_SIZE);
+        let round_up = |gws: usize| gws + (lws - (gws % lws)) % lws;
+        let lws = match self.work_group_size {
+            Some(lws) => SpatialDims::One(lws),
+            None => SpatialDims::Unspecified,
+        };
 
-        let sz = std::mem::size_of::<G>(); // Trick, used for dispatching between G1 and G2!
-        if sz == std::mem::size_of::<E::G1Affine>() {
-            let tbases = unsafe {
-                &*(bases as *const [G]
-                    as *const [structs::CurveAffineStruct<<E as Engine>::G1Affine>])
-            };
-            self.g1_base_buffer.write(tbases).enq()?;
+        if let Some(ref flags) = self.flag_buffer {
             let kernel = self
                 .proque
-                .kernel_builder("G1_bellman_multiexp")
-                .global_work_size([gws])
-                .arg(&self.g1_base_buffer)
-                .arg(&self.g1_bucket_buffer)
-                .arg(&self.g1_result_buffer)
-                .arg(&self.exp_buffer)
+                .kernel_builder(format!("{}_signed_windows", prefix))
+                .global_work_size([round_up(n)])
+                .local_work_size(lws)
+                .arg(&self.exp_buffers[slot])
+                .arg(flags)
                 .arg(n as u32)
-                .arg(num_groups as u32)
-                .arg(num_windows as u32)
-                .arg(window_size as u32)
+                .arg(params.num_windows as u32)
+                .arg(params.window_size as u32)
                 .build()?;
             unsafe {
                 kernel.enq()?;
             }
-            let tres = unsafe {
-                &mut *(&mut res as *mut Vec<<G as CurveAffine>::Projective>
-                    as *mut Vec<structs::CurveProjectiveStruct<<E as Engine>::G1>>)
-            };
-            self.g1_result_buffer.read(tres).enq()?;
-        } else if sz == std::mem::size_of::<E::G2Affine>() {
-            let tbases = unsafe {
-                &*(bases as *const [G]
-                    as *const [structs::CurveAffineStruct<<E as Engine>::G2Affine>])
-            };
-            self.g2_base_buffer.write(tbases).enq()?;
-            let kernel = self
-                .proque
-                .kernel_builder("G2_bellman_multiexp")
-                .global_work_size([gws])
-                .arg(&self.g2_base_buffer)
+        }
+
+        let name = match self.flag_buffer {
+            Some(_) => format!("{}_bellman_multiexp_signed", prefix),
+            None => format!("{}_bellman_multiexp", prefix),
+        };
+        let mut builder = self.proque.kernel_builder(name);
+        builder
+            .global_work_size([round_up(params.num_windows * params.num_groups)])
+            .local_work_size(lws);
+        if prefix == "G1" {
+            builder
+                .arg(&self.g1_base_buffers[slot])
+                .arg(&self.g1_bucket_buffer)
+                .arg(&self.g1_result_buffer);
+        } else {
+            builder
+                .arg(&self.g2_base_buffers[slot])
                 .arg(&self.g2_bucket_buffer)
-                .arg(&self.g2_result_buffer)
-                .arg(&self.exp_buffer)
-                .arg(n as u32)
-                .arg(num_groups as u32)
-                .arg(num_windows as u32)
-                .arg(window_size as u32)
-                .build()?;
-            unsafe {
-                kernel.enq()?;
-            }
-            let tres = unsafe {
-                &mut *(&mut res as *mut Vec<<G as CurveAffine>::Projective>
-                    as *mut Vec<structs::CurveProjectiveStruct<<E as Engine>::G2>>)
-            };
+                .arg(&self.g2_result_buffer);
+        }
+        builder.arg(&self.exp_buffers[slot]);
+        if let Some(ref flags) = self.flag_buffer {
+            builder.arg(flags);
+        }
+        let kernel = builder
+            .arg(n as u32)
+            .arg(params.num_groups as u32)
+            .arg(params.num_windows as u32)
+            .arg(params.window_size as u32)
+            .build()?;
+        unsafe {
+            kernel.enq()?;
+        }
+
+        Ok(params)
+    }
+
+    // Waits for the last launched kernel and accumulates the results of its threads
+    fn result<G>(&self, params: MultiexpParams) -> GPUResult<<G as CurveAffine>::Projective>
+    where
+        G: CurveAffine,
+    {
+        let MultiexpParams {
+            window_size,
+            num_windows,
+            num_groups,
+        } = params;
+        let exp_bits = std::mem::size_of::<E::Fr>() * 8;
+
+        let mut res = vec![<G as CurveAffine>::Projective::zero(); num_groups * num_windows];
+        watchdog::finish(self.proque.queue())?; // Reads block until the kernel is done
+        if let Some(tres) =
+            structs::CurveProjectiveStruct::<E::G1>::to_repr_slice_mut_of(&mut res[..])
+        {
+            self.g1_result_buffer.read(tres).enq()?;
+        } else if let Some(tres) =
+            structs::CurveProjectiveStruct::<E::G2>::to_repr_slice_mut_of(&mut res[..])
+        {
             self.g2_result_buffer.read(tres).enq()?;
         } else {
             return Err(GPUError::Simple("Only E::G1 and E::G2 are supported!"));
@@ -305,25 +533,62 @@ where
 }
 
 // A struct that containts several multiexp kernels for different devices
+/// Progress of a `MultiexpKernel::multiexp_from` call which was interrupted, e.g. because the
+/// GPU was taken by a high priority process.
+pub struct MultiexpProgress<G>
+where
+    G: CurveAffine,
+{
+    // Number of elements already processed by each device and their accumulated result
+    gpu: Vec<(usize, G::Projective)>,
+    cpu: Option<G::Projective>,
+}
+
+impl<G> MultiexpProgress<G>
+where
+    G: CurveAffine,
+{
+    pub fn new() -> MultiexpProgress<G> {
+        MultiexpProgress {
+            gpu: Vec::new(),
+            cpu: None,
+        }
+    }
+}
+
+impl<G> Default for MultiexpProgress<G>
+where
+    G: CurveAffine,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct MultiexpKernel<E>
 where
     E: Engine,
 {
     kernels: Vec<SingleMultiexpKernel<E>>,
-    _lock: locks::GPULock, // RFC 1857: struct fields are dropped in the same order as they are declared.
+    _locks: Vec<locks::GPULock>, // RFC 1857: struct fields are dropped in the same order as they are declared.
 }
 
 impl<E> MultiexpKernel<E>
 where
     E: Engine,
 {
-    pub fn create(priority: bool) -> GPUResult<MultiexpKernel<E>> {
-        let lock = locks::GPULock::lock();
+    pub fn create(priority: Priority) -> GPUResult<MultiexpKernel<E>> {
+        let devices = get_assigned_devices(KernelKind::Multiexp).unwrap_or_default();
+        Self::create_on_devices(&devices, priority)
+    }
 
-        let platform = get_platform(None)?;
-        let devices = &get_devices(&platform).unwrap_or_default();
+    /// Creates a kernel running on `device` only, regardless of `BELLMAN_GPU_INDEX`
+    pub fn create_on(device: &Device, priority: Priority) -> GPUResult<MultiexpKernel<E>> {
+        Self::create_on_devices(&[*device], priority)
+    }
 
-        info!("Platform selected: {}", platform.name()?);
+    fn create_on_devices(devices: &[Device], priority: Priority) -> GPUResult<MultiexpKernel<E>> {
+        let locks = locks::GPULock::lock_all(devices);
 
         let kernels: Vec<_> = devices
             .iter()
@@ -350,10 +615,18 @@ where
         }
         Ok(MultiexpKernel::<E> {
             kernels,
-            _lock: lock,
+            _locks: locks,
         })
     }
 
+    /// Makes the multiexps fail with `GPUError::Cancelled` before their next chunk once `cancel`
+    /// is cancelled.
+    pub fn set_cancellation(&mut self, cancel: Option<CancellationToken>) {
+        for k in self.kernels.iter_mut() {
+            k.cancel = cancel.clone();
+        }
+    }
+
     pub fn multiexp<G>(
         &mut self,
         pool: &Worker,
@@ -362,6 +635,25 @@ where
         skip: usize,
         n: usize,
     ) -> GPUResult<<G as CurveAffine>::Projective>
+    where
+        G: CurveAffine,
+        <G as groupy::CurveAffine>::Engine: paired::Engine,
+    {
+        self.multiexp_from(pool, bases, exps, skip, n, &mut MultiexpProgress::new())
+    }
+
+    /// Like `multiexp`, but skips the work already recorded in `progress` and records the
+    /// chunks it completes, so that it can be called again with the same `progress` after
+    /// failing with `GPUError::GPUTaken`.
+    pub fn multiexp_from<G>(
+        &mut self,
+        pool: &Worker,
+        bases: Arc<Vec<G>>,
+        exps: Arc<Vec<<<G::Engine as ScalarEngine>::Fr as PrimeField>::Repr>>,
+        skip: usize,
+        n: usize,
+        progress: &mut MultiexpProgress<G>,
+    ) -> GPUResult<<G as CurveAffine>::Projective>
     where
         G: CurveAffine,
         <G as groupy::CurveAffine>::Engine: paired::Engine,
@@ -378,51 +670,121 @@ where
         let (cpu_exps, exps) = exps.split_at(cpu_n);
 
         let chunk_size = ((n as f64) / (num_devices as f64)).ceil() as usize;
+        if progress.gpu.len() != num_devices {
+            // The work was split across another number of devices (E.g. the kernel was
+            // re-created after losing a GPU), so the progress of each device isn't valid anymore.
+            progress.gpu.clear();
+        }
+        progress
+            .gpu
+            .resize(num_devices, (0, <G as CurveAffine>::Projective::zero()));
 
-        match thread::scope(|s| -> Result<<G as CurveAffine>::Projective, GPUError> {
-            let mut acc = <G as CurveAffine>::Projective::zero();
+        match thread::scope(|s| -> Result<(), GPUError> {
             let mut threads = Vec::new();
             if n > 0 {
-                for ((bases, exps), kern) in bases
+                for (((bases, exps), kern), device_progress) in bases
                     .chunks(chunk_size)
                     .zip(exps.chunks(chunk_size))
                     .zip(self.kernels.iter_mut())
+                    .zip(progress.gpu.iter_mut())
                 {
-                    threads.push(s.spawn(
-                        move |_| -> Result<<G as CurveAffine>::Projective, GPUError> {
-                            let mut acc = <G as CurveAffine>::Projective::zero();
-                            for (bases, exps) in bases.chunks(kern.n).zip(exps.chunks(kern.n)) {
-                                let result = kern.multiexp(bases, exps, bases.len())?;
-                                acc.add_assign(&result);
-                            }
-                            Ok(acc)
-                        },
-                    ));
+                    let parent = Span::current();
+                    threads.push(s.spawn(move |_| -> GPUResult<()> {
+                        let _enter = parent.enter();
+                        kern.multiexp_streamed(bases, exps, device_progress)?;
+                        if paranoid::is_paranoid() {
+                            kern.spot_check(bases, exps)?;
+                        }
+                        Ok(())
+                    }));
                 }
             }
 
-            let cpu_acc = cpu_multiexp(
-                &pool,
-                (Arc::new(cpu_bases.to_vec()), 0),
-                FullDensity,
-                Arc::new(cpu_exps.to_vec()),
-                &mut None,
-            );
+            let cpu_acc = if progress.cpu.is_none() {
+                Some(cpu_multiexp(
+                    &pool,
+                    (Arc::new(cpu_bases.to_vec()), 0),
+                    FullDensity,
+                    Arc::new(cpu_exps.to_vec()),
+                    &mut None,
+                ))
+            } else {
+                None
+            };
 
             let mut results = vec![];
             for t in threads {
                 results.push(t.join());
             }
-            for r in results {
-                acc.add_assign(&r??);
+
+            // The CPU result is kept even if the GPU part failed, so that it isn't recomputed
+            if let Some(cpu_acc) = cpu_acc {
+                progress.cpu = Some(cpu_acc.wait().unwrap());
             }
 
-            acc.add_assign(&cpu_acc.wait().unwrap());
+            for r in results {
+                r??;
+            }
 
-            Ok(acc)
+            Ok(())
         }) {
-            Ok(res) => res,
-            Err(e) => Err(GPUError::from(e)),
+            Ok(res) => res?,
+            Err(e) => return Err(GPUError::from(e)),
+        }
+
+        let mut acc = <G as CurveAffine>::Projective::zero();
+        for (_, gpu_acc) in progress.gpu.iter() {
+            acc.add_assign(gpu_acc);
+        }
+        if let Some(ref cpu_acc) = progress.cpu {
+            acc.add_assign(cpu_acc);
         }
+        Ok(acc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calc_params() {
+        // A window size from the config is clamped to the supported range
+        assert_eq!(
+            calc_params(1 << 20, 256, 4352, Some(20)).window_size,
+            MAX_WINDOW_SIZE
+        );
+        assert_eq!(calc_params(1 << 20, 256, 4352, Some(0)).window_size, 1);
+
+        // Small inputs don't get more groups than bases
+        let params = calc_params(4, 256, 4352, None);
+        assert_eq!(params.num_groups, 4);
+        assert!(params.num_groups * params.num_windows <= 2 * 4352);
+
+        let params = calc_params(1 << 20, 256, 4352, Some(8));
+        assert_eq!(
+            params,
+            MultiexpParams {
+                window_size: 8,
+                num_windows: 32,
+                num_groups: calc_num_groups(4352, 32),
+            }
+        );
+    }
+
+    #[test]
+    fn test_multiexp_memory() {
+        use paired::bls12_381::Bls12;
+
+        assert_eq!(max_multiexp_chunk_size::<Bls12>(0, 4352), None);
+        assert_eq!(
+            max_multiexp_chunk_size::<Bls12>(multiexp_required_memory::<Bls12>(0, 4352), 4352),
+            None
+        );
+
+        // Both are the inverse of each other, up to the rounding of the padding
+        let mem = multiexp_required_memory::<Bls12>(1 << 20, 4352);
+        let n = max_multiexp_chunk_size::<Bls12>(mem, 4352).unwrap();
+        assert!(n + 1 >= 1 << 20 && n <= (1 << 20) + 1);
     }
 }
@@ -43,6 +43,12 @@ POINT_projective POINT_double(POINT_projective inp) {
 }
 
 // http://www.hyperelliptic.org/EFD/g1p/auto-shortw-jacobian-0.html#addition-madd-2007-bl
+POINT_affine POINT_neg_affine(POINT_affine a) {
+  const FIELD local_zero = FIELD_ZERO;
+  a.y = FIELD_sub(local_zero, a.y);
+  return a;
+}
+
 POINT_projective POINT_add_mixed(POINT_projective a, POINT_affine b) {
   if(b.inf) {
       return a;
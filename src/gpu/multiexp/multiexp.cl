@@ -64,3 +64,98 @@ __kernel void POINT_bellman_multiexp(
 
   results[gid] = res;
 }
+
+/*
+ * Signed windows: each window `k` of an exponent is turned into a digit in
+ * (-2^(w - 1), 2^(w - 1)], by borrowing 2^w from the next (More significant)
+ * window when its value (Plus the carry of the previous window) is larger than
+ * 2^(w - 1). Windows are numbered from the most significant one, as in
+ * `POINT_bellman_multiexp`, so the carry of window `k` goes to window `k - 1`.
+ * Only half of the buckets are needed, since a negative digit adds the negated
+ * base to the bucket of its absolute value.
+ * The most significant bit of the exponents must be zero, so that the first
+ * window never needs to borrow.
+ */
+
+#define POINT_FLAG_WORDS (EXPONENT_BITS / 32)
+
+// Whether window `k` of an exponent is negative, given the flags of the exponent
+bool POINT_is_negative_window(__global uint *flags, uint k) {
+  return (flags[k / 32] >> (k % 32)) & 1;
+}
+
+// Sets bit `k` of the `POINT_FLAG_WORDS` words of `flags[i]` when the window
+// `k` of `exps[i]` is negative.
+__kernel void POINT_signed_windows(
+    __global EXPONENT *exps,
+    __global uint *flags,
+    uint n,
+    uint num_windows,
+    uint window_size) {
+
+  const uint i = get_global_id(0);
+  if(i >= n) return;
+
+  flags += POINT_FLAG_WORDS * i;
+  for(uint j = 0; j < POINT_FLAG_WORDS; j++) flags[j] = 0;
+
+  uint carry = 0;
+  for(int k = num_windows - 1; k > 0; k--) {
+    const uint bits = k * window_size;
+    const uint w = min(window_size, EXPONENT_BITS - bits);
+    carry = EXPONENT_get_bits(exps[i], bits, w) + carry > (1 << (w - 1));
+    if(carry) flags[k / 32] |= 1 << (k % 32);
+  }
+}
+
+// Same as `POINT_bellman_multiexp`, with the signed windows of the exponents
+// computed by `POINT_signed_windows`.
+__kernel void POINT_bellman_multiexp_signed(
+    __global POINT_affine *bases,
+    __global POINT_projective *buckets,
+    __global POINT_projective *results,
+    __global EXPONENT *exps,
+    __global uint *flags,
+    uint n,
+    uint num_groups,
+    uint num_windows,
+    uint window_size) {
+
+  const uint gid = get_global_id(0);
+  if(gid >= num_windows * num_groups) return;
+
+  // We have 2^(window_size - 1) buckets, for the absolute values of the digits.
+  const uint bucket_len = 1 << (window_size - 1);
+
+  buckets += bucket_len * gid;
+
+  const POINT_projective local_zero = POINT_ZERO;
+  for(uint i = 0; i < bucket_len; i++) buckets[i] = local_zero;
+
+  const uint len = (uint)ceil(n / (float)num_groups);
+
+  const uint nstart = len * (gid / num_windows);
+  const uint nend = min(nstart + len, n);
+  const uint k = gid % num_windows;
+  const uint bits = k * window_size;
+  const ushort w = min((ushort)window_size, (ushort)(EXPONENT_BITS - bits));
+
+  POINT_projective res = POINT_ZERO;
+  for(uint i = nstart; i < nend; i++) {
+    __global uint *f = flags + POINT_FLAG_WORDS * i;
+    int digit = EXPONENT_get_bits(exps[i], bits, w);
+    if(k + 1 < num_windows && POINT_is_negative_window(f, k + 1)) digit++;
+    if(POINT_is_negative_window(f, k)) digit -= 1 << w;
+
+    if(digit > 0) buckets[digit - 1] = POINT_add_mixed(buckets[digit - 1], bases[i]);
+    else if(digit < 0) buckets[-digit - 1] = POINT_add_mixed(buckets[-digit - 1], POINT_neg_affine(bases[i]));
+  }
+
+  POINT_projective acc = POINT_ZERO;
+  for(int j = bucket_len - 1; j >= 0; j--) {
+    acc = POINT_add(acc, buckets[j]);
+    res = POINT_add(res, acc);
+  }
+
+  results[gid] = res;
+}
@@ -1,29 +1,235 @@
 use super::error::{GPUError, GPUResult};
+use super::Priority;
+use crate::cancellation::CancellationToken;
 use crate::multicore::Worker;
+use crate::poseidon::PoseidonParams;
 use ff::{PrimeField, ScalarEngine};
 use groupy::CurveAffine;
 use std::marker::PhantomData;
 use std::sync::Arc;
+use std::time::Duration;
 
 // This module is compiled instead of `fft.rs` and `multiexp.rs` if `gpu` feature is disabled.
 
-pub struct FFTKernel<E>(PhantomData<E>)
+pub struct FFTKernel<F>(PhantomData<F>)
 where
-    E: ScalarEngine;
+    F: PrimeField;
 
-impl<E> FFTKernel<E>
+impl<F> FFTKernel<F>
 where
-    E: ScalarEngine,
+    F: PrimeField,
+{
+    pub fn create(_: u32, _: Priority) -> GPUResult<FFTKernel<F>> {
+        return Err(GPUError::Simple("GPU accelerator is not enabled!"));
+    }
+
+    pub fn create_with_memory_limit(
+        _: u32,
+        _: Priority,
+        _: Option<u64>,
+    ) -> GPUResult<FFTKernel<F>> {
+        return Err(GPUError::Simple("GPU accelerator is not enabled!"));
+    }
+
+    pub fn set_cancellation(&mut self, _: Option<CancellationToken>) {}
+
+    pub fn radix_fft(&mut self, _: &mut [F], _: &F, _: u32) -> GPUResult<()> {
+        return Err(GPUError::Simple("GPU accelerator is not enabled!"));
+    }
+
+    pub fn coset_fft(&mut self, _: &mut [F], _: &F, _: &F, _: u32) -> GPUResult<()> {
+        return Err(GPUError::Simple("GPU accelerator is not enabled!"));
+    }
+
+    pub fn icoset_fft(&mut self, _: &mut [F], _: &F, _: &F, _: &F, _: u32) -> GPUResult<()> {
+        return Err(GPUError::Simple("GPU accelerator is not enabled!"));
+    }
+
+    pub fn quotient(
+        &mut self,
+        _: &mut [F],
+        _: &[F],
+        _: &[F],
+        _: &F,
+        _: &F,
+        _: u32,
+    ) -> GPUResult<()> {
+        return Err(GPUError::Simple("GPU accelerator is not enabled!"));
+    }
+
+    pub fn polynomial_mul(
+        &mut self,
+        _: &[F],
+        _: &[F],
+        _: &mut [F],
+        _: &F,
+        _: u32,
+    ) -> GPUResult<()> {
+        return Err(GPUError::Simple("GPU accelerator is not enabled!"));
+    }
+
+    pub fn batch_invert(&mut self, _: &mut [F]) -> GPUResult<()> {
+        return Err(GPUError::Simple("GPU accelerator is not enabled!"));
+    }
+
+    pub fn batch_invert_from(&mut self, _: &mut [F], _: &mut usize) -> GPUResult<()> {
+        return Err(GPUError::Simple("GPU accelerator is not enabled!"));
+    }
+
+    pub fn is_split(&self, _: u32) -> bool {
+        false
+    }
+
+    pub fn mixed_radix_fft(&mut self, _: &mut [F], _: &F, _: u32, _: u32) -> GPUResult<()> {
+        return Err(GPUError::Simple("GPU accelerator is not enabled!"));
+    }
+
+    pub fn radix_fft_resume(
+        &mut self,
+        _: &mut [F],
+        _: &F,
+        _: u32,
+        _: &mut Option<FFTCheckpoint<F>>,
+    ) -> GPUResult<()> {
+        return Err(GPUError::Simple("GPU accelerator is not enabled!"));
+    }
+
+    pub fn radix_fft_async<'a>(
+        &'a mut self,
+        _: &'a mut [F],
+        _: &F,
+        _: u32,
+    ) -> GPUResult<FFTHandle<'a, F>> {
+        return Err(GPUError::Simple("GPU accelerator is not enabled!"));
+    }
+
+    pub fn radix_fft_many(&mut self, _: &mut [&mut [F]], _: &F, _: u32) -> GPUResult<()> {
+        return Err(GPUError::Simple("GPU accelerator is not enabled!"));
+    }
+
+    pub fn radix_fft_many_from(
+        &mut self,
+        _: &mut [&mut [F]],
+        _: &F,
+        _: u32,
+        _: &mut usize,
+    ) -> GPUResult<()> {
+        return Err(GPUError::Simple("GPU accelerator is not enabled!"));
+    }
+}
+
+pub struct FFTCheckpoint<F>(PhantomData<F>)
+where
+    F: PrimeField;
+
+pub struct FFTHandle<'a, F>(PhantomData<&'a F>)
+where
+    F: PrimeField;
+
+impl<'a, F> FFTHandle<'a, F>
+where
+    F: PrimeField,
 {
-    pub fn create(_: u32, _: bool) -> GPUResult<FFTKernel<E>> {
+    pub fn wait(self) -> GPUResult<()> {
+        return Err(GPUError::Simple("GPU accelerator is not enabled!"));
+    }
+}
+
+pub struct FFTSession<F>(PhantomData<F>)
+where
+    F: PrimeField;
+
+impl<F> FFTSession<F>
+where
+    F: PrimeField,
+{
+    pub fn create(_: u32, _: Priority) -> GPUResult<FFTSession<F>> {
+        return Err(GPUError::Simple("GPU accelerator is not enabled!"));
+    }
+
+    pub fn upload(&mut self, _: &[F]) -> GPUResult<()> {
+        return Err(GPUError::Simple("GPU accelerator is not enabled!"));
+    }
+
+    pub fn transform(&mut self, _: &F) -> GPUResult<()> {
+        return Err(GPUError::Simple("GPU accelerator is not enabled!"));
+    }
+
+    pub fn mul_by_powers(&mut self, _: &F, _: &F) -> GPUResult<()> {
+        return Err(GPUError::Simple("GPU accelerator is not enabled!"));
+    }
+
+    pub fn mul_assign(&mut self, _: &[F]) -> GPUResult<()> {
+        return Err(GPUError::Simple("GPU accelerator is not enabled!"));
+    }
+
+    pub fn sub_assign(&mut self, _: &[F]) -> GPUResult<()> {
+        return Err(GPUError::Simple("GPU accelerator is not enabled!"));
+    }
+
+    pub fn mul_assign_buffer(&mut self, _: &FFTBuffer<F>) -> GPUResult<()> {
+        return Err(GPUError::Simple("GPU accelerator is not enabled!"));
+    }
+
+    pub fn sub_assign_buffer(&mut self, _: &FFTBuffer<F>) -> GPUResult<()> {
+        return Err(GPUError::Simple("GPU accelerator is not enabled!"));
+    }
+
+    pub fn create_buffer(&mut self, _: &[F]) -> GPUResult<FFTBuffer<F>> {
+        return Err(GPUError::Simple("GPU accelerator is not enabled!"));
+    }
+
+    pub fn with_buffer<R, T>(&mut self, _: &mut FFTBuffer<F>, _: T) -> GPUResult<R>
+    where
+        T: FnOnce(&mut FFTSession<F>) -> GPUResult<R>,
+    {
+        return Err(GPUError::Simple("GPU accelerator is not enabled!"));
+    }
+
+    pub fn lgn(&self) -> u32 {
+        0
+    }
+
+    pub fn download(&mut self, _: &mut [F]) -> GPUResult<()> {
         return Err(GPUError::Simple("GPU accelerator is not enabled!"));
     }
+}
 
-    pub fn radix_fft(&mut self, _: &mut [E::Fr], _: &E::Fr, _: u32) -> GPUResult<()> {
+pub struct FFTBuffer<F>(PhantomData<F>)
+where
+    F: PrimeField;
+
+impl<F> FFTBuffer<F>
+where
+    F: PrimeField,
+{
+    pub fn download(&self, _: &mut [F]) -> GPUResult<()> {
         return Err(GPUError::Simple("GPU accelerator is not enabled!"));
     }
 }
 
+pub struct MultiexpProgress<G>(PhantomData<G>)
+where
+    G: CurveAffine;
+
+impl<G> MultiexpProgress<G>
+where
+    G: CurveAffine,
+{
+    pub fn new() -> MultiexpProgress<G> {
+        MultiexpProgress::<G>(PhantomData)
+    }
+}
+
+impl<G> Default for MultiexpProgress<G>
+where
+    G: CurveAffine,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct MultiexpKernel<E>(PhantomData<E>)
 where
     E: ScalarEngine;
@@ -32,10 +238,12 @@ impl<E> MultiexpKernel<E>
 where
     E: ScalarEngine,
 {
-    pub fn create(_: bool) -> GPUResult<MultiexpKernel<E>> {
+    pub fn create(_: Priority) -> GPUResult<MultiexpKernel<E>> {
         return Err(GPUError::Simple("GPU accelerator is not enabled!"));
     }
 
+    pub fn set_cancellation(&mut self, _: Option<CancellationToken>) {}
+
     pub fn multiexp<G>(
         &mut self,
         _: &Worker,
@@ -49,6 +257,96 @@ where
     {
         return Err(GPUError::Simple("GPU accelerator is not enabled!"));
     }
+
+    pub fn multiexp_from<G>(
+        &mut self,
+        _: &Worker,
+        _: Arc<Vec<G>>,
+        _: Arc<Vec<<<G::Engine as ScalarEngine>::Fr as PrimeField>::Repr>>,
+        _: usize,
+        _: usize,
+        _: &mut MultiexpProgress<G>,
+    ) -> GPUResult<<G as CurveAffine>::Projective>
+    where
+        G: CurveAffine,
+    {
+        return Err(GPUError::Simple("GPU accelerator is not enabled!"));
+    }
+}
+
+pub fn precompile_kernels<E>() -> GPUResult<()>
+where
+    E: ScalarEngine,
+{
+    return Err(GPUError::Simple("GPU accelerator is not enabled!"));
+}
+
+#[derive(Debug, Clone)]
+pub struct SelfTestReport {
+    pub name: String,
+    pub memory: u64,
+    pub core_count: usize,
+    pub error: Option<String>,
+    pub elapsed: Duration,
+}
+
+impl SelfTestReport {
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+pub fn selftest() -> GPUResult<Vec<SelfTestReport>> {
+    return Err(GPUError::Simple("GPU accelerator is not enabled!"));
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Vendor {
+    Nvidia,
+    Amd,
+    Intel,
+    Other(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    OpenCL,
+}
+
+#[derive(Debug, Clone)]
+pub struct DeviceInfo {
+    pub vendor: Vendor,
+    pub name: String,
+    pub platform: String,
+    pub memory: u64,
+    pub compute_units: u32,
+    pub cores: usize,
+    pub driver_version: String,
+    pub bus_id: Option<u32>,
+    pub backends: Vec<Backend>,
+    pub allowed: bool,
+}
+
+pub fn devices() -> Vec<DeviceInfo> {
+    Vec::new()
+}
+
+pub fn max_fft_lgn<F: PrimeField>(_: u64) -> Option<u32> {
+    None
+}
+
+pub fn multiexp_required_memory<E: ScalarEngine>(_: usize, _: usize) -> u64 {
+    0
+}
+
+pub fn max_multiexp_chunk_size<E: ScalarEngine>(_: u64, _: usize) -> Option<usize> {
+    None
+}
+
+pub fn set_paranoid(_: bool) {}
+
+pub fn is_paranoid() -> bool {
+    false
 }
 
 use paired::Engine;
@@ -61,10 +359,12 @@ macro_rules! locked_kernel {
         where
             E: Engine,
         {
-            pub fn new(_: usize, _: bool) -> $class<E> {
+            pub fn new(_: usize, _: Priority) -> $class<E> {
                 $class::<E>(PhantomData)
             }
 
+            pub fn set_cancellation(&mut self, _: Option<CancellationToken>) {}
+
             pub fn with<F, R, K>(&mut self, _: F) -> GPUResult<R>
             where
                 F: FnMut(&mut K) -> GPUResult<R>,
@@ -77,3 +377,63 @@ macro_rules! locked_kernel {
 
 locked_kernel!(LockedFFTKernel);
 locked_kernel!(LockedMultiexpKernel);
+
+pub struct SchedulerFuture<T>(PhantomData<T>);
+
+impl<T> futures::Future for SchedulerFuture<T> {
+    type Item = T;
+    type Error = GPUError;
+
+    fn poll(&mut self) -> futures::Poll<T, GPUError> {
+        return Err(GPUError::Simple("GPU accelerator is not enabled!"));
+    }
+}
+
+pub struct Scheduler<E>(PhantomData<E>)
+where
+    E: Engine;
+
+impl<E> Scheduler<E>
+where
+    E: Engine,
+{
+    pub fn new(_: usize, _: Priority) -> Scheduler<E> {
+        Scheduler::<E>(PhantomData)
+    }
+
+    pub fn fft(&self, _: Vec<E::Fr>, _: E::Fr, _: u32) -> SchedulerFuture<Vec<E::Fr>> {
+        SchedulerFuture(PhantomData)
+    }
+
+    pub fn multiexp<G>(
+        &self,
+        _: Arc<Vec<G>>,
+        _: Arc<Vec<<E::Fr as PrimeField>::Repr>>,
+        _: usize,
+        _: usize,
+    ) -> SchedulerFuture<G::Projective>
+    where
+        G: CurveAffine<Engine = E>,
+    {
+        SchedulerFuture(PhantomData)
+    }
+}
+
+pub const POSEIDON_MAX_WIDTH: usize = 16;
+
+pub struct PoseidonKernel<F>(PhantomData<F>)
+where
+    F: PrimeField;
+
+impl<F> PoseidonKernel<F>
+where
+    F: PrimeField,
+{
+    pub fn create(_: &PoseidonParams<F>) -> GPUResult<PoseidonKernel<F>> {
+        return Err(GPUError::Simple("GPU accelerator is not enabled!"));
+    }
+
+    pub fn hash_many(&mut self, _: &[F]) -> GPUResult<Vec<F>> {
+        return Err(GPUError::Simple("GPU accelerator is not enabled!"));
+    }
+}
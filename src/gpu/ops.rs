@@ -0,0 +1,193 @@
+//! Building blocks running on the GPU kernels of this crate, for provers other than Groth16.
+//!
+//! All of the functions work on host slices and take the kernel to run on, so that it (And its
+//! GPU lock) can be kept across calls. Polynomials have a power of two number of elements, and
+//! the functions taking an `FFTSession` need the length of the polynomials to match the size of
+//! the session. Without the `gpu` feature, every function fails with `GPUError::Simple`.
+
+use super::error::{GPUError, GPUResult};
+use super::{FFTKernel, FFTSession, MultiexpKernel};
+use crate::multicore::Worker;
+use ff::{PrimeField, ScalarEngine};
+use groupy::CurveAffine;
+use paired::Engine;
+use std::sync::Arc;
+
+// log2 of the length of a polynomial
+fn log2_len(len: usize) -> GPUResult<u32> {
+    if !len.is_power_of_two() {
+        return Err(GPUError::Simple(
+            "Polynomial length must be a power of two!",
+        ));
+    }
+    Ok(len.trailing_zeros())
+}
+
+// Inverse of the length of a polynomial, as a field element
+fn inv_len<F: PrimeField>(lgn: u32) -> GPUResult<F> {
+    F::from_str(&(1u64 << lgn).to_string())
+        .and_then(|n| n.inverse())
+        .ok_or(GPUError::Simple("Polynomial length isn't invertible!"))
+}
+
+fn inverse<F: PrimeField>(x: &F) -> GPUResult<F> {
+    x.inverse().ok_or(GPUError::Simple("Cannot invert zero!"))
+}
+
+/// Evaluates the polynomial of coefficients `a` on the powers of `omega`, which must be a
+/// primitive root of unity of order `a.len()`, in place.
+pub fn fft<F: PrimeField>(kern: &mut FFTKernel<F>, a: &mut [F], omega: &F) -> GPUResult<()> {
+    let lgn = log2_len(a.len())?;
+    kern.radix_fft(a, omega, lgn)
+}
+
+/// Interpolates the coefficients of the polynomial of evaluations `a` on the powers of `omega`,
+/// in place. This is the inverse of `fft`.
+pub fn ifft<F: PrimeField>(kern: &mut FFTKernel<F>, a: &mut [F], omega: &F) -> GPUResult<()> {
+    let lgn = log2_len(a.len())?;
+    kern.icoset_fft(a, &inverse(omega)?, &F::one(), &inv_len(lgn)?, lgn)
+}
+
+/// Evaluates the polynomial of coefficients `a` on the coset `g * <omega>`, in place.
+pub fn coset_fft<F: PrimeField>(
+    kern: &mut FFTKernel<F>,
+    a: &mut [F],
+    omega: &F,
+    g: &F,
+) -> GPUResult<()> {
+    let lgn = log2_len(a.len())?;
+    kern.coset_fft(a, omega, g, lgn)
+}
+
+/// Interpolates the coefficients of the polynomial of evaluations `a` on the coset
+/// `g * <omega>`, in place. This is the inverse of `coset_fft`.
+pub fn icoset_fft<F: PrimeField>(
+    kern: &mut FFTKernel<F>,
+    a: &mut [F],
+    omega: &F,
+    g: &F,
+) -> GPUResult<()> {
+    let lgn = log2_len(a.len())?;
+    kern.icoset_fft(a, &inverse(omega)?, &inverse(g)?, &inv_len(lgn)?, lgn)
+}
+
+/// Replaces every non-zero element of `a` with its inverse. `a` can have any length.
+pub fn batch_invert<F: PrimeField>(kern: &mut FFTKernel<F>, a: &mut [F]) -> GPUResult<()> {
+    kern.batch_invert(a)
+}
+
+/// Multiplies `a[i]` by `g^i`, which turns the coefficients of `P(x)` into the coefficients of
+/// `P(g * x)`.
+pub fn coset_shift<F: PrimeField>(
+    session: &mut FFTSession<F>,
+    a: &mut [F],
+    g: &F,
+) -> GPUResult<()> {
+    session.upload(a)?;
+    session.mul_by_powers(g, &F::one())?;
+    session.download(a)
+}
+
+/// Multiplies every element of `a` by `c`.
+pub fn scale<F: PrimeField>(session: &mut FFTSession<F>, a: &mut [F], c: &F) -> GPUResult<()> {
+    session.upload(a)?;
+    session.mul_by_powers(&F::one(), c)?;
+    session.download(a)
+}
+
+/// `a[i] *= b[i]`
+pub fn mul_assign<F: PrimeField>(
+    session: &mut FFTSession<F>,
+    a: &mut [F],
+    b: &[F],
+) -> GPUResult<()> {
+    session.upload(a)?;
+    session.mul_assign(b)?;
+    session.download(a)
+}
+
+/// `a[i] -= b[i]`
+pub fn sub_assign<F: PrimeField>(
+    session: &mut FFTSession<F>,
+    a: &mut [F],
+    b: &[F],
+) -> GPUResult<()> {
+    session.upload(a)?;
+    session.sub_assign(b)?;
+    session.download(a)
+}
+
+/// Computes `sum(bases[i] * exps[i])` over all of `bases`, which needs as many `exps`.
+pub fn multiexp<E, G>(
+    kern: &mut MultiexpKernel<E>,
+    pool: &Worker,
+    bases: Arc<Vec<G>>,
+    exps: Arc<Vec<<<G::Engine as ScalarEngine>::Fr as PrimeField>::Repr>>,
+) -> GPUResult<G::Projective>
+where
+    E: Engine,
+    G: CurveAffine<Engine = E>,
+{
+    if exps.len() != bases.len() {
+        return Err(GPUError::Simple(
+            "Number of bases and exponents don't match!",
+        ));
+    }
+    let n = bases.len();
+    kern.multiexp(pool, bases, exps, 0, n)
+}
+
+#[cfg(feature = "gpu")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{serial_fft, Scalar};
+    use crate::gpu::Priority;
+    use ff::Field;
+    use paired::bls12_381::{Bls12, Fr};
+
+    #[test]
+    fn test_ops_consistency() {
+        const LOG_N: u32 = 12;
+        let rng = &mut rand::thread_rng();
+
+        let mut omega = Fr::root_of_unity();
+        for _ in LOG_N..Fr::S {
+            omega.square();
+        }
+        let g = Fr::multiplicative_generator();
+        let a = (0..1 << LOG_N).map(|_| Fr::random(rng)).collect::<Vec<_>>();
+        let b = (0..1 << LOG_N).map(|_| Fr::random(rng)).collect::<Vec<_>>();
+
+        let mut kern = FFTKernel::<Fr>::create(1 << LOG_N, Priority::Low).unwrap();
+        let mut session = FFTSession::<Fr>::create(LOG_N, Priority::Low).unwrap();
+
+        let mut gpu = a.clone();
+        fft(&mut kern, &mut gpu, &omega).unwrap();
+        let mut cpu = a.iter().map(|&e| Scalar::<Bls12>(e)).collect::<Vec<_>>();
+        serial_fft::<Bls12, _>(&mut cpu, &omega, LOG_N);
+        assert!(cpu.iter().map(|s| s.0).eq(gpu.iter().cloned()));
+
+        ifft(&mut kern, &mut gpu, &omega).unwrap();
+        assert_eq!(gpu, a);
+
+        coset_fft(&mut kern, &mut gpu, &omega, &g).unwrap();
+        icoset_fft(&mut kern, &mut gpu, &omega, &g).unwrap();
+        assert_eq!(gpu, a);
+
+        let mut gpu = a.clone();
+        mul_assign(&mut session, &mut gpu, &b).unwrap();
+        sub_assign(&mut session, &mut gpu, &b).unwrap();
+        scale(&mut session, &mut gpu, &g).unwrap();
+        coset_shift(&mut session, &mut gpu, &g).unwrap();
+        let mut power = g;
+        for (i, (gpu, (&a, &b))) in gpu.iter().zip(a.iter().zip(b.iter())).enumerate() {
+            let mut expected = a;
+            expected.mul_assign(&b);
+            expected.sub_assign(&b);
+            expected.mul_assign(&power);
+            assert_eq!(*gpu, expected, "Mismatch at {}", i);
+            power.mul_assign(&g);
+        }
+    }
+}
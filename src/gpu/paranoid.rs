@@ -0,0 +1,110 @@
+use super::error::{GPUError, GPUResult};
+use ff::PrimeField;
+use rand::Rng;
+use rayon::prelude::*;
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Number of random elements of each FFT result which are checked
+const FFT_SPOT_CHECKS: usize = 2;
+// Elements of a polynomial evaluated by a single thread
+const EVAL_CHUNK_SIZE: usize = 1 << 16;
+
+lazy_static::lazy_static! {
+    static ref PARANOID: AtomicBool = AtomicBool::new(env::var("BELLMAN_PARANOID").is_ok());
+}
+
+/// Enables or disables the paranoid mode, in which the results of the GPU FFTs and multiexps
+/// are spot-checked on the CPU. It's enabled by default if `BELLMAN_PARANOID` is set.
+pub fn set_paranoid(enabled: bool) {
+    PARANOID.store(enabled, Ordering::SeqCst);
+}
+
+pub fn is_paranoid() -> bool {
+    PARANOID.load(Ordering::SeqCst)
+}
+
+// Evaluates the polynomial of coefficients `a` at `x`
+fn evaluate<F: PrimeField>(a: &[F], x: &F) -> F {
+    a.par_chunks(EVAL_CHUNK_SIZE)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let mut acc = F::zero();
+            for c in chunk.iter().rev() {
+                acc.mul_assign(x);
+                acc.add_assign(c);
+            }
+            acc.mul_assign(&x.pow([(i * EVAL_CHUNK_SIZE) as u64]));
+            acc
+        })
+        .reduce(F::zero, |mut acc, e| {
+            acc.add_assign(&e);
+            acc
+        })
+}
+
+/// Checks random elements of `result`, the FFT of `input` with the optional coset shifts of
+/// `coset_fft` (`pre`) and `icoset_fft` (`post`), i.e.
+/// `result[k] = c * h^k * input(g * omega^k)` where `pre = g` and `post = (h, c)`.
+pub fn spot_check_fft<F: PrimeField>(
+    input: &[F],
+    result: &[F],
+    omega: &F,
+    pre: Option<&F>,
+    post: Option<(&F, &F)>,
+) -> GPUResult<()> {
+    let mut rng = rand::thread_rng();
+    for _ in 0..FFT_SPOT_CHECKS {
+        let k = rng.gen_range(0, result.len());
+        let mut x = omega.pow([k as u64]);
+        if let Some(g) = pre {
+            x.mul_assign(g);
+        }
+        let mut expected = evaluate(input, &x);
+        if let Some((h, c)) = post {
+            expected.mul_assign(&h.pow([k as u64]));
+            expected.mul_assign(c);
+        }
+        if expected != result[k] {
+            return Err(GPUError::ResultMismatch("FFT"));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{serial_fft, Scalar};
+    use ff::Field;
+    use paired::bls12_381::{Bls12, Fr};
+
+    #[test]
+    fn test_spot_check_fft() {
+        const LOG_N: u32 = 12;
+        let rng = &mut rand::thread_rng();
+
+        let mut omega = Fr::root_of_unity();
+        for _ in LOG_N..Fr::S {
+            omega.square();
+        }
+        let input = (0..1 << LOG_N).map(|_| Fr::random(rng)).collect::<Vec<_>>();
+        let mut result = input
+            .iter()
+            .map(|&e| Scalar::<Bls12>(e))
+            .collect::<Vec<_>>();
+        serial_fft::<Bls12, _>(&mut result, &omega, LOG_N);
+        let mut result = result.into_iter().map(|s| s.0).collect::<Vec<_>>();
+
+        assert!(spot_check_fft(&input, &result, &omega, None, None).is_ok());
+
+        // Every element is corrupted, so that any spot-check catches it
+        for e in result.iter_mut() {
+            e.double();
+        }
+        match spot_check_fft(&input, &result, &omega, None, None) {
+            Err(GPUError::ResultMismatch(_)) => {}
+            _ => panic!("Corrupted FFT result wasn't detected!"),
+        }
+    }
+}
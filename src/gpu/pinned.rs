@@ -0,0 +1,158 @@
+use super::error::GPUResult;
+use super::metrics::{is_metrics_enabled, timed, Stage};
+use super::watchdog;
+use log::warn;
+use ocl::traits::OclPrm;
+use ocl::{Buffer, MemFlags, Queue};
+use std::env;
+
+fn is_pinned_memory_enabled() -> bool {
+    env::var("BELLMAN_NO_PINNED_MEMORY").is_err()
+}
+
+/// Host staging memory for the transfers from and to GPU buffers.
+///
+/// The memory is allocated by the OpenCL driver with `CL_MEM_ALLOC_HOST_PTR`, which is
+/// page-locked on NVIDIA and AMD drivers, so that the copies between host and device run at the
+/// full PCIe bandwidth instead of going through pageable memory first. It's allocated on first
+/// use and grows to the largest transfer. If it can't be allocated, the transfers are done
+/// directly from and to the given slices.
+pub struct PinnedBuffer<T>
+where
+    T: OclPrm,
+{
+    buffer: Option<Buffer<T>>,
+    enabled: bool,
+}
+
+impl<T> PinnedBuffer<T>
+where
+    T: OclPrm,
+{
+    pub fn new() -> PinnedBuffer<T> {
+        PinnedBuffer {
+            buffer: None,
+            enabled: is_pinned_memory_enabled(),
+        }
+    }
+
+    fn staging(&mut self, queue: &Queue, len: usize) -> Option<&Buffer<T>> {
+        if !self.enabled {
+            return None;
+        }
+        if self.buffer.as_ref().map(|b| b.len() < len).unwrap_or(true) {
+            self.buffer = None; // Free the smaller buffer first
+            match Buffer::builder()
+                .queue(queue.clone())
+                .flags(MemFlags::new().read_write().alloc_host_ptr())
+                .len(len)
+                .build()
+            {
+                Ok(buffer) => self.buffer = Some(buffer),
+                Err(e) => {
+                    warn!(
+                        "Cannot allocate pinned host memory, using pageable memory! Error: {}",
+                        e
+                    );
+                    self.enabled = false;
+                }
+            }
+        }
+        self.buffer.as_ref()
+    }
+
+    /// Copies `data` to the beginning of `dst`, through the pinned memory.
+    pub fn write(&mut self, data: &[T], dst: &Buffer<T>, queue: &Queue) -> GPUResult<()> {
+        let bytes = std::mem::size_of_val(data) as u64;
+        timed(Stage::Upload, bytes, || self.write_inner(data, dst, queue))
+    }
+
+    fn write_inner(&mut self, data: &[T], dst: &Buffer<T>, queue: &Queue) -> GPUResult<()> {
+        let len = data.len();
+        match self.staging(queue, len) {
+            Some(staging) => {
+                unsafe {
+                    let mut map = staging
+                        .map()
+                        .queue(queue)
+                        .write_invalidate()
+                        .len(len)
+                        .enq()?;
+                    map.copy_from_slice(data);
+                    map.unmap().queue(queue).enq()?;
+                }
+                staging.copy(dst, None, Some(len)).queue(queue).enq()?;
+            }
+            None => dst.write(data).queue(queue).enq()?,
+        }
+        if is_metrics_enabled() {
+            watchdog::finish(queue)?; // The copy to the device is only timed once it's done
+        }
+        Ok(())
+    }
+
+    /// Copies the beginning of `src` to `data`, through the pinned memory.
+    pub fn read(&mut self, src: &Buffer<T>, data: &mut [T], queue: &Queue) -> GPUResult<()> {
+        let bytes = std::mem::size_of_val(data) as u64;
+        timed(Stage::Download, bytes, || self.read_inner(src, data, queue))
+    }
+
+    fn read_inner(&mut self, src: &Buffer<T>, data: &mut [T], queue: &Queue) -> GPUResult<()> {
+        // Reads block until the kernels writing `src` are done
+        watchdog::finish(queue)?;
+        let len = data.len();
+        match self.staging(queue, len) {
+            Some(staging) => {
+                src.copy(staging, None, Some(len)).queue(queue).enq()?;
+                unsafe {
+                    let mut map = staging.map().queue(queue).read().len(len).enq()?;
+                    data.copy_from_slice(&map[..]);
+                    map.unmap().queue(queue).enq()?;
+                }
+            }
+            None => src.read(data).queue(queue).enq()?,
+        }
+        Ok(())
+    }
+}
+
+impl<T> Default for PinnedBuffer<T>
+where
+    T: OclPrm,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Clears the staging memory, which holds a copy of the last transfer, before it's freed
+#[cfg(feature = "zeroize")]
+impl<T> Drop for PinnedBuffer<T>
+where
+    T: OclPrm,
+{
+    fn drop(&mut self) {
+        let buffer = match self.buffer {
+            Some(ref buffer) => buffer,
+            None => return,
+        };
+        let queue = match buffer.default_queue() {
+            Some(queue) => queue,
+            None => return,
+        };
+        let cleared = unsafe {
+            buffer
+                .map()
+                .queue(queue)
+                .write_invalidate()
+                .enq()
+                .and_then(|mut map| {
+                    crate::secret::zeroize(&mut map[..]);
+                    map.unmap().queue(queue).enq()
+                })
+        };
+        if let Err(e) = cleared {
+            warn!("Cannot clear the pinned host memory! Error: {}", e);
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use crate::gpu::{build_proque, get_assigned_devices, KernelKind};
+use crate::gpu::{
+    error::{GPUError, GPUResult},
+    locks, sources,
+    structs::{self, GpuRepr},
+};
+use crate::poseidon::PoseidonParams;
+use ff::PrimeField;
+use log::info;
+use ocl::{Buffer, MemFlags, ProQue};
+
+/// Largest width of the permutations of `PoseidonKernel`, the size of the states of its
+/// work-items.
+pub const POSEIDON_MAX_WIDTH: usize = 16;
+
+/// Hashes batches of Poseidon inputs on the GPU, e.g. the leaves of a tree, with the constants of
+/// `PoseidonParams` uploaded once. It runs on the devices of the FFT kernels, the Poseidon
+/// permutation being field arithmetic only.
+/// NOTE: The GPU lock is held for the whole lifetime of the kernel.
+pub struct PoseidonKernel<F>
+where
+    F: PrimeField,
+{
+    proque: ProQue,
+    params: PoseidonParams<F>,
+    constants: Buffer<structs::PrimeFieldStruct<F>>,
+    mds: Buffer<structs::PrimeFieldStruct<F>>,
+    _lock: locks::GPULock, // RFC 1857: struct fields are dropped in the same order as they are declared.
+}
+
+impl<F> PoseidonKernel<F>
+where
+    F: PrimeField,
+{
+    pub fn create(params: &PoseidonParams<F>) -> GPUResult<PoseidonKernel<F>> {
+        if params.width() > POSEIDON_MAX_WIDTH {
+            return Err(GPUError::Simple(
+                "Poseidon width is too large for the GPU kernel!",
+            ));
+        }
+
+        let devices = get_assigned_devices(KernelKind::FFT).unwrap_or_default();
+        let device = *devices
+            .first()
+            .ok_or(GPUError::Simple("No working GPUs found!"))?;
+        let lock = locks::GPULock::lock(&device);
+
+        let proque = build_proque(device, &sources::poseidon_kernel::<F>(), 1)?;
+        let upload = |values: &[F]| -> GPUResult<Buffer<structs::PrimeFieldStruct<F>>> {
+            Ok(Buffer::builder()
+                .queue(proque.queue().clone())
+                .flags(MemFlags::new().read_only())
+                .len(values.len())
+                .copy_host_slice(structs::PrimeFieldStruct::<F>::to_repr_slice(values))
+                .build()?)
+        };
+        let constants = upload(params.round_constants())?;
+        let mds = upload(params.mds())?;
+        info!(
+            "Poseidon: Kernel of width {} created on {}",
+            params.width(),
+            device.name()?
+        );
+
+        Ok(PoseidonKernel {
+            proque,
+            params: params.clone(),
+            constants,
+            mds,
+            _lock: lock,
+        })
+    }
+
+    /// Hashes each chunk of `arity` elements of `inputs`, as `PoseidonParams::hash_many` does.
+    pub fn hash_many(&mut self, inputs: &[F]) -> GPUResult<Vec<F>> {
+        let arity = self.params.arity();
+        let width = self.params.width();
+        if inputs.len() % arity != 0 {
+            return Err(GPUError::Simple(
+                "Number of Poseidon inputs isn't a multiple of the arity!",
+            ));
+        }
+        let n = inputs.len() / arity;
+        if n == 0 {
+            return Ok(vec![]);
+        }
+
+        let tag = self.params.domain_tag();
+        let mut states = Vec::with_capacity(n * width);
+        for chunk in inputs.chunks(arity) {
+            states.push(tag);
+            states.extend_from_slice(chunk);
+        }
+
+        let buffer = Buffer::builder()
+            .queue(self.proque.queue().clone())
+            .flags(MemFlags::new().read_write())
+            .len(states.len())
+            .copy_host_slice(structs::PrimeFieldStruct::<F>::to_repr_slice(&states))
+            .build()?;
+        let kernel = self
+            .proque
+            .kernel_builder("Fr_poseidon")
+            .global_work_size(n)
+            .arg(&buffer)
+            .arg(&self.constants)
+            .arg(&self.mds)
+            .arg(width as u32)
+            .arg(self.params.full_rounds() as u32)
+            .arg(self.params.partial_rounds() as u32)
+            .arg(n as u32)
+            .build()?;
+        unsafe {
+            kernel.enq()?;
+        } // Running a GPU kernel is unsafe!
+        buffer
+            .read(structs::PrimeFieldStruct::<F>::to_repr_slice_mut(
+                &mut states,
+            ))
+            .enq()?;
+
+        Ok(states.chunks(width).map(|state| state[1]).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ff::Field;
+    use paired::bls12_381::Fr;
+
+    #[test]
+    fn test_poseidon_kernel() {
+        let _ = env_logger::try_init();
+        let rng = &mut rand::thread_rng();
+
+        for &(width, partial_rounds) in &[(3, 57), (9, 63)] {
+            let params = PoseidonParams::<Fr>::new(width, 8, partial_rounds);
+            let inputs = (0..(width - 1) * 1000)
+                .map(|_| Fr::random(rng))
+                .collect::<Vec<_>>();
+            let mut kernel = PoseidonKernel::create(&params).unwrap();
+            assert_eq!(
+                kernel.hash_many(&inputs).unwrap(),
+                params.hash_many(&inputs)
+            );
+            assert!(kernel.hash_many(&inputs[1..]).is_err());
+        }
+    }
+}
@@ -0,0 +1,45 @@
+/*
+ * Poseidon permutations of `n` states of `width` elements, stored one after the other, with the
+ * `x^5` S-box. Each work-item permutes a single state in place; the round constants (`width`
+ * per round) and the row-major MDS matrix are shared by all of them.
+ */
+#define FIELD_POSEIDON_MAX_WIDTH 16
+
+FIELD FIELD_poseidon_sbox(FIELD x) {
+  return FIELD_mul(FIELD_sqr(FIELD_sqr(x)), x);
+}
+
+__kernel void FIELD_poseidon(__global FIELD* states,
+                        __global FIELD* constants,
+                        __global FIELD* mds,
+                        uint width,
+                        uint full_rounds,
+                        uint partial_rounds,
+                        uint n) {
+  const uint gid = get_global_id(0);
+  if(gid >= n) return;
+
+  FIELD state[FIELD_POSEIDON_MAX_WIDTH];
+  FIELD mixed[FIELD_POSEIDON_MAX_WIDTH];
+  for(uint i = 0; i < width; i++) state[i] = states[gid * width + i];
+
+  const uint half = full_rounds / 2;
+  for(uint r = 0; r < full_rounds + partial_rounds; r++) {
+    for(uint i = 0; i < width; i++) state[i] = FIELD_add(state[i], constants[r * width + i]);
+
+    if(r < half || r >= half + partial_rounds) {
+      for(uint i = 0; i < width; i++) state[i] = FIELD_poseidon_sbox(state[i]);
+    } else {
+      state[0] = FIELD_poseidon_sbox(state[0]);
+    }
+
+    for(uint i = 0; i < width; i++) {
+      FIELD acc = FIELD_ZERO;
+      for(uint j = 0; j < width; j++) acc = FIELD_add(acc, FIELD_mul(state[j], mds[i * width + j]));
+      mixed[i] = acc;
+    }
+    for(uint i = 0; i < width; i++) state[i] = mixed[i];
+  }
+
+  for(uint i = 0; i < width; i++) states[gid * width + i] = state[i];
+}
@@ -0,0 +1,48 @@
+use std::fmt;
+
+/// Priority of a process using the GPU. A process is asked to release the GPU as soon as a
+/// process of a strictly higher priority needs it (See `PriorityLock`), e.g. with Filecoin
+/// workloads, sealing is `Low`, WinningPoSt is `Medium` and WindowPoSt is `High`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    Low = 0,
+    Medium = 1,
+    High = 2,
+}
+
+impl Priority {
+    /// The levels a process of this priority has to give the GPU up to
+    pub fn higher(self) -> impl Iterator<Item = Priority> {
+        [Priority::Low, Priority::Medium, Priority::High]
+            .iter()
+            .cloned()
+            .filter(move |p| *p > self)
+    }
+}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Priority::Low
+    }
+}
+
+/// `true` is the highest priority, `false` the lowest, as with the former boolean flag.
+impl From<bool> for Priority {
+    fn from(priority: bool) -> Self {
+        if priority {
+            Priority::High
+        } else {
+            Priority::Low
+        }
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Priority::Low => write!(f, "low"),
+            Priority::Medium => write!(f, "medium"),
+            Priority::High => write!(f, "high"),
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use crate::gpu::error::GPUResult;
+use crate::gpu::metrics::{timed, Stage};
+use crate::gpu::{get_all_devices, sources};
+use log::{info, warn};
+use ocl::enums::{DeviceInfo, DeviceInfoResult, ProgramInfo, ProgramInfoResult};
+use ocl::{Device, ProQue, Program};
+use paired::Engine;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Directory of the compiled kernels, which may be set with `BELLMAN_KERNEL_CACHE_DIR`
+fn cache_dir() -> PathBuf {
+    match env::var("BELLMAN_KERNEL_CACHE_DIR") {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => {
+            let mut p = env::temp_dir();
+            p.push("bellman.kernels");
+            p
+        }
+    }
+}
+
+/// Compiled programs are only valid for the same source, device and driver
+fn cache_path(d: Device, src: &str) -> PathBuf {
+    let driver = match d.info(DeviceInfo::DriverVersion) {
+        Ok(DeviceInfoResult::DriverVersion(v)) => v,
+        _ => String::new(),
+    };
+    let hash = blake2s_simd::Params::new()
+        .hash_length(16)
+        .to_state()
+        .update(d.name().unwrap_or_default().as_bytes())
+        .update(driver.as_bytes())
+        .update(src.as_bytes())
+        .finalize();
+    let mut p = cache_dir();
+    p.push(format!("{}.bin", hash.to_hex()));
+    p
+}
+
+/// Builds a `ProQue` running `src` on `d`. The compiled program is cached on disk, so that
+/// the (Slow) compilation is only done once per device.
+pub fn build_proque(d: Device, src: &str, dims: usize) -> GPUResult<ProQue> {
+    timed(Stage::KernelCompile, 0, || {
+        load_or_build_proque(d, src, dims)
+    })
+}
+
+fn load_or_build_proque(d: Device, src: &str, dims: usize) -> GPUResult<ProQue> {
+    let platform = match d.info(DeviceInfo::Platform)? {
+        DeviceInfoResult::Platform(p) => ocl::Platform::new(p),
+        _ => ocl::Platform::default(),
+    };
+    let path = cache_path(d, src);
+
+    if let Ok(binary) = fs::read(&path) {
+        let binaries = [&binary[..]];
+        let mut prog = Program::builder();
+        prog.binaries(&binaries);
+        match ProQue::builder()
+            .platform(platform)
+            .device(d)
+            .prog_bldr(prog)
+            .dims(dims)
+            .build()
+        {
+            Ok(pq) => return Ok(pq),
+            Err(e) => warn!("Cannot load the cached kernel! Rebuilding... Error: {}", e),
+        }
+    }
+
+    let pq = ProQue::builder()
+        .platform(platform)
+        .device(d)
+        .src(src)
+        .dims(dims)
+        .build()?;
+
+    match pq.program().info(ProgramInfo::Binaries) {
+        Ok(ProgramInfoResult::Binaries(binaries)) if !binaries.is_empty() => {
+            // Written to a temporary file first, so that other processes never read a partial
+            // binary
+            let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+            match fs::create_dir_all(cache_dir())
+                .and_then(|_| fs::write(&tmp, &binaries[0]))
+                .and_then(|_| fs::rename(&tmp, &path))
+            {
+                Ok(_) => info!("Kernel cached in {:?}.", path),
+                Err(e) => warn!("Cannot cache the kernel in {:?}! Error: {}", path, e),
+            }
+        }
+        _ => warn!("Cannot read the compiled kernel!"),
+    }
+
+    Ok(pq)
+}
+
+/// Compiles the kernels of `E` for all the devices, so that creating the FFT and multiexp
+/// kernels later on doesn't have to wait for the compilation.
+pub fn precompile_kernels<E>() -> GPUResult<()>
+where
+    E: Engine,
+{
+    let srcs = [sources::fft_kernel::<E::Fr>(), sources::kernel::<E>()];
+    for d in get_all_devices()? {
+        info!("Compiling kernels for {}...", d.name()?);
+        for src in srcs.iter() {
+            build_proque(d, src, 1)?;
+        }
+    }
+    Ok(())
+}
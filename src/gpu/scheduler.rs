@@ -0,0 +1,260 @@
+use super::error::{GPUError, GPUResult};
+use super::{LockedFFTKernel, LockedMultiexpKernel, MultiexpProgress, Priority};
+use crate::multicore::Worker;
+use ff::PrimeField;
+use futures::sync::oneshot;
+use futures::{Async, Future, Poll};
+use groupy::CurveAffine;
+use log::{debug, error};
+use paired::Engine;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+struct FFTJob<E>
+where
+    E: Engine,
+{
+    a: Vec<E::Fr>,
+    omega: E::Fr,
+    lgn: u32,
+    result: oneshot::Sender<GPUResult<Vec<E::Fr>>>,
+}
+
+type MultiexpJob<E> = Box<dyn FnOnce(&mut LockedMultiexpKernel<E>, &Worker) + Send>;
+
+enum Job<E>
+where
+    E: Engine,
+{
+    FFT(FFTJob<E>),
+    Multiexp(MultiexpJob<E>),
+}
+
+/// Result of a job submitted to a `Scheduler`.
+pub struct SchedulerFuture<T> {
+    receiver: oneshot::Receiver<GPUResult<T>>,
+}
+
+impl<T> Future for SchedulerFuture<T> {
+    type Item = T;
+    type Error = GPUError;
+
+    fn poll(&mut self) -> Poll<T, GPUError> {
+        match self.receiver.poll() {
+            Ok(Async::Ready(Ok(v))) => Ok(Async::Ready(v)),
+            Ok(Async::Ready(Err(e))) => Err(e),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(oneshot::Canceled) => Err(GPUError::Simple("GPU scheduler is shut down!")),
+        }
+    }
+}
+
+/// Runs FFTs and multiexps on a worker thread which keeps its kernels (And the GPU locks) for
+/// its whole lifetime, so that repeated proving doesn't create and tear down kernels for every
+/// proof. Jobs can be submitted from any thread.
+///
+/// All of the jobs queued while the worker is busy are taken at once, and the FFTs among them
+/// of the same size and `omega` are batched with `radix_fft_many`.
+///
+/// The kernels are created on the first job and are freed and re-created like any other
+/// `LockedFFTKernel` / `LockedMultiexpKernel` when a process with a higher priority takes the GPU.
+pub struct Scheduler<E>
+where
+    E: Engine,
+{
+    sender: Mutex<Option<mpsc::Sender<Job<E>>>>,
+    worker: Option<thread::JoinHandle<()>>,
+}
+
+impl<E> Scheduler<E>
+where
+    E: Engine,
+{
+    /// Starts the worker thread. FFTs of up to 2^`log_d` elements are supported.
+    pub fn new(log_d: usize, priority: Priority) -> Scheduler<E> {
+        let (sender, receiver) = mpsc::channel();
+        let worker = thread::spawn(move || run::<E>(receiver, log_d, priority));
+        Scheduler {
+            sender: Mutex::new(Some(sender)),
+            worker: Some(worker),
+        }
+    }
+
+    fn submit(&self, job: Job<E>) {
+        if let Some(ref sender) = *self.sender.lock().unwrap() {
+            // If the worker is gone, the result sender of the job is dropped and the future fails
+            let _ = sender.send(job);
+        }
+    }
+
+    /// Performs FFT on `a`, which has 2^`lgn` elements, and returns it.
+    pub fn fft(&self, a: Vec<E::Fr>, omega: E::Fr, lgn: u32) -> SchedulerFuture<Vec<E::Fr>> {
+        let (result, receiver) = oneshot::channel();
+        self.submit(Job::FFT(FFTJob {
+            a,
+            omega,
+            lgn,
+            result,
+        }));
+        SchedulerFuture { receiver }
+    }
+
+    /// Multiexp of `n` bases starting from `bases[skip]` with `exps`, see
+    /// `MultiexpKernel::multiexp`.
+    pub fn multiexp<G>(
+        &self,
+        bases: Arc<Vec<G>>,
+        exps: Arc<Vec<<E::Fr as PrimeField>::Repr>>,
+        skip: usize,
+        n: usize,
+    ) -> SchedulerFuture<G::Projective>
+    where
+        G: CurveAffine<Engine = E>,
+    {
+        let (result, receiver) = oneshot::channel();
+        self.submit(Job::Multiexp(Box::new(
+            move |kern: &mut LockedMultiexpKernel<E>, pool: &Worker| {
+                let mut progress = MultiexpProgress::new();
+                let res = kern.with(|k| {
+                    k.multiexp_from(pool, bases.clone(), exps.clone(), skip, n, &mut progress)
+                });
+                let _ = result.send(res);
+            },
+        )));
+        SchedulerFuture { receiver }
+    }
+}
+
+impl<E> Drop for Scheduler<E>
+where
+    E: Engine,
+{
+    fn drop(&mut self) {
+        // Closing the channel stops the worker once the queued jobs are done
+        self.sender.lock().unwrap().take();
+        if let Some(worker) = self.worker.take() {
+            if worker.join().is_err() {
+                error!("GPU scheduler thread panicked!");
+            }
+        }
+    }
+}
+
+fn run<E>(receiver: mpsc::Receiver<Job<E>>, log_d: usize, priority: Priority)
+where
+    E: Engine,
+{
+    let pool = Worker::new();
+    let mut fft_kern = LockedFFTKernel::<E>::new(log_d, priority);
+    let mut multiexp_kern = LockedMultiexpKernel::<E>::new(log_d, priority);
+
+    while let Ok(job) = receiver.recv() {
+        let mut ffts = Vec::new();
+        for job in Some(job).into_iter().chain(receiver.try_iter()) {
+            match job {
+                Job::FFT(job) => ffts.push(job),
+                Job::Multiexp(job) => job(&mut multiexp_kern, &pool),
+            }
+        }
+        run_ffts(&mut fft_kern, ffts);
+    }
+}
+
+fn run_ffts<E>(kern: &mut LockedFFTKernel<E>, mut jobs: Vec<FFTJob<E>>)
+where
+    E: Engine,
+{
+    while !jobs.is_empty() {
+        let (lgn, omega) = (jobs[0].lgn, jobs[0].omega);
+        let (mut batch, rest): (Vec<_>, Vec<_>) = jobs
+            .into_iter()
+            .partition(|j| j.lgn == lgn && j.omega == omega);
+        jobs = rest;
+        debug!(
+            "Running a batch of {} FFTs of 2^{} elements.",
+            batch.len(),
+            lgn
+        );
+
+        let mut done = 0;
+        let res = kern.with(|k| {
+            let mut polys = batch[done..]
+                .iter_mut()
+                .map(|j| &mut j.a[..])
+                .collect::<Vec<_>>();
+            let mut batch_done = 0;
+            let res = k.radix_fft_many_from(&mut polys, &omega, lgn, &mut batch_done);
+            done += batch_done;
+            res
+        });
+
+        // The FFT that failed gets the actual error, the ones after it weren't run
+        let mut err = res.err();
+        for (i, job) in batch.into_iter().enumerate() {
+            let job_res = if i < done {
+                Ok(job.a)
+            } else {
+                Err(err
+                    .take()
+                    .unwrap_or(GPUError::Simple("A previous FFT of the batch failed!")))
+            };
+            let _ = job.result.send(job_res);
+        }
+    }
+}
+
+#[cfg(feature = "gpu")]
+#[test]
+pub fn scheduler_consistency() {
+    use crate::domain::{serial_fft, Scalar};
+    use crate::multiexp::{multiexp, FullDensity};
+    use ff::{Field, ScalarEngine};
+    use groupy::CurveProjective;
+    use paired::bls12_381::{Bls12, Fr};
+
+    let _ = env_logger::try_init();
+    let rng = &mut rand::thread_rng();
+    let pool = Worker::new();
+
+    const LOG_D: u32 = 16;
+    let scheduler = Scheduler::<Bls12>::new(LOG_D as usize, Priority::Low);
+
+    let mut omega = Fr::root_of_unity();
+    for _ in LOG_D..Fr::S {
+        omega.square();
+    }
+
+    // FFTs of the same size, which are batched if they are queued together
+    let inputs = (0..4)
+        .map(|_| (0..1 << LOG_D).map(|_| Fr::random(rng)).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let futures = inputs
+        .iter()
+        .map(|a| scheduler.fft(a.clone(), omega, LOG_D))
+        .collect::<Vec<_>>();
+    for (a, fut) in inputs.into_iter().zip(futures) {
+        let mut cpu = a.into_iter().map(Scalar::<Bls12>).collect::<Vec<_>>();
+        serial_fft::<Bls12, _>(&mut cpu, &omega, LOG_D);
+        let gpu = fut.wait().unwrap();
+        assert!(cpu.iter().map(|s| s.0).eq(gpu.into_iter()));
+    }
+
+    let bases = Arc::new(
+        (0..1 << LOG_D)
+            .map(|_| <Bls12 as Engine>::G1::random(rng).into_affine())
+            .collect::<Vec<_>>(),
+    );
+    let exps = Arc::new(
+        (0..1 << LOG_D)
+            .map(|_| <Bls12 as ScalarEngine>::Fr::random(rng).into_repr())
+            .collect::<Vec<_>>(),
+    );
+    let gpu = scheduler
+        .multiexp(bases.clone(), exps.clone(), 0, 1 << LOG_D)
+        .wait()
+        .unwrap();
+    let cpu = multiexp(&pool, (bases, 0), FullDensity, exps, &mut None)
+        .wait()
+        .unwrap();
+    assert_eq!(cpu, gpu);
+}
@@ -0,0 +1,121 @@
+use crate::domain::{serial_fft, Scalar};
+use crate::gpu::error::{GPUError, GPUResult};
+use crate::gpu::{
+    get_all_devices, get_core_count, get_memory, FFTKernel, MultiexpKernel, Priority,
+};
+use crate::multicore::Worker;
+use ff::{Field, PrimeField};
+use groupy::{CurveAffine, CurveProjective};
+use log::{info, warn};
+use ocl::Device;
+use paired::bls12_381::{Bls12, Fr, G1};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// Small enough to run on any card in a fraction of a second
+const SELFTEST_LOG_N: u32 = 10;
+
+/// Result of `selftest` on a single device
+#[derive(Debug, Clone)]
+pub struct SelfTestReport {
+    pub name: String,
+    /// Global memory size in bytes
+    pub memory: u64,
+    pub core_count: usize,
+    /// `None` if the device passed the test, otherwise what went wrong
+    pub error: Option<String>,
+    pub elapsed: Duration,
+}
+
+impl SelfTestReport {
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Runs a tiny FFT and multiexp on each detected device and compares them with the results of
+/// the CPU, so that rigs can be validated before committing to a long proving run.
+pub fn selftest() -> GPUResult<Vec<SelfTestReport>> {
+    let devices = get_all_devices()?;
+    Ok(devices.iter().map(test_device).collect())
+}
+
+fn test_device(d: &Device) -> SelfTestReport {
+    let now = Instant::now();
+    let error = test_fft(d)
+        .and_then(|_| test_multiexp(d))
+        .err()
+        .map(|e| e.to_string());
+    let report = SelfTestReport {
+        name: d.name().unwrap_or_default(),
+        memory: get_memory(*d).unwrap_or_default(),
+        core_count: get_core_count(*d).unwrap_or_default(),
+        error,
+        elapsed: now.elapsed(),
+    };
+    match report.error {
+        None => info!("Self-test of {} passed!", report.name),
+        Some(ref e) => warn!("Self-test of {} failed! Error: {}", report.name, e),
+    }
+    report
+}
+
+fn test_fft(d: &Device) -> GPUResult<()> {
+    let rng = &mut rand::thread_rng();
+    let mut kern = FFTKernel::<Fr>::create_on(d, 1 << SELFTEST_LOG_N, Priority::Low)?;
+
+    let mut omega = Fr::root_of_unity();
+    for _ in SELFTEST_LOG_N..Fr::S {
+        omega.square();
+    }
+
+    let mut gpu = (0..1 << SELFTEST_LOG_N)
+        .map(|_| Fr::random(rng))
+        .collect::<Vec<_>>();
+    let mut cpu = gpu.iter().map(|&e| Scalar::<Bls12>(e)).collect::<Vec<_>>();
+
+    kern.radix_fft(&mut gpu, &omega, SELFTEST_LOG_N)?;
+    serial_fft::<Bls12, _>(&mut cpu, &omega, SELFTEST_LOG_N);
+
+    if gpu.iter().zip(cpu.iter()).any(|(g, c)| *g != c.0) {
+        return Err(GPUError::Simple("FFT result doesn't match the CPU!"));
+    }
+    Ok(())
+}
+
+fn test_multiexp(d: &Device) -> GPUResult<()> {
+    let rng = &mut rand::thread_rng();
+    let mut kern = MultiexpKernel::<Bls12>::create_on(d, Priority::Low)?;
+
+    let n = 1 << SELFTEST_LOG_N;
+    let bases = Arc::new(
+        (0..n)
+            .map(|_| G1::random(rng).into_affine())
+            .collect::<Vec<_>>(),
+    );
+    let exps = Arc::new(
+        (0..n)
+            .map(|_| Fr::random(rng).into_repr())
+            .collect::<Vec<_>>(),
+    );
+
+    let mut expected = G1::zero();
+    for (base, exp) in bases.iter().zip(exps.iter()) {
+        expected.add_assign(&base.mul(*exp));
+    }
+    let result = kern.multiexp(&Worker::new(), bases, exps, 0, n)?;
+
+    if result != expected {
+        return Err(GPUError::Simple("Multiexp result doesn't match the CPU!"));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "gpu")]
+#[test]
+pub fn test_selftest() {
+    let _ = env_logger::try_init();
+    for report in selftest().expect("Cannot run the self-test!") {
+        assert!(report.passed(), "{:?}", report);
+    }
+}
@@ -1,5 +1,5 @@
+use ff::PrimeField;
 use ff_cl_gen as ffgen;
-use log::debug;
 use paired::Engine;
 
 // Instead of having a very large OpenCL program written for a specific curve, with a lot of
@@ -7,9 +7,12 @@ use paired::Engine;
 // generate OpenCL codes given different PrimeFields and curves.
 
 static FFT_SRC: &str = include_str!("fft/fft.cl");
+static POLYNOMIAL_SRC: &str = include_str!("fft/polynomial.cl");
 static FIELD2_SRC: &str = include_str!("multiexp/field2.cl");
 static EC_SRC: &str = include_str!("multiexp/ec.cl");
 static MULTIEXP_SRC: &str = include_str!("multiexp/multiexp.cl");
+static TRANSPOSE_SRC: &str = include_str!("transpose/transpose.cl");
+static POSEIDON_SRC: &str = include_str!("poseidon/poseidon.cl");
 
 fn field2(field2: &str, field: &str) -> String {
     String::from(FIELD2_SRC)
@@ -21,6 +24,10 @@ fn fft(field: &str) -> String {
     String::from(FFT_SRC).replace("FIELD", field)
 }
 
+fn polynomial(field: &str) -> String {
+    String::from(POLYNOMIAL_SRC).replace("FIELD", field)
+}
+
 fn ec(field: &str, point: &str) -> String {
     String::from(EC_SRC)
         .replace("FIELD", field)
@@ -33,20 +40,52 @@ fn multiexp(point: &str, exp: &str) -> String {
         .replace("EXPONENT", exp)
 }
 
+fn transpose(element: &str) -> String {
+    String::from(TRANSPOSE_SRC).replace("ELEMENT", element)
+}
+
+fn poseidon(field: &str) -> String {
+    String::from(POSEIDON_SRC).replace("FIELD", field)
+}
+
+/// Source of the FFT kernels over the prime field `F`. The limb arithmetic is generated from the
+/// modulus and Montgomery constants of `F`, so it works with any `PrimeField`.
+pub fn fft_kernel<F>() -> String
+where
+    F: PrimeField,
+{
+    vec![
+        ffgen::field::<F>("Fr"),
+        fft("Fr"),
+        polynomial("Fr"),
+        transpose("Fr"),
+    ]
+    .join("\n\n")
+}
+
+/// Source of the Poseidon kernel over the prime field `F`.
+pub fn poseidon_kernel<F>() -> String
+where
+    F: PrimeField,
+{
+    vec![ffgen::field::<F>("Fr"), poseidon("Fr")].join("\n\n")
+}
+
 // WARNING: This function works only with Short Weierstrass Jacobian curves with Fq2 extension field.
 pub fn kernel<E>() -> String
 where
     E: Engine,
 {
     vec![
-        ffgen::field::<E::Fr>("Fr"),
-        fft("Fr"),
+        fft_kernel::<E::Fr>(),
         ffgen::field::<E::Fq>("Fq"),
         ec("Fq", "G1"),
         multiexp("G1", "Fr"),
+        transpose("G1_projective"),
         field2("Fq2", "Fq"),
         ec("Fq2", "G2"),
         multiexp("G2", "Fr"),
+        transpose("G2_projective"),
     ]
     .join("\n\n")
 }
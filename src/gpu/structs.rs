@@ -1,14 +1,81 @@
-use ff::PrimeField;
+use ff::{PrimeField, PrimeFieldRepr};
 use groupy::{CurveAffine, CurveProjective};
 use ocl::traits::OclPrm;
 
+use std::any::TypeId;
+use std::mem;
+use std::slice;
+
 // Everything that needs to be copied to GPU needs to implement OclPrm trait.
-// This module implements a generic OclPrm version of PrimeField, CurveAffine and CurveProjective
-// so that they can be copied to GPU.
-// A transmute from [PrimeField] to [PrimeFieldStruct] is safe as their sizes are equal.
-// Also safe for [CurveAffine] to [CurveAffineStruct] and [CurveProjective] to [CurveProjectiveStruct]
-// It costs less memory to just transmute the data sources for both FFT and Multiexp to their
-// OpenCL friendly equivalents instead of mapping them to a new array with each element casted.
+// This module implements a generic OclPrm version of PrimeField, PrimeFieldRepr, CurveAffine and
+// CurveProjective so that they can be copied to GPU.
+// The wrappers are `repr(transparent)`, so a slice of values has the layout of a slice of their
+// wrappers, and `GpuRepr` reinterprets one as the other. It costs less memory than mapping the
+// data sources for both FFT and Multiexp to a new array with each element casted.
+
+/// A `repr(transparent)` wrapper of `Self::Value` which can be copied to the GPU. Slices of
+/// values are reinterpreted as slices of their wrappers and back, without copying them.
+///
+/// # Safety
+///
+/// `Self` must have the layout of `Self::Value`.
+pub unsafe trait GpuRepr: OclPrm {
+    type Value: 'static;
+
+    fn to_repr_slice(values: &[Self::Value]) -> &[Self] {
+        assert_same_layout::<Self::Value, Self>();
+        // Safety: `Self` has the layout of `Self::Value`
+        unsafe { slice::from_raw_parts(values.as_ptr() as *const Self, values.len()) }
+    }
+
+    fn to_repr_slice_mut(values: &mut [Self::Value]) -> &mut [Self] {
+        assert_same_layout::<Self::Value, Self>();
+        // Safety: `Self` has the layout of `Self::Value`
+        unsafe { slice::from_raw_parts_mut(values.as_mut_ptr() as *mut Self, values.len()) }
+    }
+
+    fn from_repr_slice(reprs: &[Self]) -> &[Self::Value] {
+        assert_same_layout::<Self::Value, Self>();
+        // Safety: `Self` has the layout of `Self::Value`
+        unsafe { slice::from_raw_parts(reprs.as_ptr() as *const Self::Value, reprs.len()) }
+    }
+
+    fn from_repr_slice_mut(reprs: &mut [Self]) -> &mut [Self::Value] {
+        assert_same_layout::<Self::Value, Self>();
+        // Safety: `Self` has the layout of `Self::Value`
+        unsafe { slice::from_raw_parts_mut(reprs.as_mut_ptr() as *mut Self::Value, reprs.len()) }
+    }
+
+    /// `to_repr_slice` for values of a generic type `V`, if it is `Self::Value`. Used by the
+    /// kernels which are generic over the group they run on.
+    fn to_repr_slice_of<V: 'static>(values: &[V]) -> Option<&[Self]> {
+        if TypeId::of::<V>() != TypeId::of::<Self::Value>() {
+            return None;
+        }
+        // Safety: `V` is `Self::Value`
+        let values =
+            unsafe { slice::from_raw_parts(values.as_ptr() as *const Self::Value, values.len()) };
+        Some(Self::to_repr_slice(values))
+    }
+
+    /// `to_repr_slice_mut` for values of a generic type `V`, if it is `Self::Value`.
+    fn to_repr_slice_mut_of<V: 'static>(values: &mut [V]) -> Option<&mut [Self]> {
+        if TypeId::of::<V>() != TypeId::of::<Self::Value>() {
+            return None;
+        }
+        // Safety: `V` is `Self::Value`
+        let values = unsafe {
+            slice::from_raw_parts_mut(values.as_mut_ptr() as *mut Self::Value, values.len())
+        };
+        Some(Self::to_repr_slice_mut(values))
+    }
+}
+
+// The sizes and alignments are constants, so the assertions are folded by the compiler
+fn assert_same_layout<A, B>() {
+    assert_eq!(mem::size_of::<A>(), mem::size_of::<B>());
+    assert_eq!(mem::align_of::<A>(), mem::align_of::<B>());
+}
 
 #[derive(PartialEq, Debug, Clone, Copy)]
 #[repr(transparent)]
@@ -22,6 +89,24 @@ where
     }
 }
 unsafe impl<T> OclPrm for PrimeFieldStruct<T> where T: PrimeField {}
+unsafe impl<T> GpuRepr for PrimeFieldStruct<T>
+where
+    T: PrimeField,
+{
+    type Value = T;
+}
+
+/// The exponents of the multiexps, which are in their canonical (Not Montgomery) representation.
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+#[repr(transparent)]
+pub struct PrimeFieldReprStruct<T>(pub T);
+unsafe impl<T> OclPrm for PrimeFieldReprStruct<T> where T: PrimeFieldRepr {}
+unsafe impl<T> GpuRepr for PrimeFieldReprStruct<T>
+where
+    T: PrimeFieldRepr,
+{
+    type Value = T;
+}
 
 #[derive(PartialEq, Debug, Clone, Copy)]
 #[repr(transparent)]
@@ -35,6 +120,12 @@ where
     }
 }
 unsafe impl<T> OclPrm for CurveAffineStruct<T> where T: CurveAffine {}
+unsafe impl<T> GpuRepr for CurveAffineStruct<T>
+where
+    T: CurveAffine,
+{
+    type Value = T;
+}
 
 #[derive(PartialEq, Debug, Clone, Copy)]
 #[repr(transparent)]
@@ -48,3 +139,38 @@ where
     }
 }
 unsafe impl<T> OclPrm for CurveProjectiveStruct<T> where T: CurveProjective {}
+unsafe impl<T> GpuRepr for CurveProjectiveStruct<T>
+where
+    T: CurveProjective,
+{
+    type Value = T;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ff::Field;
+    use paired::bls12_381::{Fr, G1Affine, G1, G2};
+
+    #[test]
+    fn test_gpu_repr() {
+        let rng = &mut rand::thread_rng();
+        let mut values = (0..8).map(|_| Fr::random(rng)).collect::<Vec<_>>();
+        let expected = values.clone();
+
+        let reprs = PrimeFieldStruct::<Fr>::to_repr_slice(&values);
+        assert_eq!(reprs.len(), 8);
+        assert!(reprs.iter().zip(expected.iter()).all(|(r, v)| r.0 == *v));
+        assert_eq!(PrimeFieldStruct::from_repr_slice(reprs), &expected[..]);
+
+        let reprs = PrimeFieldStruct::<Fr>::to_repr_slice_mut(&mut values);
+        reprs[0] = PrimeFieldStruct::default();
+        assert!(PrimeFieldStruct::from_repr_slice_mut(reprs)[0].is_zero());
+        assert!(values[0].is_zero());
+
+        let mut points = vec![G1::zero(); 4];
+        assert!(CurveProjectiveStruct::<G1>::to_repr_slice_mut_of(&mut points).is_some());
+        assert!(CurveProjectiveStruct::<G2>::to_repr_slice_mut_of(&mut points).is_none());
+        assert!(CurveAffineStruct::<G1Affine>::to_repr_slice_of(&points).is_none());
+    }
+}
@@ -0,0 +1,148 @@
+use crate::gpu::{
+    error::{GPUError, GPUResult},
+    structs,
+};
+use ocl::traits::OclPrm;
+use ocl::{Buffer, ProQue};
+use paired::Engine;
+
+// Safe wrappers around the `*_transpose` kernels of `transpose.cl`. Each element type has its own
+// function, so that a buffer can't be passed to the kernel of another type. The transpose is done
+// out of place, `src` and `dst` should be different buffers.
+
+/// Transposes `batch` row-major `rows` x `cols` matrices of field elements from `src` into `dst`
+pub fn transpose_fr<E: Engine>(
+    proque: &ProQue,
+    src: &Buffer<structs::PrimeFieldStruct<E::Fr>>,
+    dst: &Buffer<structs::PrimeFieldStruct<E::Fr>>,
+    rows: usize,
+    cols: usize,
+    batch: usize,
+) -> GPUResult<()> {
+    transpose(proque, "Fr_transpose", src, dst, rows, cols, batch)
+}
+
+/// Transposes `batch` row-major `rows` x `cols` matrices of G1 points from `src` into `dst`
+pub fn transpose_g1<E: Engine>(
+    proque: &ProQue,
+    src: &Buffer<structs::CurveProjectiveStruct<E::G1>>,
+    dst: &Buffer<structs::CurveProjectiveStruct<E::G1>>,
+    rows: usize,
+    cols: usize,
+    batch: usize,
+) -> GPUResult<()> {
+    transpose(
+        proque,
+        "G1_projective_transpose",
+        src,
+        dst,
+        rows,
+        cols,
+        batch,
+    )
+}
+
+/// Transposes `batch` row-major `rows` x `cols` matrices of G2 points from `src` into `dst`
+pub fn transpose_g2<E: Engine>(
+    proque: &ProQue,
+    src: &Buffer<structs::CurveProjectiveStruct<E::G2>>,
+    dst: &Buffer<structs::CurveProjectiveStruct<E::G2>>,
+    rows: usize,
+    cols: usize,
+    batch: usize,
+) -> GPUResult<()> {
+    transpose(
+        proque,
+        "G2_projective_transpose",
+        src,
+        dst,
+        rows,
+        cols,
+        batch,
+    )
+}
+
+fn transpose<T: OclPrm>(
+    proque: &ProQue,
+    name: &str,
+    src: &Buffer<T>,
+    dst: &Buffer<T>,
+    rows: usize,
+    cols: usize,
+    batch: usize,
+) -> GPUResult<()> {
+    let len = rows * cols * batch;
+    if len == 0 {
+        return Ok(());
+    }
+    if src.len() < len || dst.len() < len {
+        return Err(GPUError::Simple(
+            "Matrices are larger than the transpose buffers!",
+        ));
+    }
+
+    let kernel = proque
+        .kernel_builder(name)
+        .global_work_size([cols, rows, batch])
+        .arg(src)
+        .arg(dst)
+        .arg(rows as u32)
+        .arg(cols as u32)
+        .build()?;
+    unsafe {
+        kernel.enq()?;
+    } // Running a GPU kernel is unsafe!
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpu::{get_devices, get_platform, sources};
+    use ff::Field;
+    use ocl::MemFlags;
+    use paired::bls12_381::{Bls12, Fr};
+
+    #[test]
+    fn test_transpose_fr() {
+        let _ = env_logger::try_init();
+        let rng = &mut rand::thread_rng();
+
+        let platform = get_platform(None).expect("Cannot find platform!");
+        let device = get_devices(&platform).expect("Cannot list devices!")[0];
+        let proque = ProQue::builder()
+            .platform(platform)
+            .device(device)
+            .src(sources::kernel::<Bls12>())
+            .dims(1)
+            .build()
+            .expect("Cannot build program!");
+
+        let (rows, cols, batch) = (37, 64, 3);
+        let elems = (0..rows * cols * batch)
+            .map(|_| structs::PrimeFieldStruct::<Fr>(Fr::random(rng)))
+            .collect::<Vec<_>>();
+        let buffer = || {
+            Buffer::builder()
+                .queue(proque.queue().clone())
+                .flags(MemFlags::new().read_write())
+                .len(elems.len())
+                .build()
+                .unwrap()
+        };
+        let (src, dst) = (buffer(), buffer());
+        src.write(&elems).enq().unwrap();
+        transpose_fr::<Bls12>(&proque, &src, &dst, rows, cols, batch).unwrap();
+        let mut result = vec![structs::PrimeFieldStruct::<Fr>::default(); elems.len()];
+        dst.read(&mut result).enq().unwrap();
+
+        for b in 0..batch {
+            let off = b * rows * cols;
+            for r in 0..rows {
+                for c in 0..cols {
+                    assert_eq!(result[off + c * rows + r], elems[off + r * cols + c]);
+                }
+            }
+        }
+    }
+}
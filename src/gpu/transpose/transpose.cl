@@ -0,0 +1,14 @@
+/*
+ * Out-of-place transpose of a batch of row-major `rows` x `cols` matrices, stored one after the
+ * other. Each work-item moves a single element, dimension 0 being the column, dimension 1 the
+ * row and dimension 2 the index of the matrix in the batch.
+ */
+__kernel void ELEMENT_transpose(__global ELEMENT* src,
+                        __global ELEMENT* dst,
+                        uint rows,
+                        uint cols) {
+  const uint c = get_global_id(0);
+  const uint r = get_global_id(1);
+  const uint offset = get_global_id(2) * rows * cols;
+  if(c < cols && r < rows) dst[offset + c * rows + r] = src[offset + r * cols + c];
+}
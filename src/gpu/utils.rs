@@ -2,13 +2,132 @@ use crate::gpu::error::{GPUError, GPUResult};
 use ocl::{Device, Platform};
 
 use log::{info, warn};
+use serde::Deserialize;
+use std::cmp;
 use std::collections::HashMap;
 use std::env;
+use std::fmt;
+use std::fs;
+use std::time::Duration;
 
 pub const GPU_NVIDIA_PLATFORM_NAME: &str = "NVIDIA CUDA";
 pub const GPU_AMD_PLATFORM_NAME: &str = "AMD Accelerated Parallel Processing";
 //pub const CPU_INTEL_PLATFORM_NAME: &str = "Intel(R) CPU Runtime for OpenCL(TM) Applications";
 
+/// GPU settings read from the TOML file set by `BELLMAN_GPU_CONFIG`, e.g.
+///
+/// ```toml
+/// blacklist = ["GeForce GTX 1050"]
+/// whitelist = ["GeForce RTX 3090", "3"]
+/// fft_gpus = [0]
+/// multiexp_gpus = [1, 2]
+/// kernel_timeout = 600
+/// reinit_on_timeout = true
+///
+/// [[device]]
+/// name = "GeForce RTX 3090"
+/// cores = 10496
+/// memory_limit = 20000
+/// work_group_size = 128
+/// multiexp_window_size = 9
+/// ```
+///
+/// Environment variables take precedence over the config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct GPUConfig {
+    /// Names or bus ids of the devices that should never be used, like `BELLMAN_GPU_BLACKLIST`
+    pub blacklist: Vec<String>,
+    /// Names or bus ids of the only devices that should be used, like `BELLMAN_GPU_WHITELIST`
+    pub whitelist: Option<Vec<String>>,
+    /// Indices of the devices used by the FFT kernel, like `BELLMAN_FFT_GPU`
+    pub fft_gpus: Option<Vec<usize>>,
+    /// Indices of the devices used by the multiexp kernel, like `BELLMAN_MULTIEXP_GPU`
+    pub multiexp_gpus: Option<Vec<usize>>,
+    /// In seconds, like `BELLMAN_GPU_TIMEOUT`
+    pub kernel_timeout: Option<u64>,
+    /// Like `BELLMAN_GPU_TIMEOUT_REINIT`
+    pub reinit_on_timeout: bool,
+    #[serde(rename = "device")]
+    pub devices: Vec<DeviceConfig>,
+}
+
+/// Settings of the devices with the given name
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct DeviceConfig {
+    pub name: String,
+    /// Number of cores, like `BELLMAN_CUSTOM_GPU`
+    pub cores: Option<usize>,
+    /// In MiB, like `BELLMAN_GPU_MEMORY_LIMIT`
+    pub memory_limit: Option<u64>,
+    /// Maximum work-group size of the kernels
+    pub work_group_size: Option<usize>,
+    /// Window size of the multiexp kernel, chosen from the input size otherwise
+    pub multiexp_window_size: Option<usize>,
+}
+
+lazy_static::lazy_static! {
+    static ref GPU_CONFIG: GPUConfig = match env::var("BELLMAN_GPU_CONFIG") {
+        Ok(path) => match fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|config| toml::from_str(&config).map_err(|e| e.to_string()))
+        {
+            Ok(config) => {
+                info!("GPU config read from {}.", path);
+                config
+            }
+            Err(e) => {
+                warn!("Cannot read the GPU config from {}! Error: {}", path, e);
+                GPUConfig::default()
+            }
+        },
+        Err(_) => GPUConfig::default(),
+    };
+}
+
+/// The config set by `BELLMAN_GPU_CONFIG`, empty if there is none
+pub fn get_config() -> &'static GPUConfig {
+    &GPU_CONFIG
+}
+
+/// The settings of `d` in the GPU config, if any
+pub fn get_device_config(d: Device) -> Option<&'static DeviceConfig> {
+    let name = d.name().ok()?;
+    GPU_CONFIG.devices.iter().find(|c| c.name == name)
+}
+
+/// Maximum work-group size of the kernels on `d`, if set in the GPU config
+pub fn get_work_group_size(d: Device) -> Option<usize> {
+    get_device_config(d).and_then(|c| c.work_group_size)
+}
+
+/// Window size of the multiexp kernel on `d`, if set in the GPU config
+pub fn get_multiexp_window_size(d: Device) -> Option<usize> {
+    get_device_config(d).and_then(|c| c.multiexp_window_size)
+}
+
+/// Time allowed for the commands enqueued on a GPU to complete, set in seconds by the
+/// `BELLMAN_GPU_TIMEOUT` env var or the GPU config. There is no timeout by default.
+pub fn get_kernel_timeout() -> Option<Duration> {
+    match env::var("BELLMAN_GPU_TIMEOUT") {
+        Ok(secs) => match secs.trim().parse::<u64>() {
+            Ok(secs) => Some(Duration::from_secs(secs)),
+            Err(_) => {
+                warn!("Invalid BELLMAN_GPU_TIMEOUT! Ignoring...");
+                None
+            }
+        },
+        Err(_) => GPU_CONFIG.kernel_timeout.map(Duration::from_secs),
+    }
+}
+
+/// Whether kernels that timed out are re-created and retried once (Instead of falling back to
+/// the CPU right away), set by the `BELLMAN_GPU_TIMEOUT_REINIT` env var or the GPU config.
+pub fn is_reinit_on_timeout() -> bool {
+    env::var("BELLMAN_GPU_TIMEOUT_REINIT").is_ok() || GPU_CONFIG.reinit_on_timeout
+}
+
 fn find_platform(platform_name: &str) -> GPUResult<Platform> {
     if env::var("BELLMAN_NO_GPU").is_ok() {
         return Err(GPUError::Simple("GPU accelerator is disabled!"));
@@ -49,7 +168,203 @@ pub fn get_devices(platform: &Platform) -> GPUResult<Vec<Device>> {
     if env::var("BELLMAN_NO_GPU").is_ok() {
         return Err(GPUError::Simple("GPU accelerator is disabled!"));
     }
-    Ok(Device::list_all(platform)?)
+    Ok(filter_devices(Device::list_all(platform)?))
+}
+
+/// The devices the kernels should choose from: the GPUs of all of the platforms (NVIDIA, AMD,
+/// Intel...), or all of the devices of the platform set by `BELLMAN_PLATFORM`. They are ordered
+/// from the most capable one (By number of cores, then by memory), so that the best devices
+/// are preferred regardless of their vendor.
+pub fn get_all_devices() -> GPUResult<Vec<Device>> {
+    if env::var("BELLMAN_NO_GPU").is_ok() {
+        return Err(GPUError::Simple("GPU accelerator is disabled!"));
+    }
+    let mut devices = match env::var("BELLMAN_PLATFORM") {
+        Ok(name) => {
+            info!("Platform set by environment: {}", name);
+            get_devices(&find_platform(&name)?)?
+        }
+        Err(_) => {
+            let mut devices = Vec::new();
+            for p in Platform::list()? {
+                let gpus = Device::list(p, Some(ocl::flags::DEVICE_TYPE_GPU)).unwrap_or_default();
+                devices.extend(filter_devices(gpus));
+            }
+            devices
+        }
+    };
+    // The sort is stable, so equally capable devices keep the order of the platforms
+    devices.sort_by_key(|d| {
+        cmp::Reverse((
+            lookup_core_count(*d).unwrap_or(DEFAULT_CORE_COUNT),
+            get_memory(*d).unwrap_or(0),
+        ))
+    });
+    Ok(devices)
+}
+
+// Devices are listed on every call, so that the ones which were unplugged or reset since the
+// last call are skipped.
+fn filter_devices(devices: Vec<Device>) -> Vec<Device> {
+    let devices = devices
+        .into_iter()
+        .filter(|d| {
+            let available = is_device_available(*d);
+            if !available {
+                warn!(
+                    "Skipping unavailable device: {}",
+                    d.name().unwrap_or_default()
+                );
+            }
+            available
+        })
+        .collect::<Vec<_>>();
+    devices
+        .into_iter()
+        .filter(|d| {
+            let allowed = is_device_allowed(*d);
+            if !allowed {
+                info!(
+                    "Skipping blacklisted device: {}",
+                    d.name().unwrap_or_default()
+                );
+            }
+            allowed
+        })
+        .collect()
+}
+
+// Comma separated list of device names or bus ids set by `var`
+fn get_device_list_env(var: &str) -> Option<Vec<String>> {
+    env::var(var)
+        .ok()
+        .map(|list| list.split(',').map(|e| e.trim().to_string()).collect())
+}
+
+// Whether an entry of a blacklist or whitelist is the name or the bus id of a device
+fn device_matches(entry: &str, name: &str, bus_id: Option<u32>) -> bool {
+    entry == name || (bus_id.is_some() && entry.parse::<u32>().ok() == bus_id)
+}
+
+fn is_allowed_by(
+    name: &str,
+    bus_id: Option<u32>,
+    blacklist: &[String],
+    whitelist: Option<&[String]>,
+) -> bool {
+    let matches = |list: &[String]| list.iter().any(|e| device_matches(e, name, bus_id));
+    !matches(blacklist) && whitelist.map(matches).unwrap_or(true)
+}
+
+/// Whether `d` may be used by the kernels. Devices listed in `BELLMAN_GPU_BLACKLIST` are
+/// excluded, and when `BELLMAN_GPU_WHITELIST` is set, only the devices listed there are used
+/// (Comma separated device names or PCI bus ids). The env vars replace the `blacklist` and
+/// `whitelist` of the GPU config.
+pub fn is_device_allowed(d: Device) -> bool {
+    let blacklist = get_device_list_env("BELLMAN_GPU_BLACKLIST");
+    let whitelist = get_device_list_env("BELLMAN_GPU_WHITELIST");
+    is_allowed_by(
+        &d.name().unwrap_or_default(),
+        get_bus_id(d),
+        blacklist.as_ref().unwrap_or(&GPU_CONFIG.blacklist),
+        whitelist
+            .as_ref()
+            .or_else(|| GPU_CONFIG.whitelist.as_ref())
+            .map(|w| &w[..]),
+    )
+}
+
+/// Index of the device set by `BELLMAN_GPU_INDEX`, among the devices returned by
+/// `get_all_devices`
+pub fn get_gpu_index() -> Option<usize> {
+    match env::var("BELLMAN_GPU_INDEX") {
+        Ok(index) => match index.trim().parse() {
+            Ok(index) => Some(index),
+            Err(_) => {
+                warn!("Invalid BELLMAN_GPU_INDEX! Using all of the devices...");
+                None
+            }
+        },
+        Err(_) => None,
+    }
+}
+
+/// Devices that the kernels should use: all of the devices of `get_all_devices`, or only the
+/// one set by `BELLMAN_GPU_INDEX`.
+pub fn get_selected_devices() -> GPUResult<Vec<Device>> {
+    let devices = get_all_devices()?;
+    match get_gpu_index() {
+        Some(index) => match devices.get(index) {
+            Some(d) => {
+                info!("Device set by environment: {}", index);
+                Ok(vec![*d])
+            }
+            None => Err(GPUError::Simple("BELLMAN_GPU_INDEX is out of range!")),
+        },
+        None => Ok(devices),
+    }
+}
+
+/// The kinds of GPU kernels, which may be assigned to different devices
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KernelKind {
+    FFT,
+    Multiexp,
+}
+
+/// Devices that kernels of the given kind should use. By default, when there is more than one
+/// device (And `BELLMAN_GPU_INDEX` isn't set), FFTs run on the last (Least capable) device and
+/// multiexps on all of the others, so that proofs running in parallel don't compete for the same
+/// GPU. This can be overridden with `BELLMAN_FFT_GPU` and `BELLMAN_MULTIEXP_GPU` (Comma
+/// separated indices of `get_all_devices`).
+pub fn get_assigned_devices(kind: KernelKind) -> GPUResult<Vec<Device>> {
+    let var = match kind {
+        KernelKind::FFT => "BELLMAN_FFT_GPU",
+        KernelKind::Multiexp => "BELLMAN_MULTIEXP_GPU",
+    };
+    if let Ok(indices) = env::var(var) {
+        let devices = get_all_devices()?;
+        let mut assigned = Vec::new();
+        for index in indices.split(',') {
+            match index
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| devices.get(i))
+            {
+                Some(d) => assigned.push(*d),
+                None => return Err(GPUError::Simple("Invalid device index in assignment!")),
+            }
+        }
+        info!("{:?} devices set by environment: {}", kind, indices);
+        return Ok(assigned);
+    }
+
+    let indices = match kind {
+        KernelKind::FFT => &GPU_CONFIG.fft_gpus,
+        KernelKind::Multiexp => &GPU_CONFIG.multiexp_gpus,
+    };
+    if let Some(indices) = indices {
+        let devices = get_all_devices()?;
+        let mut assigned = Vec::new();
+        for &i in indices {
+            match devices.get(i) {
+                Some(d) => assigned.push(*d),
+                None => return Err(GPUError::Simple("Invalid device index in assignment!")),
+            }
+        }
+        info!("{:?} devices set by config: {:?}", kind, indices);
+        return Ok(assigned);
+    }
+
+    let mut devices = get_selected_devices()?;
+    if devices.len() > 1 {
+        let last = devices.pop().unwrap();
+        if kind == KernelKind::FFT {
+            devices = vec![last];
+        }
+    }
+    Ok(devices)
 }
 
 lazy_static::lazy_static! {
@@ -57,6 +372,7 @@ lazy_static::lazy_static! {
         let mut core_counts : HashMap<String, usize> = vec![
             // AMD
             ("gfx1010".to_string(), 2560),
+            ("gfx906".to_string(), 3840),
 
             // NVIDIA
             ("Quadro RTX 6000".to_string(), 4608),
@@ -82,6 +398,14 @@ lazy_static::lazy_static! {
             ("GeForce GTX 1650".to_string(), 896),
         ].into_iter().collect();
 
+        for device in GPU_CONFIG.devices.iter() {
+            if let Some(cores) = device.cores {
+                info!("Adding \"{}\" to GPU list with {} CUDA cores from config.",
+                      device.name, cores);
+                core_counts.insert(device.name.clone(), cores);
+            }
+        }
+
         match env::var("BELLMAN_CUSTOM_GPU").and_then(|var| {
             for card in var.split(",") {
                 let splitted = card.split(":").collect::<Vec<_>>();
@@ -99,10 +423,22 @@ lazy_static::lazy_static! {
 }
 
 const DEFAULT_CORE_COUNT: usize = 2560;
+
+/// Number of cores of `d`. Cards listed in `CORE_COUNTS` (Or `BELLMAN_CUSTOM_GPU`) use the
+/// listed value, the core count of other cards is estimated from what the driver reports.
 pub fn get_core_count(d: Device) -> GPUResult<usize> {
     let name = d.name()?;
-    match CORE_COUNTS.get(&name[..]) {
-        Some(&cores) => Ok(cores),
+    if let Some(&cores) = CORE_COUNTS.get(&name[..]) {
+        return Ok(cores);
+    }
+    match estimate_core_count(d) {
+        Some(cores) => {
+            info!(
+                "Number of cores for your device ({}) is estimated to be {}.",
+                name, cores
+            );
+            Ok(cores)
+        }
         None => {
             warn!(
                 "Number of CUDA cores for your device ({}) is unknown! Best performance is \
@@ -116,6 +452,53 @@ pub fn get_core_count(d: Device) -> GPUResult<usize> {
     }
 }
 
+// Same as `get_core_count`, without logging and `None` if the core count is unknown
+fn lookup_core_count(d: Device) -> Option<usize> {
+    let name = d.name().ok()?;
+    CORE_COUNTS
+        .get(&name[..])
+        .cloned()
+        .or_else(|| estimate_core_count(d))
+}
+
+/// Estimates the number of cores of `d` as its number of compute units times the number of
+/// cores per compute unit, which depends on the vendor and the architecture of the device.
+fn estimate_core_count(d: Device) -> Option<usize> {
+    let compute_units = match d.info(ocl::enums::DeviceInfo::MaxComputeUnits).ok()? {
+        ocl::enums::DeviceInfoResult::MaxComputeUnits(cu) => cu as usize,
+        _ => return None,
+    };
+    let cores_per_unit = match Vendor::from_name(&d.vendor().ok()?) {
+        Vendor::Nvidia => {
+            // Cores per streaming multiprocessor of each compute capability, newer architectures
+            // are assumed to schedule 4 warps per multiprocessor.
+            let major = get_device_info_u32(d, CL_DEVICE_COMPUTE_CAPABILITY_MAJOR_NV)?;
+            let minor = get_device_info_u32(d, CL_DEVICE_COMPUTE_CAPABILITY_MINOR_NV)?;
+            match (major, minor) {
+                (3, _) => 192,
+                (5, _) => 128,
+                (6, 0) => 64,
+                (6, _) => 128,
+                (7, _) => 64,
+                (8, 0) => 64,
+                _ => get_device_info_u32(d, CL_DEVICE_WARP_SIZE_NV).unwrap_or(32) * 4,
+            }
+        }
+        // A compute unit runs a whole wavefront at once
+        Vendor::Amd => get_device_info_u32(d, CL_DEVICE_WAVEFRONT_WIDTH_AMD).unwrap_or(64),
+        _ => return None,
+    };
+    Some(compute_units * cores_per_unit as usize)
+}
+
+/// Whether `d` is reported as available by the driver
+pub fn is_device_available(d: Device) -> bool {
+    match d.info(ocl::enums::DeviceInfo::Available) {
+        Ok(ocl::enums::DeviceInfoResult::Available(available)) => available,
+        _ => false,
+    }
+}
+
 pub fn get_memory(d: Device) -> GPUResult<u64> {
     match d.info(ocl::enums::DeviceInfo::GlobalMemSize)? {
         ocl::enums::DeviceInfoResult::GlobalMemSize(sz) => Ok(sz),
@@ -123,19 +506,269 @@ pub fn get_memory(d: Device) -> GPUResult<u64> {
     }
 }
 
-pub fn dump_device_list() {
-    for p in Platform::list().unwrap_or_default().iter() {
-        info!("Platform: {:?} - {:?}", p.name(), p.as_ptr());
-        for d in Device::list_all(p).unwrap_or_default().iter() {
-            let info_kind = ocl::enums::DeviceInfo::MaxComputeUnits;
-            let dev_info = d.info(info_kind).unwrap();
-            info!("\tDevice: {:?} {:?}", d.name(), dev_info);
+/// Memory that is currently free on `d`, only reported by AMD devices
+pub fn get_free_memory(d: Device) -> Option<u64> {
+    // Two `size_t` in KiB: the total free memory and the largest free block
+    let raw =
+        ocl::core::get_device_info_raw(*d.as_core(), CL_DEVICE_GLOBAL_FREE_MEMORY_AMD).ok()?;
+    if raw.len() >= 8 {
+        let mut kb = [0u8; 8];
+        kb.copy_from_slice(&raw[..8]);
+        Some(u64::from_le_bytes(kb) << 10)
+    } else {
+        None
+    }
+}
+
+/// Memory that kernels are allowed to allocate on `d`, which is the global memory size of the
+/// device unless overridden by the `BELLMAN_GPU_MEMORY_LIMIT` env var or the GPU config (In MiB).
+/// It never exceeds the free memory of the device, when it's known (See `get_free_memory`), so
+/// that kernels are sized to what other processes left instead of failing to allocate.
+pub fn get_memory_limit(d: Device) -> GPUResult<u64> {
+    let limit = get_configured_memory_limit(d)?;
+    Ok(match get_free_memory(d) {
+        Some(free) if free < limit => {
+            info!(
+                "Only {} MiB of GPU memory are free on {}.",
+                free >> 20,
+                d.name().unwrap_or_default()
+            );
+            free
+        }
+        _ => limit,
+    })
+}
+
+fn get_configured_memory_limit(d: Device) -> GPUResult<u64> {
+    match env::var("BELLMAN_GPU_MEMORY_LIMIT") {
+        Ok(limit) => match limit.trim().parse::<u64>() {
+            Ok(mb) => Ok(mb << 20),
+            Err(_) => {
+                warn!("Invalid BELLMAN_GPU_MEMORY_LIMIT! Using the memory size of the device...");
+                get_memory(d)
+            }
+        },
+        Err(_) => match get_device_config(d).and_then(|c| c.memory_limit) {
+            Some(mb) => Ok(mb << 20),
+            None => get_memory(d),
+        },
+    }
+}
+
+// Vendor specific OpenCL extensions: `cl_nv_device_attribute_query` and `cl_amd_device_attribute_query`
+const CL_DEVICE_COMPUTE_CAPABILITY_MAJOR_NV: u32 = 0x4000;
+const CL_DEVICE_COMPUTE_CAPABILITY_MINOR_NV: u32 = 0x4001;
+const CL_DEVICE_WARP_SIZE_NV: u32 = 0x4003;
+const CL_DEVICE_PCI_BUS_ID_NV: u32 = 0x4008;
+const CL_DEVICE_TOPOLOGY_AMD: u32 = 0x4037;
+const CL_DEVICE_GLOBAL_FREE_MEMORY_AMD: u32 = 0x4039;
+const CL_DEVICE_WAVEFRONT_WIDTH_AMD: u32 = 0x4043;
+
+/// Queries a `cl_uint` device property, `None` if the device doesn't support it
+fn get_device_info_u32(d: Device, param: u32) -> Option<u32> {
+    let raw = ocl::core::get_device_info_raw(*d.as_core(), param).ok()?;
+    if raw.len() >= 4 {
+        Some(u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]))
+    } else {
+        None
+    }
+}
+
+/// PCI bus id of `d`, only available on NVIDIA and AMD devices
+pub fn get_bus_id(d: Device) -> Option<u32> {
+    if let Some(bus_id) = get_device_info_u32(d, CL_DEVICE_PCI_BUS_ID_NV) {
+        return Some(bus_id);
+    }
+    // `cl_device_topology_amd` is a 24 byte union, the PCIe bus number is at byte 21
+    if let Ok(raw) = ocl::core::get_device_info_raw(*d.as_core(), CL_DEVICE_TOPOLOGY_AMD) {
+        if raw.len() >= 24 {
+            return Some(raw[21] as u32);
+        }
+    }
+    None
+}
+
+/// A string identifying `d` on this machine, used for naming its lock file
+pub fn get_device_lock_id(d: Device) -> String {
+    match get_bus_id(d) {
+        Some(bus_id) => format!("bus{}", bus_id),
+        None => d
+            .name()
+            .unwrap_or_default()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect(),
+    }
+}
+
+/// Vendor of a GPU
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Vendor {
+    Nvidia,
+    Amd,
+    Intel,
+    Other(String),
+}
+
+impl Vendor {
+    /// Parses the vendor name reported by the driver
+    pub fn from_name(name: &str) -> Vendor {
+        let lower = name.to_lowercase();
+        if lower.contains("nvidia") {
+            Vendor::Nvidia
+        } else if lower.contains("advanced micro devices") || lower.contains("amd") {
+            Vendor::Amd
+        } else if lower.contains("intel") {
+            Vendor::Intel
+        } else {
+            Vendor::Other(name.to_string())
+        }
+    }
+}
+
+impl fmt::Display for Vendor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Vendor::Nvidia => write!(f, "NVIDIA"),
+            Vendor::Amd => write!(f, "AMD"),
+            Vendor::Intel => write!(f, "Intel"),
+            Vendor::Other(name) => write!(f, "{}", name),
         }
     }
 }
 
+/// APIs through which the kernels can run on a GPU
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    OpenCL,
+}
+
+/// Properties of a GPU, as listed by `devices`
+#[derive(Debug, Clone)]
+pub struct DeviceInfo {
+    pub vendor: Vendor,
+    pub name: String,
+    /// Name of the OpenCL platform of the device
+    pub platform: String,
+    /// Global memory size, in bytes
+    pub memory: u64,
+    pub compute_units: u32,
+    /// Number of cores, from `CORE_COUNTS` (Or `BELLMAN_CUSTOM_GPU`) for the known cards and
+    /// estimated from the compute units otherwise
+    pub cores: usize,
+    pub driver_version: String,
+    /// PCI bus id, only available on NVIDIA and AMD devices
+    pub bus_id: Option<u32>,
+    pub backends: Vec<Backend>,
+    /// Whether the kernels may use the device, see `is_device_allowed`
+    pub allowed: bool,
+}
+
+impl DeviceInfo {
+    fn new(platform: Platform, d: Device) -> GPUResult<DeviceInfo> {
+        let name = d.name()?;
+        let compute_units = match d.info(ocl::enums::DeviceInfo::MaxComputeUnits)? {
+            ocl::enums::DeviceInfoResult::MaxComputeUnits(cu) => cu,
+            _ => return Err(GPUError::Simple("Cannot extract GPU compute units!")),
+        };
+        let driver_version = match d.info(ocl::enums::DeviceInfo::DriverVersion)? {
+            ocl::enums::DeviceInfoResult::DriverVersion(version) => version,
+            _ => return Err(GPUError::Simple("Cannot extract GPU driver version!")),
+        };
+        let cores = lookup_core_count(d).unwrap_or(DEFAULT_CORE_COUNT);
+        Ok(DeviceInfo {
+            vendor: Vendor::from_name(&d.vendor()?),
+            platform: platform.name()?,
+            memory: get_memory(d)?,
+            compute_units,
+            cores,
+            driver_version,
+            bus_id: get_bus_id(d),
+            backends: vec![Backend::OpenCL],
+            allowed: is_device_allowed(d),
+            name,
+        })
+    }
+}
+
+/// Lists the GPUs of all of the platforms, regardless of the device selection env vars and the
+/// GPU config. Devices which can't be queried are skipped.
+pub fn devices() -> Vec<DeviceInfo> {
+    let mut infos = Vec::new();
+    for p in Platform::list().unwrap_or_default() {
+        for d in Device::list(p, Some(ocl::flags::DEVICE_TYPE_GPU)).unwrap_or_default() {
+            match DeviceInfo::new(p, d) {
+                Ok(info) => infos.push(info),
+                Err(e) => warn!("Cannot query device {:?}! Error: {}", d.name(), e),
+            }
+        }
+    }
+    infos
+}
+
+pub fn dump_device_list() {
+    for d in devices() {
+        info!(
+            "Device: {} {} ({}) - {} MiB, {} compute units, {} cores, driver {}, bus id {:?}",
+            d.vendor,
+            d.name,
+            d.platform,
+            d.memory >> 20,
+            d.compute_units,
+            d.cores,
+            d.driver_version,
+            d.bus_id
+        );
+    }
+}
+
 #[cfg(feature = "gpu")]
 #[test]
 pub fn test_list_platform() {
     dump_device_list();
 }
+
+#[cfg(feature = "gpu")]
+#[test]
+pub fn test_devices() {
+    let devices = devices();
+    assert!(!devices.is_empty());
+    for d in devices {
+        assert!(d.memory > 0);
+        assert!(d.compute_units > 0);
+        assert_eq!(d.backends, vec![Backend::OpenCL]);
+    }
+}
+
+#[test]
+fn test_is_allowed_by() {
+    let list = |l: &[&str]| l.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+    let blacklist = list(&["GeForce GTX 1050", "3"]);
+    assert!(!is_allowed_by("GeForce GTX 1050", None, &blacklist, None));
+    assert!(!is_allowed_by("TITAN RTX", Some(3), &blacklist, None));
+    assert!(is_allowed_by("TITAN RTX", Some(4), &blacklist, None));
+    assert!(is_allowed_by("TITAN RTX", None, &blacklist, None));
+
+    let whitelist = list(&["TITAN RTX", "5"]);
+    assert!(is_allowed_by("TITAN RTX", None, &[], Some(&whitelist)));
+    assert!(is_allowed_by("Tesla T4", Some(5), &[], Some(&whitelist)));
+    assert!(!is_allowed_by("Tesla T4", Some(6), &[], Some(&whitelist)));
+    // The blacklist wins over the whitelist
+    assert!(!is_allowed_by(
+        "TITAN RTX",
+        Some(3),
+        &blacklist,
+        Some(&whitelist)
+    ));
+}
+
+#[test]
+fn test_vendor_from_name() {
+    assert_eq!(Vendor::from_name("NVIDIA Corporation"), Vendor::Nvidia);
+    assert_eq!(
+        Vendor::from_name("Advanced Micro Devices, Inc."),
+        Vendor::Amd
+    );
+    assert_eq!(Vendor::from_name("Intel(R) Corporation"), Vendor::Intel);
+    assert_eq!(Vendor::from_name("Acme"), Vendor::Other("Acme".to_string()));
+}
@@ -0,0 +1,31 @@
+use super::error::{GPUError, GPUResult};
+use super::utils;
+use log::error;
+use ocl::{Event, Queue};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Interval at which the completion of the enqueued commands is polled
+const POLL_INTERVAL: Duration = Duration::from_micros(100);
+
+/// Waits for all of the commands enqueued on `queue`, like `Queue::finish`, but fails with
+/// `GPUError::Timeout` if they don't complete within the kernel timeout (See
+/// `utils::get_kernel_timeout`), so that a hung driver doesn't block the caller forever.
+pub fn finish(queue: &Queue) -> GPUResult<()> {
+    let timeout = match utils::get_kernel_timeout() {
+        Some(timeout) => timeout,
+        None => return Ok(queue.finish()?),
+    };
+
+    let marker = queue.enqueue_marker(None::<&Event>)?;
+    queue.flush()?;
+    let deadline = Instant::now() + timeout;
+    while !marker.is_complete().map_err(ocl::Error::from)? {
+        if Instant::now() >= deadline {
+            error!("GPU commands didn't complete within {:?}!", timeout);
+            return Err(GPUError::Timeout);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    Ok(())
+}
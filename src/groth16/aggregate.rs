@@ -0,0 +1,753 @@
+//! Aggregation of Groth16 proofs, following SnarkPack.
+//!
+//! `aggregate_proofs` combines `n` proofs of the same circuit into a proof of size `O(log n)`,
+//! which `verify_aggregate` checks with `O(log n)` pairings. The `A`, `B` and `C` points of the
+//! proofs are committed with pairing based commitments, then the aggregator proves with an inner
+//! product argument (TIPP for `A` and `B`, MIPP for `C`) that the random linear combination of the
+//! Groth16 equations `e(A_i, B_i) = e(alpha, beta) e(IC_i, gamma) e(C_i, delta)`, scaled by the
+//! powers of a challenge `r`, holds. The commitment keys folded by the argument are checked with
+//! KZG openings against the `AggregateParameters`, structured powers of two secrets which have to
+//! come from a trusted setup.
+
+use blake2b_simd::{Params as Blake2bParams, State};
+use ff::{Field, PrimeField, PrimeFieldRepr};
+use futures::Future;
+use groupy::{CurveAffine, CurveProjective};
+use paired::bls12_381::{Bls12, Fq12};
+use paired::{Engine, PairingCurveAffine};
+use rand_chacha::ChaChaRng;
+use rand_core::{RngCore, SeedableRng};
+use rayon::prelude::*;
+use thiserror::Error;
+
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+use super::vk_export::read_point;
+use super::{PreparedVerifyingKey, Proof};
+use crate::multicore::Worker;
+use crate::multiexp::{multiexp, FullDensity};
+use crate::SynthesisError;
+
+const TRANSCRIPT_DOMAIN: &[u8] = b"bellperson/groth16/aggregate";
+
+// Number of pairs sharing a Miller loop in the pairing products
+const MILLER_CHUNK: usize = 64;
+
+#[derive(Error, Debug)]
+pub enum AggregationError {
+    #[error("the number of proofs must be a power of two, got {0}")]
+    InvalidProofCount(usize),
+    #[error("the parameters can aggregate up to {0} proofs")]
+    ParametersTooSmall(usize),
+    #[error("encountered a synthesis error: {0}")]
+    Synthesis(#[from] SynthesisError),
+}
+
+/// Engines whose target group elements have a canonical encoding, which the aggregation hashes
+/// into its Fiat-Shamir transcript.
+pub trait GtEncoding: Engine {
+    /// Writes the canonical encoding of `f` to `bytes`.
+    fn write_gt(f: &Self::Fqk, bytes: &mut Vec<u8>);
+}
+
+impl GtEncoding for Bls12 {
+    // The 12 coefficients over `Fq`, as big-endian integers, in the order of the towers
+    // `Fq12 = Fq6[w]`, `Fq6 = Fq2[v]` and `Fq2 = Fq[u]`, from the constant coefficients up
+    fn write_gt(f: &Fq12, bytes: &mut Vec<u8>) {
+        for fq6 in &[f.c0, f.c1] {
+            for fq2 in &[fq6.c0, fq6.c1, fq6.c2] {
+                for fq in &[fq2.c0, fq2.c1] {
+                    fq.into_repr()
+                        .write_be(&mut *bytes)
+                        .expect("writing to a vector can't fail");
+                }
+            }
+        }
+    }
+}
+
+/// A pair of elements of the target group committing to vectors of points.
+pub type Commitment<E> = (<E as Engine>::Fqk, <E as Engine>::Fqk);
+
+/// The powers of the secrets `a` and `b` the proofs are committed and the commitment keys are
+/// opened with.
+#[derive(Clone)]
+pub struct AggregateParameters<E: Engine> {
+    /// `g^(a^i)` and `g^(b^i)`, for `i < 2 * max_proofs`.
+    pub g_alpha_powers: Vec<E::G1Affine>,
+    pub g_beta_powers: Vec<E::G1Affine>,
+    /// `h^(a^i)` and `h^(b^i)`, for `i < max_proofs`.
+    pub h_alpha_powers: Vec<E::G2Affine>,
+    pub h_beta_powers: Vec<E::G2Affine>,
+    pub vk: AggregateVerifyingKey<E>,
+}
+
+/// The points of the `AggregateParameters` needed to verify aggregated proofs.
+#[derive(Clone)]
+pub struct AggregateVerifyingKey<E: Engine> {
+    pub g: E::G1Affine,
+    pub h: E::G2Affine,
+    pub g_alpha: E::G1Affine,
+    pub g_beta: E::G1Affine,
+    pub h_alpha: E::G2Affine,
+    pub h_beta: E::G2Affine,
+}
+
+impl<E: Engine> AggregateVerifyingKey<E> {
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(self.g.into_compressed().as_ref())?;
+        writer.write_all(self.h.into_compressed().as_ref())?;
+        writer.write_all(self.g_alpha.into_compressed().as_ref())?;
+        writer.write_all(self.g_beta.into_compressed().as_ref())?;
+        writer.write_all(self.h_alpha.into_compressed().as_ref())?;
+        writer.write_all(self.h_beta.into_compressed().as_ref())?;
+
+        Ok(())
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> io::Result<Self> {
+        Ok(AggregateVerifyingKey {
+            g: read_point(&mut reader)?,
+            h: read_point(&mut reader)?,
+            g_alpha: read_point(&mut reader)?,
+            g_beta: read_point(&mut reader)?,
+            h_alpha: read_point(&mut reader)?,
+            h_beta: read_point(&mut reader)?,
+        })
+    }
+}
+
+impl<E: Engine> AggregateParameters<E> {
+    /// Generates parameters aggregating up to `max_proofs` proofs from random secrets. Anyone
+    /// knowing the secrets can forge aggregated proofs, so production parameters have to be
+    /// derived from a powers of tau ceremony instead.
+    pub fn generate<R: RngCore>(max_proofs: usize, rng: &mut R) -> Self {
+        let a = E::Fr::random(rng);
+        let b = E::Fr::random(rng);
+        let g = E::G1Affine::one();
+        let h = E::G2Affine::one();
+
+        let g_alpha_powers = batch_powers(g, a, 2 * max_proofs);
+        let g_beta_powers = batch_powers(g, b, 2 * max_proofs);
+        let h_alpha_powers = batch_powers(h, a, max_proofs);
+        let h_beta_powers = batch_powers(h, b, max_proofs);
+
+        let vk = AggregateVerifyingKey {
+            g,
+            h,
+            g_alpha: g.mul(a.into_repr()).into_affine(),
+            g_beta: g.mul(b.into_repr()).into_affine(),
+            h_alpha: h.mul(a.into_repr()).into_affine(),
+            h_beta: h.mul(b.into_repr()).into_affine(),
+        };
+
+        AggregateParameters {
+            g_alpha_powers,
+            g_beta_powers,
+            h_alpha_powers,
+            h_beta_powers,
+            vk,
+        }
+    }
+
+    /// The maximum number of proofs these parameters can aggregate.
+    pub fn max_proofs(&self) -> usize {
+        self.h_alpha_powers.len()
+    }
+}
+
+/// The cross commitments and products of a round of the inner product argument, for the left
+/// and the right halves of the vectors.
+#[derive(Clone)]
+pub struct GipaRound<E: Engine> {
+    pub com_ab: (Commitment<E>, Commitment<E>),
+    pub com_c: (Commitment<E>, Commitment<E>),
+    pub z_ab: (E::Fqk, E::Fqk),
+    pub z_c: (E::G1Affine, E::G1Affine),
+}
+
+#[derive(Clone)]
+pub struct AggregateProof<E: Engine> {
+    /// Commitments to the `A` and `B`, and to the `C` points of the proofs.
+    pub com_ab: Commitment<E>,
+    pub com_c: Commitment<E>,
+    /// `prod e(A_i, B_i)^(r^i)` and `sum r^i C_i`.
+    pub ip_ab: E::Fqk,
+    pub agg_c: E::G1Affine,
+    pub rounds: Vec<GipaRound<E>>,
+    /// The vectors and commitment keys once folded to a single element.
+    pub final_a: E::G1Affine,
+    pub final_b: E::G2Affine,
+    pub final_c: E::G1Affine,
+    pub final_vkey: (E::G2Affine, E::G2Affine),
+    pub final_wkey: (E::G1Affine, E::G1Affine),
+    /// KZG openings of the final commitment keys.
+    pub vkey_opening: (E::G2Affine, E::G2Affine),
+    pub wkey_opening: (E::G1Affine, E::G1Affine),
+}
+
+/// Aggregates `proofs`, whose number must be a power of two. `public_inputs[i]` are the public
+/// inputs of `proofs[i]`, which the aggregated proof is bound to.
+pub fn aggregate_proofs<E: GtEncoding>(
+    params: &AggregateParameters<E>,
+    proofs: &[Proof<E>],
+    public_inputs: &[Vec<E::Fr>],
+) -> Result<AggregateProof<E>, AggregationError> {
+    let n = proofs.len();
+    if !n.is_power_of_two() {
+        return Err(AggregationError::InvalidProofCount(n));
+    }
+    if n > params.max_proofs() {
+        return Err(AggregationError::ParametersTooSmall(params.max_proofs()));
+    }
+    if public_inputs.len() != n {
+        return Err(SynthesisError::MalformedProofs.into());
+    }
+
+    let a = proofs.iter().map(|proof| proof.a).collect::<Vec<_>>();
+    let b = proofs.iter().map(|proof| proof.b).collect::<Vec<_>>();
+    let c = proofs.iter().map(|proof| proof.c).collect::<Vec<_>>();
+
+    // The G1 points are committed with v, the G2 points with w
+    let v = (&params.h_alpha_powers[..n], &params.h_beta_powers[..n]);
+    let w = (
+        &params.g_alpha_powers[n..2 * n],
+        &params.g_beta_powers[n..2 * n],
+    );
+    let com_ab = commit::<E>(&a, v, &b, w);
+    let com_c = commit::<E>(&c, v, &[], (&[], &[]));
+
+    let mut transcript = Transcript::new();
+    transcript.append_statement::<E>(public_inputs, &com_ab, &com_c);
+    let r = transcript.challenge::<E::Fr>();
+    let r_inv = r.inverse().unwrap();
+
+    // Scaling A and C by the powers of r, and v by the inverse powers, keeps the commitments
+    let r_powers = powers(r, n);
+    let r_inv_powers = powers(r_inv, n);
+    let mut a = scale(&a, &r_powers);
+    let mut b = b;
+    let mut c = scale(&c, &r_powers);
+    let mut v = (scale(v.0, &r_inv_powers), scale(v.1, &r_inv_powers));
+    let mut w = (w.0.to_vec(), w.1.to_vec());
+
+    let ip_ab = final_product::<E>(&[miller_product::<E>(&a, &b)]);
+    let agg_c = sum(&c).into_affine();
+    transcript.append_gt::<E>(&ip_ab);
+    transcript.append_point(&agg_c);
+
+    // All the elements of the scalar vector of the MIPP are equal
+    let mut sigma = E::Fr::one();
+    let mut rounds = Vec::new();
+    let mut challenges = Vec::new();
+    while a.len() > 1 {
+        let m = a.len() / 2;
+        let (a_l, a_r) = a.split_at(m);
+        let (b_l, b_r) = b.split_at(m);
+        let (c_l, c_r) = c.split_at(m);
+        let (v1_l, v1_r) = v.0.split_at(m);
+        let (v2_l, v2_r) = v.1.split_at(m);
+        let (w1_l, w1_r) = w.0.split_at(m);
+        let (w2_l, w2_r) = w.1.split_at(m);
+
+        let mut z_c_l = sum(c_r);
+        z_c_l.mul_assign(sigma.into_repr());
+        let mut z_c_r = sum(c_l);
+        z_c_r.mul_assign(sigma.into_repr());
+        let round = GipaRound {
+            com_ab: (
+                commit::<E>(a_r, (v1_l, v2_l), b_l, (w1_r, w2_r)),
+                commit::<E>(a_l, (v1_r, v2_r), b_r, (w1_l, w2_l)),
+            ),
+            com_c: (
+                commit::<E>(c_r, (v1_l, v2_l), &[], (&[], &[])),
+                commit::<E>(c_l, (v1_r, v2_r), &[], (&[], &[])),
+            ),
+            z_ab: (
+                final_product::<E>(&[miller_product::<E>(a_r, b_l)]),
+                final_product::<E>(&[miller_product::<E>(a_l, b_r)]),
+            ),
+            z_c: (z_c_l.into_affine(), z_c_r.into_affine()),
+        };
+        transcript.append_round::<E>(&round);
+        let x = transcript.challenge::<E::Fr>();
+        let x_inv = x.inverse().unwrap();
+
+        let folded_a = fold(a_l, a_r, x);
+        let folded_b = fold(b_l, b_r, x_inv);
+        let folded_c = fold(c_l, c_r, x);
+        let folded_v = (fold(v1_l, v1_r, x_inv), fold(v2_l, v2_r, x_inv));
+        let folded_w = (fold(w1_l, w1_r, x), fold(w2_l, w2_r, x));
+        a = folded_a;
+        b = folded_b;
+        c = folded_c;
+        v = folded_v;
+        w = folded_w;
+
+        let mut factor = x_inv;
+        factor.add_assign(&E::Fr::one());
+        sigma.mul_assign(&factor);
+        rounds.push(round);
+        challenges.push(x);
+    }
+
+    let final_vkey = (v.0[0], v.1[0]);
+    let final_wkey = (w.0[0], w.1[0]);
+    transcript.append_finals::<E>(&a[0], &b[0], &c[0], &final_vkey, &final_wkey);
+    let z = transcript.challenge::<E::Fr>();
+
+    // The final v is h^(f_v(secret)), with f_v the product of (1 + x_j^-1 (X / r)^(2^(k-1-j))),
+    // and the final w is g^(secret^n f_w(secret)), with f_w the product of (1 + x_j X^(2^(k-1-j)))
+    let challenges_inv = challenges
+        .iter()
+        .map(|x| x.inverse().unwrap())
+        .collect::<Vec<_>>();
+    let mut f_v = polynomial_coefficients(&challenges_inv);
+    for (coeff, r_inv_power) in f_v.iter_mut().zip(r_inv_powers.iter()) {
+        coeff.mul_assign(r_inv_power);
+    }
+    let mut f_w = vec![E::Fr::zero(); n];
+    f_w.extend(polynomial_coefficients(&challenges));
+
+    let worker = Worker::new();
+    let q_v = Arc::new(quotient(&f_v, z));
+    let q_w = Arc::new(quotient(&f_w, z));
+    let vkey_opening = (
+        kzg_commit(&worker, &params.h_alpha_powers, &q_v)?,
+        kzg_commit(&worker, &params.h_beta_powers, &q_v)?,
+    );
+    let wkey_opening = (
+        kzg_commit(&worker, &params.g_alpha_powers, &q_w)?,
+        kzg_commit(&worker, &params.g_beta_powers, &q_w)?,
+    );
+
+    Ok(AggregateProof {
+        com_ab,
+        com_c,
+        ip_ab,
+        agg_c,
+        rounds,
+        final_a: a[0],
+        final_b: b[0],
+        final_c: c[0],
+        final_vkey,
+        final_wkey,
+        vkey_opening,
+        wkey_opening,
+    })
+}
+
+/// Verifies a proof aggregated from proofs with the given public inputs.
+pub fn verify_aggregate<E: GtEncoding>(
+    vk: &AggregateVerifyingKey<E>,
+    pvk: &PreparedVerifyingKey<E>,
+    public_inputs: &[Vec<E::Fr>],
+    proof: &AggregateProof<E>,
+) -> Result<bool, AggregationError> {
+    let n = public_inputs.len();
+    if !n.is_power_of_two() {
+        return Err(AggregationError::InvalidProofCount(n));
+    }
+    for inputs in public_inputs {
+        if inputs.len() + 1 != pvk.ic.len() {
+            return Err(SynthesisError::MalformedVerifyingKey.into());
+        }
+    }
+    if proof.rounds.len() != n.trailing_zeros() as usize {
+        return Ok(false);
+    }
+
+    let mut transcript = Transcript::new();
+    transcript.append_statement::<E>(public_inputs, &proof.com_ab, &proof.com_c);
+    let r = transcript.challenge::<E::Fr>();
+    transcript.append_gt::<E>(&proof.ip_ab);
+    transcript.append_point(&proof.agg_c);
+
+    // Fold the commitments and the products the same way as the prover folded the vectors
+    let mut com_ab = proof.com_ab;
+    let mut com_c = proof.com_c;
+    let mut ip_ab = proof.ip_ab;
+    let mut agg_c = proof.agg_c.into_projective();
+    let mut sigma = E::Fr::one();
+    let mut challenges = Vec::with_capacity(proof.rounds.len());
+    for round in proof.rounds.iter() {
+        transcript.append_round::<E>(round);
+        let x = transcript.challenge::<E::Fr>();
+        let x_inv = x.inverse().unwrap();
+
+        com_ab = fold_commitment::<E>(&round.com_ab, &com_ab, x, x_inv);
+        com_c = fold_commitment::<E>(&round.com_c, &com_c, x, x_inv);
+        ip_ab = fold_gt::<E>(&round.z_ab, &ip_ab, x, x_inv);
+        agg_c.add_assign(&round.z_c.0.mul(x.into_repr()));
+        agg_c.add_assign(&round.z_c.1.mul(x_inv.into_repr()));
+
+        let mut factor = x_inv;
+        factor.add_assign(&E::Fr::one());
+        sigma.mul_assign(&factor);
+        challenges.push(x);
+    }
+    transcript.append_finals::<E>(
+        &proof.final_a,
+        &proof.final_b,
+        &proof.final_c,
+        &proof.final_vkey,
+        &proof.final_wkey,
+    );
+    let z = transcript.challenge::<E::Fr>();
+
+    // The folded values match the final elements
+    let (v1, v2) = proof.final_vkey;
+    let (w1, w2) = proof.final_wkey;
+    let (a, b, c) = (proof.final_a, proof.final_b, proof.final_c);
+    let pairing = |pairs: &[(E::G1Affine, E::G2Affine)]| {
+        let ml = pairs
+            .iter()
+            .map(|(p, q)| miller_product::<E>(&[*p], &[*q]))
+            .collect::<Vec<_>>();
+        final_product::<E>(&ml)
+    };
+    let mut final_c = c.mul(sigma.into_repr());
+    final_c.sub_assign(&agg_c);
+    if com_ab != (pairing(&[(a, v1), (w1, b)]), pairing(&[(a, v2), (w2, b)]))
+        || com_c != (pairing(&[(c, v1)]), pairing(&[(c, v2)]))
+        || ip_ab != pairing(&[(a, b)])
+        || !final_c.is_zero()
+    {
+        return Ok(false);
+    }
+
+    // The final keys are built from the parameters
+    let challenges_inv = challenges
+        .iter()
+        .map(|x| x.inverse().unwrap())
+        .collect::<Vec<_>>();
+    let mut z_r = z;
+    z_r.mul_assign(&r.inverse().unwrap());
+    let f_v = evaluate_polynomial(&challenges_inv, z_r);
+    let mut f_w = evaluate_polynomial(&challenges, z);
+    f_w.mul_assign(&z.pow(&[n as u64]));
+    let one = E::Fqk::one();
+    let open_v = |key: E::G2Affine, g_secret: E::G1Affine, opening: E::G2Affine| {
+        // e(g^(secret - z), opening) e(g, h^f_v(z) / key) = 1
+        let mut g_shift = g_secret.into_projective();
+        g_shift.sub_assign(&vk.g.mul(z.into_repr()));
+        let mut h_diff = vk.h.mul(f_v.into_repr());
+        h_diff.add_assign_mixed(&negate(key));
+        pairing(&[
+            (g_shift.into_affine(), opening),
+            (vk.g, h_diff.into_affine()),
+        ]) == one
+    };
+    let open_w = |key: E::G1Affine, h_secret: E::G2Affine, opening: E::G1Affine| {
+        // e(opening, h^(secret - z)) e(g^(z^n f_w(z)) / key, h) = 1
+        let mut h_shift = h_secret.into_projective();
+        h_shift.sub_assign(&vk.h.mul(z.into_repr()));
+        let mut g_diff = vk.g.mul(f_w.into_repr());
+        g_diff.add_assign_mixed(&negate(key));
+        pairing(&[
+            (opening, h_shift.into_affine()),
+            (g_diff.into_affine(), vk.h),
+        ]) == one
+    };
+    if !open_v(v1, vk.g_alpha, proof.vkey_opening.0)
+        || !open_v(v2, vk.g_beta, proof.vkey_opening.1)
+        || !open_w(w1, vk.h_alpha, proof.wkey_opening.0)
+        || !open_w(w2, vk.h_beta, proof.wkey_opening.1)
+    {
+        return Ok(false);
+    }
+
+    // The random linear combination of the Groth16 equations:
+    // ip_ab = e(alpha, beta)^(sum r^i) e(sum r^i IC_i, gamma) e(agg_c, delta)
+    let r_powers = powers(r, n);
+    let mut sum_r = E::Fr::zero();
+    for r_power in r_powers.iter() {
+        sum_r.add_assign(r_power);
+    }
+    let mut acc = pvk.ic[0].mul(sum_r.into_repr());
+    for (i, ic) in pvk.ic.iter().skip(1).enumerate() {
+        let mut scalar = E::Fr::zero();
+        for (inputs, r_power) in public_inputs.iter().zip(r_powers.iter()) {
+            let mut tmp = inputs[i];
+            tmp.mul_assign(r_power);
+            scalar.add_assign(&tmp);
+        }
+        acc.add_assign(&ic.mul(scalar.into_repr()));
+    }
+    let mut lhs = E::final_exponentiation(&E::miller_loop(
+        [
+            (&acc.into_affine().prepare(), &pvk.neg_gamma_g2),
+            (&proof.agg_c.prepare(), &pvk.neg_delta_g2),
+        ]
+        .iter(),
+    ))
+    .unwrap();
+    lhs.mul_assign(&proof.ip_ab);
+
+    Ok(lhs == pvk.alpha_g1_beta_g2.pow(&sum_r.into_repr()))
+}
+
+// Fiat-Shamir transcript of the aggregation
+struct Transcript {
+    state: State,
+}
+
+impl Transcript {
+    fn new() -> Self {
+        let mut state = Blake2bParams::new().hash_length(64).to_state();
+        state.update(TRANSCRIPT_DOMAIN);
+        Transcript { state }
+    }
+
+    fn append_point<G: CurveAffine>(&mut self, point: &G) {
+        self.state.update(point.into_uncompressed().as_ref());
+    }
+
+    fn append_gt<E: GtEncoding>(&mut self, f: &E::Fqk) {
+        let mut bytes = Vec::new();
+        E::write_gt(f, &mut bytes);
+        self.state.update(&bytes);
+    }
+
+    fn append_commitment<E: GtEncoding>(&mut self, com: &Commitment<E>) {
+        self.append_gt::<E>(&com.0);
+        self.append_gt::<E>(&com.1);
+    }
+
+    fn append_statement<E: GtEncoding>(
+        &mut self,
+        public_inputs: &[Vec<E::Fr>],
+        com_ab: &Commitment<E>,
+        com_c: &Commitment<E>,
+    ) {
+        self.state
+            .update(&(public_inputs.len() as u64).to_be_bytes());
+        for input in public_inputs.iter().flat_map(|inputs| inputs.iter()) {
+            for limb in input.into_repr().as_ref() {
+                self.state.update(&limb.to_be_bytes());
+            }
+        }
+        self.append_commitment::<E>(com_ab);
+        self.append_commitment::<E>(com_c);
+    }
+
+    fn append_round<E: GtEncoding>(&mut self, round: &GipaRound<E>) {
+        self.append_commitment::<E>(&round.com_ab.0);
+        self.append_commitment::<E>(&round.com_ab.1);
+        self.append_commitment::<E>(&round.com_c.0);
+        self.append_commitment::<E>(&round.com_c.1);
+        self.append_gt::<E>(&round.z_ab.0);
+        self.append_gt::<E>(&round.z_ab.1);
+        self.append_point(&round.z_c.0);
+        self.append_point(&round.z_c.1);
+    }
+
+    fn append_finals<E: Engine>(
+        &mut self,
+        a: &E::G1Affine,
+        b: &E::G2Affine,
+        c: &E::G1Affine,
+        vkey: &(E::G2Affine, E::G2Affine),
+        wkey: &(E::G1Affine, E::G1Affine),
+    ) {
+        self.append_point(a);
+        self.append_point(b);
+        self.append_point(c);
+        self.append_point(&vkey.0);
+        self.append_point(&vkey.1);
+        self.append_point(&wkey.0);
+        self.append_point(&wkey.1);
+    }
+
+    // A non-zero challenge, which is absorbed back into the transcript
+    fn challenge<F: PrimeField>(&mut self) -> F {
+        loop {
+            let digest = self.state.finalize();
+            self.state.update(digest.as_bytes());
+
+            let mut seed = [0u8; 32];
+            seed.copy_from_slice(&digest.as_bytes()[..32]);
+            let challenge = F::random(&mut ChaChaRng::from_seed(seed));
+            if !challenge.is_zero() {
+                return challenge;
+            }
+        }
+    }
+}
+
+// Product of the Miller loops of the pairs of points of `g1` and `g2`
+fn miller_product<E: Engine>(g1: &[E::G1Affine], g2: &[E::G2Affine]) -> E::Fqk {
+    g1.par_chunks(MILLER_CHUNK)
+        .zip(g2.par_chunks(MILLER_CHUNK))
+        .map(|(g1, g2)| {
+            let prepared = g1
+                .iter()
+                .zip(g2.iter())
+                .map(|(p, q)| (p.prepare(), q.prepare()))
+                .collect::<Vec<_>>();
+            E::miller_loop(&prepared.iter().map(|(p, q)| (p, q)).collect::<Vec<_>>())
+        })
+        .reduce(E::Fqk::one, |mut acc, ml| {
+            acc.mul_assign(&ml);
+            acc
+        })
+}
+
+fn final_product<E: Engine>(miller_loops: &[E::Fqk]) -> E::Fqk {
+    let mut acc = E::Fqk::one();
+    for ml in miller_loops {
+        acc.mul_assign(ml);
+    }
+    E::final_exponentiation(&acc).unwrap()
+}
+
+// Commitment to `a` under the key `v`, and to `b` under the key `w`
+fn commit<E: Engine>(
+    a: &[E::G1Affine],
+    v: (&[E::G2Affine], &[E::G2Affine]),
+    b: &[E::G2Affine],
+    w: (&[E::G1Affine], &[E::G1Affine]),
+) -> Commitment<E> {
+    (
+        final_product::<E>(&[miller_product::<E>(a, v.0), miller_product::<E>(w.0, b)]),
+        final_product::<E>(&[miller_product::<E>(a, v.1), miller_product::<E>(w.1, b)]),
+    )
+}
+
+// left^x middle right^(x^-1)
+fn fold_gt<E: Engine>(cross: &(E::Fqk, E::Fqk), middle: &E::Fqk, x: E::Fr, x_inv: E::Fr) -> E::Fqk {
+    let mut acc = cross.0.pow(&x.into_repr());
+    acc.mul_assign(middle);
+    acc.mul_assign(&cross.1.pow(&x_inv.into_repr()));
+    acc
+}
+
+fn fold_commitment<E: Engine>(
+    cross: &(Commitment<E>, Commitment<E>),
+    middle: &Commitment<E>,
+    x: E::Fr,
+    x_inv: E::Fr,
+) -> Commitment<E> {
+    (
+        fold_gt::<E>(&((cross.0).0, (cross.1).0), &middle.0, x, x_inv),
+        fold_gt::<E>(&((cross.0).1, (cross.1).1), &middle.1, x, x_inv),
+    )
+}
+
+// left + x right, element-wise
+fn fold<G: CurveAffine>(left: &[G], right: &[G], x: G::Scalar) -> Vec<G> {
+    let x = x.into_repr();
+    left.par_iter()
+        .zip(right.par_iter())
+        .map(|(l, r)| {
+            let mut p = r.mul(x);
+            p.add_assign_mixed(l);
+            p.into_affine()
+        })
+        .collect()
+}
+
+fn scale<G: CurveAffine>(points: &[G], scalars: &[G::Scalar]) -> Vec<G> {
+    points
+        .par_iter()
+        .zip(scalars.par_iter())
+        .map(|(p, s)| p.mul(s.into_repr()).into_affine())
+        .collect()
+}
+
+fn sum<G: CurveAffine>(points: &[G]) -> G::Projective {
+    let mut acc = G::Projective::zero();
+    for p in points {
+        acc.add_assign_mixed(p);
+    }
+    acc
+}
+
+fn negate<G: CurveAffine>(point: G) -> G {
+    let mut point = point;
+    point.negate();
+    point
+}
+
+fn powers<F: Field>(x: F, n: usize) -> Vec<F> {
+    let mut powers = Vec::with_capacity(n);
+    let mut acc = F::one();
+    for _ in 0..n {
+        powers.push(acc);
+        acc.mul_assign(&x);
+    }
+    powers
+}
+
+// `base`, multiplied by the powers of `x`
+fn batch_powers<G: CurveAffine>(base: G, x: G::Scalar, n: usize) -> Vec<G> {
+    powers(x, n)
+        .par_iter()
+        .map(|s| base.mul(s.into_repr()).into_affine())
+        .collect()
+}
+
+// Coefficients of the product of (1 + y_j X^(2^(k-1-j))), for the k challenges y_j
+fn polynomial_coefficients<F: Field>(challenges: &[F]) -> Vec<F> {
+    let mut coeffs = vec![F::one()];
+    for y in challenges.iter().rev() {
+        let high = coeffs
+            .iter()
+            .map(|c| {
+                let mut c = *c;
+                c.mul_assign(y);
+                c
+            })
+            .collect::<Vec<_>>();
+        coeffs.extend(high);
+    }
+    coeffs
+}
+
+fn evaluate_polynomial<F: Field>(challenges: &[F], z: F) -> F {
+    let mut acc = F::one();
+    let mut z_power = z;
+    for y in challenges.iter().rev() {
+        let mut factor = z_power;
+        factor.mul_assign(y);
+        factor.add_assign(&F::one());
+        acc.mul_assign(&factor);
+        z_power.square();
+    }
+    acc
+}
+
+// Coefficients of (p(X) - p(z)) / (X - z)
+fn quotient<F: PrimeField>(coeffs: &[F], z: F) -> Vec<<F as PrimeField>::Repr> {
+    let mut quotient = vec![F::zero(); coeffs.len().saturating_sub(1)];
+    let mut acc = F::zero();
+    for i in (1..coeffs.len()).rev() {
+        acc.mul_assign(&z);
+        acc.add_assign(&coeffs[i]);
+        quotient[i - 1] = acc;
+    }
+    quotient.into_iter().map(|q| q.into_repr()).collect()
+}
+
+// The polynomial of coefficients `coeffs` evaluated at the secret of `powers`
+fn kzg_commit<E, G>(
+    worker: &Worker,
+    powers: &[G],
+    coeffs: &Arc<Vec<<E::Fr as PrimeField>::Repr>>,
+) -> Result<G, SynthesisError>
+where
+    E: Engine,
+    G: CurveAffine<Engine = E>,
+{
+    if coeffs.is_empty() {
+        return Ok(G::zero());
+    }
+
+    let bases = Arc::new(powers[..coeffs.len()].to_vec());
+    Ok(
+        multiexp(worker, (bases, 0), FullDensity, coeffs.clone(), &mut None)
+            .wait()?
+            .into_affine(),
+    )
+}
@@ -0,0 +1,110 @@
+//! Proofs bound to a message, e.g. the transaction submitting them.
+//!
+//! A Groth16 proof can be rerandomized by anyone (See `rerandomize_proof`), but not moved to
+//! other public inputs. `BoundCircuit` appends the hash of a message to the public inputs of a
+//! circuit, so that a proof made for a message doesn't verify for any other. The parameters
+//! have to be generated for the `BoundCircuit`, which adds a public input to the circuit.
+
+use ff::Field;
+use paired::Engine;
+use rand_core::RngCore;
+
+use super::{
+    create_random_proof, rng_from_seed, verify_proof, ParameterSource, PreparedVerifyingKey, Proof,
+};
+use crate::{Circuit, ConstraintSystem, SynthesisError};
+
+/// Domain separation tag of `hash_to_field`.
+pub const MESSAGE_HASH_DOMAIN: &[u8] = b"bellperson/groth16/message";
+
+/// Hashes a message to a uniformly distributed scalar.
+pub fn hash_to_field<E: Engine>(message: &[u8]) -> E::Fr {
+    E::Fr::random(&mut rng_from_seed(MESSAGE_HASH_DOMAIN, message))
+}
+
+/// A circuit with the hash of a message as its last public input.
+pub struct BoundCircuit<E: Engine, C> {
+    circuit: C,
+    message_hash: Option<E::Fr>,
+}
+
+impl<E: Engine, C: Circuit<E>> BoundCircuit<E, C> {
+    /// Binds `circuit` to `message`, which is only needed when proving.
+    pub fn new(circuit: C, message: Option<&[u8]>) -> Self {
+        BoundCircuit {
+            circuit,
+            message_hash: message.map(hash_to_field::<E>),
+        }
+    }
+}
+
+impl<E: Engine, C: Circuit<E>> Circuit<E> for BoundCircuit<E, C> {
+    fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        self.circuit.synthesize(&mut cs.namespace(|| "circuit"))?;
+
+        // The generator constrains every public input, so the hash is bound to the proof
+        // without a constraint of its own
+        let message_hash = self.message_hash;
+        cs.alloc_input(
+            || "message hash",
+            || message_hash.ok_or(SynthesisError::AssignmentMissing),
+        )?;
+
+        Ok(())
+    }
+}
+
+/// Creates a proof of `circuit` bound to `message`, with the parameters of the `BoundCircuit`.
+pub fn create_proof_bound<E, C, R, P>(
+    circuit: C,
+    message: &[u8],
+    params: P,
+    rng: &mut R,
+) -> Result<Proof<E>, SynthesisError>
+where
+    E: Engine,
+    C: Circuit<E> + Send,
+    R: RngCore,
+    P: ParameterSource<E>,
+{
+    create_random_proof(BoundCircuit::new(circuit, Some(message)), params, rng)
+}
+
+/// Verifies a proof of the public inputs of the circuit created by `create_proof_bound`,
+/// which only verifies for the message it is bound to.
+pub fn verify_proof_bound<E: Engine>(
+    pvk: &PreparedVerifyingKey<E>,
+    proof: &Proof<E>,
+    public_inputs: &[E::Fr],
+    message: &[u8],
+) -> Result<bool, SynthesisError> {
+    let mut inputs = public_inputs.to_vec();
+    inputs.push(hash_to_field::<E>(message));
+
+    verify_proof(pvk, proof, &inputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::groth16::witness::tests::Square;
+    use crate::groth16::{generate_parameters_from_seed, prepare_verifying_key};
+    use ff::PrimeField;
+    use paired::bls12_381::{Bls12, Fr};
+
+    #[test]
+    fn test_bound_proof() {
+        let rng = &mut rand::thread_rng();
+        let circuit = BoundCircuit::<Bls12, _>::new(Square(None), None);
+        let params = generate_parameters_from_seed(circuit, b"bound").unwrap();
+        let pvk = prepare_verifying_key(&params.vk);
+        let three = Fr::from_str("3").unwrap();
+        let nine = Fr::from_str("9").unwrap();
+
+        let proof = create_proof_bound(Square(Some(three)), b"tx 1", &params, rng).unwrap();
+        assert!(verify_proof_bound(&pvk, &proof, &[nine], b"tx 1").unwrap());
+        assert!(!verify_proof_bound(&pvk, &proof, &[nine], b"tx 2").unwrap());
+        assert!(!verify_proof_bound(&pvk, &proof, &[three], b"tx 1").unwrap());
+        assert!(hash_to_field::<Bls12>(b"tx 1") != hash_to_field::<Bls12>(b"tx 2"));
+    }
+}
@@ -1,5 +1,7 @@
 use super::{create_proof_batch_priority, create_random_proof_batch_priority};
-use super::{ParameterSource, Proof};
+use super::{create_random_proof_batch_with_config, ParameterSource, Proof, ProverConfig};
+use crate::cancellation::CancellationToken;
+use crate::gpu::Priority;
 use crate::{Circuit, SynthesisError};
 use paired::Engine;
 use rand_core::RngCore;
@@ -14,8 +16,13 @@ where
     E: Engine,
     C: Circuit<E> + Send,
 {
-    let proofs =
-        create_proof_batch_priority::<E, C, P>(vec![circuit], params, vec![r], vec![s], false)?;
+    let proofs = create_proof_batch_priority::<E, C, P>(
+        vec![circuit],
+        params,
+        vec![r],
+        vec![s],
+        Priority::Low,
+    )?;
     Ok(proofs.into_iter().next().unwrap())
 }
 
@@ -29,8 +36,12 @@ where
     C: Circuit<E> + Send,
     R: RngCore,
 {
-    let proofs =
-        create_random_proof_batch_priority::<E, C, R, P>(vec![circuit], params, rng, false)?;
+    let proofs = create_random_proof_batch_priority::<E, C, R, P>(
+        vec![circuit],
+        params,
+        rng,
+        Priority::Low,
+    )?;
     Ok(proofs.into_iter().next().unwrap())
 }
 
@@ -44,7 +55,7 @@ where
     E: Engine,
     C: Circuit<E> + Send,
 {
-    create_proof_batch_priority::<E, C, P>(circuits, params, r, s, false)
+    create_proof_batch_priority::<E, C, P>(circuits, params, r, s, Priority::Low)
 }
 
 pub fn create_random_proof_batch<E, C, R, P: ParameterSource<E>>(
@@ -57,7 +68,7 @@ where
     C: Circuit<E> + Send,
     R: RngCore,
 {
-    create_random_proof_batch_priority::<E, C, R, P>(circuits, params, rng, false)
+    create_random_proof_batch_priority::<E, C, R, P>(circuits, params, rng, Priority::Low)
 }
 
 pub fn create_proof_in_priority<E, C, P: ParameterSource<E>>(
@@ -70,8 +81,13 @@ where
     E: Engine,
     C: Circuit<E> + Send,
 {
-    let proofs =
-        create_proof_batch_priority::<E, C, P>(vec![circuit], params, vec![r], vec![s], true)?;
+    let proofs = create_proof_batch_priority::<E, C, P>(
+        vec![circuit],
+        params,
+        vec![r],
+        vec![s],
+        Priority::High,
+    )?;
     Ok(proofs.into_iter().next().unwrap())
 }
 
@@ -85,8 +101,12 @@ where
     C: Circuit<E> + Send,
     R: RngCore,
 {
-    let proofs =
-        create_random_proof_batch_priority::<E, C, R, P>(vec![circuit], params, rng, true)?;
+    let proofs = create_random_proof_batch_priority::<E, C, R, P>(
+        vec![circuit],
+        params,
+        rng,
+        Priority::High,
+    )?;
     Ok(proofs.into_iter().next().unwrap())
 }
 
@@ -100,7 +120,7 @@ where
     E: Engine,
     C: Circuit<E> + Send,
 {
-    create_proof_batch_priority::<E, C, P>(circuits, params, r, s, true)
+    create_proof_batch_priority::<E, C, P>(circuits, params, r, s, Priority::High)
 }
 
 pub fn create_random_proof_batch_in_priority<E, C, R, P: ParameterSource<E>>(
@@ -113,5 +133,27 @@ where
     C: Circuit<E> + Send,
     R: RngCore,
 {
-    create_random_proof_batch_priority::<E, C, R, P>(circuits, params, rng, true)
+    create_random_proof_batch_priority::<E, C, R, P>(circuits, params, rng, Priority::High)
+}
+
+/// Same as `create_random_proof`, failing with `SynthesisError::Cancelled` once `cancel` is
+/// cancelled (See `ProverConfig::cancel`).
+pub fn create_random_proof_cancellable<E, C, R, P: ParameterSource<E>>(
+    circuit: C,
+    params: P,
+    rng: &mut R,
+    cancel: &CancellationToken,
+) -> Result<Proof<E>, SynthesisError>
+where
+    E: Engine,
+    C: Circuit<E> + Send,
+    R: RngCore,
+{
+    let config = ProverConfig {
+        cancel: Some(cancel.clone()),
+        ..ProverConfig::default()
+    };
+    let proofs =
+        create_random_proof_batch_with_config::<E, C, R, P>(vec![circuit], params, rng, &config)?;
+    Ok(proofs.into_iter().next().unwrap())
 }
@@ -1,4 +1,6 @@
-use rand_core::RngCore;
+use blake2b_simd::Params as Blake2bParams;
+use rand_chacha::ChaChaRng;
+use rand_core::{RngCore, SeedableRng};
 
 use std::sync::Arc;
 
@@ -36,6 +38,36 @@ where
     generate_parameters::<E, C>(circuit, g1, g2, alpha, beta, gamma, delta, tau)
 }
 
+/// Domain separation tag of `generate_parameters_from_seed`.
+pub const PARAMETERS_SEED_DOMAIN: &[u8] = b"bellperson/groth16/parameters";
+
+/// A ChaCha RNG seeded with the BLAKE2b hash of `domain` and `seed`, so that the RNGs of
+/// different uses of the same seed are independent.
+pub fn rng_from_seed(domain: &[u8], seed: &[u8]) -> ChaChaRng {
+    let mut state = Blake2bParams::new().hash_length(32).to_state();
+    state.update(&(domain.len() as u64).to_be_bytes());
+    state.update(domain);
+    state.update(seed);
+
+    let mut rng_seed = [0u8; 32];
+    rng_seed.copy_from_slice(state.finalize().as_bytes());
+    ChaChaRng::from_seed(rng_seed)
+}
+
+/// Generates a common reference string for a circuit deterministically from `seed`, so that
+/// tests on different machines build identical parameters. Anyone knowing the seed can forge
+/// proofs, so these parameters are only meant for testing.
+pub fn generate_parameters_from_seed<E, C>(
+    circuit: C,
+    seed: &[u8],
+) -> Result<Parameters<E>, SynthesisError>
+where
+    E: Engine,
+    C: Circuit<E>,
+{
+    generate_random_parameters(circuit, &mut rng_from_seed(PARAMETERS_SEED_DOMAIN, seed))
+}
+
 /// This is our assembly structure that we'll use to synthesize the
 /// circuit into a QAP.
 struct KeypairAssembly<E: Engine> {
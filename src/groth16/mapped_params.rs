@@ -1,17 +1,25 @@
-use groupy::{CurveAffine, EncodedPoint};
+use byteorder::{BigEndian, ReadBytesExt};
+use groupy::{CurveAffine, CurveProjective, EncodedPoint};
+use log::warn;
 use paired::Engine;
+use rayon::prelude::*;
 
+use crate::multiexp::{Source, SourceBuilder};
 use crate::SynthesisError;
 
-use memmap::Mmap;
+use memmap::{Mmap, MmapOptions};
 
 use std::fs::File;
 use std::io;
+use std::marker::PhantomData;
+use std::mem;
 use std::ops::Range;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::slice;
 use std::sync::Arc;
 
-use super::{ParameterSource, VerifyingKey};
+use super::params_v2::checksum_mismatch;
+use super::{ParameterSource, Parameters, ParamsHeader, ParamsSection, SectionEntry, VerifyingKey};
 
 pub struct MappedParameters<E: Engine> {
     /// The parameter file we're reading from.  
@@ -194,3 +202,633 @@ pub fn read_g2<E: Engine>(
         }
     })
 }
+
+/// Parameters which are memory-mapped rather than read, see `Parameters::open_mmap`. Only the
+/// verifying key and the lengths of the queries are read when opening the file; the points of
+/// the queries are decoded from the mapping when a multiexp uses them.
+pub struct MmapParameters<E: Engine> {
+    /// The header of the file, for the v2 format.
+    pub header: Option<ParamsHeader>,
+    pub vk: VerifyingKey<E>,
+    pub h: MmapSource<E::G1Affine>,
+    pub l: MmapSource<E::G1Affine>,
+    pub a: MmapSource<E::G1Affine>,
+    pub b_g1: MmapSource<E::G1Affine>,
+    pub b_g2: MmapSource<E::G2Affine>,
+    mmap: Arc<Mmap>,
+}
+
+impl<E: Engine> MmapParameters<E> {
+    pub(crate) fn open<P: AsRef<Path>>(path: P, checked: bool) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let mmap = Arc::new(unsafe { MmapOptions::new().map(&file)? });
+
+        // Files in the v2 format are read at the offsets of their sections, and their
+        // verifying key is checked against its checksum, as it is decoded right away.
+        let header = if ParamsHeader::is_v2(&mmap) {
+            Some(ParamsHeader::read(&mmap[..])?)
+        } else {
+            None
+        };
+        let section = |section: ParamsSection, offset: &mut usize| -> io::Result<()> {
+            if let Some(ref header) = header {
+                let entry = header.section(section);
+                let (start, end) = section_range(entry, &mmap)?;
+                if section == ParamsSection::Vk && !entry.verify(&mmap[start..end]) {
+                    return Err(checksum_mismatch(section));
+                }
+                *offset = start;
+            }
+            Ok(())
+        };
+
+        let mut offset = 0;
+        section(ParamsSection::Vk, &mut offset)?;
+        let vk = VerifyingKey::<E>::read_mmap(&mmap, &mut offset)?;
+        section(ParamsSection::H, &mut offset)?;
+        let h = MmapSource::open(&mmap, &mut offset, checked)?;
+        section(ParamsSection::L, &mut offset)?;
+        let l = MmapSource::open(&mmap, &mut offset, checked)?;
+        section(ParamsSection::A, &mut offset)?;
+        let a = MmapSource::open(&mmap, &mut offset, checked)?;
+        section(ParamsSection::BG1, &mut offset)?;
+        let b_g1 = MmapSource::open(&mmap, &mut offset, checked)?;
+        section(ParamsSection::BG2, &mut offset)?;
+        let b_g2 = MmapSource::open(&mmap, &mut offset, checked)?;
+
+        Ok(MmapParameters {
+            header,
+            vk,
+            h,
+            l,
+            a,
+            b_g1,
+            b_g2,
+            mmap,
+        })
+    }
+
+    /// Checks all of the sections of a v2 file against their checksums, in parallel. Files in
+    /// the legacy format have no checksums, and always pass.
+    pub fn verify_checksums(&self) -> io::Result<()> {
+        let header = match self.header {
+            Some(ref header) => header,
+            None => return Ok(()),
+        };
+
+        ParamsSection::ALL[..].par_iter().try_for_each(|&section| {
+            let entry = header.section(section);
+            let (start, end) = section_range(entry, &self.mmap)?;
+            if entry.verify(&self.mmap[start..end]) {
+                Ok(())
+            } else {
+                Err(checksum_mismatch(section))
+            }
+        })
+    }
+}
+
+// Byte range of a section in the mapped file
+fn section_range(entry: &SectionEntry, mmap: &Mmap) -> io::Result<(usize, usize)> {
+    let start = entry.offset as usize;
+    let end = start.checked_add(entry.len as usize);
+    match end {
+        Some(end) if end <= mmap.len() => Ok((start, end)),
+        _ => Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "truncated parameter file",
+        )),
+    }
+}
+
+/// A query of `MmapParameters`, whose points are borrowed from the mapped file and decoded on
+/// demand. It is a source of bases for the CPU multiexp, which decodes the points one at a
+/// time; the GPU multiexp decodes (In parallel) the part of the query it needs.
+#[derive(Clone)]
+pub struct MmapSource<G: CurveAffine> {
+    mmap: Arc<Mmap>,
+    // Offset of the first point of the query in the file
+    start: usize,
+    len: usize,
+    // Index of the next point of the source
+    pos: usize,
+    // Whether the points are checked to be on the curve and in the right subgroup
+    checked: bool,
+    _g: PhantomData<G>,
+}
+
+impl<G: CurveAffine> MmapSource<G> {
+    // Opens the query, prefixed by its length, at `offset` of `mmap`, and moves `offset` past it
+    fn open(mmap: &Arc<Mmap>, offset: &mut usize, checked: bool) -> io::Result<Self> {
+        let u32_len = mem::size_of::<u32>();
+        let point_len = mem::size_of::<G::Uncompressed>();
+        if mmap.len() < *offset + u32_len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "truncated parameter file",
+            ));
+        }
+        let len = (&mmap[*offset..*offset + u32_len]).read_u32::<BigEndian>()? as usize;
+        let start = *offset + u32_len;
+        if mmap.len() < start + len * point_len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "truncated parameter file",
+            ));
+        }
+        *offset = start + len * point_len;
+
+        Ok(MmapSource {
+            mmap: mmap.clone(),
+            start,
+            len,
+            pos: 0,
+            checked,
+            _g: PhantomData,
+        })
+    }
+
+    /// Number of points of the query.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The encoded points of the query, without copying them.
+    pub fn encoded(&self) -> &[G::Uncompressed] {
+        let bytes =
+            &self.mmap[self.start..self.start + self.len * mem::size_of::<G::Uncompressed>()];
+        // Safety: the encoded points are arrays of bytes, which are laid out one after the
+        // other in the file.
+        unsafe { slice::from_raw_parts(bytes.as_ptr() as *const G::Uncompressed, self.len) }
+    }
+
+    /// Decodes the point of index `i` of the query.
+    pub fn point(&self, i: usize) -> io::Result<G> {
+        let repr = self.encoded()[i];
+        if self.checked {
+            repr.into_affine()
+        } else {
+            repr.into_affine_unchecked()
+        }
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn at(&self, pos: usize) -> Self {
+        let mut source = self.clone();
+        source.pos = pos;
+        source
+    }
+
+    // Decodes the next point of the source
+    fn next_point(&mut self) -> Result<G, SynthesisError> {
+        if self.len <= self.pos {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "expected more bases from source",
+            )
+            .into());
+        }
+
+        let point = self.point(self.pos)?;
+        if point.is_zero() {
+            return Err(SynthesisError::UnexpectedIdentity);
+        }
+        self.pos += 1;
+
+        Ok(point)
+    }
+}
+
+impl<G: CurveAffine> SourceBuilder<G> for MmapSource<G> {
+    type Source = MmapSource<G>;
+
+    fn new(self) -> MmapSource<G> {
+        self
+    }
+
+    fn get(self) -> (Arc<Vec<G>>, usize) {
+        match (self.pos..self.len)
+            .into_par_iter()
+            .map(|i| self.point(i))
+            .collect::<io::Result<Vec<_>>>()
+        {
+            Ok(points) => (Arc::new(points), 0),
+            Err(e) => {
+                // The multiexp has too few bases then, and fails on the CPU with the error.
+                warn!("Cannot decode the mapped parameters: {}", e);
+                (Arc::new(vec![]), 0)
+            }
+        }
+    }
+}
+
+impl<G: CurveAffine> Source<G> for MmapSource<G> {
+    fn add_assign_mixed(
+        &mut self,
+        to: &mut <G as CurveAffine>::Projective,
+    ) -> Result<(), SynthesisError> {
+        to.add_assign_mixed(&self.next_point()?);
+        Ok(())
+    }
+
+    fn sub_assign_mixed(
+        &mut self,
+        to: &mut <G as CurveAffine>::Projective,
+    ) -> Result<(), SynthesisError> {
+        let mut point = self.next_point()?;
+        point.negate();
+        to.add_assign_mixed(&point);
+        Ok(())
+    }
+
+    fn skip(&mut self, amt: usize) -> Result<(), SynthesisError> {
+        if self.len <= self.pos {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "expected more bases from source",
+            )
+            .into());
+        }
+
+        self.pos += amt;
+
+        Ok(())
+    }
+}
+
+impl<'a, E: Engine> ParameterSource<E> for &'a MmapParameters<E> {
+    type G1Builder = MmapSource<E::G1Affine>;
+    type G2Builder = MmapSource<E::G2Affine>;
+
+    fn get_vk(&self, _: usize) -> Result<&VerifyingKey<E>, SynthesisError> {
+        Ok(&self.vk)
+    }
+
+    fn get_h(&self, _: usize) -> Result<Self::G1Builder, SynthesisError> {
+        Ok(self.h.clone())
+    }
+
+    fn get_l(&self, _: usize) -> Result<Self::G1Builder, SynthesisError> {
+        Ok(self.l.clone())
+    }
+
+    fn get_a(
+        &self,
+        num_inputs: usize,
+        _: usize,
+    ) -> Result<(Self::G1Builder, Self::G1Builder), SynthesisError> {
+        Ok((self.a.clone(), self.a.at(num_inputs)))
+    }
+
+    fn get_b_g1(
+        &self,
+        num_inputs: usize,
+        _: usize,
+    ) -> Result<(Self::G1Builder, Self::G1Builder), SynthesisError> {
+        Ok((self.b_g1.clone(), self.b_g1.at(num_inputs)))
+    }
+
+    fn get_b_g2(
+        &self,
+        num_inputs: usize,
+        _: usize,
+    ) -> Result<(Self::G2Builder, Self::G2Builder), SynthesisError> {
+        Ok((self.b_g2.clone(), self.b_g2.at(num_inputs)))
+    }
+}
+
+impl<E: Engine> Parameters<E> {
+    /// Memory-maps the parameter file at `path`, reading only its verifying key and the
+    /// lengths of its queries. The points of the queries are decoded when the prover uses
+    /// them, and checked to be on the curve and in the right subgroup if `checked` is set, so
+    /// that the parameters don't need to fit in memory.
+    pub fn open_mmap<P: AsRef<Path>>(path: P, checked: bool) -> io::Result<MmapParameters<E>> {
+        MmapParameters::open(path, checked)
+    }
+
+    // Quickly iterates through the parameter file, recording all
+    // parameter offsets and caches the verifying key (vk) for quick
+    // access via reference.
+    pub fn build_mapped_parameters(
+        param_file_path: PathBuf,
+        checked: bool,
+    ) -> io::Result<MappedParameters<E>> {
+        let mut offset: usize = 0;
+        let param_file = File::open(&param_file_path)?;
+        let params = unsafe { MmapOptions::new().map(&param_file)? };
+
+        let u32_len = mem::size_of::<u32>();
+        let g1_len = mem::size_of::<<E::G1Affine as CurveAffine>::Uncompressed>();
+        let g2_len = mem::size_of::<<E::G2Affine as CurveAffine>::Uncompressed>();
+
+        let read_length = |params: &Mmap, offset: &mut usize| -> Result<usize, std::io::Error> {
+            let mut raw_len = &params[*offset..*offset + u32_len];
+            *offset += u32_len;
+
+            match raw_len.read_u32::<BigEndian>() {
+                Ok(len) => Ok(len as usize),
+                Err(err) => Err(err),
+            }
+        };
+
+        let get_offsets = |params: &Mmap,
+                           offset: &mut usize,
+                           param: &mut Vec<Range<usize>>,
+                           range_len: usize|
+         -> Result<(), std::io::Error> {
+            let len = read_length(&params, &mut *offset)?;
+            for _ in 0..len {
+                (*param).push(Range {
+                    start: *offset,
+                    end: *offset + range_len,
+                });
+                *offset += range_len;
+            }
+
+            Ok(())
+        };
+
+        let vk = VerifyingKey::<E>::read_mmap(&params, &mut offset)?;
+
+        let mut h = vec![];
+        let mut l = vec![];
+        let mut a = vec![];
+        let mut b_g1 = vec![];
+        let mut b_g2 = vec![];
+
+        get_offsets(&params, &mut offset, &mut h, g1_len)?;
+        get_offsets(&params, &mut offset, &mut l, g1_len)?;
+        get_offsets(&params, &mut offset, &mut a, g1_len)?;
+        get_offsets(&params, &mut offset, &mut b_g1, g1_len)?;
+        get_offsets(&params, &mut offset, &mut b_g2, g2_len)?;
+
+        Ok(MappedParameters {
+            param_file_path,
+            param_file,
+            params,
+            vk,
+            h,
+            l,
+            a,
+            b_g1,
+            b_g2,
+            checked,
+        })
+    }
+
+    // This method is provided as a proof of concept, but isn't
+    // advantageous to use (can be called by read_cached_params in
+    // rust-fil-proofs repo).  It's equivalent to the existing read
+    // method, in that it loads all parameters to RAM.
+    pub fn read_mmap(mmap: &Mmap, checked: bool) -> io::Result<Self> {
+        let u32_len = mem::size_of::<u32>();
+        let g1_len = mem::size_of::<<E::G1Affine as CurveAffine>::Uncompressed>();
+        let g2_len = mem::size_of::<<E::G2Affine as CurveAffine>::Uncompressed>();
+
+        let read_g1 = |mmap: &Mmap, offset: &mut usize| -> io::Result<E::G1Affine> {
+            let ptr = &mmap[*offset..*offset + g1_len];
+            *offset += g1_len;
+            // Safety: this operation is safe, because it's simply
+            // casting to a known struct at the correct offset, given
+            // the structure of the on-disk data.
+            let repr: <E::G1Affine as CurveAffine>::Uncompressed = unsafe {
+                *(ptr as *const [u8] as *const <E::G1Affine as CurveAffine>::Uncompressed)
+            };
+
+            if checked {
+                repr.into_affine()
+            } else {
+                repr.into_affine_unchecked()
+            }
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            .and_then(|e| {
+                if e.is_zero() {
+                    Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "point at infinity",
+                    ))
+                } else {
+                    Ok(e)
+                }
+            })
+        };
+
+        let read_g2 = |mmap: &Mmap, offset: &mut usize| -> io::Result<E::G2Affine> {
+            let ptr = &mmap[*offset..*offset + g2_len];
+            *offset += g2_len;
+            // Safety: this operation is safe, because it's simply
+            // casting to a known struct at the correct offset, given
+            // the structure of the on-disk data.
+            let repr: <E::G2Affine as CurveAffine>::Uncompressed = unsafe {
+                *(ptr as *const [u8] as *const <E::G2Affine as CurveAffine>::Uncompressed)
+            };
+
+            if checked {
+                repr.into_affine()
+            } else {
+                repr.into_affine_unchecked()
+            }
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            .and_then(|e| {
+                if e.is_zero() {
+                    Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "point at infinity",
+                    ))
+                } else {
+                    Ok(e)
+                }
+            })
+        };
+
+        let read_length = |mmap: &Mmap, offset: &mut usize| -> Result<usize, std::io::Error> {
+            let mut raw_len = &mmap[*offset..*offset + u32_len];
+            *offset += u32_len;
+
+            match raw_len.read_u32::<BigEndian>() {
+                Ok(len) => Ok(len as usize),
+                Err(err) => Err(err),
+            }
+        };
+
+        let get_g1s = |mmap: &Mmap,
+                       offset: &mut usize,
+                       param: &mut Vec<E::G1Affine>|
+         -> Result<(), std::io::Error> {
+            let len = read_length(&mmap, &mut *offset)?;
+            for _ in 0..len {
+                (*param).push(read_g1(&mmap, &mut *offset)?);
+            }
+
+            Ok(())
+        };
+
+        let get_g2s = |mmap: &Mmap,
+                       offset: &mut usize,
+                       param: &mut Vec<E::G2Affine>|
+         -> Result<(), std::io::Error> {
+            let len = read_length(&mmap, &mut *offset)?;
+            for _ in 0..len {
+                (*param).push(read_g2(&mmap, &mut *offset)?);
+            }
+
+            Ok(())
+        };
+
+        let mut offset: usize = 0;
+        let vk = VerifyingKey::<E>::read_mmap(&mmap, &mut offset)?;
+
+        let mut h = vec![];
+        let mut l = vec![];
+        let mut a = vec![];
+        let mut b_g1 = vec![];
+        let mut b_g2 = vec![];
+
+        get_g1s(&mmap, &mut offset, &mut h)?;
+        get_g1s(&mmap, &mut offset, &mut l)?;
+        get_g1s(&mmap, &mut offset, &mut a)?;
+        get_g1s(&mmap, &mut offset, &mut b_g1)?;
+        get_g2s(&mmap, &mut offset, &mut b_g2)?;
+
+        Ok(Parameters {
+            vk,
+            h: Arc::new(h),
+            l: Arc::new(l),
+            a: Arc::new(a),
+            b_g1: Arc::new(b_g1),
+            b_g2: Arc::new(b_g2),
+        })
+    }
+}
+
+impl<E: Engine> VerifyingKey<E> {
+    pub fn read_mmap(mmap: &Mmap, offset: &mut usize) -> io::Result<Self> {
+        let u32_len = mem::size_of::<u32>();
+        let g1_len = mem::size_of::<<E::G1Affine as CurveAffine>::Uncompressed>();
+        let g2_len = mem::size_of::<<E::G2Affine as CurveAffine>::Uncompressed>();
+
+        let read_g1 = |mmap: &Mmap,
+                       offset: &mut usize|
+         -> Result<<E as paired::Engine>::G1Affine, std::io::Error> {
+            let ptr = &mmap[*offset..*offset + g1_len];
+            // Safety: this operation is safe, because it's simply
+            // casting to a known struct at the correct offset, given
+            // the structure of the on-disk data.
+            let g1_repr: <E::G1Affine as CurveAffine>::Uncompressed = unsafe {
+                *(ptr as *const [u8] as *const <E::G1Affine as CurveAffine>::Uncompressed)
+            };
+
+            *offset += g1_len;
+            g1_repr
+                .into_affine()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        };
+
+        let read_g2 = |mmap: &Mmap,
+                       offset: &mut usize|
+         -> Result<<E as paired::Engine>::G2Affine, std::io::Error> {
+            let ptr = &mmap[*offset..*offset + g2_len];
+            // Safety: this operation is safe, because it's simply
+            // casting to a known struct at the correct offset, given
+            // the structure of the on-disk data.
+            let g2_repr: <E::G2Affine as CurveAffine>::Uncompressed = unsafe {
+                *(ptr as *const [u8] as *const <E::G2Affine as CurveAffine>::Uncompressed)
+            };
+
+            *offset += g2_len;
+            g2_repr
+                .into_affine()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        };
+
+        let alpha_g1 = read_g1(&mmap, &mut *offset)?;
+        let beta_g1 = read_g1(&mmap, &mut *offset)?;
+        let beta_g2 = read_g2(&mmap, &mut *offset)?;
+        let gamma_g2 = read_g2(&mmap, &mut *offset)?;
+        let delta_g1 = read_g1(&mmap, &mut *offset)?;
+        let delta_g2 = read_g2(&mmap, &mut *offset)?;
+
+        let mut raw_ic_len = &mmap[*offset..*offset + u32_len];
+        let ic_len = raw_ic_len.read_u32::<BigEndian>()? as usize;
+        *offset += u32_len;
+
+        let mut ic = vec![];
+
+        for _ in 0..ic_len {
+            let g1_repr = read_g1(&mmap, &mut *offset);
+            let g1 = g1_repr.and_then(|e| {
+                if e.is_zero() {
+                    Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "point at infinity",
+                    ))
+                } else {
+                    Ok(e)
+                }
+            })?;
+
+            ic.push(g1);
+        }
+
+        Ok(VerifyingKey {
+            alpha_g1,
+            beta_g1,
+            beta_g2,
+            gamma_g2,
+            delta_g1,
+            delta_g2,
+            ic,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::groth16::tests::random_parameters;
+    use crate::multicore::Worker;
+    use crate::multiexp::{multiexp, FullDensity};
+    use ff::{Field, PrimeField};
+    use futures::Future;
+    use paired::bls12_381::{Bls12, Fr};
+
+    #[test]
+    fn test_open_mmap() {
+        let rng = &mut rand::thread_rng();
+        let params = random_parameters();
+
+        let path = std::env::temp_dir().join(format!("bellperson-mmap-{}", std::process::id()));
+        params.write(File::create(&path).unwrap()).unwrap();
+        let mapped = Parameters::<Bls12>::open_mmap(&path, true).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(mapped.header.is_none());
+        assert!(mapped.vk == params.vk);
+        assert_eq!(mapped.h.len(), params.h.len());
+        for (i, g) in params.b_g2.iter().enumerate() {
+            assert_eq!(mapped.b_g2.point(i).unwrap(), *g);
+        }
+
+        let pool = Worker::new();
+        let exps = Arc::new(
+            (0..7)
+                .map(|_| Fr::random(rng).into_repr())
+                .collect::<Vec<_>>(),
+        );
+        let (_, expected) = (&params).get_a(3, 7).unwrap();
+        let (_, source) = (&mapped).get_a(3, 7).unwrap();
+        let expected = multiexp(&pool, expected, FullDensity, exps.clone(), &mut None)
+            .wait()
+            .unwrap();
+        let result = multiexp(&pool, source.clone(), FullDensity, exps.clone(), &mut None)
+            .wait()
+            .unwrap();
+        assert_eq!(result, expected);
+        let (bases, skip) = source.get();
+        assert_eq!(&bases[skip..], &params.a[3..]);
+    }
+}
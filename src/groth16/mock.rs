@@ -0,0 +1,97 @@
+//! A mock prover, for the tests of services which make proofs.
+//!
+//! `create_mock_proof` synthesizes a circuit and checks that its witness satisfies it, like a
+//! real prover, but returns a proof derived from its public inputs instead of running the FFTs
+//! and multiexps, so it needs no parameters and returns at once. `verify_mock_proof` accepts
+//! exactly the mock proofs of the given public inputs. Mock proofs are neither zero-knowledge
+//! nor sound. They aren't valid Groth16 proofs, so `verify_proof` would only accept one with
+//! negligible probability.
+
+use ff::{Field, PrimeField, PrimeFieldRepr};
+use groupy::{CurveAffine, CurveProjective};
+use log::error;
+use paired::Engine;
+
+use super::{rng_from_seed, Proof};
+use crate::util_cs::debug_cs::DebugConstraintSystem;
+use crate::{Circuit, SynthesisError};
+
+const MOCK_DOMAIN: &[u8] = b"bellperson/groth16/mock";
+
+// The points of the mock proof of `public_inputs`, which are multiples of the generators
+fn mock_proof<E: Engine>(public_inputs: &[E::Fr]) -> Result<Proof<E>, SynthesisError> {
+    let mut seed = vec![];
+    for input in public_inputs {
+        input.into_repr().write_le(&mut seed)?;
+    }
+    let mut rng = rng_from_seed(MOCK_DOMAIN, &seed);
+    let mut scalar = || loop {
+        let s = E::Fr::random(&mut rng);
+        if !s.is_zero() {
+            return s.into_repr();
+        }
+    };
+
+    Ok(Proof {
+        a: E::G1Affine::one().mul(scalar()).into_affine(),
+        b: E::G2Affine::one().mul(scalar()).into_affine(),
+        c: E::G1Affine::one().mul(scalar()).into_affine(),
+    })
+}
+
+/// Synthesizes `circuit` and returns its mock proof, or `SynthesisError::Unsatisfiable` if its
+/// witness doesn't satisfy it (The first unsatisfied constraint is logged).
+pub fn create_mock_proof<E, C>(circuit: C) -> Result<Proof<E>, SynthesisError>
+where
+    E: Engine,
+    C: Circuit<E>,
+{
+    let mut cs = DebugConstraintSystem::<E>::new();
+    circuit.synthesize(&mut cs)?;
+    if let Some(constraint) = cs.first_unsatisfied() {
+        error!("Mock proving failed: {}", constraint);
+        return Err(SynthesisError::Unsatisfiable);
+    }
+
+    mock_proof(cs.public_inputs())
+}
+
+/// Whether `proof` is the mock proof of `public_inputs`.
+pub fn verify_mock_proof<E: Engine>(
+    proof: &Proof<E>,
+    public_inputs: &[E::Fr],
+) -> Result<bool, SynthesisError> {
+    Ok(mock_proof(public_inputs)? == *proof)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::groth16::witness::tests::Square;
+    use crate::groth16::{
+        create_random_proof, generate_parameters_from_seed, prepare_verifying_key, verify_proof,
+    };
+    use paired::bls12_381::{Bls12, Fr};
+
+    #[test]
+    fn test_mock_proof() {
+        let three = Fr::from_str("3").unwrap();
+        let nine = Fr::from_str("9").unwrap();
+        let proof = create_mock_proof(Square(Some(three))).unwrap();
+        assert!(verify_mock_proof(&proof, &[nine]).unwrap());
+        assert!(!verify_mock_proof(&proof, &[three]).unwrap());
+        assert!(create_mock_proof(Square(Some(three))).unwrap() == proof);
+        match create_mock_proof::<Bls12, _>(Square(None)) {
+            Err(SynthesisError::AssignmentMissing) => (),
+            _ => panic!("the witness is missing"),
+        }
+
+        // Real and mock proofs aren't interchangeable
+        let params = generate_parameters_from_seed(Square(None), b"mock").unwrap();
+        let pvk = prepare_verifying_key(&params.vk);
+        assert!(!verify_proof(&pvk, &proof, &[nine]).unwrap());
+        let real =
+            create_random_proof(Square(Some(three)), &params, &mut rand::thread_rng()).unwrap();
+        assert!(!verify_mock_proof(&real, &[nine]).unwrap());
+    }
+}
@@ -10,21 +10,41 @@ use std::io::{self, Read, Write};
 #[cfg(test)]
 mod tests;
 
+pub mod aggregate;
+mod bound;
 mod ext;
 mod generator;
+#[cfg(feature = "mmap")]
 mod mapped_params;
+pub mod mock;
+pub mod mpc;
 mod params;
+mod params_v2;
 mod prover;
+pub mod r1cs;
+mod rerandomize;
+#[cfg(feature = "serde")]
+mod serialization;
 mod verifier;
 mod verifying_key;
+mod vk_export;
+mod witness;
+mod witness_export;
 
+pub use self::bound::*;
 pub use self::ext::*;
 pub use self::generator::*;
+#[cfg(feature = "mmap")]
 pub use self::mapped_params::*;
 pub use self::prover::*;
+pub use self::rerandomize::*;
 pub use self::verifier::*;
 pub use self::verifying_key::*;
+pub use self::vk_export::*;
+pub use self::witness::*;
+pub use self::witness_export::*;
 pub use params::*;
+pub use params_v2::*;
 
 #[derive(Clone, Debug)]
 pub struct Proof<E: Engine> {
@@ -39,8 +59,44 @@ impl<E: Engine> PartialEq for Proof<E> {
     }
 }
 
+// Reads a point of the encoding `P`, which can't be the point at infinity. The point is only
+// checked to be on the curve and in the right subgroup if `checked` is set.
+fn read_encoded_point<P: EncodedPoint, R: Read>(
+    reader: &mut R,
+    checked: bool,
+) -> io::Result<P::Affine> {
+    let mut repr = P::empty();
+    reader.read_exact(repr.as_mut())?;
+    let point = if checked {
+        repr.into_affine()
+    } else {
+        repr.into_affine_unchecked()
+    }
+    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    if point.is_zero() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "point at infinity",
+        ));
+    }
+    Ok(point)
+}
+
 impl<E: Engine> Proof<E> {
-    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+    /// Writes the proof with its points compressed, see `write_compressed`.
+    pub fn write<W: Write>(&self, writer: W) -> io::Result<()> {
+        self.write_compressed(writer)
+    }
+
+    /// Reads a proof with its points compressed, see `read_compressed`.
+    pub fn read<R: Read>(reader: R) -> io::Result<Self> {
+        Self::read_compressed(reader)
+    }
+
+    /// Writes the proof in 192 bytes (For BLS12-381). Reading it back needs a square root per
+    /// point.
+    pub fn write_compressed<W: Write>(&self, mut writer: W) -> io::Result<()> {
         writer.write_all(self.a.into_compressed().as_ref())?;
         writer.write_all(self.b.into_compressed().as_ref())?;
         writer.write_all(self.c.into_compressed().as_ref())?;
@@ -48,56 +104,63 @@ impl<E: Engine> Proof<E> {
         Ok(())
     }
 
-    pub fn read<R: Read>(mut reader: R) -> io::Result<Self> {
-        let mut g1_repr = <E::G1Affine as CurveAffine>::Compressed::empty();
-        let mut g2_repr = <E::G2Affine as CurveAffine>::Compressed::empty();
-
-        reader.read_exact(g1_repr.as_mut())?;
-        let a = g1_repr
-            .into_affine()
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
-            .and_then(|e| {
-                if e.is_zero() {
-                    Err(io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        "point at infinity",
-                    ))
-                } else {
-                    Ok(e)
-                }
-            })?;
-
-        reader.read_exact(g2_repr.as_mut())?;
-        let b = g2_repr
-            .into_affine()
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
-            .and_then(|e| {
-                if e.is_zero() {
-                    Err(io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        "point at infinity",
-                    ))
-                } else {
-                    Ok(e)
-                }
-            })?;
-
-        reader.read_exact(g1_repr.as_mut())?;
-        let c = g1_repr
-            .into_affine()
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
-            .and_then(|e| {
-                if e.is_zero() {
-                    Err(io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        "point at infinity",
-                    ))
-                } else {
-                    Ok(e)
-                }
-            })?;
-
-        Ok(Proof { a, b, c })
+    /// Writes the proof in 384 bytes (For BLS12-381), which are faster to read than the
+    /// compressed encoding.
+    pub fn write_uncompressed<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(self.a.into_uncompressed().as_ref())?;
+        writer.write_all(self.b.into_uncompressed().as_ref())?;
+        writer.write_all(self.c.into_uncompressed().as_ref())?;
+
+        Ok(())
+    }
+
+    pub fn read_compressed<R: Read>(reader: R) -> io::Result<Self> {
+        Self::read_encoded(reader, true, true)
+    }
+
+    pub fn read_uncompressed<R: Read>(reader: R) -> io::Result<Self> {
+        Self::read_encoded(reader, false, true)
+    }
+
+    /// Reads an uncompressed proof without checking that its points are on the curve and in the
+    /// right subgroup, which is only sound for proofs from a trusted storage (e.g. proofs which
+    /// were checked when they were stored).
+    pub fn read_uncompressed_unchecked<R: Read>(reader: R) -> io::Result<Self> {
+        Self::read_encoded(reader, false, false)
+    }
+
+    fn read_encoded<R: Read>(mut reader: R, compressed: bool, checked: bool) -> io::Result<Self> {
+        if compressed {
+            Ok(Proof {
+                a: read_encoded_point::<<E::G1Affine as CurveAffine>::Compressed, _>(
+                    &mut reader,
+                    checked,
+                )?,
+                b: read_encoded_point::<<E::G2Affine as CurveAffine>::Compressed, _>(
+                    &mut reader,
+                    checked,
+                )?,
+                c: read_encoded_point::<<E::G1Affine as CurveAffine>::Compressed, _>(
+                    &mut reader,
+                    checked,
+                )?,
+            })
+        } else {
+            Ok(Proof {
+                a: read_encoded_point::<<E::G1Affine as CurveAffine>::Uncompressed, _>(
+                    &mut reader,
+                    checked,
+                )?,
+                b: read_encoded_point::<<E::G2Affine as CurveAffine>::Uncompressed, _>(
+                    &mut reader,
+                    checked,
+                )?,
+                c: read_encoded_point::<<E::G1Affine as CurveAffine>::Uncompressed, _>(
+                    &mut reader,
+                    checked,
+                )?,
+            })
+        }
     }
 }
 
@@ -186,6 +249,13 @@ mod test_with_bls12_381 {
             let de_proof = Proof::read(&v[..]).unwrap();
             assert!(proof == de_proof);
 
+            let mut v = vec![];
+            proof.write_uncompressed(&mut v).unwrap();
+            assert_eq!(v.len(), 384);
+            assert!(Proof::<Bls12>::read_uncompressed(&v[..]).unwrap() == proof);
+            assert!(Proof::<Bls12>::read_uncompressed_unchecked(&v[..]).unwrap() == proof);
+            assert!(Proof::<Bls12>::read_compressed(&v[..]).is_err());
+
             assert!(verify_proof(&pvk, &proof, &[c]).unwrap());
             assert!(!verify_proof(&pvk, &proof, &[a]).unwrap());
         }
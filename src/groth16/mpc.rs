@@ -0,0 +1,513 @@
+//! The phase 2 of the multi-party computation generating Groth16 parameters for a circuit.
+//!
+//! Starting from parameters whose delta is known to be 1 (Built from the output of a phase 1
+//! "powers of tau" ceremony), each participant multiplies delta by a secret of their own and
+//! divides the h and l queries by it. The parameters are secure as long as a single participant
+//! destroyed their secret. Every contribution comes with a public key proving that it was made
+//! correctly, which `verify_contribution` and `MPCParameters::verify` check.
+//! `MPCParameters::contribute_with_beacon` makes a last contribution from a public random
+//! beacon, so that the final parameters don't depend on the last participant only.
+
+use blake2b_simd::Params as Blake2bParams;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use ff::{Field, PrimeField};
+use futures::Future;
+use groupy::{CurveAffine, CurveProjective, EncodedPoint};
+use paired::Engine;
+use rand_chacha::ChaChaRng;
+use rand_core::{RngCore, SeedableRng};
+use thiserror::Error;
+
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+use super::Parameters;
+use crate::multicore::Worker;
+use crate::multiexp::{multiexp, FullDensity};
+
+/// Length of the hashes of the ceremony.
+pub const MPC_HASH_LEN: usize = 64;
+
+#[derive(Error, Debug)]
+pub enum MPCError {
+    #[error("invalid contribution: {0}")]
+    InvalidContribution(&'static str),
+    #[error("invalid beacon: {0}")]
+    InvalidBeacon(&'static str),
+    #[error("encountered an I/O error: {0}")]
+    IoError(#[from] io::Error),
+}
+
+/// The public key of a contribution, proving the knowledge of the secret delta it multiplied
+/// the delta of the parameters with.
+#[derive(Clone)]
+pub struct PublicKey<E: Engine> {
+    /// The delta of the parameters after the contribution, in G1.
+    pub delta_after: E::G1Affine,
+    /// A random point, and its multiple by the secret.
+    pub s: E::G1Affine,
+    pub s_delta: E::G1Affine,
+    /// The multiple by the secret of the point of G2 hashed from the transcript, `s` and
+    /// `s_delta`.
+    pub r_delta: E::G2Affine,
+    /// Hash of the ceremony before the contribution.
+    pub transcript: [u8; MPC_HASH_LEN],
+}
+
+impl<E: Engine> PartialEq for PublicKey<E> {
+    fn eq(&self, other: &Self) -> bool {
+        self.delta_after == other.delta_after
+            && self.s == other.s
+            && self.s_delta == other.s_delta
+            && self.r_delta == other.r_delta
+            && self.transcript[..] == other.transcript[..]
+    }
+}
+
+impl<E: Engine> PublicKey<E> {
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(self.delta_after.into_uncompressed().as_ref())?;
+        writer.write_all(self.s.into_uncompressed().as_ref())?;
+        writer.write_all(self.s_delta.into_uncompressed().as_ref())?;
+        writer.write_all(self.r_delta.into_uncompressed().as_ref())?;
+        writer.write_all(&self.transcript)
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut g1 = <E::G1Affine as CurveAffine>::Uncompressed::empty();
+        let mut g2 = <E::G2Affine as CurveAffine>::Uncompressed::empty();
+        let mut read_g1 = |reader: &mut R| -> io::Result<E::G1Affine> {
+            reader.read_exact(g1.as_mut())?;
+            g1.into_affine()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        };
+
+        let delta_after = read_g1(&mut reader)?;
+        let s = read_g1(&mut reader)?;
+        let s_delta = read_g1(&mut reader)?;
+        reader.read_exact(g2.as_mut())?;
+        let r_delta = g2
+            .into_affine()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let mut transcript = [0u8; MPC_HASH_LEN];
+        reader.read_exact(&mut transcript)?;
+
+        Ok(PublicKey {
+            delta_after,
+            s,
+            s_delta,
+            r_delta,
+            transcript,
+        })
+    }
+
+    /// Hash of the public key, which identifies the contribution.
+    pub fn hash(&self) -> [u8; MPC_HASH_LEN] {
+        let mut bytes = vec![];
+        self.write(&mut bytes).unwrap();
+        hash(&bytes)
+    }
+}
+
+/// Parameters going through the phase 2 of the ceremony.
+#[derive(Clone)]
+pub struct MPCParameters<E: Engine> {
+    params: Parameters<E>,
+    /// Hash of the initial parameters, which ties the contributions to them.
+    cs_hash: [u8; MPC_HASH_LEN],
+    contributions: Vec<PublicKey<E>>,
+}
+
+impl<E: Engine> PartialEq for MPCParameters<E> {
+    fn eq(&self, other: &Self) -> bool {
+        self.params == other.params
+            && self.cs_hash[..] == other.cs_hash[..]
+            && self.contributions == other.contributions
+    }
+}
+
+impl<E: Engine> MPCParameters<E> {
+    /// Starts the phase 2 from `params`, whose delta must be 1.
+    pub fn new(params: Parameters<E>) -> Result<Self, MPCError> {
+        if params.vk.delta_g1 != E::G1Affine::one() || params.vk.delta_g2 != E::G2Affine::one() {
+            return Err(MPCError::InvalidContribution(
+                "initial parameters must have a delta of 1",
+            ));
+        }
+
+        let mut bytes = vec![];
+        params.write(&mut bytes)?;
+        Ok(MPCParameters {
+            cs_hash: hash(&bytes),
+            params,
+            contributions: vec![],
+        })
+    }
+
+    pub fn params(&self) -> &Parameters<E> {
+        &self.params
+    }
+
+    pub fn contributions(&self) -> &[PublicKey<E>] {
+        &self.contributions
+    }
+
+    /// Contributes a secret drawn from `rng`, which is forgotten once the function returns.
+    /// Returns the hash of the contribution, which the participant should publish.
+    pub fn contribute<R: RngCore>(&mut self, rng: &mut R) -> [u8; MPC_HASH_LEN] {
+        let delta = loop {
+            let delta = E::Fr::random(rng);
+            if !delta.is_zero() {
+                break delta;
+            }
+        };
+        let delta_inv = delta.inverse().unwrap();
+
+        let transcript = self.transcript();
+        let s = E::G1::random(rng).into_affine();
+        let s_delta = s.mul(delta.into_repr()).into_affine();
+        let r = hash_to_g2::<E>(&transcript, &s, &s_delta);
+        let r_delta = r.mul(delta.into_repr()).into_affine();
+
+        let worker = Worker::new();
+        let h = batch_mul(&worker, &self.params.h, delta_inv);
+        let l = batch_mul(&worker, &self.params.l, delta_inv);
+        self.params.h = Arc::new(h);
+        self.params.l = Arc::new(l);
+        self.params.vk.delta_g1 = self.params.vk.delta_g1.mul(delta.into_repr()).into_affine();
+        self.params.vk.delta_g2 = self.params.vk.delta_g2.mul(delta.into_repr()).into_affine();
+
+        let pubkey = PublicKey {
+            delta_after: self.params.vk.delta_g1,
+            s,
+            s_delta,
+            r_delta,
+            transcript,
+        };
+        let contribution = pubkey.hash();
+        self.contributions.push(pubkey);
+        contribution
+    }
+
+    /// Contributes a secret derived from the public `beacon` by `2^log_iterations` rounds of
+    /// BLAKE2b, so that anyone can check that the parameters were finalized with it.
+    /// `log_iterations` must be lower than 64.
+    pub fn contribute_with_beacon(
+        &mut self,
+        beacon: &[u8],
+        log_iterations: u8,
+    ) -> Result<[u8; MPC_HASH_LEN], MPCError> {
+        let iterations = 1u64
+            .checked_shl(u32::from(log_iterations))
+            .ok_or(MPCError::InvalidBeacon("too many iterations"))?;
+        let mut digest = hash(beacon);
+        for _ in 0..iterations {
+            digest = hash(&digest);
+        }
+        let mut seed = [0u8; 32];
+        seed.copy_from_slice(&digest[..32]);
+        Ok(self.contribute(&mut ChaChaRng::from_seed(seed)))
+    }
+
+    /// Checks all of the contributions made since `initial`, the parameters the ceremony
+    /// started from. Returns the hashes of the contributions.
+    pub fn verify(&self, initial: &Parameters<E>) -> Result<Vec<[u8; MPC_HASH_LEN]>, MPCError> {
+        let start = MPCParameters::new(initial.clone())?;
+        if start.cs_hash[..] != self.cs_hash[..] {
+            return Err(MPCError::InvalidContribution(
+                "parameters don't start from the initial ones",
+            ));
+        }
+
+        let mut delta_before = initial.vk.delta_g1;
+        let mut hashes = vec![];
+        for (i, pubkey) in self.contributions.iter().enumerate() {
+            verify_pubkey(pubkey, &delta_before, &self.transcript_at(i))?;
+            delta_before = pubkey.delta_after;
+            hashes.push(pubkey.hash());
+        }
+
+        verify_transition(initial, &self.params)?;
+        if self.params.vk.delta_g1 != delta_before {
+            return Err(MPCError::InvalidContribution(
+                "delta doesn't match the last contribution",
+            ));
+        }
+
+        Ok(hashes)
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        self.params.write(&mut writer)?;
+        writer.write_all(&self.cs_hash)?;
+        writer.write_u32::<BigEndian>(self.contributions.len() as u32)?;
+        for pubkey in &self.contributions {
+            pubkey.write(&mut writer)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn read<R: Read>(mut reader: R, checked: bool) -> io::Result<Self> {
+        let params = Parameters::read(&mut reader, checked)?;
+        let mut cs_hash = [0u8; MPC_HASH_LEN];
+        reader.read_exact(&mut cs_hash)?;
+        let len = reader.read_u32::<BigEndian>()? as usize;
+        let contributions = (0..len)
+            .map(|_| PublicKey::read(&mut reader))
+            .collect::<io::Result<_>>()?;
+
+        Ok(MPCParameters {
+            params,
+            cs_hash,
+            contributions,
+        })
+    }
+
+    // Hash of the ceremony before the contribution of index `i`
+    fn transcript_at(&self, i: usize) -> [u8; MPC_HASH_LEN] {
+        let mut bytes = self.cs_hash.to_vec();
+        for pubkey in &self.contributions[..i] {
+            pubkey.write(&mut bytes).unwrap();
+        }
+        hash(&bytes)
+    }
+
+    fn transcript(&self) -> [u8; MPC_HASH_LEN] {
+        self.transcript_at(self.contributions.len())
+    }
+}
+
+/// Checks that `after` is `before` with a single valid contribution more. Returns the hash of
+/// the contribution.
+pub fn verify_contribution<E: Engine>(
+    before: &MPCParameters<E>,
+    after: &MPCParameters<E>,
+) -> Result<[u8; MPC_HASH_LEN], MPCError> {
+    if after.cs_hash[..] != before.cs_hash[..] {
+        return Err(MPCError::InvalidContribution(
+            "parameters are from different ceremonies",
+        ));
+    }
+    if after.contributions.len() != before.contributions.len() + 1
+        || after.contributions[..before.contributions.len()] != before.contributions[..]
+    {
+        return Err(MPCError::InvalidContribution(
+            "contributions don't extend the previous ones",
+        ));
+    }
+
+    let pubkey = after.contributions.last().unwrap();
+    verify_pubkey(pubkey, &before.params.vk.delta_g1, &before.transcript())?;
+    verify_transition(&before.params, &after.params)?;
+    if after.params.vk.delta_g1 != pubkey.delta_after {
+        return Err(MPCError::InvalidContribution(
+            "delta doesn't match the contribution",
+        ));
+    }
+
+    Ok(pubkey.hash())
+}
+
+// Checks the proof of knowledge of a contribution, made from `delta_before`
+fn verify_pubkey<E: Engine>(
+    pubkey: &PublicKey<E>,
+    delta_before: &E::G1Affine,
+    transcript: &[u8; MPC_HASH_LEN],
+) -> Result<(), MPCError> {
+    if pubkey.transcript[..] != transcript[..] {
+        return Err(MPCError::InvalidContribution("transcript mismatch"));
+    }
+    if pubkey.s.is_zero() || pubkey.s_delta.is_zero() {
+        return Err(MPCError::InvalidContribution("point at infinity"));
+    }
+
+    let r = hash_to_g2::<E>(transcript, &pubkey.s, &pubkey.s_delta);
+    if !same_ratio::<E>((pubkey.s, pubkey.s_delta), (r, pubkey.r_delta)) {
+        return Err(MPCError::InvalidContribution(
+            "wrong proof of knowledge of the secret",
+        ));
+    }
+    if !same_ratio::<E>((*delta_before, pubkey.delta_after), (r, pubkey.r_delta)) {
+        return Err(MPCError::InvalidContribution(
+            "delta wasn't multiplied by the secret",
+        ));
+    }
+
+    Ok(())
+}
+
+// Checks that `after` only differs from `before` by a new delta, which h and l are divided by
+fn verify_transition<E: Engine>(
+    before: &Parameters<E>,
+    after: &Parameters<E>,
+) -> Result<(), MPCError> {
+    let (vk_before, vk_after) = (&before.vk, &after.vk);
+    if vk_before.alpha_g1 != vk_after.alpha_g1
+        || vk_before.beta_g1 != vk_after.beta_g1
+        || vk_before.beta_g2 != vk_after.beta_g2
+        || vk_before.gamma_g2 != vk_after.gamma_g2
+        || vk_before.ic != vk_after.ic
+        || before.a != after.a
+        || before.b_g1 != after.b_g1
+        || before.b_g2 != after.b_g2
+        || before.h.len() != after.h.len()
+        || before.l.len() != after.l.len()
+    {
+        return Err(MPCError::InvalidContribution(
+            "parameters differ by more than delta",
+        ));
+    }
+
+    let deltas_g2 = (vk_before.delta_g2, vk_after.delta_g2);
+    if !same_ratio::<E>((vk_before.delta_g1, vk_after.delta_g1), deltas_g2) {
+        return Err(MPCError::InvalidContribution("delta differs in G1 and G2"));
+    }
+
+    let worker = Worker::new();
+    let h = merge_pairs::<E>(&worker, &after.h, &before.h)?;
+    let l = merge_pairs::<E>(&worker, &after.l, &before.l)?;
+    if !same_ratio::<E>(h, deltas_g2) || !same_ratio::<E>(l, deltas_g2) {
+        return Err(MPCError::InvalidContribution(
+            "h and l aren't divided by delta",
+        ));
+    }
+
+    Ok(())
+}
+
+// Whether `g1.1 / g1.0 == g2.1 / g2.0`
+fn same_ratio<E: Engine>(g1: (E::G1Affine, E::G1Affine), g2: (E::G2Affine, E::G2Affine)) -> bool {
+    E::pairing(g1.0, g2.1) == E::pairing(g1.1, g2.0)
+}
+
+// Random linear combinations with the same coefficients of `v1` and `v2`, which have the same
+// ratio only if all of the pairs of elements of `v1` and `v2` have it
+fn merge_pairs<E: Engine>(
+    worker: &Worker,
+    v1: &Arc<Vec<E::G1Affine>>,
+    v2: &Arc<Vec<E::G1Affine>>,
+) -> Result<(E::G1Affine, E::G1Affine), MPCError> {
+    let rng = &mut rand::thread_rng();
+    let coeffs = Arc::new(
+        (0..v1.len())
+            .map(|_| E::Fr::random(rng).into_repr())
+            .collect::<Vec<_>>(),
+    );
+    let combine = |v: &Arc<Vec<E::G1Affine>>| {
+        multiexp(
+            worker,
+            (v.clone(), 0),
+            FullDensity,
+            coeffs.clone(),
+            &mut None,
+        )
+        .wait()
+        .map(|p| p.into_affine())
+        .map_err(|_| MPCError::InvalidContribution("point at infinity"))
+    };
+
+    Ok((combine(v1)?, combine(v2)?))
+}
+
+// Multiplies all of the points by `c`
+fn batch_mul<G: CurveAffine>(worker: &Worker, points: &[G], c: G::Scalar) -> Vec<G> {
+    let c = c.into_repr();
+    let mut projective = vec![G::Projective::zero(); points.len()];
+    worker.scope(points.len(), |scope, chunk| {
+        for (projective, points) in projective.chunks_mut(chunk).zip(points.chunks(chunk)) {
+            scope.spawn(move |_scope| {
+                for (p, point) in projective.iter_mut().zip(points.iter()) {
+                    *p = point.mul(c);
+                }
+                G::Projective::batch_normalization(projective);
+            });
+        }
+    });
+
+    projective.into_iter().map(|p| p.into_affine()).collect()
+}
+
+fn hash(bytes: &[u8]) -> [u8; MPC_HASH_LEN] {
+    let mut digest = [0u8; MPC_HASH_LEN];
+    digest.copy_from_slice(
+        Blake2bParams::new()
+            .hash_length(MPC_HASH_LEN)
+            .hash(bytes)
+            .as_bytes(),
+    );
+    digest
+}
+
+// Point of G2 depending on the transcript and the random point of a contribution
+fn hash_to_g2<E: Engine>(
+    transcript: &[u8; MPC_HASH_LEN],
+    s: &E::G1Affine,
+    s_delta: &E::G1Affine,
+) -> E::G2Affine {
+    let mut bytes = transcript.to_vec();
+    bytes.extend_from_slice(s.into_uncompressed().as_ref());
+    bytes.extend_from_slice(s_delta.into_uncompressed().as_ref());
+    let mut seed = [0u8; 32];
+    seed.copy_from_slice(&hash(&bytes)[..32]);
+    E::G2::random(&mut ChaChaRng::from_seed(seed)).into_affine()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::groth16::tests::random_parameters;
+    use paired::bls12_381::Bls12;
+
+    #[test]
+    fn test_mpc() {
+        let mut initial = random_parameters();
+        initial.vk.delta_g1 = <Bls12 as Engine>::G1Affine::one();
+        initial.vk.delta_g2 = <Bls12 as Engine>::G2Affine::one();
+        let rng = &mut rand::thread_rng();
+
+        let start = MPCParameters::new(initial.clone()).unwrap();
+        let mut first = start.clone();
+        let first_hash = first.contribute(rng);
+        assert_eq!(
+            verify_contribution(&start, &first).unwrap()[..],
+            first_hash[..]
+        );
+
+        let mut second = first.clone();
+        let second_hash = second.contribute(rng);
+        assert_eq!(
+            verify_contribution(&first, &second).unwrap()[..],
+            second_hash[..]
+        );
+        assert!(verify_contribution(&start, &second).is_err());
+
+        let mut last = second.clone();
+        let last_hash = last.contribute_with_beacon(b"beacon", 4).unwrap();
+        let mut again = second.clone();
+        assert_eq!(
+            again.contribute_with_beacon(b"beacon", 4).unwrap()[..],
+            last_hash[..]
+        );
+        assert!(again == last);
+        assert!(second
+            .clone()
+            .contribute_with_beacon(b"beacon", 64)
+            .is_err());
+
+        let hashes = last.verify(&initial).unwrap();
+        assert_eq!(hashes.len(), 3);
+        assert_eq!(hashes[1][..], second_hash[..]);
+
+        let mut bytes = vec![];
+        last.write(&mut bytes).unwrap();
+        assert!(MPCParameters::<Bls12>::read(&bytes[..], true).unwrap() == last);
+
+        // A contribution which doesn't update l along with delta
+        let mut tampered = second.clone();
+        tampered.contribute(rng);
+        tampered.params.l = second.params.l.clone();
+        assert!(verify_contribution(&second, &tampered).is_err());
+        assert!(tampered.verify(&initial).is_err());
+    }
+}
@@ -1,19 +1,16 @@
 use groupy::{CurveAffine, EncodedPoint};
 use paired::Engine;
 
-use crate::multiexp::SourceBuilder;
+use crate::multicore::Worker;
+use crate::multiexp::{PrecomputedBases, SourceBuilder};
 use crate::SynthesisError;
 
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
-use memmap::{Mmap, MmapOptions};
-use std::fs::File;
+use std::cmp;
 use std::io::{self, Read, Write};
-use std::mem;
-use std::ops::Range;
-use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
-use super::{MappedParameters, VerifyingKey};
+use super::{ParamsSection, VerifyingKey};
 
 #[derive(Clone)]
 pub struct Parameters<E: Engine> {
@@ -53,219 +50,62 @@ impl<E: Engine> PartialEq for Parameters<E> {
 
 impl<E: Engine> Parameters<E> {
     pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
-        self.vk.write(&mut writer)?;
-
-        writer.write_u32::<BigEndian>(self.h.len() as u32)?;
-        for g in &self.h[..] {
-            writer.write_all(g.into_uncompressed().as_ref())?;
-        }
-
-        writer.write_u32::<BigEndian>(self.l.len() as u32)?;
-        for g in &self.l[..] {
-            writer.write_all(g.into_uncompressed().as_ref())?;
-        }
-
-        writer.write_u32::<BigEndian>(self.a.len() as u32)?;
-        for g in &self.a[..] {
-            writer.write_all(g.into_uncompressed().as_ref())?;
-        }
-
-        writer.write_u32::<BigEndian>(self.b_g1.len() as u32)?;
-        for g in &self.b_g1[..] {
-            writer.write_all(g.into_uncompressed().as_ref())?;
-        }
-
-        writer.write_u32::<BigEndian>(self.b_g2.len() as u32)?;
-        for g in &self.b_g2[..] {
-            writer.write_all(g.into_uncompressed().as_ref())?;
+        for &section in ParamsSection::ALL.iter() {
+            self.write_section(section, &mut writer)?;
         }
 
         Ok(())
     }
 
-    // Quickly iterates through the parameter file, recording all
-    // parameter offsets and caches the verifying key (vk) for quick
-    // access via reference.
-    pub fn build_mapped_parameters(
-        param_file_path: PathBuf,
-        checked: bool,
-    ) -> io::Result<MappedParameters<E>> {
-        let mut offset: usize = 0;
-        let param_file = File::open(&param_file_path)?;
-        let params = unsafe { MmapOptions::new().map(&param_file)? };
-
-        let u32_len = mem::size_of::<u32>();
-        let g1_len = mem::size_of::<<E::G1Affine as CurveAffine>::Uncompressed>();
-        let g2_len = mem::size_of::<<E::G2Affine as CurveAffine>::Uncompressed>();
-
-        let read_length = |params: &Mmap, offset: &mut usize| -> Result<usize, std::io::Error> {
-            let mut raw_len = &params[*offset..*offset + u32_len];
-            *offset += u32_len;
-
-            match raw_len.read_u32::<BigEndian>() {
-                Ok(len) => Ok(len as usize),
-                Err(err) => Err(err),
-            }
-        };
-
-        let get_offsets = |params: &Mmap,
-                           offset: &mut usize,
-                           param: &mut Vec<Range<usize>>,
-                           range_len: usize|
-         -> Result<(), std::io::Error> {
-            let len = read_length(&params, &mut *offset)?;
-            for _ in 0..len {
-                (*param).push(Range {
-                    start: *offset,
-                    end: *offset + range_len,
-                });
-                *offset += range_len;
-            }
-
-            Ok(())
-        };
-
-        let vk = VerifyingKey::<E>::read_mmap(&params, &mut offset)?;
-
-        let mut h = vec![];
-        let mut l = vec![];
-        let mut a = vec![];
-        let mut b_g1 = vec![];
-        let mut b_g2 = vec![];
-
-        get_offsets(&params, &mut offset, &mut h, g1_len)?;
-        get_offsets(&params, &mut offset, &mut l, g1_len)?;
-        get_offsets(&params, &mut offset, &mut a, g1_len)?;
-        get_offsets(&params, &mut offset, &mut b_g1, g1_len)?;
-        get_offsets(&params, &mut offset, &mut b_g2, g2_len)?;
-
-        Ok(MappedParameters {
-            param_file_path,
-            param_file,
-            params,
-            vk,
-            h,
-            l,
-            a,
-            b_g1,
-            b_g2,
-            checked,
-        })
-    }
-
-    // This method is provided as a proof of concept, but isn't
-    // advantageous to use (can be called by read_cached_params in
-    // rust-fil-proofs repo).  It's equivalent to the existing read
-    // method, in that it loads all parameters to RAM.
-    pub fn read_mmap(mmap: &Mmap, checked: bool) -> io::Result<Self> {
-        let u32_len = mem::size_of::<u32>();
-        let g1_len = mem::size_of::<<E::G1Affine as CurveAffine>::Uncompressed>();
-        let g2_len = mem::size_of::<<E::G2Affine as CurveAffine>::Uncompressed>();
-
-        let read_g1 = |mmap: &Mmap, offset: &mut usize| -> io::Result<E::G1Affine> {
-            let ptr = &mmap[*offset..*offset + g1_len];
-            *offset += g1_len;
-            // Safety: this operation is safe, because it's simply
-            // casting to a known struct at the correct offset, given
-            // the structure of the on-disk data.
-            let repr: <E::G1Affine as CurveAffine>::Uncompressed = unsafe {
-                *(ptr as *const [u8] as *const <E::G1Affine as CurveAffine>::Uncompressed)
-            };
-
-            if checked {
-                repr.into_affine()
-            } else {
-                repr.into_affine_unchecked()
-            }
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
-            .and_then(|e| {
-                if e.is_zero() {
-                    Err(io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        "point at infinity",
-                    ))
-                } else {
-                    Ok(e)
-                }
-            })
-        };
-
-        let read_g2 = |mmap: &Mmap, offset: &mut usize| -> io::Result<E::G2Affine> {
-            let ptr = &mmap[*offset..*offset + g2_len];
-            *offset += g2_len;
-            // Safety: this operation is safe, because it's simply
-            // casting to a known struct at the correct offset, given
-            // the structure of the on-disk data.
-            let repr: <E::G2Affine as CurveAffine>::Uncompressed = unsafe {
-                *(ptr as *const [u8] as *const <E::G2Affine as CurveAffine>::Uncompressed)
-            };
-
-            if checked {
-                repr.into_affine()
-            } else {
-                repr.into_affine_unchecked()
-            }
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
-            .and_then(|e| {
-                if e.is_zero() {
-                    Err(io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        "point at infinity",
-                    ))
-                } else {
-                    Ok(e)
+    // Writes a section of the parameters, as in the legacy format
+    pub(crate) fn write_section<W: Write>(
+        &self,
+        section: ParamsSection,
+        mut writer: W,
+    ) -> io::Result<()> {
+        let query = match section {
+            ParamsSection::Vk => return self.vk.write(&mut writer),
+            ParamsSection::H => &self.h,
+            ParamsSection::L => &self.l,
+            ParamsSection::A => &self.a,
+            ParamsSection::BG1 => &self.b_g1,
+            ParamsSection::BG2 => {
+                writer.write_u32::<BigEndian>(self.b_g2.len() as u32)?;
+                for g in &self.b_g2[..] {
+                    writer.write_all(g.into_uncompressed().as_ref())?;
                 }
-            })
-        };
-
-        let read_length = |mmap: &Mmap, offset: &mut usize| -> Result<usize, std::io::Error> {
-            let mut raw_len = &mmap[*offset..*offset + u32_len];
-            *offset += u32_len;
-
-            match raw_len.read_u32::<BigEndian>() {
-                Ok(len) => Ok(len as usize),
-                Err(err) => Err(err),
+                return Ok(());
             }
         };
 
-        let get_g1s = |mmap: &Mmap,
-                       offset: &mut usize,
-                       param: &mut Vec<E::G1Affine>|
-         -> Result<(), std::io::Error> {
-            let len = read_length(&mmap, &mut *offset)?;
-            for _ in 0..len {
-                (*param).push(read_g1(&mmap, &mut *offset)?);
-            }
-
-            Ok(())
-        };
+        writer.write_u32::<BigEndian>(query.len() as u32)?;
+        for g in &query[..] {
+            writer.write_all(g.into_uncompressed().as_ref())?;
+        }
 
-        let get_g2s = |mmap: &Mmap,
-                       offset: &mut usize,
-                       param: &mut Vec<E::G2Affine>|
-         -> Result<(), std::io::Error> {
-            let len = read_length(&mmap, &mut *offset)?;
-            for _ in 0..len {
-                (*param).push(read_g2(&mmap, &mut *offset)?);
-            }
+        Ok(())
+    }
 
-            Ok(())
+    pub fn read<R: Read>(reader: R, checked: bool) -> io::Result<Self> {
+        let check = if checked {
+            ParamsCheck::Full
+        } else {
+            ParamsCheck::None
         };
+        Self::read_with_check(reader, check)
+    }
 
-        let mut offset: usize = 0;
-        let vk = VerifyingKey::<E>::read_mmap(&mmap, &mut offset)?;
-
-        let mut h = vec![];
-        let mut l = vec![];
-        let mut a = vec![];
-        let mut b_g1 = vec![];
-        let mut b_g2 = vec![];
-
-        get_g1s(&mmap, &mut offset, &mut h)?;
-        get_g1s(&mmap, &mut offset, &mut l)?;
-        get_g1s(&mmap, &mut offset, &mut a)?;
-        get_g1s(&mmap, &mut offset, &mut b_g1)?;
-        get_g2s(&mmap, &mut offset, &mut b_g2)?;
+    /// Reads parameters written by `write`, checking their points as set by `check`. The points
+    /// are decoded and checked on all of the cores of the `Worker`, and the verifying key is
+    /// always fully checked.
+    pub fn read_with_check<R: Read>(mut reader: R, check: ParamsCheck) -> io::Result<Self> {
+        let pool = Worker::new();
+        let vk = VerifyingKey::<E>::read(&mut reader)?;
+        let h = read_points(&pool, &mut reader, check)?;
+        let l = read_points(&pool, &mut reader, check)?;
+        let a = read_points(&pool, &mut reader, check)?;
+        let b_g1 = read_points(&pool, &mut reader, check)?;
+        let b_g2 = read_points(&pool, &mut reader, check)?;
 
         Ok(Parameters {
             vk,
@@ -277,103 +117,178 @@ impl<E: Engine> Parameters<E> {
         })
     }
 
-    pub fn read<R: Read>(mut reader: R, checked: bool) -> io::Result<Self> {
-        let read_g1 = |reader: &mut R| -> io::Result<E::G1Affine> {
-            let mut repr = <E::G1Affine as CurveAffine>::Uncompressed::empty();
-            reader.read_exact(repr.as_mut())?;
+    /// Builds the `PrecomputedBases` of the queries of the parameters, for windows of `window`
+    /// bits (See `PrecomputedBases::default_window`). They only have to be built once for
+    /// proving any number of instances, and can be persisted with `PrecomputedParameters::write`.
+    pub fn precompute(&self, pool: &Worker, window: u32) -> PrecomputedParameters<E> {
+        PrecomputedParameters {
+            h: PrecomputedBases::new(pool, &self.h, window),
+            l: PrecomputedBases::new(pool, &self.l, window),
+            a: PrecomputedBases::new(pool, &self.a, window),
+            b_g1: PrecomputedBases::new(pool, &self.b_g1, window),
+            b_g2: PrecomputedBases::new(pool, &self.b_g2, window),
+        }
+    }
+}
 
-            if checked {
-                repr.into_affine()
-            } else {
-                repr.into_affine_unchecked()
-            }
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
-            .and_then(|e| {
-                if e.is_zero() {
-                    Err(io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        "point at infinity",
-                    ))
-                } else {
-                    Ok(e)
-                }
-            })
-        };
+/// Parameters with the one-time work of the prover done, reusable across the proofs of a
+/// circuit like the `PreparedVerifyingKey` is across verifications. The query multiexps of the
+/// proofs created with them run on the CPU, on the precomputed tables of the queries, which only
+/// depend on the parameters and not on the densities of the assignments.
+#[derive(Clone)]
+pub struct PreparedParameters<E: Engine> {
+    pub params: Parameters<E>,
+    pub precomputed: PrecomputedParameters<E>,
+}
 
-        let read_g2 = |reader: &mut R| -> io::Result<E::G2Affine> {
-            let mut repr = <E::G2Affine as CurveAffine>::Uncompressed::empty();
-            reader.read_exact(repr.as_mut())?;
+/// Prepares parameters for proving, with the default window for the size of their queries.
+pub fn prepare_parameters<E: Engine>(params: Parameters<E>) -> PreparedParameters<E> {
+    let len = cmp::max(params.h.len(), cmp::max(params.l.len(), params.a.len()));
+    let window = PrecomputedBases::<E::G1Affine>::default_window(len);
+    let precomputed = params.precompute(&Worker::new(), window);
 
-            if checked {
-                repr.into_affine()
-            } else {
-                repr.into_affine_unchecked()
-            }
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
-            .and_then(|e| {
-                if e.is_zero() {
-                    Err(io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        "point at infinity",
-                    ))
-                } else {
-                    Ok(e)
-                }
-            })
-        };
+    PreparedParameters {
+        params,
+        precomputed,
+    }
+}
 
-        let vk = VerifyingKey::<E>::read(&mut reader)?;
+/// The queries of `Parameters`, precomputed for `multiexp_precomputed`.
+#[derive(Clone)]
+pub struct PrecomputedParameters<E: Engine> {
+    pub h: PrecomputedBases<E::G1Affine>,
+    pub l: PrecomputedBases<E::G1Affine>,
+    pub a: PrecomputedBases<E::G1Affine>,
+    pub b_g1: PrecomputedBases<E::G1Affine>,
+    pub b_g2: PrecomputedBases<E::G2Affine>,
+}
 
-        let mut h = vec![];
-        let mut l = vec![];
-        let mut a = vec![];
-        let mut b_g1 = vec![];
-        let mut b_g2 = vec![];
+impl<E: Engine> PartialEq for PrecomputedParameters<E> {
+    fn eq(&self, other: &Self) -> bool {
+        self.h == other.h
+            && self.l == other.l
+            && self.a == other.a
+            && self.b_g1 == other.b_g1
+            && self.b_g2 == other.b_g2
+    }
+}
 
-        {
-            let len = reader.read_u32::<BigEndian>()? as usize;
-            for _ in 0..len {
-                h.push(read_g1(&mut reader)?);
-            }
-        }
+impl<E: Engine> PrecomputedParameters<E> {
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        self.h.write(&mut writer)?;
+        self.l.write(&mut writer)?;
+        self.a.write(&mut writer)?;
+        self.b_g1.write(&mut writer)?;
+        self.b_g2.write(&mut writer)
+    }
 
-        {
-            let len = reader.read_u32::<BigEndian>()? as usize;
-            for _ in 0..len {
-                l.push(read_g1(&mut reader)?);
-            }
-        }
+    pub fn read<R: Read>(mut reader: R, checked: bool) -> io::Result<Self> {
+        Ok(PrecomputedParameters {
+            h: PrecomputedBases::read(&mut reader, checked)?,
+            l: PrecomputedBases::read(&mut reader, checked)?,
+            a: PrecomputedBases::read(&mut reader, checked)?,
+            b_g1: PrecomputedBases::read(&mut reader, checked)?,
+            b_g2: PrecomputedBases::read(&mut reader, checked)?,
+        })
+    }
+}
 
-        {
-            let len = reader.read_u32::<BigEndian>()? as usize;
-            for _ in 0..len {
-                a.push(read_g1(&mut reader)?);
-            }
-        }
+/// How the points of the queries are checked by `Parameters::read_with_check`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParamsCheck {
+    /// The points are only checked not to be at infinity.
+    None,
+    /// The points are checked to be on the curve and in the right subgroup.
+    Full,
+    /// Only a random sample of this many points of each query is fully checked, which is much
+    /// faster for big parameters while still catching most corruptions.
+    SpotCheck(usize),
+}
 
-        {
-            let len = reader.read_u32::<BigEndian>()? as usize;
-            for _ in 0..len {
-                b_g1.push(read_g1(&mut reader)?);
-            }
+// Number of points which are read before being decoded
+const READ_CHUNK: usize = 1 << 16;
+
+// Reads a query prefixed by its length, decoding and checking its points on `pool`
+fn read_points<G: CurveAffine, R: Read>(
+    pool: &Worker,
+    reader: &mut R,
+    check: ParamsCheck,
+) -> io::Result<Vec<G>> {
+    let len = reader.read_u32::<BigEndian>()? as usize;
+
+    let sample = match check {
+        ParamsCheck::SpotCheck(n) => {
+            let mut sample =
+                rand::seq::index::sample(&mut rand::thread_rng(), len, cmp::min(n, len)).into_vec();
+            sample.sort_unstable();
+            sample
+        }
+        _ => vec![],
+    };
+    let checked = |i: usize| match check {
+        ParamsCheck::None => false,
+        ParamsCheck::Full => true,
+        ParamsCheck::SpotCheck(_) => sample.binary_search(&i).is_ok(),
+    };
+
+    let mut points = Vec::with_capacity(cmp::min(len, READ_CHUNK));
+    let mut reprs = vec![G::Uncompressed::empty(); cmp::min(len, READ_CHUNK)];
+    while points.len() < len {
+        let start = points.len();
+        let reprs = &mut reprs[..cmp::min(READ_CHUNK, len - start)];
+        for repr in reprs.iter_mut() {
+            reader.read_exact(repr.as_mut())?;
         }
 
-        {
-            let len = reader.read_u32::<BigEndian>()? as usize;
-            for _ in 0..len {
-                b_g2.push(read_g2(&mut reader)?);
+        let mut decoded = vec![G::zero(); reprs.len()];
+        let error = Mutex::new(None);
+        pool.scope(reprs.len(), |scope, chunk| {
+            for (i, (decoded, reprs)) in decoded
+                .chunks_mut(chunk)
+                .zip(reprs.chunks(chunk))
+                .enumerate()
+            {
+                let (checked, error) = (&checked, &error);
+                scope.spawn(move |_scope| {
+                    for (j, (point, repr)) in decoded.iter_mut().zip(reprs.iter()).enumerate() {
+                        match decode_point(repr, checked(start + i * chunk + j)) {
+                            Ok(p) => *point = p,
+                            Err(e) => {
+                                *error.lock().unwrap() = Some(e);
+                                return;
+                            }
+                        }
+                    }
+                });
             }
+        });
+        if let Some(e) = error.into_inner().unwrap() {
+            return Err(e);
         }
 
-        Ok(Parameters {
-            vk,
-            h: Arc::new(h),
-            l: Arc::new(l),
-            a: Arc::new(a),
-            b_g1: Arc::new(b_g1),
-            b_g2: Arc::new(b_g2),
-        })
+        points.extend(decoded);
+    }
+
+    Ok(points)
+}
+
+fn decode_point<G: CurveAffine>(repr: &G::Uncompressed, checked: bool) -> io::Result<G> {
+    if checked {
+        repr.into_affine()
+    } else {
+        repr.into_affine_unchecked()
     }
+    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    .and_then(|e| {
+        if e.is_zero() {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "point at infinity",
+            ))
+        } else {
+            Ok(e)
+        }
+    })
 }
 
 pub trait ParameterSource<E: Engine>: Send + Sync {
@@ -398,6 +313,12 @@ pub trait ParameterSource<E: Engine>: Send + Sync {
         num_inputs: usize,
         num_aux: usize,
     ) -> Result<(Self::G2Builder, Self::G2Builder), SynthesisError>;
+
+    /// The precomputed tables of the queries, which the prover uses instead of the builders if
+    /// there are some.
+    fn get_precomputed(&self) -> Option<&PrecomputedParameters<E>> {
+        None
+    }
 }
 
 impl<'a, E: Engine> ParameterSource<E> for &'a Parameters<E> {
@@ -440,3 +361,87 @@ impl<'a, E: Engine> ParameterSource<E> for &'a Parameters<E> {
         Ok(((self.b_g2.clone(), 0), (self.b_g2.clone(), num_inputs)))
     }
 }
+
+impl<'a, E: Engine> ParameterSource<E> for &'a PreparedParameters<E> {
+    type G1Builder = (Arc<Vec<E::G1Affine>>, usize);
+    type G2Builder = (Arc<Vec<E::G2Affine>>, usize);
+
+    fn get_vk(&self, _: usize) -> Result<&VerifyingKey<E>, SynthesisError> {
+        Ok(&self.params.vk)
+    }
+
+    fn get_h(&self, num_h: usize) -> Result<Self::G1Builder, SynthesisError> {
+        (&self.params).get_h(num_h)
+    }
+
+    fn get_l(&self, num_l: usize) -> Result<Self::G1Builder, SynthesisError> {
+        (&self.params).get_l(num_l)
+    }
+
+    fn get_a(
+        &self,
+        num_inputs: usize,
+        num_aux: usize,
+    ) -> Result<(Self::G1Builder, Self::G1Builder), SynthesisError> {
+        (&self.params).get_a(num_inputs, num_aux)
+    }
+
+    fn get_b_g1(
+        &self,
+        num_inputs: usize,
+        num_aux: usize,
+    ) -> Result<(Self::G1Builder, Self::G1Builder), SynthesisError> {
+        (&self.params).get_b_g1(num_inputs, num_aux)
+    }
+
+    fn get_b_g2(
+        &self,
+        num_inputs: usize,
+        num_aux: usize,
+    ) -> Result<(Self::G2Builder, Self::G2Builder), SynthesisError> {
+        (&self.params).get_b_g2(num_inputs, num_aux)
+    }
+
+    fn get_precomputed(&self) -> Option<&PrecomputedParameters<E>> {
+        Some(&self.precomputed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::groth16::tests::random_parameters;
+    use paired::bls12_381::Bls12;
+    use std::mem;
+
+    #[test]
+    fn test_read_with_check() {
+        let params = random_parameters();
+        let mut bytes = vec![];
+        params.write(&mut bytes).unwrap();
+
+        for &check in &[
+            ParamsCheck::None,
+            ParamsCheck::Full,
+            ParamsCheck::SpotCheck(0),
+            ParamsCheck::SpotCheck(4),
+        ] {
+            let read = Parameters::<Bls12>::read_with_check(&bytes[..], check).unwrap();
+            assert!(read == params, "Mismatch for {:?}", check);
+        }
+
+        // Move the first point of the h query off the curve
+        let mut vk = vec![];
+        params.vk.write(&mut vk).unwrap();
+        let g1_len = mem::size_of::<<<Bls12 as Engine>::G1Affine as CurveAffine>::Uncompressed>();
+        bytes[vk.len() + 4 + g1_len - 1] ^= 1;
+
+        assert!(Parameters::<Bls12>::read_with_check(&bytes[..], ParamsCheck::None).is_ok());
+        assert!(Parameters::<Bls12>::read_with_check(&bytes[..], ParamsCheck::Full).is_err());
+        assert!(Parameters::<Bls12>::read_with_check(
+            &bytes[..],
+            ParamsCheck::SpotCheck(usize::MAX)
+        )
+        .is_err());
+    }
+}
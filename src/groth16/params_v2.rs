@@ -0,0 +1,347 @@
+//! Version 2 of the parameter file format.
+//!
+//! A v2 file starts with a header holding the offset, the length and the BLAKE2b checksum of
+//! each section of the parameters (The verifying key and the h, l, a, b_g1 and b_g2 queries),
+//! followed by the sections, which are encoded as in the legacy format. Loaders can then seek
+//! directly to the sections they need, and detect a corrupted section before using it.
+
+use blake2b_simd::{Params as Blake2bParams, State};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use paired::Engine;
+
+use std::cmp;
+use std::io::{self, Read, Write};
+
+use super::Parameters;
+
+/// Magic bytes at the start of a v2 parameter file.
+pub const PARAMS_MAGIC: [u8; 8] = *b"BELLPARM";
+/// Version of the parameter files written by `Parameters::write_v2`.
+pub const PARAMS_VERSION: u32 = 2;
+/// Length of the BLAKE2b checksums of the sections.
+pub const CHECKSUM_LEN: usize = 32;
+/// Length of the header of a v2 parameter file.
+pub const PARAMS_HEADER_LEN: usize = 8 + 4 + NUM_SECTIONS * (8 + 8 + CHECKSUM_LEN);
+
+const NUM_SECTIONS: usize = 6;
+
+/// The sections of a parameter file, in the order they are written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParamsSection {
+    Vk,
+    H,
+    L,
+    A,
+    BG1,
+    BG2,
+}
+
+impl ParamsSection {
+    pub const ALL: [ParamsSection; NUM_SECTIONS] = [
+        ParamsSection::Vk,
+        ParamsSection::H,
+        ParamsSection::L,
+        ParamsSection::A,
+        ParamsSection::BG1,
+        ParamsSection::BG2,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ParamsSection::Vk => "vk",
+            ParamsSection::H => "h",
+            ParamsSection::L => "l",
+            ParamsSection::A => "a",
+            ParamsSection::BG1 => "b_g1",
+            ParamsSection::BG2 => "b_g2",
+        }
+    }
+}
+
+/// Location and checksum of a section of a v2 parameter file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SectionEntry {
+    /// Offset of the section from the start of the file.
+    pub offset: u64,
+    /// Length of the section, in bytes.
+    pub len: u64,
+    pub checksum: [u8; CHECKSUM_LEN],
+}
+
+impl SectionEntry {
+    /// Whether `bytes` match the checksum of the section.
+    pub fn verify(&self, bytes: &[u8]) -> bool {
+        let mut state = hasher();
+        state.update(bytes);
+        state.finalize().as_bytes() == self.checksum
+    }
+}
+
+/// Header of a v2 parameter file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParamsHeader {
+    pub version: u32,
+    pub sections: [SectionEntry; NUM_SECTIONS],
+}
+
+impl ParamsHeader {
+    /// Whether `bytes` start with the magic of a v2 parameter file.
+    pub fn is_v2(bytes: &[u8]) -> bool {
+        bytes.starts_with(&PARAMS_MAGIC)
+    }
+
+    pub fn section(&self, section: ParamsSection) -> &SectionEntry {
+        &self.sections[section as usize]
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(&PARAMS_MAGIC)?;
+        writer.write_u32::<BigEndian>(self.version)?;
+        for entry in self.sections.iter() {
+            writer.write_u64::<BigEndian>(entry.offset)?;
+            writer.write_u64::<BigEndian>(entry.len)?;
+            writer.write_all(&entry.checksum)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if magic != PARAMS_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a v2 parameter file",
+            ));
+        }
+
+        let version = reader.read_u32::<BigEndian>()?;
+        if version != PARAMS_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported parameter file version {}", version),
+            ));
+        }
+
+        let mut sections = [SectionEntry::default(); NUM_SECTIONS];
+        for entry in sections.iter_mut() {
+            entry.offset = reader.read_u64::<BigEndian>()?;
+            entry.len = reader.read_u64::<BigEndian>()?;
+            reader.read_exact(&mut entry.checksum)?;
+        }
+
+        Ok(ParamsHeader { version, sections })
+    }
+}
+
+fn hasher() -> State {
+    Blake2bParams::new().hash_length(CHECKSUM_LEN).to_state()
+}
+
+pub(crate) fn checksum_mismatch(section: ParamsSection) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("checksum mismatch in the {} section", section.name()),
+    )
+}
+
+// Hashes and counts the bytes written to it
+struct HashingWriter {
+    state: State,
+    len: u64,
+}
+
+impl Write for HashingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.state.update(buf);
+        self.len += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Reads the sections following a header, checking each section against its checksum as soon
+// as it has been read
+struct SectionReader<'a, R> {
+    inner: R,
+    header: &'a ParamsHeader,
+    section: usize,
+    remaining: u64,
+    state: State,
+}
+
+impl<'a, R: Read> SectionReader<'a, R> {
+    fn new(inner: R, header: &'a ParamsHeader) -> Self {
+        SectionReader {
+            inner,
+            header,
+            section: 0,
+            remaining: header.sections[0].len,
+            state: hasher(),
+        }
+    }
+
+    fn next_section(&mut self) -> io::Result<()> {
+        loop {
+            if self.state.finalize().as_bytes() != self.header.sections[self.section].checksum {
+                return Err(checksum_mismatch(ParamsSection::ALL[self.section]));
+            }
+            self.section += 1;
+            self.state = hasher();
+            if self.section == NUM_SECTIONS {
+                return Ok(());
+            }
+            self.remaining = self.header.sections[self.section].len;
+            if self.remaining != 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    fn finish(&self) -> io::Result<()> {
+        if self.section != NUM_SECTIONS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "sections are longer than their content",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl<'a, R: Read> Read for SectionReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.section == NUM_SECTIONS {
+            return Ok(0);
+        }
+
+        let len = cmp::min(buf.len() as u64, self.remaining) as usize;
+        let n = self.inner.read(&mut buf[..len])?;
+        self.state.update(&buf[..n]);
+        self.remaining -= n as u64;
+        if self.remaining == 0 {
+            self.next_section()?;
+        }
+
+        Ok(n)
+    }
+}
+
+impl<E: Engine> Parameters<E> {
+    /// Writes the parameters in the v2 format. The sections are encoded twice, first for their
+    /// checksums and then to the writer, so that they don't have to be held in memory.
+    pub fn write_v2<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut sections = [SectionEntry::default(); NUM_SECTIONS];
+        let mut offset = PARAMS_HEADER_LEN as u64;
+        for (entry, &section) in sections.iter_mut().zip(ParamsSection::ALL.iter()) {
+            let mut hashing = HashingWriter {
+                state: hasher(),
+                len: 0,
+            };
+            self.write_section(section, &mut hashing)?;
+
+            entry.offset = offset;
+            entry.len = hashing.len;
+            entry
+                .checksum
+                .copy_from_slice(hashing.state.finalize().as_bytes());
+            offset += hashing.len;
+        }
+
+        ParamsHeader {
+            version: PARAMS_VERSION,
+            sections,
+        }
+        .write(&mut writer)?;
+        for &section in ParamsSection::ALL.iter() {
+            self.write_section(section, &mut writer)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads parameters written by `write_v2`, failing as soon as a section doesn't match its
+    /// checksum.
+    pub fn read_v2<R: Read>(mut reader: R, checked: bool) -> io::Result<Self> {
+        let header = ParamsHeader::read(&mut reader)?;
+
+        // The sections are read in order, so they have to follow each other
+        let mut offset = PARAMS_HEADER_LEN as u64;
+        for entry in header.sections.iter() {
+            if entry.offset != offset {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "sections aren't contiguous",
+                ));
+            }
+            offset += entry.len;
+        }
+
+        let mut sections = SectionReader::new(reader, &header);
+        let params = Self::read(&mut sections, checked)?;
+        sections.finish()?;
+
+        Ok(params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::groth16::tests::random_parameters;
+    use paired::bls12_381::Bls12;
+
+    #[test]
+    fn test_params_v2() {
+        let params = random_parameters();
+
+        let mut legacy = vec![];
+        params.write(&mut legacy).unwrap();
+        let mut v2 = vec![];
+        params.write_v2(&mut v2).unwrap();
+        // The sections are encoded as in the legacy format
+        assert_eq!(&v2[PARAMS_HEADER_LEN..], &legacy[..]);
+
+        let header = ParamsHeader::read(&v2[..]).unwrap();
+        for &section in ParamsSection::ALL.iter() {
+            let entry = header.section(section);
+            let (start, end) = (entry.offset as usize, (entry.offset + entry.len) as usize);
+            assert!(entry.verify(&v2[start..end]));
+        }
+        assert!(Parameters::<Bls12>::read_v2(&v2[..], true).unwrap() == params);
+        assert!(Parameters::<Bls12>::read_v2(&legacy[..], true).is_err());
+
+        // Corrupt the last point of the l query
+        let l = header.section(ParamsSection::L);
+        let mut corrupted = v2.clone();
+        corrupted[(l.offset + l.len - 1) as usize] ^= 1;
+        let err = Parameters::<Bls12>::read_v2(&corrupted[..], false)
+            .err()
+            .unwrap();
+        assert!(err
+            .to_string()
+            .contains("checksum mismatch in the l section"));
+
+        #[cfg(feature = "mmap")]
+        {
+            use std::fs::File;
+
+            let path = std::env::temp_dir().join(format!("bellperson-v2-{}", std::process::id()));
+            File::create(&path).unwrap().write_all(&v2).unwrap();
+            let mapped = Parameters::<Bls12>::open_mmap(&path, true).unwrap();
+            assert_eq!(mapped.header, Some(header));
+            assert!(mapped.vk == params.vk);
+            assert_eq!(mapped.b_g2.point(3).unwrap(), params.b_g2[3]);
+            mapped.verify_checksums().unwrap();
+
+            File::create(&path).unwrap().write_all(&corrupted).unwrap();
+            let mapped = Parameters::<Bls12>::open_mmap(&path, false).unwrap();
+            assert!(mapped.verify_checksums().is_err());
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+}
@@ -1,21 +1,33 @@
-use std::sync::Arc;
-
-use ff::{Field, PrimeField};
+use std::io;
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use blake2b_simd::Params as Blake2bParams;
+use ff::{Field, PrimeField, PrimeFieldRepr, ScalarEngine};
+use futures::sync::mpsc;
 use futures::Future;
 use groupy::{CurveAffine, CurveProjective};
 use paired::Engine;
 use rand_core::RngCore;
 use rayon::prelude::*;
 
-use super::{ParameterSource, Proof};
+use super::{rng_from_seed, CircuitConstraints, ParameterSource, Parameters, Proof};
+use crate::cancellation::CancellationToken;
 use crate::domain::{EvaluationDomain, Scalar};
-use crate::gpu::{LockedFFTKernel, LockedMultiexpKernel};
-use crate::multicore::{Worker, THREAD_POOL};
-use crate::multiexp::{multiexp, DensityTracker, FullDensity};
+use crate::gpu::{self, LockedFFTKernel, LockedMultiexpKernel, Priority};
+use crate::multicore::{self, Worker};
+use crate::multiexp::{
+    multiexp_constant_time, multiexp_placed, multiexp_precomputed, DensityTracker, FullDensity,
+    HybridDensity, PrecomputedBases, QueryDensity, SourceBuilder,
+};
+use crate::secret;
 use crate::{
     Circuit, ConstraintSystem, Index, LinearCombination, SynthesisError, Variable, BELLMAN_VERSION,
 };
 use log::info;
+use tracing::info_span;
 
 #[cfg(feature = "gpu")]
 use crate::gpu::PriorityLock;
@@ -111,6 +123,17 @@ impl<E: Engine> fmt::Debug for ProvingAssignment<E> {
     }
 }
 
+// Clears the witness and the evaluations the prover still holds, e.g. after a failed proof
+impl<E: Engine> Drop for ProvingAssignment<E> {
+    fn drop(&mut self) {
+        secret::zeroize(&mut self.a);
+        secret::zeroize(&mut self.b);
+        secret::zeroize(&mut self.c);
+        secret::zeroize(&mut self.input_assignment);
+        secret::zeroize(&mut self.aux_assignment);
+    }
+}
+
 impl<E: Engine> PartialEq for ProvingAssignment<E> {
     fn eq(&self, other: &ProvingAssignment<E>) -> bool {
         self.a_aux_density == other.a_aux_density
@@ -227,19 +250,24 @@ impl<E: Engine> ConstraintSystem<E> for ProvingAssignment<E> {
         true
     }
 
-    fn extend(&mut self, other: Self) {
-        self.a_aux_density.extend(other.a_aux_density, false);
-        self.b_input_density.extend(other.b_input_density, true);
-        self.b_aux_density.extend(other.b_aux_density, false);
+    fn extend(&mut self, mut other: Self) {
+        let take = |density: &mut DensityTracker| mem::replace(density, DensityTracker::new());
+        self.a_aux_density
+            .extend(take(&mut other.a_aux_density), false);
+        self.b_input_density
+            .extend(take(&mut other.b_input_density), true);
+        self.b_aux_density
+            .extend(take(&mut other.b_aux_density), false);
 
-        self.a.extend(other.a);
-        self.b.extend(other.b);
-        self.c.extend(other.c);
+        // Copied rather than moved, so that `other` clears them when it's dropped
+        self.a.extend_from_slice(&other.a);
+        self.b.extend_from_slice(&other.b);
+        self.c.extend_from_slice(&other.c);
 
         self.input_assignment
             // Skip first input, which must have been a temporarily allocated one variable.
             .extend(&other.input_assignment[1..]);
-        self.aux_assignment.extend(other.aux_assignment);
+        self.aux_assignment.extend_from_slice(&other.aux_assignment);
     }
 }
 
@@ -247,7 +275,7 @@ pub fn create_random_proof_batch_priority<E, C, R, P: ParameterSource<E>>(
     circuits: Vec<C>,
     params: P,
     rng: &mut R,
-    priority: bool,
+    priority: Priority,
 ) -> Result<Vec<Proof<E>>, SynthesisError>
 where
     E: Engine,
@@ -265,48 +293,794 @@ pub fn create_proof_batch_priority<E, C, P: ParameterSource<E>>(
     params: P,
     r_s: Vec<E::Fr>,
     s_s: Vec<E::Fr>,
-    priority: bool,
+    priority: Priority,
 ) -> Result<Vec<Proof<E>>, SynthesisError>
 where
     E: Engine,
     C: Circuit<E> + Send,
 {
-    info!("Bellperson {} is being used!", BELLMAN_VERSION);
+    let config = ProverConfig {
+        priority,
+        ..ProverConfig::default()
+    };
+    create_proof_batch_with_config::<E, C, P>(circuits, params, r_s, s_s, &config)
+}
 
-    THREAD_POOL.install(|| create_proof_batch_priority_inner(circuits, params, r_s, s_s, priority))
+/// Options of a single proving call, see `create_proof_batch_with_config`.
+#[derive(Clone)]
+pub struct ProverConfig {
+    /// Maximum number of CPU threads used by the call. A thread pool of that size is created for
+    /// the call, instead of using the pool shared by all of the proofs (See `multicore`).
+    pub cpu_threads: Option<usize>,
+    /// If false, the FFTs and multiexps are done on the CPU even if there is a GPU.
+    pub use_gpu: bool,
+    pub priority: Priority,
+    /// If set, the call fails with `SynthesisError::Cancelled` once the token is cancelled. It
+    /// is checked after the synthesis, between the FFTs and multiexps, and by the GPU kernels
+    /// between FFT rounds and multiexp chunks.
+    pub cancel: Option<CancellationToken>,
+    /// Notified of the progress of the call.
+    pub progress: Option<Arc<dyn ProgressObserver>>,
+    /// If true, the multiexps run on the CPU with `multiexp_constant_time`, whose work doesn't
+    /// depend on the values of the witness, for proofs over secrets on shared machines. The
+    /// precomputed tables of the parameters aren't used. The FFTs don't depend on the values,
+    /// so they may still run on the GPU. This is slower, and only removes the shortcuts of the
+    /// multiexps: the field and curve arithmetic isn't constant time.
+    pub constant_time: bool,
 }
 
-fn create_proof_batch_priority_inner<E, C, P: ParameterSource<E>>(
+impl Default for ProverConfig {
+    fn default() -> Self {
+        ProverConfig {
+            cpu_threads: None,
+            use_gpu: true,
+            priority: Priority::Low,
+            cancel: None,
+            progress: None,
+            constant_time: false,
+        }
+    }
+}
+
+impl fmt::Debug for ProverConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ProverConfig")
+            .field("cpu_threads", &self.cpu_threads)
+            .field("use_gpu", &self.use_gpu)
+            .field("priority", &self.priority)
+            .field("cancel", &self.cancel)
+            .field("progress", &self.progress.is_some())
+            .field("constant_time", &self.constant_time)
+            .finish()
+    }
+}
+
+/// Stage of a proving call, see `ProgressObserver`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProvingStage {
+    /// Synthesis of the circuits.
+    Synthesis,
+    /// FFTs computing the H polynomials.
+    Fft,
+    /// Multiexps of the queries, 8 per proof.
+    Multiexp,
+}
+
+/// Observer of the progress of a proving call, see `ProverConfig::progress`.
+pub trait ProgressObserver: Send + Sync {
+    /// Called each time a circuit is synthesized, the FFTs of a proof are done or a multiexp is
+    /// done, with the fraction of the current stage that is done (Reaching 1.0 at the end of
+    /// the stage). It is called from the threads of the prover, so it should return quickly.
+    fn on_stage(&self, stage: ProvingStage, fraction_done: f64);
+}
+
+// Counts the completed steps of a stage for the progress observer
+struct StageProgress<'a> {
+    observer: Option<&'a dyn ProgressObserver>,
+    stage: ProvingStage,
+    total: usize,
+    done: AtomicUsize,
+}
+
+impl<'a> StageProgress<'a> {
+    fn new(config: &'a ProverConfig, stage: ProvingStage, total: usize) -> Self {
+        StageProgress {
+            observer: config.progress.as_ref().map(|o| &**o),
+            stage,
+            total,
+            done: AtomicUsize::new(0),
+        }
+    }
+
+    fn step(&self) {
+        if let Some(observer) = self.observer {
+            let done = self.done.fetch_add(1, Ordering::SeqCst) + 1;
+            observer.on_stage(self.stage, done as f64 / self.total as f64);
+        }
+    }
+}
+
+/// Where a multiexp of the prover ran.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    Cpu,
+    Gpu,
+}
+
+/// Statistics of a multiexp of the prover.
+#[derive(Debug, Clone)]
+pub struct MultiexpStats {
+    /// The query and the part of the assignment of the multiexp, e.g. `a_aux`.
+    pub query: &'static str,
+    pub exponents: usize,
+    pub placement: Placement,
+    /// Time spent starting the multiexp and waiting for its result.
+    pub elapsed: Duration,
+}
+
+/// Timing and resource breakdown of a proving call, see `create_proof_batch_with_stats`.
+#[derive(Debug, Clone, Default)]
+pub struct ProofStats {
+    /// Synthesis of all of the circuits.
+    pub synthesis: Duration,
+    /// The FFTs computing the H polynomial, for each proof.
+    pub ffts: Vec<Duration>,
+    /// The multiexps of each proof, in the order they were waited for.
+    pub multiexps: Vec<MultiexpStats>,
+    /// Time spent waiting for the GPU locks and for the processes of higher priorities.
+    pub lock_wait: Duration,
+    /// Estimate of the peak memory used by the evaluation domains and the assignments, in bytes.
+    pub peak_memory: usize,
+    pub total: Duration,
+}
+
+pub fn create_random_proof_batch_with_config<E, C, R, P: ParameterSource<E>>(
+    circuits: Vec<C>,
+    params: P,
+    rng: &mut R,
+    config: &ProverConfig,
+) -> Result<Vec<Proof<E>>, SynthesisError>
+where
+    E: Engine,
+    C: Circuit<E> + Send,
+    R: RngCore,
+{
+    let r_s = (0..circuits.len()).map(|_| E::Fr::random(rng)).collect();
+    let s_s = (0..circuits.len()).map(|_| E::Fr::random(rng)).collect();
+
+    create_proof_batch_with_config::<E, C, P>(circuits, params, r_s, s_s, config)
+}
+
+/// Same as `create_proof_batch_priority`, with the CPU threads, GPU usage and priority of the
+/// call set by `config`, e.g. so that a latency critical proof doesn't compete for the CPUs
+/// with background proofs.
+pub fn create_proof_batch_with_config<E, C, P: ParameterSource<E>>(
     circuits: Vec<C>,
     params: P,
     r_s: Vec<E::Fr>,
     s_s: Vec<E::Fr>,
-    priority: bool,
+    config: &ProverConfig,
 ) -> Result<Vec<Proof<E>>, SynthesisError>
 where
     E: Engine,
     C: Circuit<E> + Send,
 {
-    let mut provers = circuits
-        .into_par_iter()
-        .map(|circuit| -> Result<_, SynthesisError> {
-            let mut prover = ProvingAssignment::new();
+    create_proof_batch_with_stats(circuits, params, r_s, s_s, config).map(|(proofs, _)| proofs)
+}
+
+/// Same as `create_proof_batch_with_stats`, for a single proof.
+pub fn create_proof_with_stats<E, C, P: ParameterSource<E>>(
+    circuit: C,
+    params: P,
+    r: E::Fr,
+    s: E::Fr,
+    config: &ProverConfig,
+) -> Result<(Proof<E>, ProofStats), SynthesisError>
+where
+    E: Engine,
+    C: Circuit<E> + Send,
+{
+    let (mut proofs, stats) =
+        create_proof_batch_with_stats(vec![circuit], params, vec![r], vec![s], config)?;
+    Ok((proofs.pop().unwrap(), stats))
+}
+
+/// Same as `create_proof_batch_with_config`, also returning the `ProofStats` of the call, for
+/// tuning the provers without parsing the logs.
+pub fn create_proof_batch_with_stats<E, C, P: ParameterSource<E>>(
+    circuits: Vec<C>,
+    params: P,
+    r_s: Vec<E::Fr>,
+    s_s: Vec<E::Fr>,
+    config: &ProverConfig,
+) -> Result<(Vec<Proof<E>>, ProofStats), SynthesisError>
+where
+    E: Engine,
+    C: Circuit<E> + Send,
+{
+    info!("Bellperson {} is being used!", BELLMAN_VERSION);
 
-            prover.alloc_input(|| "", || Ok(E::Fr::one()))?;
+    let span = info_span!("prove", proofs = circuits.len(), priority = %config.priority);
+    let prove = || {
+        multicore::install(|| {
+            let _enter = span.enter();
+            create_proof_batch_priority_inner(circuits, params, r_s, s_s, config)
+        })
+    };
+    match config_thread_pool(config)? {
+        Some(pool) => multicore::with_thread_pool(pool, prove),
+        None => prove(),
+    }
+}
 
-            circuit.synthesize(&mut prover)?;
+// The thread pool of `config.cpu_threads`, if it is set
+fn config_thread_pool(
+    config: &ProverConfig,
+) -> Result<Option<Arc<rayon::ThreadPool>>, SynthesisError> {
+    match config.cpu_threads {
+        Some(threads) => {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+            Ok(Some(Arc::new(pool)))
+        }
+        None => Ok(None),
+    }
+}
 
-            for i in 0..prover.input_assignment.len() {
-                prover.enforce(|| "", |lc| lc + Variable(Index::Input(i)), |lc| lc, |lc| lc);
-            }
+/// Domain separation tag of the randomness of `create_proof_deterministic`.
+pub const DETERMINISTIC_PROOF_DOMAIN: &[u8] = b"bellperson/groth16/deterministic";
+
+/// Creates a proof of `circuit` whose randomness `r` and `s` is derived from the hash of `key`,
+/// `nonce` and the witness of the circuit, instead of being drawn from an RNG, so that proving
+/// the same witness with the same key and nonce gives the same proof, e.g. to cache or to
+/// deduplicate proofs and in reproducible tests.
+///
+/// The proofs are only zero-knowledge as long as `key` is secret and has enough entropy (e.g.
+/// 32 random bytes): anyone knowing the key can check a guess of the witness by proving it
+/// again, which recovers low entropy witnesses. Even with a secret key, the proofs of the same
+/// witness and nonce are identical, so they can be linked to each other; a fresh nonce should
+/// be used whenever that matters.
+pub fn create_proof_deterministic<E, C, P: ParameterSource<E>>(
+    circuit: C,
+    params: P,
+    key: &[u8],
+    nonce: &[u8],
+) -> Result<Proof<E>, SynthesisError>
+where
+    E: Engine,
+    C: Circuit<E> + Send,
+{
+    info!("Bellperson {} is being used!", BELLMAN_VERSION);
+
+    let config = ProverConfig::default();
+    let span = info_span!("prove", proofs = 1, priority = %config.priority);
+    multicore::install(|| -> Result<_, SynthesisError> {
+        let _enter = span.enter();
+        let prover = synthesize(circuit)?;
+
+        let mut state = Blake2bParams::new().hash_length(32).to_state();
+        for bytes in &[key, nonce] {
+            state.update(&(bytes.len() as u64).to_be_bytes());
+            state.update(bytes);
+        }
+        let mut repr = vec![];
+        for value in prover.input_assignment.iter().chain(&prover.aux_assignment) {
+            repr.clear();
+            value.into_repr().write_le(&mut repr)?;
+            state.update(&repr);
+        }
+        secret::zeroize(&mut repr);
+        let rng = &mut rng_from_seed(DETERMINISTIC_PROOF_DOMAIN, state.finalize().as_bytes());
+        let r = E::Fr::random(rng);
+        let s = E::Fr::random(rng);
+
+        let (mut proofs, _) = prove_synthesized(vec![prover], &params, vec![r], vec![s], &config)?;
+        Ok(proofs.pop().unwrap())
+    })
+}
+
+/// A proof computed in the background, see `create_random_proof_async`.
+pub struct AsyncProof<E: Engine> {
+    /// Resolves to the proof.
+    pub proof: Box<dyn Future<Item = Proof<E>, Error = SynthesisError> + Send>,
+    /// Receives the progress of the proof (See `ProgressObserver`), ends once the proof is done.
+    pub progress: mpsc::UnboundedReceiver<(ProvingStage, f64)>,
+}
+
+// Forwards the progress of an async proof to its channel and to the observer of its config
+struct ChannelObserver {
+    tx: Mutex<mpsc::UnboundedSender<(ProvingStage, f64)>>,
+    inner: Option<Arc<dyn ProgressObserver>>,
+}
+
+impl ProgressObserver for ChannelObserver {
+    fn on_stage(&self, stage: ProvingStage, fraction_done: f64) {
+        // The receiver may have been dropped, the proof is still computed
+        let _ = self
+            .tx
+            .lock()
+            .unwrap()
+            .unbounded_send((stage, fraction_done));
+        if let Some(ref inner) = self.inner {
+            inner.on_stage(stage, fraction_done);
+        }
+    }
+}
+
+/// Same as `create_random_proof_batch_with_config`, for a single proof computed on a background
+/// thread of the crate, so that async services don't block their executor. The progress of the
+/// proof is reported on `AsyncProof::progress`.
+///
+/// Without the `multicore` feature, the proof is computed before returning.
+pub fn create_random_proof_async<E, C, R, T>(
+    circuit: C,
+    params: Arc<T>,
+    rng: &mut R,
+    mut config: ProverConfig,
+) -> AsyncProof<E>
+where
+    E: Engine,
+    C: Circuit<E> + Send + 'static,
+    R: RngCore,
+    T: Send + Sync + 'static,
+    for<'a> &'a T: ParameterSource<E>,
+{
+    let r = E::Fr::random(rng);
+    let s = E::Fr::random(rng);
+    let (tx, progress) = mpsc::unbounded();
+    config.progress = Some(Arc::new(ChannelObserver {
+        tx: Mutex::new(tx),
+        inner: config.progress.take(),
+    }));
+
+    // A worker of its own, as the future of a worker on a rayon pool may be computed by the
+    // thread polling it
+    let proof = Worker::new_with_cpus(1).compute(move || {
+        create_proof_batch_with_config(vec![circuit], &*params, vec![r], vec![s], &config)
+            .map(|mut proofs| proofs.pop().unwrap())
+    });
+
+    AsyncProof {
+        proof: Box::new(proof),
+        progress,
+    }
+}
+
+/// The proofs of `prove_many`, in the order of the circuits.
+pub struct ProofStream<E: Engine, C, P> {
+    params: P,
+    config: ProverConfig,
+    pool: Option<Arc<rayon::ThreadPool>>,
+    // Synthesizes the circuits in the background, one at a time
+    synthesis: Worker,
+    circuits: std::vec::IntoIter<C>,
+    randomness: std::vec::IntoIter<(E::Fr, E::Fr)>,
+    // The synthesis of the circuit of the next proof
+    next: Option<Box<dyn Future<Item = ProvingAssignment<E>, Error = SynthesisError>>>,
+}
+
+impl<E, C, P> ProofStream<E, C, P>
+where
+    E: Engine,
+    C: Circuit<E> + Send + 'static,
+    P: ParameterSource<E>,
+{
+    fn synthesize_next(&mut self) {
+        self.next = self.circuits.next().map(|circuit| {
+            let future = self.synthesis.compute(move || synthesize::<E, C>(circuit));
+            Box::new(future) as Box<dyn Future<Item = ProvingAssignment<E>, Error = SynthesisError>>
+        });
+    }
+}
+
+impl<E, C, P> Iterator for ProofStream<E, C, P>
+where
+    E: Engine,
+    C: Circuit<E> + Send + 'static,
+    P: ParameterSource<E>,
+{
+    type Item = Result<Proof<E>, SynthesisError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let synthesis = self.next.take()?;
+        let (r, s) = self.randomness.next().unwrap();
+        let prover = synthesis.wait();
+        if let (Ok(_), Some(ref observer)) = (&prover, &self.config.progress) {
+            observer.on_stage(ProvingStage::Synthesis, 1.0);
+        }
+        // The next circuit is synthesized while this one is proven
+        self.synthesize_next();
+
+        let (params, config) = (&self.params, &self.config);
+        let span = info_span!("prove", proofs = 1, priority = %config.priority);
+        let prove = || {
+            multicore::install(|| {
+                let _enter = span.enter();
+                prove_synthesized(vec![prover?], params, vec![r], vec![s], config)
+            })
+        };
+        let proof = match self.pool {
+            Some(ref pool) => multicore::with_thread_pool(pool.clone(), prove),
+            None => prove(),
+        };
+        Some(proof.map(|(mut proofs, _)| proofs.pop().unwrap()))
+    }
 
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.randomness.size_hint()
+    }
+}
+
+/// Proves `circuits` one after the other, synthesizing the witness of the next circuit on a
+/// background thread while the FFTs and multiexps of the current one run on the GPU or on the
+/// thread pool of the crate, as the sealing pipelines do. A circuit is only synthesized once the
+/// proof of the previous one is requested, so that at most two witnesses are in memory.
+///
+/// The proofs are returned in the order of the circuits. An error only fails the proof of its
+/// circuit, e.g. a circuit which can't be synthesized, but all of the remaining proofs fail if
+/// `config.cancel` is cancelled. Without the `multicore` feature, the circuits are synthesized
+/// on the calling thread.
+pub fn prove_many<E, C, R, P>(
+    circuits: Vec<C>,
+    params: P,
+    rng: &mut R,
+    config: ProverConfig,
+) -> Result<ProofStream<E, C, P>, SynthesisError>
+where
+    E: Engine,
+    C: Circuit<E> + Send + 'static,
+    R: RngCore,
+    P: ParameterSource<E>,
+{
+    info!("Bellperson {} is being used!", BELLMAN_VERSION);
+
+    let pool = config_thread_pool(&config)?;
+    let randomness = (0..circuits.len())
+        .map(|_| (E::Fr::random(rng), E::Fr::random(rng)))
+        .collect::<Vec<_>>();
+
+    let mut stream = ProofStream {
+        params,
+        config,
+        pool,
+        synthesis: Worker::new_with_cpus(1),
+        circuits: circuits.into_iter(),
+        randomness: randomness.into_iter(),
+        next: None,
+    };
+    stream.synthesize_next();
+    Ok(stream)
+}
+
+/// Proves batches of instances of the circuit of `params`, with the same config.
+///
+/// The instances of a batch are synthesized in parallel, then their FFTs run back to back on a
+/// single FFT kernel and their multiexps on a single multiexp kernel, so that the GPU buffers
+/// and the pq and omega tables of the domain are only set up once for the batch. The kernels
+/// are released between batches (The FFT and multiexp kernels hold the same GPU locks), so a
+/// batch should hold as many instances as fit in memory.
+pub struct BatchProver<'a, E: Engine> {
+    params: &'a Parameters<E>,
+    config: ProverConfig,
+    pool: Option<Arc<rayon::ThreadPool>>,
+}
+
+impl<'a, E: Engine> BatchProver<'a, E> {
+    pub fn new(params: &'a Parameters<E>, config: ProverConfig) -> Result<Self, SynthesisError> {
+        let pool = config_thread_pool(&config)?;
+        Ok(BatchProver {
+            params,
+            config,
+            pool,
+        })
+    }
+
+    pub fn prove<C, R>(
+        &self,
+        circuits: Vec<C>,
+        rng: &mut R,
+    ) -> Result<Vec<Proof<E>>, SynthesisError>
+    where
+        C: Circuit<E> + Send,
+        R: RngCore,
+    {
+        self.prove_with_stats(circuits, rng)
+            .map(|(proofs, _)| proofs)
+    }
+
+    /// Same as `prove`, also returning the `ProofStats` of the batch.
+    pub fn prove_with_stats<C, R>(
+        &self,
+        circuits: Vec<C>,
+        rng: &mut R,
+    ) -> Result<(Vec<Proof<E>>, ProofStats), SynthesisError>
+    where
+        C: Circuit<E> + Send,
+        R: RngCore,
+    {
+        let r_s = (0..circuits.len()).map(|_| E::Fr::random(rng)).collect();
+        let s_s = (0..circuits.len()).map(|_| E::Fr::random(rng)).collect();
+
+        let span = info_span!("prove", proofs = circuits.len(), priority = %self.config.priority);
+        let prove = || {
+            multicore::install(|| {
+                let _enter = span.enter();
+                create_proof_batch_priority_inner(circuits, self.params, r_s, s_s, &self.config)
+            })
+        };
+        match self.pool {
+            Some(ref pool) => multicore::with_thread_pool(pool.clone(), prove),
+            None => prove(),
+        }
+    }
+}
+
+// A multiexp started by the prover, with its statistics so far
+struct PendingMultiexp<G: CurveAffine> {
+    future: Box<dyn Future<Item = G::Projective, Error = SynthesisError>>,
+    stats: MultiexpStats,
+    // Whether the multiexp was done before returning the future, and reported as such
+    finished: bool,
+}
+
+impl<G: CurveAffine> PendingMultiexp<G> {
+    fn wait(
+        self,
+        stats: &mut ProofStats,
+        progress: &StageProgress,
+    ) -> Result<G::Projective, SynthesisError> {
+        let now = Instant::now();
+        let res = self.future.wait();
+        if !self.finished {
+            progress.step();
+        }
+        let mut multiexp_stats = self.stats;
+        multiexp_stats.elapsed += now.elapsed();
+        stats.multiexps.push(multiexp_stats);
+        res
+    }
+}
+
+// Multiexp of a query, on its precomputed table if there is one (Unless it is `constant_time`).
+// `skip` is the index of the first base of `source` in the query.
+#[allow(clippy::too_many_arguments)]
+fn query_multiexp<Q, D, G, S>(
+    worker: &Worker,
+    query: &'static str,
+    precomputed: Option<&PrecomputedBases<G>>,
+    skip: usize,
+    source: S,
+    density_map: D,
+    exponents: Arc<Vec<<<G::Engine as ScalarEngine>::Fr as PrimeField>::Repr>>,
+    kern: &mut Option<LockedMultiexpKernel<G::Engine>>,
+    progress: &StageProgress,
+    constant_time: bool,
+) -> PendingMultiexp<G>
+where
+    for<'a> &'a Q: QueryDensity,
+    D: Send + Sync + 'static + Clone + AsRef<Q>,
+    G: CurveAffine,
+    G::Engine: Engine,
+    S: SourceBuilder<G>,
+{
+    let now = Instant::now();
+    let num_exponents = exponents.len();
+    let (future, on_gpu) = match precomputed {
+        _ if constant_time => (
+            multiexp_constant_time(worker, source, density_map, exponents),
+            false,
+        ),
+        Some(bases) => (
+            multiexp_precomputed(worker, bases, skip, density_map, exponents),
+            false,
+        ),
+        None => multiexp_placed(worker, source, density_map, exponents, kern),
+    };
+    // On GPU builds, `multiexp_placed` only returns once the multiexp is done, even on the CPU
+    let finished = !constant_time && precomputed.is_none() && (on_gpu || cfg!(feature = "gpu"));
+    if finished {
+        progress.step();
+    }
+
+    PendingMultiexp {
+        future,
+        finished,
+        stats: MultiexpStats {
+            query,
+            exponents: num_exponents,
+            placement: if on_gpu {
+                Placement::Gpu
+            } else {
+                Placement::Cpu
+            },
+            elapsed: now.elapsed(),
+        },
+    }
+}
+
+fn create_proof_batch_priority_inner<E, C, P: ParameterSource<E>>(
+    circuits: Vec<C>,
+    params: P,
+    r_s: Vec<E::Fr>,
+    s_s: Vec<E::Fr>,
+    config: &ProverConfig,
+) -> Result<(Vec<Proof<E>>, ProofStats), SynthesisError>
+where
+    E: Engine,
+    C: Circuit<E> + Send,
+{
+    let start = Instant::now();
+    let synthesis_progress = StageProgress::new(config, ProvingStage::Synthesis, circuits.len());
+    let provers = circuits
+        .into_par_iter()
+        .map(|circuit| -> Result<_, SynthesisError> {
+            let prover = synthesize(circuit)?;
+            synthesis_progress.step();
             Ok(prover)
         })
         .collect::<Result<Vec<_>, _>>()?;
+    let synthesis = start.elapsed();
+    if let Some(ref cancel) = config.cancel {
+        cancel.check()?;
+    }
+
+    let (proofs, mut stats) = prove_synthesized(provers, &params, r_s, s_s, config)?;
+    stats.synthesis = synthesis;
+    stats.total = start.elapsed();
+
+    Ok((proofs, stats))
+}
+
+// Runs the witness generation of `circuit`
+fn synthesize<E, C>(circuit: C) -> Result<ProvingAssignment<E>, SynthesisError>
+where
+    E: Engine,
+    C: Circuit<E>,
+{
+    let mut prover = ProvingAssignment::new();
+
+    prover.alloc_input(|| "", || Ok(E::Fr::one()))?;
+
+    circuit.synthesize(&mut prover)?;
+
+    for i in 0..prover.input_assignment.len() {
+        prover.enforce(|| "", |lc| lc + Variable(Index::Input(i)), |lc| lc, |lc| lc);
+    }
+
+    Ok(prover)
+}
+
+// The assignment of the constraints recorded by `CircuitConstraints`, whose linear combinations
+// are evaluated directly against the witness instead of being built again by `enforce`. The
+// evaluations are the same as those of `synthesize`, including the `x * 0 = 0` constraints of the
+// inputs. `input_assignment` doesn't hold the "one" input variable.
+fn assign_constraints<E: Engine>(
+    constraints: &CircuitConstraints<E>,
+    mut input_assignment: Vec<E::Fr>,
+    aux_assignment: Vec<E::Fr>,
+) -> ProvingAssignment<E> {
+    input_assignment.insert(0, E::Fr::one());
+    let density = |len: usize| {
+        let mut density = DensityTracker::new();
+        for _ in 0..len {
+            density.add_element();
+        }
+        density
+    };
+
+    let (input, aux) = (&input_assignment[..], &aux_assignment[..]);
+    let zeros = || input.iter().map(|_| Scalar(E::Fr::zero()));
+    let ((a, a_aux_density), ((b, b_input_density, b_aux_density), c)) = rayon::join(
+        || {
+            let mut aux_density = density(aux.len());
+            let a = constraints
+                .constraints
+                .iter()
+                .map(|(a, _, _)| Scalar(eval(a, None, Some(&mut aux_density), input, aux)))
+                .chain(input.iter().map(|&value| Scalar(value)))
+                .collect::<Vec<_>>();
+            (a, aux_density)
+        },
+        || {
+            rayon::join(
+                || {
+                    let mut input_density = density(input.len());
+                    let mut aux_density = density(aux.len());
+                    let b = constraints
+                        .constraints
+                        .iter()
+                        .map(|(_, b, _)| {
+                            Scalar(eval(
+                                b,
+                                Some(&mut input_density),
+                                Some(&mut aux_density),
+                                input,
+                                aux,
+                            ))
+                        })
+                        .chain(zeros())
+                        .collect::<Vec<_>>();
+                    (b, input_density, aux_density)
+                },
+                || {
+                    constraints
+                        .constraints
+                        .iter()
+                        .map(|(_, _, c)| Scalar(eval(c, None, None, input, aux)))
+                        .chain(zeros())
+                        .collect::<Vec<_>>()
+                },
+            )
+        },
+    );
+
+    ProvingAssignment {
+        a_aux_density,
+        b_input_density,
+        b_aux_density,
+        a,
+        b,
+        c,
+        input_assignment,
+        aux_assignment,
+    }
+}
+
+/// Proves the constraints recorded by `CircuitConstraints` with the given assignments, whose
+/// lengths have been checked, see `create_proof_from_assignments`.
+pub(crate) fn create_proof_from_constraints<E, P: ParameterSource<E>>(
+    constraints: &CircuitConstraints<E>,
+    params: P,
+    r: E::Fr,
+    s: E::Fr,
+    input_assignment: Vec<E::Fr>,
+    aux_assignment: Vec<E::Fr>,
+) -> Result<Proof<E>, SynthesisError>
+where
+    E: Engine,
+{
+    info!("Bellperson {} is being used!", BELLMAN_VERSION);
+
+    let config = ProverConfig::default();
+    let span = info_span!("prove", proofs = 1, priority = %config.priority);
+    multicore::install(|| {
+        let _enter = span.enter();
+        let prover = assign_constraints(constraints, input_assignment, aux_assignment);
+        let (proofs, _) = prove_synthesized(vec![prover], &params, vec![r], vec![s], &config)?;
+        Ok(proofs.into_iter().next().unwrap())
+    })
+}
+
+// The FFTs and multiexps of the proofs of synthesized circuits. The synthesis and total times of
+// the statistics are left to the caller.
+fn prove_synthesized<E, P: ParameterSource<E>>(
+    mut provers: Vec<ProvingAssignment<E>>,
+    params: &P,
+    mut r_s: Vec<E::Fr>,
+    mut s_s: Vec<E::Fr>,
+    config: &ProverConfig,
+) -> Result<(Vec<Proof<E>>, ProofStats), SynthesisError>
+where
+    E: Engine,
+{
+    let lock_wait_start = gpu::lock_wait_time();
+    let mut stats = ProofStats::default();
+
+    let priority = config.priority;
+    let check_cancelled = || match config.cancel {
+        Some(ref cancel) => cancel.check(),
+        None => Ok(()),
+    };
+    let num_circuits = provers.len();
+    let fft_progress = StageProgress::new(config, ProvingStage::Fft, num_circuits);
+    let multiexp_progress = StageProgress::new(config, ProvingStage::Multiexp, 8 * num_circuits);
 
     let worker = Worker::new();
     let input_len = provers[0].input_assignment.len();
     let vk = params.get_vk(input_len)?;
+    let precomputed = params.get_precomputed();
     let n = provers[0].a.len();
 
     // Make sure all circuits have the same input len.
@@ -323,18 +1097,36 @@ where
         log_d += 1;
     }
 
+    stats.peak_memory = provers
+        .iter()
+        .map(|prover| {
+            let assignment_len = prover.input_assignment.len() + prover.aux_assignment.len();
+            (3 << log_d) * mem::size_of::<Scalar<E>>()
+                + 2 * assignment_len * mem::size_of::<E::Fr>()
+        })
+        .sum();
+
     #[cfg(feature = "gpu")]
-    let prio_lock = if priority {
-        Some(PriorityLock::lock())
+    let prio_lock = if config.use_gpu && priority > Priority::Low {
+        Some(PriorityLock::lock(priority))
     } else {
         None
     };
 
-    let mut fft_kern = Some(LockedFFTKernel::<E>::new(log_d, priority));
+    // The stub kernel of the builds without the `gpu` feature would fail, and log the fallback
+    // to the CPU, on every FFT
+    let mut fft_kern = if config.use_gpu && cfg!(feature = "gpu") {
+        let mut kern = LockedFFTKernel::<E>::new(log_d, priority);
+        kern.set_cancellation(config.cancel.clone());
+        Some(kern)
+    } else {
+        None
+    };
 
-    let a_s = provers
+    let mut a_s = provers
         .iter_mut()
         .map(|prover| {
+            check_cancelled()?;
             let mut a =
                 EvaluationDomain::from_coeffs(std::mem::replace(&mut prover.a, Vec::new()))?;
             let mut b =
@@ -342,81 +1134,100 @@ where
             let mut c =
                 EvaluationDomain::from_coeffs(std::mem::replace(&mut prover.c, Vec::new()))?;
 
-            a.ifft(&worker, &mut fft_kern)?;
-            a.coset_fft(&worker, &mut fft_kern)?;
-            b.ifft(&worker, &mut fft_kern)?;
-            b.coset_fft(&worker, &mut fft_kern)?;
-            c.ifft(&worker, &mut fft_kern)?;
-            c.coset_fft(&worker, &mut fft_kern)?;
-
-            a.mul_assign(&worker, &b);
+            let fft_start = Instant::now();
+            let quotient = a.quotient(&worker, &mut b, &mut c, &mut fft_kern);
+            secret::zeroize(b.as_mut());
+            secret::zeroize(c.as_mut());
+            if let Err(err) = quotient {
+                secret::zeroize(a.as_mut());
+                return Err(SynthesisError::from(err));
+            }
+            stats.ffts.push(fft_start.elapsed());
+            fft_progress.step();
             drop(b);
-            a.sub_assign(&worker, &c);
             drop(c);
-            a.divide_by_z_on_coset(&worker);
-            a.icoset_fft(&worker, &mut fft_kern)?;
             let mut a = a.into_coeffs();
             let a_len = a.len() - 1;
-            a.truncate(a_len);
+            let h_exponents = a[..a_len]
+                .iter()
+                .map(|s| s.0.into_repr())
+                .collect::<Vec<_>>();
+            secret::zeroize(&mut a);
 
-            Ok(Arc::new(
-                a.into_iter().map(|s| s.0.into_repr()).collect::<Vec<_>>(),
-            ))
+            Ok(Arc::new(h_exponents))
         })
         .collect::<Result<Vec<_>, SynthesisError>>()?;
 
     drop(fft_kern);
-    let mut multiexp_kern = Some(LockedMultiexpKernel::<E>::new(log_d, priority));
+    let mut multiexp_kern = if config.use_gpu && cfg!(feature = "gpu") && !config.constant_time {
+        let mut kern = LockedMultiexpKernel::<E>::new(log_d, priority);
+        kern.set_cancellation(config.cancel.clone());
+        Some(kern)
+    } else {
+        None
+    };
 
     let h_s = a_s
-        .into_iter()
+        .iter()
         .map(|a| {
-            let h = multiexp(
+            check_cancelled()?;
+            let h = query_multiexp(
                 &worker,
+                "h",
+                precomputed.map(|p| &p.h),
+                0,
                 params.get_h(a.len())?,
                 FullDensity,
-                a,
+                a.clone(),
                 &mut multiexp_kern,
+                &multiexp_progress,
+                config.constant_time,
             );
             Ok(h)
         })
         .collect::<Result<Vec<_>, SynthesisError>>()?;
 
-    let input_assignments = provers
+    let mut input_assignments = provers
         .par_iter_mut()
         .map(|prover| {
-            let input_assignment = std::mem::replace(&mut prover.input_assignment, Vec::new());
-            Arc::new(
-                input_assignment
-                    .into_iter()
-                    .map(|s| s.into_repr())
-                    .collect::<Vec<_>>(),
-            )
+            let mut input_assignment = std::mem::replace(&mut prover.input_assignment, Vec::new());
+            let reprs = input_assignment
+                .iter()
+                .map(|s| s.into_repr())
+                .collect::<Vec<_>>();
+            secret::zeroize(&mut input_assignment);
+            Arc::new(reprs)
         })
         .collect::<Vec<_>>();
 
-    let aux_assignments = provers
+    let mut aux_assignments = provers
         .par_iter_mut()
         .map(|prover| {
-            let aux_assignment = std::mem::replace(&mut prover.aux_assignment, Vec::new());
-            Arc::new(
-                aux_assignment
-                    .into_iter()
-                    .map(|s| s.into_repr())
-                    .collect::<Vec<_>>(),
-            )
+            let mut aux_assignment = std::mem::replace(&mut prover.aux_assignment, Vec::new());
+            let reprs = aux_assignment
+                .iter()
+                .map(|s| s.into_repr())
+                .collect::<Vec<_>>();
+            secret::zeroize(&mut aux_assignment);
+            Arc::new(reprs)
         })
         .collect::<Vec<_>>();
 
     let l_s = aux_assignments
         .iter()
         .map(|aux_assignment| {
-            let l = multiexp(
+            check_cancelled()?;
+            let l = query_multiexp(
                 &worker,
+                "l",
+                precomputed.map(|p| &p.l),
+                0,
                 params.get_l(aux_assignment.len())?,
                 FullDensity,
                 aux_assignment.clone(),
                 &mut multiexp_kern,
+                &multiexp_progress,
+                config.constant_time,
             );
             Ok(l)
         })
@@ -426,68 +1237,102 @@ where
         .into_iter()
         .zip(input_assignments.iter())
         .zip(aux_assignments.iter())
-        .map(|((prover, input_assignment), aux_assignment)| {
+        .map(|((mut prover, input_assignment), aux_assignment)| {
+            check_cancelled()?;
             let a_aux_density_total = prover.a_aux_density.get_total_density();
 
             let (a_inputs_source, a_aux_source) =
                 params.get_a(input_assignment.len(), a_aux_density_total)?;
 
-            let a_inputs = multiexp(
+            let a_inputs = query_multiexp(
                 &worker,
+                "a_inputs",
+                precomputed.map(|p| &p.a),
+                0,
                 a_inputs_source,
                 FullDensity,
                 input_assignment.clone(),
                 &mut multiexp_kern,
+                &multiexp_progress,
+                config.constant_time,
             );
 
-            let a_aux = multiexp(
+            let a_aux = query_multiexp(
                 &worker,
+                "a_aux",
+                precomputed.map(|p| &p.a),
+                input_assignment.len(),
                 a_aux_source,
-                Arc::new(prover.a_aux_density),
+                Arc::new(HybridDensity::from(&prover.a_aux_density)),
                 aux_assignment.clone(),
                 &mut multiexp_kern,
+                &multiexp_progress,
+                config.constant_time,
             );
 
-            let b_input_density = Arc::new(prover.b_input_density);
+            let b_input_density = Arc::new(mem::replace(
+                &mut prover.b_input_density,
+                DensityTracker::new(),
+            ));
             let b_input_density_total = b_input_density.get_total_density();
-            let b_aux_density = Arc::new(prover.b_aux_density);
+            let b_aux_density = Arc::new(HybridDensity::from(&prover.b_aux_density));
             let b_aux_density_total = b_aux_density.get_total_density();
 
             let (b_g1_inputs_source, b_g1_aux_source) =
                 params.get_b_g1(b_input_density_total, b_aux_density_total)?;
 
-            let b_g1_inputs = multiexp(
+            let b_g1_inputs = query_multiexp(
                 &worker,
+                "b_g1_inputs",
+                precomputed.map(|p| &p.b_g1),
+                0,
                 b_g1_inputs_source,
                 b_input_density.clone(),
                 input_assignment.clone(),
                 &mut multiexp_kern,
+                &multiexp_progress,
+                config.constant_time,
             );
 
-            let b_g1_aux = multiexp(
+            let b_g1_aux = query_multiexp(
                 &worker,
+                "b_g1_aux",
+                precomputed.map(|p| &p.b_g1),
+                b_input_density_total,
                 b_g1_aux_source,
                 b_aux_density.clone(),
                 aux_assignment.clone(),
                 &mut multiexp_kern,
+                &multiexp_progress,
+                config.constant_time,
             );
 
             let (b_g2_inputs_source, b_g2_aux_source) =
                 params.get_b_g2(b_input_density_total, b_aux_density_total)?;
 
-            let b_g2_inputs = multiexp(
+            let b_g2_inputs = query_multiexp(
                 &worker,
+                "b_g2_inputs",
+                precomputed.map(|p| &p.b_g2),
+                0,
                 b_g2_inputs_source,
                 b_input_density,
                 input_assignment.clone(),
                 &mut multiexp_kern,
+                &multiexp_progress,
+                config.constant_time,
             );
-            let b_g2_aux = multiexp(
+            let b_g2_aux = query_multiexp(
                 &worker,
+                "b_g2_aux",
+                precomputed.map(|p| &p.b_g2),
+                b_input_density_total,
                 b_g2_aux_source,
                 b_aux_density,
                 aux_assignment.clone(),
                 &mut multiexp_kern,
+                &multiexp_progress,
+                config.constant_time,
             );
 
             Ok((
@@ -510,13 +1355,15 @@ where
         .into_iter()
         .zip(l_s.into_iter())
         .zip(inputs.into_iter())
-        .zip(r_s.into_iter())
-        .zip(s_s.into_iter())
+        .zip(r_s.iter())
+        .zip(s_s.iter())
         .map(
             |(
                 (((h, l), (a_inputs, a_aux, b_g1_inputs, b_g1_aux, b_g2_inputs, b_g2_aux)), r),
                 s,
             )| {
+                check_cancelled()?;
+                let (r, s) = (*r, *s);
                 if vk.delta_g1.is_zero() || vk.delta_g2.is_zero() {
                     // If this element is zero, someone is trying to perform a
                     // subversion-CRS attack.
@@ -535,23 +1382,24 @@ where
                     g_c = vk.delta_g1.mul(rs);
                     g_c.add_assign(&vk.alpha_g1.mul(s));
                     g_c.add_assign(&vk.beta_g1.mul(r));
+                    secret::zeroize(std::slice::from_mut(&mut rs));
                 }
-                let mut a_answer = a_inputs.wait()?;
-                a_answer.add_assign(&a_aux.wait()?);
+                let mut a_answer = a_inputs.wait(&mut stats, &multiexp_progress)?;
+                a_answer.add_assign(&a_aux.wait(&mut stats, &multiexp_progress)?);
                 g_a.add_assign(&a_answer);
                 a_answer.mul_assign(s);
                 g_c.add_assign(&a_answer);
 
-                let mut b1_answer = b_g1_inputs.wait()?;
-                b1_answer.add_assign(&b_g1_aux.wait()?);
-                let mut b2_answer = b_g2_inputs.wait()?;
-                b2_answer.add_assign(&b_g2_aux.wait()?);
+                let mut b1_answer = b_g1_inputs.wait(&mut stats, &multiexp_progress)?;
+                b1_answer.add_assign(&b_g1_aux.wait(&mut stats, &multiexp_progress)?);
+                let mut b2_answer = b_g2_inputs.wait(&mut stats, &multiexp_progress)?;
+                b2_answer.add_assign(&b_g2_aux.wait(&mut stats, &multiexp_progress)?);
 
                 g_b.add_assign(&b2_answer);
                 b1_answer.mul_assign(r);
                 g_c.add_assign(&b1_answer);
-                g_c.add_assign(&h.wait()?);
-                g_c.add_assign(&l.wait()?);
+                g_c.add_assign(&h.wait(&mut stats, &multiexp_progress)?);
+                g_c.add_assign(&l.wait(&mut stats, &multiexp_progress)?);
 
                 Ok(Proof {
                     a: g_a.into_affine(),
@@ -560,9 +1408,23 @@ where
                 })
             },
         )
-        .collect::<Result<Vec<_>, SynthesisError>>()?;
+        .collect::<Result<Vec<_>, SynthesisError>>();
+
+    // The multiexps are done, so their futures no longer share the exponents
+    for exponents in a_s
+        .iter_mut()
+        .chain(input_assignments.iter_mut())
+        .chain(aux_assignments.iter_mut())
+    {
+        secret::zeroize_shared(exponents);
+    }
+    secret::zeroize(&mut r_s);
+    secret::zeroize(&mut s_s);
+    let proofs = proofs?;
 
-    Ok(proofs)
+    stats.lock_wait = gpu::lock_wait_time() - lock_wait_start;
+
+    Ok((proofs, stats))
 }
 
 #[cfg(test)]
@@ -634,4 +1496,56 @@ mod tests {
             }
         }
     }
+    // Random constraints over interleaved inputs and auxiliary variables, the same for every
+    // synthesis
+    struct RandomCircuit {
+        inputs: Vec<Fr>,
+        aux: Vec<Fr>,
+    }
+
+    impl Circuit<Bls12> for RandomCircuit {
+        fn synthesize<CS: ConstraintSystem<Bls12>>(
+            self,
+            cs: &mut CS,
+        ) -> Result<(), SynthesisError> {
+            let mut vars = vec![];
+            for (i, &value) in self.aux.iter().enumerate() {
+                vars.push(cs.alloc(|| format!("aux {}", i), || Ok(value))?);
+                if let Some(&value) = self.inputs.get(i) {
+                    vars.push(cs.alloc_input(|| format!("input {}", i), || Ok(value))?);
+                }
+            }
+
+            let mut rng = XorShiftRng::from_seed([7; 16]);
+            for i in 0..32 {
+                let mut lcs = vec![];
+                for _ in 0..3 {
+                    let mut lc = LinearCombination::zero();
+                    for _ in 0..rng.gen_range(0, 4) {
+                        lc = lc + (Fr::random(&mut rng), vars[rng.gen_range(0, vars.len())]);
+                    }
+                    lcs.push(lc);
+                }
+                let (c, b, a) = (lcs.pop().unwrap(), lcs.pop().unwrap(), lcs.pop().unwrap());
+                cs.enforce(|| format!("constraint {}", i), |_| a, |_| b, |_| c);
+            }
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_assign_constraints() {
+        let rng = &mut XorShiftRng::from_seed([3; 16]);
+        let inputs = (0..4).map(|_| Fr::random(rng)).collect::<Vec<_>>();
+        let aux = (0..8).map(|_| Fr::random(rng)).collect::<Vec<_>>();
+        let circuit = || RandomCircuit {
+            inputs: inputs.clone(),
+            aux: aux.clone(),
+        };
+
+        let constraints = CircuitConstraints::from_circuit(circuit()).unwrap();
+        let assigned = assign_constraints(&constraints, inputs.clone(), aux.clone());
+        assert_eq!(assigned, synthesize(circuit()).unwrap());
+    }
 }
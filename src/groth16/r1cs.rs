@@ -0,0 +1,357 @@
+//! Import and export of constraint systems and witnesses in the binary `.r1cs` and `.wtns`
+//! formats of circom and snarkjs, so that the circuits authored with these tools can be proven
+//! by this crate (See `R1csCircuit`).
+//!
+//! Wire 0 is the "one" input variable, followed by the public inputs and by the auxiliary
+//! variables. The public outputs of circom circuits are public inputs of this crate.
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use ff::{Field, PrimeField, PrimeFieldRepr};
+use paired::Engine;
+
+use std::io::{self, Read, Write};
+
+use super::CircuitConstraints;
+use crate::{Circuit, ConstraintSystem, Index, LinearCombination, SynthesisError, Variable};
+
+const R1CS_MAGIC: [u8; 4] = *b"r1cs";
+const R1CS_VERSION: u32 = 1;
+const R1CS_HEADER: u32 = 1;
+const R1CS_CONSTRAINTS: u32 = 2;
+const R1CS_WIRE_TO_LABEL: u32 = 3;
+
+const WTNS_MAGIC: [u8; 4] = *b"wtns";
+const WTNS_VERSION: u32 = 2;
+const WTNS_HEADER: u32 = 1;
+const WTNS_DATA: u32 = 2;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+// Size in bytes of the elements of `F` in both formats
+fn field_size<F: PrimeField>() -> usize {
+    F::char().as_ref().len() * 8
+}
+
+fn write_field_header<F: PrimeField>(writer: &mut Vec<u8>) -> io::Result<()> {
+    writer.write_u32::<LittleEndian>(field_size::<F>() as u32)?;
+    F::char().write_le(writer)
+}
+
+fn read_field_header<F: PrimeField>(reader: &mut &[u8]) -> io::Result<()> {
+    if reader.read_u32::<LittleEndian>()? as usize != field_size::<F>() {
+        return Err(invalid("field size mismatch"));
+    }
+    let mut prime = F::Repr::default();
+    prime.read_le(&mut *reader)?;
+    if prime != F::char() {
+        return Err(invalid("prime mismatch"));
+    }
+    Ok(())
+}
+
+fn read_element<F: PrimeField>(reader: &mut &[u8]) -> io::Result<F> {
+    let mut repr = F::Repr::default();
+    repr.read_le(&mut *reader)?;
+    F::from_repr(repr).map_err(|e| invalid(&e.to_string()))
+}
+
+fn write_file<W: Write>(
+    mut writer: W,
+    magic: [u8; 4],
+    version: u32,
+    sections: &[(u32, Vec<u8>)],
+) -> io::Result<()> {
+    writer.write_all(&magic)?;
+    writer.write_u32::<LittleEndian>(version)?;
+    writer.write_u32::<LittleEndian>(sections.len() as u32)?;
+    for (kind, data) in sections.iter() {
+        writer.write_u32::<LittleEndian>(*kind)?;
+        writer.write_u64::<LittleEndian>(data.len() as u64)?;
+        writer.write_all(data)?;
+    }
+    Ok(())
+}
+
+// The sections of a file, which may come in any order
+fn read_file(bytes: &[u8], magic: [u8; 4], version: u32) -> io::Result<Vec<(u32, &[u8])>> {
+    let mut reader = bytes;
+    let mut file_magic = [0u8; 4];
+    reader.read_exact(&mut file_magic)?;
+    if file_magic != magic {
+        return Err(invalid("invalid magic"));
+    }
+    if reader.read_u32::<LittleEndian>()? != version {
+        return Err(invalid("unsupported version"));
+    }
+
+    let num_sections = reader.read_u32::<LittleEndian>()?;
+    let mut sections = vec![];
+    for _ in 0..num_sections {
+        let kind = reader.read_u32::<LittleEndian>()?;
+        let len = reader.read_u64::<LittleEndian>()?;
+        if len > reader.len() as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "truncated section",
+            ));
+        }
+        let (data, rest) = reader.split_at(len as usize);
+        sections.push((kind, data));
+        reader = rest;
+    }
+    Ok(sections)
+}
+
+fn section<'a>(sections: &[(u32, &'a [u8])], kind: u32) -> io::Result<&'a [u8]> {
+    sections
+        .iter()
+        .find(|&&(k, _)| k == kind)
+        .map(|&(_, data)| data)
+        .ok_or_else(|| invalid(&format!("missing section {}", kind)))
+}
+
+/// Writes `constraints` in the `.r1cs` format.
+pub fn write_r1cs<E: Engine, W: Write>(
+    constraints: &CircuitConstraints<E>,
+    writer: W,
+) -> io::Result<()> {
+    let num_inputs = constraints.num_inputs;
+    let num_wires = 1 + num_inputs + constraints.num_aux;
+
+    let mut header = vec![];
+    write_field_header::<E::Fr>(&mut header)?;
+    header.write_u32::<LittleEndian>(num_wires as u32)?;
+    // No public outputs, and the auxiliary variables are internal wires rather than private
+    // inputs
+    header.write_u32::<LittleEndian>(0)?;
+    header.write_u32::<LittleEndian>(num_inputs as u32)?;
+    header.write_u32::<LittleEndian>(0)?;
+    header.write_u64::<LittleEndian>(num_wires as u64)?;
+    header.write_u32::<LittleEndian>(constraints.constraints.len() as u32)?;
+
+    let wire = |var: &Variable| match var.get_unchecked() {
+        Index::Input(i) => i,
+        Index::Aux(i) => 1 + num_inputs + i,
+    };
+    let mut data = vec![];
+    for (a, b, c) in constraints.constraints.iter() {
+        for lc in [a, b, c].iter() {
+            let mut terms =
+                lc.0.iter()
+                    .filter(|(_, coeff)| !coeff.is_zero())
+                    .map(|(var, coeff)| (wire(var), *coeff))
+                    .collect::<Vec<_>>();
+            terms.sort_by_key(|&(wire, _)| wire);
+            data.write_u32::<LittleEndian>(terms.len() as u32)?;
+            for (wire, coeff) in terms {
+                data.write_u32::<LittleEndian>(wire as u32)?;
+                coeff.into_repr().write_le(&mut data)?;
+            }
+        }
+    }
+
+    let mut labels = vec![];
+    for i in 0..num_wires {
+        labels.write_u64::<LittleEndian>(i as u64)?;
+    }
+
+    write_file(
+        writer,
+        R1CS_MAGIC,
+        R1CS_VERSION,
+        &[
+            (R1CS_HEADER, header),
+            (R1CS_CONSTRAINTS, data),
+            (R1CS_WIRE_TO_LABEL, labels),
+        ],
+    )
+}
+
+fn read_lc<E: Engine>(
+    reader: &mut &[u8],
+    variable: &dyn Fn(usize) -> io::Result<Variable>,
+) -> io::Result<LinearCombination<E>> {
+    let mut lc = LinearCombination::zero();
+    for _ in 0..reader.read_u32::<LittleEndian>()? {
+        let wire = reader.read_u32::<LittleEndian>()? as usize;
+        let coeff = read_element::<E::Fr>(reader)?;
+        lc = lc + (coeff, variable(wire)?);
+    }
+    Ok(lc)
+}
+
+/// Reads constraints in the `.r1cs` format, failing if they aren't over the scalar field of `E`.
+pub fn read_r1cs<E: Engine, R: Read>(mut reader: R) -> io::Result<CircuitConstraints<E>> {
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes)?;
+    let sections = read_file(&bytes, R1CS_MAGIC, R1CS_VERSION)?;
+
+    let mut header = section(&sections, R1CS_HEADER)?;
+    read_field_header::<E::Fr>(&mut header)?;
+    let num_wires = header.read_u32::<LittleEndian>()? as usize;
+    let num_outputs = header.read_u32::<LittleEndian>()? as usize;
+    let num_public_inputs = header.read_u32::<LittleEndian>()? as usize;
+    let _num_private_inputs = header.read_u32::<LittleEndian>()?;
+    let _num_labels = header.read_u64::<LittleEndian>()?;
+    let num_constraints = header.read_u32::<LittleEndian>()?;
+
+    let num_inputs = num_outputs + num_public_inputs;
+    if num_wires < 1 + num_inputs {
+        return Err(invalid("more public wires than wires"));
+    }
+    let num_aux = num_wires - 1 - num_inputs;
+    let variable = |wire: usize| {
+        if wire >= num_wires {
+            Err(invalid("wire out of range"))
+        } else if wire <= num_inputs {
+            Ok(Variable(Index::Input(wire)))
+        } else {
+            Ok(Variable(Index::Aux(wire - 1 - num_inputs)))
+        }
+    };
+
+    let mut data = section(&sections, R1CS_CONSTRAINTS)?;
+    let mut constraints = vec![];
+    for _ in 0..num_constraints {
+        let a = read_lc(&mut data, &variable)?;
+        let b = read_lc(&mut data, &variable)?;
+        let c = read_lc(&mut data, &variable)?;
+        constraints.push((a, b, c));
+    }
+
+    Ok(CircuitConstraints {
+        num_inputs,
+        num_aux,
+        constraints,
+    })
+}
+
+/// Writes a witness in the `.wtns` format, from the public inputs (Not counting the "one" input
+/// variable) and the auxiliary assignments.
+pub fn write_wtns<F: PrimeField, W: Write>(
+    input_assignment: &[F],
+    aux_assignment: &[F],
+    writer: W,
+) -> io::Result<()> {
+    let mut header = vec![];
+    write_field_header::<F>(&mut header)?;
+    header.write_u32::<LittleEndian>((1 + input_assignment.len() + aux_assignment.len()) as u32)?;
+
+    let mut data = vec![];
+    F::one().into_repr().write_le(&mut data)?;
+    for value in input_assignment.iter().chain(aux_assignment.iter()) {
+        value.into_repr().write_le(&mut data)?;
+    }
+
+    write_file(
+        writer,
+        WTNS_MAGIC,
+        WTNS_VERSION,
+        &[(WTNS_HEADER, header), (WTNS_DATA, data)],
+    )
+}
+
+/// Reads a witness in the `.wtns` format, returning the values of all of the wires (Starting with
+/// the "one" wire).
+pub fn read_wtns<F: PrimeField, R: Read>(mut reader: R) -> io::Result<Vec<F>> {
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes)?;
+    let sections = read_file(&bytes, WTNS_MAGIC, WTNS_VERSION)?;
+
+    let mut header = section(&sections, WTNS_HEADER)?;
+    read_field_header::<F>(&mut header)?;
+    let num_wires = header.read_u32::<LittleEndian>()?;
+
+    let mut data = section(&sections, WTNS_DATA)?;
+    (0..num_wires).map(|_| read_element(&mut data)).collect()
+}
+
+/// A circuit of constraints read by `read_r1cs`, with the values of its wires read by `read_wtns`
+/// (Or `None` when generating the parameters).
+pub struct R1csCircuit<'a, E: Engine> {
+    pub constraints: &'a CircuitConstraints<E>,
+    pub witness: Option<&'a [E::Fr]>,
+}
+
+impl<'a, E: Engine> Circuit<E> for R1csCircuit<'a, E> {
+    fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let constraints = self.constraints;
+        let num_inputs = constraints.num_inputs;
+        if let Some(witness) = self.witness {
+            if witness.len() != 1 + num_inputs + constraints.num_aux {
+                return Err(SynthesisError::AssignmentMissing);
+            }
+        }
+        let value = |wire: usize| {
+            self.witness
+                .map(|witness| witness[wire])
+                .ok_or(SynthesisError::AssignmentMissing)
+        };
+
+        for i in 0..constraints.num_aux {
+            cs.alloc(|| format!("aux {}", i), || value(1 + num_inputs + i))?;
+        }
+        for i in 0..num_inputs {
+            cs.alloc_input(|| format!("input {}", i), || value(1 + i))?;
+        }
+        constraints.enforce(cs);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::groth16::witness::tests::Square;
+    use crate::groth16::{
+        create_proof, generate_parameters_from_seed, prepare_verifying_key, verify_proof,
+        CircuitConstraints,
+    };
+    use paired::bls12_381::{Bls12, Fr};
+
+    #[test]
+    fn test_r1cs_roundtrip() {
+        let constraints = CircuitConstraints::<Bls12>::from_circuit(Square(None)).unwrap();
+        let mut r1cs = vec![];
+        write_r1cs(&constraints, &mut r1cs).unwrap();
+        assert_eq!(&r1cs[..4], b"r1cs");
+
+        let read = read_r1cs::<Bls12, _>(&r1cs[..]).unwrap();
+        assert_eq!(read.num_inputs(), 1);
+        assert_eq!(read.num_aux(), 1);
+        assert_eq!(read.num_constraints(), 1);
+        let mut again = vec![];
+        write_r1cs(&read, &mut again).unwrap();
+        assert_eq!(r1cs, again);
+        assert!(read_r1cs::<Bls12, _>(&r1cs[..r1cs.len() - 1]).is_err());
+
+        let x = Fr::from_str("3").unwrap();
+        let y = Fr::from_str("9").unwrap();
+        let mut wtns = vec![];
+        write_wtns(&[y], &[x], &mut wtns).unwrap();
+        let witness = read_wtns::<Fr, _>(&wtns[..]).unwrap();
+        assert_eq!(witness, vec![Fr::one(), y, x]);
+
+        // The circuit read from the file has the same parameters and proofs as the original one
+        let params = generate_parameters_from_seed(
+            R1csCircuit {
+                constraints: &read,
+                witness: None,
+            },
+            b"r1cs",
+        )
+        .unwrap();
+        assert!(params == generate_parameters_from_seed(Square(None), b"r1cs").unwrap());
+        let (r, s) = (Fr::from_str("5").unwrap(), Fr::from_str("7").unwrap());
+        let circuit = R1csCircuit {
+            constraints: &read,
+            witness: Some(&witness),
+        };
+        let proof = create_proof(circuit, &params, r, s).unwrap();
+        assert!(proof == create_proof(Square(Some(x)), &params, r, s).unwrap());
+        assert!(verify_proof(&prepare_verifying_key(&params.vk), &proof, &[y]).unwrap());
+    }
+}
@@ -0,0 +1,69 @@
+use ff::{Field, PrimeField};
+use groupy::{CurveAffine, CurveProjective};
+use paired::Engine;
+use rand_core::RngCore;
+
+use super::{Proof, VerifyingKey};
+
+/// Rerandomizes a proof for the circuit of `vk`, returning another valid proof of the same
+/// public inputs which can't be linked to the original one, without reproving.
+///
+/// With random `r` and `s`, the proof `(A, B, C)` becomes `(A / r, r * B + r * s * delta, C + s * A)`.
+pub fn rerandomize_proof<E, R>(vk: &VerifyingKey<E>, proof: &Proof<E>, rng: &mut R) -> Proof<E>
+where
+    E: Engine,
+    R: RngCore,
+{
+    let (r, r_inv) = loop {
+        let r = E::Fr::random(rng);
+        if let Some(r_inv) = r.inverse() {
+            break (r, r_inv);
+        }
+    };
+    let s = E::Fr::random(rng);
+    let mut rs = r;
+    rs.mul_assign(&s);
+
+    let a = proof.a.mul(r_inv.into_repr());
+
+    let mut b = proof.b.mul(r.into_repr());
+    b.add_assign(&vk.delta_g2.mul(rs.into_repr()));
+
+    let mut c = proof.a.mul(s.into_repr());
+    c.add_assign_mixed(&proof.c);
+
+    Proof {
+        a: a.into_affine(),
+        b: b.into_affine(),
+        c: c.into_affine(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::groth16::witness::tests::Square;
+    use crate::groth16::{
+        create_random_proof, generate_parameters_from_seed, prepare_verifying_key, verify_proof,
+    };
+    use paired::bls12_381::Fr;
+
+    #[test]
+    fn test_rerandomize_proof() {
+        let rng = &mut rand::thread_rng();
+        let params = generate_parameters_from_seed(Square(None), b"rerandomize").unwrap();
+        let pvk = prepare_verifying_key(&params.vk);
+        let three = Fr::from_str("3").unwrap();
+        let nine = Fr::from_str("9").unwrap();
+
+        let proof = create_random_proof(Square(Some(three)), &params, rng).unwrap();
+        let rerandomized = rerandomize_proof(&params.vk, &proof, rng);
+        assert!(rerandomized != proof);
+        assert!(rerandomized.a != proof.a && rerandomized.c != proof.c);
+        assert!(verify_proof(&pvk, &rerandomized, &[nine]).unwrap());
+        assert!(!verify_proof(&pvk, &rerandomized, &[three]).unwrap());
+
+        let twice = rerandomize_proof(&params.vk, &rerandomized, rng);
+        assert!(verify_proof(&pvk, &twice, &[nine]).unwrap());
+    }
+}
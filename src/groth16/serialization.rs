@@ -0,0 +1,176 @@
+//! Serde support for the proofs and verifying keys, behind the `serde` feature.
+//!
+//! The types are serialized as their byte encodings (`Proof::write`, `VerifyingKey::write` and
+//! `AggregateVerifyingKey::write`): as a hex string for human readable formats such as JSON, and
+//! as bytes for the binary ones. `PreparedVerifyingKey` and `AggregateProof` hold elements of the
+//! target group, which have no encoding, so they aren't serializable; the prepared key is built
+//! from a deserialized `VerifyingKey` instead.
+
+use paired::Engine;
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use serde::ser::{self, Serialize, Serializer};
+
+use std::fmt;
+use std::io;
+
+use super::aggregate::AggregateVerifyingKey;
+use super::{Proof, VerifyingKey};
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Result<Vec<u8>, String> {
+    let hex = hex.trim_start_matches("0x");
+    if hex.len() % 2 != 0 {
+        return Err("odd number of hex digits".to_string());
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .filter(|byte| byte.chars().all(|c| c.is_ascii_hexdigit()))
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(|| format!("invalid hex digits at {}", i))
+        })
+        .collect()
+}
+
+fn serialize_encoded<S, F>(serializer: S, write: F) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    F: FnOnce(&mut Vec<u8>) -> io::Result<()>,
+{
+    let mut bytes = vec![];
+    write(&mut bytes).map_err(ser::Error::custom)?;
+    if serializer.is_human_readable() {
+        serializer.serialize_str(&to_hex(&bytes))
+    } else {
+        serializer.serialize_bytes(&bytes)
+    }
+}
+
+struct BytesVisitor;
+
+impl<'de> Visitor<'de> for BytesVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("bytes or a hex string")
+    }
+
+    fn visit_str<E: de::Error>(self, hex: &str) -> Result<Vec<u8>, E> {
+        from_hex(hex).map_err(E::custom)
+    }
+
+    fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Vec<u8>, E> {
+        Ok(bytes.to_vec())
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, bytes: Vec<u8>) -> Result<Vec<u8>, E> {
+        Ok(bytes)
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        Ok(bytes)
+    }
+}
+
+fn deserialize_encoded<'de, D, T, F>(deserializer: D, read: F) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    F: FnOnce(&[u8]) -> io::Result<T>,
+{
+    let bytes = if deserializer.is_human_readable() {
+        deserializer.deserialize_str(BytesVisitor)?
+    } else {
+        deserializer.deserialize_byte_buf(BytesVisitor)?
+    };
+    read(&bytes).map_err(de::Error::custom)
+}
+
+impl<E: Engine> Serialize for Proof<E> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_encoded(serializer, |bytes| self.write(bytes))
+    }
+}
+
+impl<'de, E: Engine> Deserialize<'de> for Proof<E> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_encoded(deserializer, |bytes| Proof::read(bytes))
+    }
+}
+
+impl<E: Engine> Serialize for VerifyingKey<E> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_encoded(serializer, |bytes| self.write(bytes))
+    }
+}
+
+impl<'de, E: Engine> Deserialize<'de> for VerifyingKey<E> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_encoded(deserializer, |bytes| VerifyingKey::read(bytes))
+    }
+}
+
+impl<E: Engine> Serialize for AggregateVerifyingKey<E> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_encoded(serializer, |bytes| self.write(bytes))
+    }
+}
+
+impl<'de, E: Engine> Deserialize<'de> for AggregateVerifyingKey<E> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_encoded(deserializer, |bytes| AggregateVerifyingKey::read(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::groth16::aggregate::AggregateParameters;
+    use crate::groth16::tests::random_parameters;
+    use groupy::CurveProjective;
+    use paired::bls12_381::{Bls12, G1, G2};
+
+    #[test]
+    fn test_serde() {
+        let rng = &mut rand::thread_rng();
+        let proof = Proof::<Bls12> {
+            a: G1::random(rng).into_affine(),
+            b: G2::random(rng).into_affine(),
+            c: G1::random(rng).into_affine(),
+        };
+        let vk = random_parameters().vk;
+
+        let json = serde_json::to_string(&proof).unwrap();
+        let mut encoded = vec![];
+        proof.write(&mut encoded).unwrap();
+        assert_eq!(json, format!("\"{}\"", to_hex(&encoded)));
+        assert!(serde_json::from_str::<Proof<Bls12>>(&json).unwrap() == proof);
+        let binary = bincode::serialize(&proof).unwrap();
+        assert!(bincode::deserialize::<Proof<Bls12>>(&binary).unwrap() == proof);
+
+        let json = serde_json::to_string(&vk).unwrap();
+        assert!(serde_json::from_str::<VerifyingKey<Bls12>>(&json).unwrap() == vk);
+        let binary = bincode::serialize(&vk).unwrap();
+        assert!(bincode::deserialize::<VerifyingKey<Bls12>>(&binary).unwrap() == vk);
+        assert!(serde_json::from_str::<VerifyingKey<Bls12>>("\"00ff\"").is_err());
+        assert!(serde_json::from_str::<VerifyingKey<Bls12>>("\"0g\"").is_err());
+
+        let agg_vk = AggregateParameters::<Bls12>::generate(2, rng).vk;
+        let json = serde_json::to_string(&agg_vk).unwrap();
+        let read = serde_json::from_str::<AggregateVerifyingKey<Bls12>>(&json).unwrap();
+        assert_eq!((read.g, read.h_beta), (agg_vk.g, agg_vk.h_beta));
+        let binary = bincode::serialize(&agg_vk).unwrap();
+        let read = bincode::deserialize::<AggregateVerifyingKey<Bls12>>(&binary).unwrap();
+        assert_eq!(
+            (read.g_alpha, read.h_alpha),
+            (agg_vk.g_alpha, agg_vk.h_alpha)
+        );
+    }
+}
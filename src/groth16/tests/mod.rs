@@ -7,10 +7,49 @@ use self::dummy_engine::*;
 use std::marker::PhantomData;
 
 use super::{
-    create_proof, create_proof_batch, generate_parameters, prepare_verifying_key, verify_proof,
+    create_proof, create_proof_batch, create_proof_deterministic, create_proof_with_stats,
+    create_random_proof, create_random_proof_async, create_random_proof_cancellable,
+    generate_parameters, generate_parameters_from_seed, prepare_batch_verifying_key,
+    prepare_parameters, prepare_verifying_key, prove_many, rng_from_seed, verify_proof,
+    verify_proofs_batch, verify_proofs_batch_on, BatchProver, Parameters, Placement,
+    PreparedInputs, ProverConfig, ProvingStage, VerifyingKey,
 };
+use crate::cancellation::CancellationToken;
+use crate::gpu::{LockedMultiexpKernel, Priority};
 use crate::{Circuit, ConstraintSystem, SynthesisError};
 
+// Parameters of random points, which can't prove anything
+pub(crate) fn random_parameters() -> Parameters<paired::bls12_381::Bls12> {
+    use groupy::CurveProjective;
+    use paired::bls12_381::{Bls12, G1, G2};
+    use std::sync::Arc;
+
+    let rng = &mut rand::thread_rng();
+    let g1s = |n: usize, rng: &mut rand::rngs::ThreadRng| {
+        (0..n)
+            .map(|_| G1::random(rng).into_affine())
+            .collect::<Vec<_>>()
+    };
+    let g2 = G2::random(rng).into_affine();
+
+    Parameters::<Bls12> {
+        vk: VerifyingKey {
+            alpha_g1: G1::random(rng).into_affine(),
+            beta_g1: G1::random(rng).into_affine(),
+            beta_g2: g2,
+            gamma_g2: g2,
+            delta_g1: G1::random(rng).into_affine(),
+            delta_g2: g2,
+            ic: g1s(2, rng),
+        },
+        h: Arc::new(g1s(16, rng)),
+        l: Arc::new(g1s(8, rng)),
+        a: Arc::new(g1s(10, rng)),
+        b_g1: Arc::new(g1s(10, rng)),
+        b_g2: Arc::new((0..10).map(|_| G2::random(rng).into_affine()).collect()),
+    }
+}
+
 #[derive(Clone)]
 struct XORDemo<E: Engine> {
     a: Option<bool>,
@@ -436,3 +475,608 @@ fn test_create_batch_single() {
         assert!(verify_proof(&pvk, &proof, &[Fr::one()]).unwrap());
     }
 }
+
+#[test]
+fn test_parameters_from_seed() {
+    use paired::bls12_381::{Bls12, Fr as BlsFr};
+
+    let circuit = XORDemo::<Bls12> {
+        a: None,
+        b: None,
+        _marker: PhantomData,
+    };
+    let params = generate_parameters_from_seed(circuit.clone(), b"seed").unwrap();
+    assert!(generate_parameters_from_seed(circuit.clone(), b"seed").unwrap() == params);
+    assert!(generate_parameters_from_seed(circuit, b"other seed").unwrap() != params);
+
+    let circuit = XORDemo::<Bls12> {
+        a: Some(true),
+        b: Some(false),
+        _marker: PhantomData,
+    };
+    let prove = || {
+        let rng = &mut rng_from_seed(b"proof", b"seed");
+        create_random_proof(circuit.clone(), &params, rng).unwrap()
+    };
+    let proof = prove();
+    assert!(prove() == proof);
+
+    let pvk = prepare_verifying_key(&params.vk);
+    assert!(verify_proof(&pvk, &proof, &[BlsFr::one()]).unwrap());
+}
+
+#[test]
+fn test_create_proof_without_aux() {
+    use paired::bls12_381::{Bls12, Fr as BlsFr};
+
+    // Proves that the public input `x` is its own square, with no auxiliary variable, so that
+    // the queries over the auxiliary assignment are empty
+    struct Idempotent(Option<BlsFr>);
+
+    impl Circuit<Bls12> for Idempotent {
+        fn synthesize<CS: ConstraintSystem<Bls12>>(
+            self,
+            cs: &mut CS,
+        ) -> Result<(), SynthesisError> {
+            let x = cs.alloc_input(|| "x", || self.0.ok_or(SynthesisError::AssignmentMissing))?;
+            cs.enforce(|| "x * x = x", |lc| lc + x, |lc| lc + x, |lc| lc + x);
+
+            Ok(())
+        }
+    }
+
+    let params = generate_parameters_from_seed(Idempotent(None), b"no aux").unwrap();
+    let pvk = prepare_verifying_key(&params.vk);
+    let rng = &mut rng_from_seed(b"proofs", b"no aux");
+
+    let proof = create_random_proof(Idempotent(Some(BlsFr::one())), &params, rng).unwrap();
+    assert!(verify_proof(&pvk, &proof, &[BlsFr::one()]).unwrap());
+    assert!(!verify_proof(&pvk, &proof, &[BlsFr::zero()]).unwrap());
+}
+
+#[test]
+fn test_verify_proofs_batch() {
+    use paired::bls12_381::{Bls12, Fr as BlsFr};
+
+    let params = generate_parameters_from_seed(
+        XORDemo::<Bls12> {
+            a: None,
+            b: None,
+            _marker: PhantomData,
+        },
+        b"batch",
+    )
+    .unwrap();
+    let pvk = prepare_batch_verifying_key(&params.vk);
+    let rng = &mut rng_from_seed(b"proofs", b"batch");
+
+    let mut proofs = vec![];
+    let mut inputs = vec![];
+    for &(a, b) in [(false, false), (true, false), (false, true), (true, true)].iter() {
+        let circuit = XORDemo::<Bls12> {
+            a: Some(a),
+            b: Some(b),
+            _marker: PhantomData,
+        };
+        proofs.push(create_random_proof(circuit, &params, rng).unwrap());
+        inputs.push(vec![if a ^ b { BlsFr::one() } else { BlsFr::zero() }]);
+    }
+    let proofs = proofs.iter().collect::<Vec<_>>();
+
+    assert!(verify_proofs_batch(&pvk, rng, &proofs, &inputs).unwrap());
+    assert!(verify_proofs_batch(&pvk, rng, &[], &[]).unwrap());
+
+    // A single wrong public input fails the whole batch
+    let mut bad_inputs = inputs.clone();
+    bad_inputs[2][0] = BlsFr::zero();
+    assert!(!verify_proofs_batch(&pvk, rng, &proofs, &bad_inputs).unwrap());
+
+    // A proof with C at infinity is invalid, not an error
+    let mut bad_proof = proofs[1].clone();
+    bad_proof.c = groupy::CurveAffine::zero();
+    let mut bad_proofs = proofs.clone();
+    bad_proofs[1] = &bad_proof;
+    assert!(!verify_proofs_batch(&pvk, rng, &bad_proofs, &inputs).unwrap());
+
+    // Also when every C is at infinity, and the multiexps are given a kernel
+    let bad_proofs = proofs
+        .iter()
+        .map(|&proof| {
+            let mut proof = proof.clone();
+            proof.c = groupy::CurveAffine::zero();
+            proof
+        })
+        .collect::<Vec<_>>();
+    let bad_proofs = bad_proofs.iter().collect::<Vec<_>>();
+    let mut kern = Some(LockedMultiexpKernel::new(2, Priority::Low));
+    assert!(!verify_proofs_batch_on(&pvk, rng, &bad_proofs, &inputs, &mut kern).unwrap());
+
+    match verify_proofs_batch(&pvk, rng, &proofs, &inputs[1..]) {
+        Err(SynthesisError::MalformedProofs) => (),
+        _ => panic!("mismatched proofs and inputs should be rejected"),
+    }
+}
+
+#[test]
+fn test_aggregate_proofs() {
+    use super::aggregate::{
+        aggregate_proofs, verify_aggregate, AggregateParameters, AggregationError,
+    };
+    use paired::bls12_381::{Bls12, Fr as BlsFr};
+
+    let params = generate_parameters_from_seed(
+        XORDemo::<Bls12> {
+            a: None,
+            b: None,
+            _marker: PhantomData,
+        },
+        b"aggregate",
+    )
+    .unwrap();
+    let pvk = prepare_verifying_key(&params.vk);
+    let rng = &mut rng_from_seed(b"proofs", b"aggregate");
+    let agg_params = AggregateParameters::<Bls12>::generate(8, rng);
+
+    let mut proofs = vec![];
+    let mut inputs = vec![];
+    for i in 0..8 {
+        let (a, b) = (i & 1 == 1, i & 2 == 2);
+        let circuit = XORDemo::<Bls12> {
+            a: Some(a),
+            b: Some(b),
+            _marker: PhantomData,
+        };
+        proofs.push(create_random_proof(circuit, &params, rng).unwrap());
+        inputs.push(vec![if a ^ b { BlsFr::one() } else { BlsFr::zero() }]);
+    }
+
+    for &n in [1, 2, 8].iter() {
+        let aggregate = aggregate_proofs(&agg_params, &proofs[..n], &inputs[..n]).unwrap();
+        assert_eq!(aggregate.rounds.len(), n.trailing_zeros() as usize);
+        assert!(verify_aggregate(&agg_params.vk, &pvk, &inputs[..n], &aggregate).unwrap());
+    }
+
+    let aggregate = aggregate_proofs(&agg_params, &proofs, &inputs).unwrap();
+    let mut bad_inputs = inputs.clone();
+    bad_inputs[5][0] = BlsFr::zero();
+    assert!(!verify_aggregate(&agg_params.vk, &pvk, &bad_inputs, &aggregate).unwrap());
+    assert!(!verify_aggregate(&agg_params.vk, &pvk, &inputs[..4], &aggregate).unwrap());
+
+    // A proof which doesn't verify can't be hidden in the aggregate
+    let mut bad_proofs = proofs.clone();
+    bad_proofs[3].c = bad_proofs[2].c;
+    let aggregate = aggregate_proofs(&agg_params, &bad_proofs, &inputs).unwrap();
+    assert!(!verify_aggregate(&agg_params.vk, &pvk, &inputs, &aggregate).unwrap());
+
+    match aggregate_proofs(&agg_params, &proofs[..3], &inputs[..3]) {
+        Err(AggregationError::InvalidProofCount(3)) => (),
+        _ => panic!("3 proofs can't be aggregated"),
+    }
+    proofs.extend(proofs.clone());
+    inputs.extend(inputs.clone());
+    match aggregate_proofs(&agg_params, &proofs, &inputs) {
+        Err(AggregationError::ParametersTooSmall(8)) => (),
+        _ => panic!("the parameters are too small for 16 proofs"),
+    }
+}
+
+#[test]
+fn test_gt_encoding() {
+    use super::aggregate::GtEncoding;
+    use paired::bls12_381::{Bls12, Fq12};
+
+    // One is the constant coefficient of the tower, followed by eleven zero coefficients
+    let mut bytes = Vec::new();
+    Bls12::write_gt(&Fq12::one(), &mut bytes);
+    assert_eq!(bytes.len(), 12 * 48);
+    assert_eq!(bytes[47], 1);
+    assert_eq!(bytes.iter().filter(|&&byte| byte != 0).count(), 1);
+
+    let rng = &mut rand::thread_rng();
+    let f = Fq12::random(rng);
+    let mut again = Vec::new();
+    Bls12::write_gt(&f, &mut bytes);
+    Bls12::write_gt(&f, &mut again);
+    assert_eq!(&bytes[12 * 48..], &again[..]);
+}
+
+#[test]
+fn test_prepared_parameters() {
+    use paired::bls12_381::{Bls12, Fr as BlsFr};
+
+    let params = generate_parameters_from_seed(
+        XORDemo::<Bls12> {
+            a: None,
+            b: None,
+            _marker: PhantomData,
+        },
+        b"prepared",
+    )
+    .unwrap();
+    let prepared = prepare_parameters(params.clone());
+    let pvk = prepare_verifying_key(&params.vk);
+
+    let rng = &mut rng_from_seed(b"proofs", b"prepared");
+    for &(a, b) in [(false, true), (true, true)].iter() {
+        let circuit = XORDemo::<Bls12> {
+            a: Some(a),
+            b: Some(b),
+            _marker: PhantomData,
+        };
+        let (r, s) = (BlsFr::random(rng), BlsFr::random(rng));
+        let proof = create_proof(circuit.clone(), &prepared, r, s).unwrap();
+        assert!(proof == create_proof(circuit, &params, r, s).unwrap());
+
+        let input = if a ^ b { BlsFr::one() } else { BlsFr::zero() };
+        assert!(verify_proof(&pvk, &proof, &[input]).unwrap());
+    }
+}
+
+#[test]
+fn test_create_proof_with_stats() {
+    use paired::bls12_381::{Bls12, Fr as BlsFr};
+
+    let params = generate_parameters_from_seed(
+        XORDemo::<Bls12> {
+            a: None,
+            b: None,
+            _marker: PhantomData,
+        },
+        b"stats",
+    )
+    .unwrap();
+    let circuit = XORDemo::<Bls12> {
+        a: Some(true),
+        b: Some(false),
+        _marker: PhantomData,
+    };
+    let rng = &mut rng_from_seed(b"proofs", b"stats");
+    let (r, s) = (BlsFr::random(rng), BlsFr::random(rng));
+
+    let config = ProverConfig {
+        use_gpu: false,
+        ..ProverConfig::default()
+    };
+    let (proof, stats) = create_proof_with_stats(circuit.clone(), &params, r, s, &config).unwrap();
+    assert!(proof == create_proof(circuit, &params, r, s).unwrap());
+
+    assert_eq!(stats.ffts.len(), 1);
+    let queries = stats.multiexps.iter().map(|m| m.query).collect::<Vec<_>>();
+    assert_eq!(
+        queries,
+        [
+            "a_inputs",
+            "a_aux",
+            "b_g1_inputs",
+            "b_g1_aux",
+            "b_g2_inputs",
+            "b_g2_aux",
+            "h",
+            "l"
+        ]
+    );
+    assert!(stats
+        .multiexps
+        .iter()
+        .all(|m| m.placement == Placement::Cpu));
+    assert!(stats.synthesis + stats.ffts[0] <= stats.total);
+    assert!(stats.peak_memory > 0);
+}
+
+#[test]
+fn test_create_random_proof_cancellable() {
+    use paired::bls12_381::{Bls12, Fr as BlsFr};
+
+    // Cancels its token while being synthesized
+    struct Cancelling(XORDemo<Bls12>, CancellationToken);
+
+    impl Circuit<Bls12> for Cancelling {
+        fn synthesize<CS: ConstraintSystem<Bls12>>(
+            self,
+            cs: &mut CS,
+        ) -> Result<(), SynthesisError> {
+            self.1.cancel();
+            self.0.synthesize(cs)
+        }
+    }
+
+    let params = generate_parameters_from_seed(
+        XORDemo::<Bls12> {
+            a: None,
+            b: None,
+            _marker: PhantomData,
+        },
+        b"cancellation",
+    )
+    .unwrap();
+    let pvk = prepare_verifying_key(&params.vk);
+    let circuit = XORDemo::<Bls12> {
+        a: Some(true),
+        b: Some(true),
+        _marker: PhantomData,
+    };
+    let rng = &mut rng_from_seed(b"proofs", b"cancellation");
+
+    let token = CancellationToken::new();
+    let proof = create_random_proof_cancellable(circuit.clone(), &params, rng, &token).unwrap();
+    assert!(verify_proof(&pvk, &proof, &[BlsFr::zero()]).unwrap());
+
+    let res = create_random_proof_cancellable(
+        Cancelling(circuit.clone(), token.clone()),
+        &params,
+        rng,
+        &token,
+    );
+    match res {
+        Err(SynthesisError::Cancelled) => (),
+        _ => panic!("the proof should be cancelled"),
+    }
+
+    // The token stays cancelled
+    let res = create_random_proof_cancellable(circuit, &params, rng, &token);
+    match res {
+        Err(SynthesisError::Cancelled) => (),
+        _ => panic!("the proof should be cancelled"),
+    }
+}
+
+#[test]
+fn test_create_random_proof_async() {
+    use futures::{Future, Stream};
+    use paired::bls12_381::{Bls12, Fr as BlsFr};
+    use std::sync::Arc;
+
+    let params = Arc::new(
+        generate_parameters_from_seed(
+            XORDemo::<Bls12> {
+                a: None,
+                b: None,
+                _marker: PhantomData,
+            },
+            b"async",
+        )
+        .unwrap(),
+    );
+    let pvk = prepare_verifying_key(&params.vk);
+    let circuit = XORDemo::<Bls12> {
+        a: Some(true),
+        b: Some(false),
+        _marker: PhantomData,
+    };
+    let rng = &mut rng_from_seed(b"proofs", b"async");
+
+    let config = ProverConfig {
+        use_gpu: false,
+        ..ProverConfig::default()
+    };
+    let proof = create_random_proof_async(circuit, params.clone(), rng, config);
+    assert!(verify_proof(&pvk, &proof.proof.wait().unwrap(), &[BlsFr::one()]).unwrap());
+    let progress = proof.progress.collect().wait().unwrap();
+    let stages = progress.iter().map(|&(stage, _)| stage).collect::<Vec<_>>();
+    let mut expected = vec![ProvingStage::Synthesis, ProvingStage::Fft];
+    expected.extend(vec![ProvingStage::Multiexp; 8]);
+    assert_eq!(stages, expected);
+    let fractions = progress.iter().map(|&(_, f)| f).collect::<Vec<_>>();
+    let mut expected = vec![1.0, 1.0];
+    expected.extend((1..=8).map(|i| i as f64 / 8.0));
+    assert_eq!(fractions, expected);
+}
+
+#[test]
+fn test_prove_many() {
+    use paired::bls12_381::{Bls12, Fr as BlsFr};
+
+    let params = generate_parameters_from_seed(
+        XORDemo::<Bls12> {
+            a: None,
+            b: None,
+            _marker: PhantomData,
+        },
+        b"prove many",
+    )
+    .unwrap();
+    let pvk = prepare_verifying_key(&params.vk);
+    let circuit = |a: Option<bool>, b: Option<bool>| XORDemo::<Bls12> {
+        a,
+        b,
+        _marker: PhantomData,
+    };
+    let circuits = vec![
+        circuit(Some(true), Some(false)),
+        // Its synthesis fails, which doesn't fail the other proofs
+        circuit(None, None),
+        circuit(Some(false), Some(true)),
+    ];
+    let rng = &mut rng_from_seed(b"proofs", b"prove many");
+
+    let config = ProverConfig {
+        use_gpu: false,
+        ..ProverConfig::default()
+    };
+    let proofs = prove_many(circuits, &params, rng, config).unwrap();
+    assert_eq!(proofs.size_hint(), (3, Some(3)));
+    let proofs = proofs.collect::<Vec<_>>();
+    assert_eq!(proofs.len(), 3);
+    assert!(verify_proof(&pvk, proofs[0].as_ref().unwrap(), &[BlsFr::one()]).unwrap());
+    match proofs[1] {
+        Err(SynthesisError::AssignmentMissing) => (),
+        _ => panic!("the synthesis should fail"),
+    }
+    assert!(verify_proof(&pvk, proofs[2].as_ref().unwrap(), &[BlsFr::one()]).unwrap());
+}
+
+#[test]
+fn test_batch_prover() {
+    use paired::bls12_381::{Bls12, Fr as BlsFr};
+
+    let params = generate_parameters_from_seed(
+        XORDemo::<Bls12> {
+            a: None,
+            b: None,
+            _marker: PhantomData,
+        },
+        b"batch prover",
+    )
+    .unwrap();
+    let pvk = prepare_verifying_key(&params.vk);
+    let rng = &mut rng_from_seed(b"proofs", b"batch prover");
+
+    let config = ProverConfig {
+        use_gpu: false,
+        cpu_threads: Some(2),
+        ..ProverConfig::default()
+    };
+    let prover = BatchProver::new(&params, config).unwrap();
+    for &(a, b, c) in &[(true, false, true), (false, false, false)] {
+        let circuits = (0..3)
+            .map(|_| XORDemo::<Bls12> {
+                a: Some(a),
+                b: Some(b),
+                _marker: PhantomData,
+            })
+            .collect::<Vec<_>>();
+        let (proofs, stats) = prover.prove_with_stats(circuits, rng).unwrap();
+        assert_eq!(proofs.len(), 3);
+        assert_eq!(stats.ffts.len(), 3);
+        let input = if c { BlsFr::one() } else { BlsFr::zero() };
+        for proof in &proofs {
+            assert!(verify_proof(&pvk, proof, &[input]).unwrap());
+        }
+    }
+}
+
+#[test]
+fn test_create_proof_deterministic() {
+    use paired::bls12_381::{Bls12, Fr as BlsFr};
+
+    let params = generate_parameters_from_seed(
+        XORDemo::<Bls12> {
+            a: None,
+            b: None,
+            _marker: PhantomData,
+        },
+        b"deterministic",
+    )
+    .unwrap();
+    let pvk = prepare_verifying_key(&params.vk);
+    let prove = |a: bool, b: bool, key: &[u8], nonce: &[u8]| {
+        let circuit = XORDemo::<Bls12> {
+            a: Some(a),
+            b: Some(b),
+            _marker: PhantomData,
+        };
+        create_proof_deterministic(circuit, &params, key, nonce).unwrap()
+    };
+
+    let proof = prove(true, false, b"key", b"nonce");
+    assert!(verify_proof(&pvk, &proof, &[BlsFr::one()]).unwrap());
+    assert!(prove(true, false, b"key", b"nonce") == proof);
+    // The same statement with another witness, key or nonce
+    assert!(prove(false, true, b"key", b"nonce") != proof);
+    assert!(prove(true, false, b"other key", b"nonce") != proof);
+    assert!(prove(true, false, b"key", b"other nonce") != proof);
+    // The lengths are hashed with the key and the nonce
+    assert!(prove(true, false, b"keyno", b"nce") != proof);
+}
+
+#[test]
+fn test_prepared_inputs() {
+    use paired::bls12_381::{Bls12, Fr as BlsFr};
+
+    // Proves that `a + b + c = d` for the public inputs `a`, `b`, `c` and `d`
+    struct Sum(Option<[BlsFr; 3]>);
+
+    impl Circuit<Bls12> for Sum {
+        fn synthesize<CS: ConstraintSystem<Bls12>>(
+            self,
+            cs: &mut CS,
+        ) -> Result<(), SynthesisError> {
+            let terms = self.0;
+            let mut lc = crate::LinearCombination::zero();
+            for i in 0..3 {
+                let term = cs.alloc_input(
+                    || format!("term {}", i),
+                    || terms.map(|t| t[i]).ok_or(SynthesisError::AssignmentMissing),
+                )?;
+                lc = lc + term;
+            }
+            let sum = cs.alloc_input(
+                || "sum",
+                || {
+                    let terms = terms.ok_or(SynthesisError::AssignmentMissing)?;
+                    let mut sum = terms[0];
+                    sum.add_assign(&terms[1]);
+                    sum.add_assign(&terms[2]);
+                    Ok(sum)
+                },
+            )?;
+            cs.enforce(|| "sum", |_| lc, |lc| lc + CS::one(), |lc| lc + sum);
+
+            Ok(())
+        }
+    }
+
+    let params = generate_parameters_from_seed(Sum(None), b"prepared inputs").unwrap();
+    let pvk = prepare_verifying_key(&params.vk);
+    let rng = &mut rng_from_seed(b"proofs", b"prepared inputs");
+    let fr = |n: &str| BlsFr::from_str(n).unwrap();
+
+    let prefix = PreparedInputs::new(&pvk, &[fr("1"), fr("2")]).unwrap();
+    assert_eq!(prefix.prefix_len(), 2);
+    for &(c, d) in &[("3", "6"), ("10", "13")] {
+        let proof =
+            create_random_proof(Sum(Some([fr("1"), fr("2"), fr(c)])), &params, rng).unwrap();
+        assert!(prefix.verify(&proof, &[fr(c), fr(d)]).unwrap());
+        assert!(!prefix.verify(&proof, &[fr(d), fr(c)]).unwrap());
+        assert!(verify_proof(&pvk, &proof, &[fr("1"), fr("2"), fr(c), fr(d)]).unwrap());
+        assert!(prefix.verify(&proof, &[fr(c)]).is_err());
+    }
+
+    // Another prefix, and a prefix of all of the inputs
+    let proof = create_random_proof(Sum(Some([fr("1"), fr("2"), fr("3")])), &params, rng).unwrap();
+    let other = PreparedInputs::new(&pvk, &[fr("2"), fr("1")]).unwrap();
+    assert!(!other.verify(&proof, &[fr("3"), fr("6")]).unwrap());
+    let all = PreparedInputs::new(&pvk, &[fr("1"), fr("2"), fr("3"), fr("6")]).unwrap();
+    assert!(all.verify(&proof, &[]).unwrap());
+    assert!(PreparedInputs::new(&pvk, &[fr("1"); 5]).is_err());
+}
+
+#[test]
+fn test_constant_time_prover() {
+    use paired::bls12_381::{Bls12, Fr as BlsFr};
+
+    let params = generate_parameters_from_seed(
+        XORDemo::<Bls12> {
+            a: None,
+            b: None,
+            _marker: PhantomData,
+        },
+        b"constant time",
+    )
+    .unwrap();
+    let pvk = prepare_verifying_key(&params.vk);
+    let prepared = prepare_parameters(params.clone());
+    let circuit = || XORDemo::<Bls12> {
+        a: Some(false),
+        b: Some(true),
+        _marker: PhantomData,
+    };
+    let (r, s) = (BlsFr::from_str("5").unwrap(), BlsFr::from_str("7").unwrap());
+
+    let config = ProverConfig {
+        constant_time: true,
+        ..ProverConfig::default()
+    };
+    let (proof, stats) = create_proof_with_stats(circuit(), &params, r, s, &config).unwrap();
+    assert!(verify_proof(&pvk, &proof, &[BlsFr::one()]).unwrap());
+    assert_eq!(stats.multiexps.len(), 8);
+    assert!(stats
+        .multiexps
+        .iter()
+        .all(|multiexp| multiexp.placement == Placement::Cpu));
+    // The same proof as with the other multiexps, and the precomputed tables are ignored
+    assert!(proof == create_proof(circuit(), &params, r, s).unwrap());
+    let (precomputed_proof, _) =
+        create_proof_with_stats(circuit(), &prepared, r, s, &config).unwrap();
+    assert!(precomputed_proof == proof);
+}
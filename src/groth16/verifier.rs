@@ -1,12 +1,14 @@
 use ff::{Field, PrimeField};
 use futures::Future;
 use groupy::{CurveAffine, CurveProjective};
+use log::warn;
 use paired::{Engine, PairingCurveAffine};
 use rayon::prelude::*;
+use std::cmp;
 use std::sync::Arc;
 
 use super::{BatchPreparedVerifyingKey, PreparedVerifyingKey, Proof, VerifyingKey};
-use crate::gpu::LockedMultiexpKernel;
+use crate::gpu::{LockedMultiexpKernel, Priority};
 use crate::multicore::Worker;
 use crate::multiexp::{multiexp, FullDensity};
 use crate::SynthesisError;
@@ -51,6 +53,15 @@ pub fn verify_proof<'a, E: Engine>(
         acc.add_assign(&b.mul(i.into_repr()));
     }
 
+    Ok(verify_with_inputs(pvk, proof, acc))
+}
+
+// Checks `proof` against `acc`, the accumulation of its public inputs
+fn verify_with_inputs<E: Engine>(
+    pvk: &PreparedVerifyingKey<E>,
+    proof: &Proof<E>,
+    acc: E::G1,
+) -> bool {
     // The original verification equation is:
     // A * B = alpha * beta + inputs * gamma + C * delta
     // ... however, we rearrange it so that it is:
@@ -59,7 +70,7 @@ pub fn verify_proof<'a, E: Engine>(
     // A * B + inputs * (-gamma) + C * (-delta) = alpha * beta
     // which allows us to do a single final exponentiation.
 
-    Ok(E::final_exponentiation(&E::miller_loop(
+    E::final_exponentiation(&E::miller_loop(
         [
             (&proof.a.prepare(), &proof.b.prepare()),
             (&acc.into_affine().prepare(), &pvk.neg_gamma_g2),
@@ -68,10 +79,66 @@ pub fn verify_proof<'a, E: Engine>(
         .iter(),
     ))
     .unwrap()
-        == pvk.alpha_g1_beta_g2)
+        == pvk.alpha_g1_beta_g2
+}
+
+/// A prefix of the public inputs shared by many proofs (e.g. the identifier of a sector), with
+/// its part of the accumulation of the inputs computed once, so that verifying a proof only
+/// accumulates the remaining inputs.
+pub struct PreparedInputs<'a, E: Engine> {
+    pvk: &'a PreparedVerifyingKey<E>,
+    prefix_len: usize,
+    // The first element of the IC plus the prefix times the elements of the IC which follow it
+    acc: E::G1,
+}
+
+impl<'a, E: Engine> PreparedInputs<'a, E> {
+    /// Accumulates the first public inputs of the proofs of `pvk`, which fails if there are more
+    /// of them than the key has public inputs.
+    pub fn new(pvk: &'a PreparedVerifyingKey<E>, prefix: &[E::Fr]) -> Result<Self, SynthesisError> {
+        if (prefix.len() + 1) > pvk.ic.len() {
+            return Err(SynthesisError::MalformedVerifyingKey);
+        }
+
+        let mut acc = pvk.ic[0].into_projective();
+        for (i, b) in prefix.iter().zip(pvk.ic.iter().skip(1)) {
+            acc.add_assign(&b.mul(i.into_repr()));
+        }
+
+        Ok(PreparedInputs {
+            pvk,
+            prefix_len: prefix.len(),
+            acc,
+        })
+    }
+
+    pub fn prefix_len(&self) -> usize {
+        self.prefix_len
+    }
+
+    /// Same as `verify_proof` for the public inputs made of the prefix followed by `suffix`.
+    pub fn verify(&self, proof: &Proof<E>, suffix: &[E::Fr]) -> Result<bool, SynthesisError> {
+        if (self.prefix_len + suffix.len() + 1) != self.pvk.ic.len() {
+            return Err(SynthesisError::MalformedVerifyingKey);
+        }
+
+        let mut acc = self.acc;
+        for (i, b) in suffix
+            .iter()
+            .zip(self.pvk.ic.iter().skip(1 + self.prefix_len))
+        {
+            acc.add_assign(&b.mul(i.into_repr()));
+        }
+
+        Ok(verify_with_inputs(self.pvk, proof, acc))
+    }
 }
 
 /// Randomized batch verification - see Appendix B.2 in Zcash spec
+///
+/// The proofs are combined with random coefficients into a single multi-pairing, so the cost
+/// of verifying one more proof is two scalar multiplications and a Miller loop, shared by a
+/// single final exponentiation. `public_inputs[i]` are the public inputs of `proofs[i]`.
 pub fn verify_proofs_batch<'a, E: Engine, R: rand::RngCore>(
     pvk: &'a BatchPreparedVerifyingKey<E>,
     rng: &mut R,
@@ -81,12 +148,36 @@ pub fn verify_proofs_batch<'a, E: Engine, R: rand::RngCore>(
 where
     <<E as ff::ScalarEngine>::Fr as ff::PrimeField>::Repr: From<<E as ff::ScalarEngine>::Fr>,
 {
+    if proofs.len() != public_inputs.len() {
+        return Err(SynthesisError::MalformedProofs);
+    }
     for pub_input in public_inputs {
         if (pub_input.len() + 1) != pvk.ic.len() {
             return Err(SynthesisError::MalformedVerifyingKey);
         }
     }
+    if proofs.is_empty() {
+        return Ok(true);
+    }
 
+    // The public inputs and the C points of the proofs are combined with multiexps, which run
+    // on the same kernel
+    let mut multiexp_kern = get_verifier_kernel(cmp::max(pvk.ic.len() - 1, proofs.len()));
+    verify_proofs_batch_on(pvk, rng, proofs, public_inputs, &mut multiexp_kern)
+}
+
+/// Same as `verify_proofs_batch`, with the multiexps running on `multiexp_kern`, for a batch of
+/// proofs and public inputs which were already checked against `pvk`.
+pub(crate) fn verify_proofs_batch_on<'a, E: Engine, R: rand::RngCore>(
+    pvk: &'a BatchPreparedVerifyingKey<E>,
+    rng: &mut R,
+    proofs: &[&Proof<E>],
+    public_inputs: &[Vec<E::Fr>],
+    multiexp_kern: &mut Option<LockedMultiexpKernel<E>>,
+) -> Result<bool, SynthesisError>
+where
+    <<E as ff::ScalarEngine>::Fr as ff::PrimeField>::Repr: From<<E as ff::ScalarEngine>::Fr>,
+{
     let worker = Worker::new();
     let pi_num = pvk.ic.len() - 1;
     let proof_num = proofs.len();
@@ -126,8 +217,6 @@ where
         })
         .collect();
 
-    let mut multiexp_kern = get_verifier_kernel(pi_num);
-
     // create group element corresponding to public input combination
     // This roughly corresponds to Accum_Gamma in spec
     let mut acc_pi = pvk.ic[0].mul(sum_r.into_repr());
@@ -137,10 +226,9 @@ where
             (Arc::new(pvk.ic[1..].to_vec()), 0),
             FullDensity,
             Arc::new(pi_scalars),
-            &mut multiexp_kern,
+            multiexp_kern,
         )
-        .wait()
-        .unwrap(),
+        .wait()?,
     );
 
     // This corresponds to Accum_Y
@@ -149,13 +237,27 @@ where
     // This corresponds to Y^-Accum_Y
     let acc_y = pvk.alpha_g1_beta_g2.pow(&sum_r.into_repr());
 
-    // This corresponds to Accum_Delta
-    let mut acc_c = E::G1::zero();
-    for (rand_coeff, proof) in r.iter().zip(proofs.iter()) {
-        let mut tmp: E::G1 = proof.c.into();
-        tmp.mul_assign(*rand_coeff);
-        acc_c.add_assign(&tmp);
-    }
+    // This corresponds to Accum_Delta. The multiexp rejects the identity, which adds nothing to
+    // the sum, so the C points at infinity are left out, and a batch without any other C point
+    // accumulates the identity
+    let (cs, c_scalars): (Vec<_>, Vec<_>) = proofs
+        .iter()
+        .zip(r.iter())
+        .filter(|(proof, _)| !proof.c.is_zero())
+        .map(|(proof, rand_coeff)| (proof.c, rand_coeff.into_repr()))
+        .unzip();
+    let acc_c = if cs.is_empty() {
+        E::G1::zero()
+    } else {
+        multiexp(
+            &worker,
+            (Arc::new(cs), 0),
+            FullDensity,
+            Arc::new(c_scalars),
+            multiexp_kern,
+        )
+        .wait()?
+    };
 
     // This corresponds to Accum_AB
     let ml = r
@@ -189,16 +291,34 @@ where
     Ok(E::final_exponentiation(&res).unwrap() == acc_y)
 }
 
-fn get_verifier_kernel<E: Engine>(pi_num: usize) -> Option<LockedMultiexpKernel<E>> {
+// Batches with fewer multiexp bases are verified on the CPU in `auto` mode
+const DEFAULT_VERIFIER_GPU_THRESHOLD: usize = 1 << 12;
+
+fn verifier_gpu_threshold() -> usize {
+    match std::env::var("BELLMAN_VERIFIER_GPU_THRESHOLD") {
+        Ok(threshold) => threshold.parse().unwrap_or_else(|_| {
+            warn!(
+                "Invalid BELLMAN_VERIFIER_GPU_THRESHOLD! Defaulting to {}...",
+                DEFAULT_VERIFIER_GPU_THRESHOLD
+            );
+            DEFAULT_VERIFIER_GPU_THRESHOLD
+        }),
+        Err(_) => DEFAULT_VERIFIER_GPU_THRESHOLD,
+    }
+}
+
+fn get_verifier_kernel<E: Engine>(n: usize) -> Option<LockedMultiexpKernel<E>> {
+    let kernel = || {
+        let log_d = (n as f32).log2().ceil() as usize;
+        Some(LockedMultiexpKernel::<E>::new(log_d, Priority::Low))
+    };
     match &std::env::var("BELLMAN_VERIFIER")
         .unwrap_or("auto".to_string())
         .to_lowercase()[..]
     {
-        "gpu" => {
-            let log_d = (pi_num as f32).log2().ceil() as usize;
-            Some(LockedMultiexpKernel::<E>::new(log_d, false))
-        }
+        "gpu" => kernel(),
         "cpu" => None,
+        "auto" if cfg!(feature = "gpu") && n >= verifier_gpu_threshold() => kernel(),
         "auto" => None,
         s => panic!("Invalid verifier device selected: {}", s),
     }
@@ -2,9 +2,7 @@ use groupy::{CurveAffine, EncodedPoint};
 use paired::{Engine, PairingCurveAffine};
 
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
-use memmap::Mmap;
 use std::io::{self, Read, Write};
-use std::mem;
 
 #[derive(Clone)]
 pub struct VerifyingKey<E: Engine> {
@@ -129,85 +127,6 @@ impl<E: Engine> VerifyingKey<E> {
             ic,
         })
     }
-
-    pub fn read_mmap(mmap: &Mmap, offset: &mut usize) -> io::Result<Self> {
-        let u32_len = mem::size_of::<u32>();
-        let g1_len = mem::size_of::<<E::G1Affine as CurveAffine>::Uncompressed>();
-        let g2_len = mem::size_of::<<E::G2Affine as CurveAffine>::Uncompressed>();
-
-        let read_g1 = |mmap: &Mmap,
-                       offset: &mut usize|
-         -> Result<<E as paired::Engine>::G1Affine, std::io::Error> {
-            let ptr = &mmap[*offset..*offset + g1_len];
-            // Safety: this operation is safe, because it's simply
-            // casting to a known struct at the correct offset, given
-            // the structure of the on-disk data.
-            let g1_repr: <E::G1Affine as CurveAffine>::Uncompressed = unsafe {
-                *(ptr as *const [u8] as *const <E::G1Affine as CurveAffine>::Uncompressed)
-            };
-
-            *offset += g1_len;
-            g1_repr
-                .into_affine()
-                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
-        };
-
-        let read_g2 = |mmap: &Mmap,
-                       offset: &mut usize|
-         -> Result<<E as paired::Engine>::G2Affine, std::io::Error> {
-            let ptr = &mmap[*offset..*offset + g2_len];
-            // Safety: this operation is safe, because it's simply
-            // casting to a known struct at the correct offset, given
-            // the structure of the on-disk data.
-            let g2_repr: <E::G2Affine as CurveAffine>::Uncompressed = unsafe {
-                *(ptr as *const [u8] as *const <E::G2Affine as CurveAffine>::Uncompressed)
-            };
-
-            *offset += g2_len;
-            g2_repr
-                .into_affine()
-                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
-        };
-
-        let alpha_g1 = read_g1(&mmap, &mut *offset)?;
-        let beta_g1 = read_g1(&mmap, &mut *offset)?;
-        let beta_g2 = read_g2(&mmap, &mut *offset)?;
-        let gamma_g2 = read_g2(&mmap, &mut *offset)?;
-        let delta_g1 = read_g1(&mmap, &mut *offset)?;
-        let delta_g2 = read_g2(&mmap, &mut *offset)?;
-
-        let mut raw_ic_len = &mmap[*offset..*offset + u32_len];
-        let ic_len = raw_ic_len.read_u32::<BigEndian>()? as usize;
-        *offset += u32_len;
-
-        let mut ic = vec![];
-
-        for _ in 0..ic_len {
-            let g1_repr = read_g1(&mmap, &mut *offset);
-            let g1 = g1_repr.and_then(|e| {
-                if e.is_zero() {
-                    Err(io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        "point at infinity",
-                    ))
-                } else {
-                    Ok(e)
-                }
-            })?;
-
-            ic.push(g1);
-        }
-
-        Ok(VerifyingKey {
-            alpha_g1,
-            beta_g1,
-            beta_g2,
-            gamma_g2,
-            delta_g1,
-            delta_g2,
-            ic,
-        })
-    }
 }
 
 pub struct PreparedVerifyingKey<E: Engine> {
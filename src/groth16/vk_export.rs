@@ -0,0 +1,227 @@
+//! Canonical encoding of the verifying key of a circuit.
+//!
+//! The encoding starts with a magic, a version and the identifier of the circuit (See
+//! `CircuitConstraints::circuit_id`), followed by the points of the key in their compressed
+//! encoding, so that a key can't silently be used with another circuit or another version of
+//! the format. `VerifyingKey::to_json` exports the key for the tools generating on-chain
+//! verifiers.
+
+use blake2b_simd::Params as Blake2bParams;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use groupy::{CurveAffine, EncodedPoint};
+use paired::Engine;
+
+use std::io::{self, Read, Write};
+
+use super::r1cs::write_r1cs;
+use super::{CircuitConstraints, VerifyingKey};
+
+/// Magic bytes at the start of a canonical verifying key.
+pub const VK_MAGIC: [u8; 8] = *b"BELLVKEY";
+/// Version of the verifying keys written by `VerifyingKey::write_canonical`.
+pub const VK_VERSION: u32 = 1;
+/// Length of the identifiers of the circuits.
+pub const CIRCUIT_ID_LEN: usize = 32;
+
+impl<E: Engine> CircuitConstraints<E> {
+    /// The BLAKE2b hash of the constraints in the `.r1cs` format, identifying the circuit.
+    pub fn circuit_id(&self) -> [u8; CIRCUIT_ID_LEN] {
+        let mut r1cs = vec![];
+        write_r1cs(self, &mut r1cs).expect("writing to a vector can't fail");
+
+        let mut id = [0u8; CIRCUIT_ID_LEN];
+        id.copy_from_slice(
+            Blake2bParams::new()
+                .hash_length(CIRCUIT_ID_LEN)
+                .hash(&r1cs)
+                .as_bytes(),
+        );
+        id
+    }
+}
+
+fn invalid<E: Into<Box<dyn std::error::Error + Send + Sync>>>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+// Reads a compressed point, which can't be the point at infinity
+pub(crate) fn read_point<G: CurveAffine, R: Read>(reader: &mut R) -> io::Result<G> {
+    let mut repr = G::Compressed::empty();
+    reader.read_exact(repr.as_mut())?;
+    let point = repr.into_affine().map_err(invalid)?;
+    if point.is_zero() {
+        return Err(invalid("point at infinity"));
+    }
+    Ok(point)
+}
+
+// The coordinates of a point as hex strings, in the order of its uncompressed encoding
+fn json_coordinates<G: CurveAffine>(point: &G, per_coordinate: usize) -> String {
+    let encoded = point.into_uncompressed();
+    let words = encoded
+        .as_ref()
+        .chunks(encoded.as_ref().len() / (2 * per_coordinate))
+        .map(|word| {
+            let hex = word
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<String>();
+            format!("\"0x{}\"", hex)
+        })
+        .collect::<Vec<_>>();
+    if per_coordinate == 1 {
+        return format!("[{}]", words.join(", "));
+    }
+    let coordinates = words
+        .chunks(per_coordinate)
+        .map(|coordinate| format!("[{}]", coordinate.join(", ")))
+        .collect::<Vec<_>>();
+    format!("[{}]", coordinates.join(", "))
+}
+
+impl<E: Engine> VerifyingKey<E> {
+    /// Writes the key in the canonical encoding, for the circuit identified by `circuit_id`.
+    pub fn write_canonical<W: Write>(
+        &self,
+        circuit_id: &[u8; CIRCUIT_ID_LEN],
+        mut writer: W,
+    ) -> io::Result<()> {
+        writer.write_all(&VK_MAGIC)?;
+        writer.write_u32::<BigEndian>(VK_VERSION)?;
+        writer.write_all(circuit_id)?;
+        writer.write_all(self.alpha_g1.into_compressed().as_ref())?;
+        writer.write_all(self.beta_g1.into_compressed().as_ref())?;
+        writer.write_all(self.beta_g2.into_compressed().as_ref())?;
+        writer.write_all(self.gamma_g2.into_compressed().as_ref())?;
+        writer.write_all(self.delta_g1.into_compressed().as_ref())?;
+        writer.write_all(self.delta_g2.into_compressed().as_ref())?;
+        writer.write_u32::<BigEndian>(self.ic.len() as u32)?;
+        for ic in &self.ic {
+            writer.write_all(ic.into_compressed().as_ref())?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads a key in the canonical encoding, returning it with the identifier of its circuit.
+    pub fn read_canonical<R: Read>(mut reader: R) -> io::Result<(Self, [u8; CIRCUIT_ID_LEN])> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if magic != VK_MAGIC {
+            return Err(invalid("not a canonical verifying key"));
+        }
+        let version = reader.read_u32::<BigEndian>()?;
+        if version != VK_VERSION {
+            return Err(invalid(format!(
+                "unsupported verifying key version {}",
+                version
+            )));
+        }
+        let mut circuit_id = [0u8; CIRCUIT_ID_LEN];
+        reader.read_exact(&mut circuit_id)?;
+
+        let alpha_g1 = read_point(&mut reader)?;
+        let beta_g1 = read_point(&mut reader)?;
+        let beta_g2 = read_point(&mut reader)?;
+        let gamma_g2 = read_point(&mut reader)?;
+        let delta_g1 = read_point(&mut reader)?;
+        let delta_g2 = read_point(&mut reader)?;
+        let ic_len = reader.read_u32::<BigEndian>()? as usize;
+        let ic = (0..ic_len)
+            .map(|_| read_point(&mut reader))
+            .collect::<io::Result<_>>()?;
+
+        let vk = VerifyingKey {
+            alpha_g1,
+            beta_g1,
+            beta_g2,
+            gamma_g2,
+            delta_g1,
+            delta_g2,
+            ic,
+        };
+        Ok((vk, circuit_id))
+    }
+
+    /// Reads a key in the canonical encoding, failing if it isn't the key of the circuit
+    /// identified by `circuit_id`.
+    pub fn read_canonical_for<R: Read>(
+        reader: R,
+        circuit_id: &[u8; CIRCUIT_ID_LEN],
+    ) -> io::Result<Self> {
+        let (vk, id) = Self::read_canonical(reader)?;
+        if &id != circuit_id {
+            return Err(invalid("the verifying key is for another circuit"));
+        }
+        Ok(vk)
+    }
+
+    /// The key as a JSON object, for the circuit identified by `circuit_id`. The coordinates
+    /// are hex strings in the order of the uncompressed encoding of the points, a point of G1
+    /// being `[x, y]` and a point of G2 `[[x_c1, x_c0], [y_c1, y_c0]]`.
+    pub fn to_json(&self, circuit_id: &[u8; CIRCUIT_ID_LEN]) -> String {
+        let g1 = |point: &E::G1Affine| json_coordinates(point, 1);
+        let g2 = |point: &E::G2Affine| json_coordinates(point, 2);
+        let ic = self.ic.iter().map(g1).collect::<Vec<_>>();
+        let circuit_id = circuit_id
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+
+        format!(
+            "{{\n  \"protocol\": \"groth16\",\n  \"version\": {},\n  \"circuit_id\": \"0x{}\",\n  \
+             \"alpha_g1\": {},\n  \"beta_g1\": {},\n  \"beta_g2\": {},\n  \"gamma_g2\": {},\n  \
+             \"delta_g1\": {},\n  \"delta_g2\": {},\n  \"ic\": [{}]\n}}\n",
+            VK_VERSION,
+            circuit_id,
+            g1(&self.alpha_g1),
+            g1(&self.beta_g1),
+            g2(&self.beta_g2),
+            g2(&self.gamma_g2),
+            g1(&self.delta_g1),
+            g2(&self.delta_g2),
+            ic.join(", ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::groth16::generate_parameters_from_seed;
+    use crate::groth16::witness::tests::Square;
+    use paired::bls12_381::Bls12;
+
+    #[test]
+    fn test_canonical_verifying_key() {
+        let params = generate_parameters_from_seed(Square(None), b"canonical vk").unwrap();
+        let circuit_id = CircuitConstraints::from_circuit(Square(None))
+            .unwrap()
+            .circuit_id();
+
+        let mut encoded = vec![];
+        params
+            .vk
+            .write_canonical(&circuit_id, &mut encoded)
+            .unwrap();
+        assert!(encoded.starts_with(&VK_MAGIC));
+        let (vk, id) = VerifyingKey::<Bls12>::read_canonical(&encoded[..]).unwrap();
+        assert!(vk == params.vk);
+        assert_eq!(id, circuit_id);
+        assert!(VerifyingKey::<Bls12>::read_canonical_for(&encoded[..], &circuit_id).is_ok());
+        assert!(VerifyingKey::<Bls12>::read_canonical_for(&encoded[..], &[0u8; 32]).is_err());
+
+        let mut legacy = vec![];
+        params.vk.write(&mut legacy).unwrap();
+        assert!(VerifyingKey::<Bls12>::read_canonical(&legacy[..]).is_err());
+        let mut future = encoded.clone();
+        future[11] += 1;
+        assert!(VerifyingKey::<Bls12>::read_canonical(&future[..]).is_err());
+
+        let json = params.vk.to_json(&circuit_id);
+        assert!(json.contains("\"protocol\": \"groth16\""));
+        // The public input `y` and the "one" input variable
+        assert_eq!(params.vk.ic.len(), 2);
+        assert_eq!(json.matches("\"0x").count(), 1 + 3 * 2 + 3 * 4 + 2 * 2);
+    }
+}
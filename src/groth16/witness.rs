@@ -0,0 +1,335 @@
+use paired::Engine;
+
+use super::{create_proof_from_constraints, ParameterSource, Proof};
+use crate::{Circuit, ConstraintSystem, Index, LinearCombination, SynthesisError, Variable};
+
+/// The constraints of a circuit, without any assignment, for proving from a witness computed
+/// outside of the circuit (See `create_proof_from_assignments`) or by `WitnessCS`. They don't
+/// depend on the witness, so they are captured once by `ShapeCS` and reused for all proofs.
+#[derive(Clone)]
+pub struct CircuitConstraints<E: Engine> {
+    // Not counting the "one" input variable
+    pub(crate) num_inputs: usize,
+    pub(crate) num_aux: usize,
+    pub(crate) constraints: Vec<(
+        LinearCombination<E>,
+        LinearCombination<E>,
+        LinearCombination<E>,
+    )>,
+}
+
+impl<E: Engine> CircuitConstraints<E> {
+    /// Records the constraints of `circuit`. The assignments of its variables are never
+    /// computed, so the circuit can be synthesized without a witness, as when generating the
+    /// parameters.
+    pub fn from_circuit<C: Circuit<E>>(circuit: C) -> Result<Self, SynthesisError> {
+        let mut cs = ShapeCS::new();
+        circuit.synthesize(&mut cs)?;
+        Ok(cs.into_constraints())
+    }
+
+    /// Number of public inputs, not counting the "one" input variable.
+    pub fn num_inputs(&self) -> usize {
+        self.num_inputs
+    }
+
+    pub fn num_aux(&self) -> usize {
+        self.num_aux
+    }
+
+    pub fn num_constraints(&self) -> usize {
+        self.constraints.len()
+    }
+
+    // Enforces the constraints in `cs`, in which the variables have been allocated
+    pub(crate) fn enforce<CS: ConstraintSystem<E>>(&self, cs: &mut CS) {
+        for (i, (a, b, c)) in self.constraints.iter().enumerate() {
+            cs.enforce(
+                || format!("constraint {}", i),
+                |lc| lc + a,
+                |lc| lc + b,
+                |lc| lc + c,
+            );
+        }
+    }
+}
+
+/// Constraint system capturing the constraints of a circuit, without computing the assignments
+/// of its variables, see `CircuitConstraints::from_circuit`.
+pub struct ShapeCS<E: Engine> {
+    constraints: CircuitConstraints<E>,
+}
+
+impl<E: Engine> ShapeCS<E> {
+    pub fn into_constraints(self) -> CircuitConstraints<E> {
+        self.constraints
+    }
+}
+
+impl<E: Engine> ConstraintSystem<E> for ShapeCS<E> {
+    type Root = Self;
+
+    fn new() -> Self {
+        ShapeCS {
+            constraints: CircuitConstraints {
+                num_inputs: 0,
+                num_aux: 0,
+                constraints: vec![],
+            },
+        }
+    }
+
+    fn alloc<F, A, AR>(&mut self, _: A, _: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<E::Fr, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        let index = self.constraints.num_aux;
+        self.constraints.num_aux += 1;
+
+        Ok(Variable(Index::Aux(index)))
+    }
+
+    fn alloc_input<F, A, AR>(&mut self, _: A, _: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<E::Fr, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        // Index 0 is the "one" input variable, allocated by the prover
+        self.constraints.num_inputs += 1;
+
+        Ok(Variable(Index::Input(self.constraints.num_inputs)))
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, _: A, a: LA, b: LB, c: LC)
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+        LA: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+        LB: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+        LC: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+    {
+        self.constraints.constraints.push((
+            a(LinearCombination::zero()),
+            b(LinearCombination::zero()),
+            c(LinearCombination::zero()),
+        ));
+    }
+
+    fn push_namespace<NR, N>(&mut self, _: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+    }
+
+    fn pop_namespace(&mut self) {}
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+}
+
+/// Constraint system only computing the assignments of the variables of a circuit, the
+/// constraints being ignored, see `create_proof_with_shape`. This skips building the linear
+/// combinations of the constraints, which is most of the synthesis of large circuits.
+pub struct WitnessCS<E: Engine> {
+    // Not counting the "one" input variable
+    input_assignment: Vec<E::Fr>,
+    aux_assignment: Vec<E::Fr>,
+}
+
+impl<E: Engine> WitnessCS<E> {
+    /// The public inputs (Not counting the "one" input variable) and the auxiliary assignments.
+    pub fn into_assignments(self) -> (Vec<E::Fr>, Vec<E::Fr>) {
+        (self.input_assignment, self.aux_assignment)
+    }
+}
+
+impl<E: Engine> ConstraintSystem<E> for WitnessCS<E> {
+    type Root = Self;
+
+    fn new() -> Self {
+        WitnessCS {
+            input_assignment: vec![],
+            aux_assignment: vec![],
+        }
+    }
+
+    fn alloc<F, A, AR>(&mut self, _: A, f: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<E::Fr, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.aux_assignment.push(f()?);
+
+        Ok(Variable(Index::Aux(self.aux_assignment.len() - 1)))
+    }
+
+    fn alloc_input<F, A, AR>(&mut self, _: A, f: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<E::Fr, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.input_assignment.push(f()?);
+
+        // Index 0 is the "one" input variable
+        Ok(Variable(Index::Input(self.input_assignment.len())))
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, _: A, _: LA, _: LB, _: LC)
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+        LA: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+        LB: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+        LC: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+    {
+    }
+
+    fn push_namespace<NR, N>(&mut self, _: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+    }
+
+    fn pop_namespace(&mut self) {}
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+}
+
+/// Creates a proof from a witness computed outside of the circuit (e.g. by an external witness
+/// generator), instead of synthesizing the circuit again. `input_assignment` holds the public
+/// inputs (Not counting the "one" input variable, as for `verify_proof`) and `aux_assignment`
+/// the auxiliary variables, in the order in which the circuit of `constraints` allocates them.
+///
+/// The linear combinations of the constraints are evaluated directly against the assignments,
+/// without going through a `ConstraintSystem`. The witness isn't checked against the
+/// constraints, an unsatisfied constraint gives a proof that doesn't verify.
+pub fn create_proof_from_assignments<E, P: ParameterSource<E>>(
+    constraints: &CircuitConstraints<E>,
+    params: P,
+    r: E::Fr,
+    s: E::Fr,
+    input_assignment: Vec<E::Fr>,
+    aux_assignment: Vec<E::Fr>,
+) -> Result<Proof<E>, SynthesisError>
+where
+    E: Engine,
+{
+    if input_assignment.len() != constraints.num_inputs
+        || aux_assignment.len() != constraints.num_aux
+    {
+        return Err(SynthesisError::AssignmentMissing);
+    }
+
+    create_proof_from_constraints(constraints, params, r, s, input_assignment, aux_assignment)
+}
+
+/// Creates a proof of `circuit`, using the constraints captured from it by `ShapeCS` (See
+/// `CircuitConstraints::from_circuit`): the circuit is only synthesized by `WitnessCS`, for its
+/// assignments, against which the captured constraints are evaluated.
+pub fn create_proof_with_shape<E, C, P: ParameterSource<E>>(
+    shape: &CircuitConstraints<E>,
+    circuit: C,
+    params: P,
+    r: E::Fr,
+    s: E::Fr,
+) -> Result<Proof<E>, SynthesisError>
+where
+    E: Engine,
+    C: Circuit<E>,
+{
+    let mut cs = WitnessCS::new();
+    circuit.synthesize(&mut cs)?;
+    let (input_assignment, aux_assignment) = cs.into_assignments();
+
+    create_proof_from_assignments(shape, params, r, s, input_assignment, aux_assignment)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::groth16::{
+        create_proof, generate_parameters_from_seed, prepare_verifying_key, verify_proof,
+    };
+    use ff::{Field, PrimeField};
+    use paired::bls12_381::{Bls12, Fr};
+
+    // Proves that `x * x = y` for the public input `y`
+    pub(crate) struct Square(pub(crate) Option<Fr>);
+
+    impl Circuit<Bls12> for Square {
+        fn synthesize<CS: ConstraintSystem<Bls12>>(
+            self,
+            cs: &mut CS,
+        ) -> Result<(), SynthesisError> {
+            let x = cs.alloc(|| "x", || self.0.ok_or(SynthesisError::AssignmentMissing))?;
+            let y = cs.alloc_input(
+                || "y",
+                || {
+                    let mut y = self.0.ok_or(SynthesisError::AssignmentMissing)?;
+                    y.square();
+                    Ok(y)
+                },
+            )?;
+            cs.enforce(|| "x * x = y", |lc| lc + x, |lc| lc + x, |lc| lc + y);
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_create_proof_from_assignments() {
+        let params = generate_parameters_from_seed(Square(None), b"assignments").unwrap();
+        let pvk = prepare_verifying_key(&params.vk);
+        let constraints = CircuitConstraints::from_circuit(Square(None)).unwrap();
+        assert_eq!(constraints.num_inputs(), 1);
+        assert_eq!(constraints.num_aux(), 1);
+        assert_eq!(constraints.num_constraints(), 1);
+
+        let x = Fr::from_str("3").unwrap();
+        let y = Fr::from_str("9").unwrap();
+        let (r, s) = (Fr::from_str("5").unwrap(), Fr::from_str("7").unwrap());
+        let proof =
+            create_proof_from_assignments(&constraints, &params, r, s, vec![y], vec![x]).unwrap();
+        assert!(verify_proof(&pvk, &proof, &[y]).unwrap());
+        // Same proof as when synthesizing the circuit with the witness
+        assert!(proof == create_proof(Square(Some(x)), &params, r, s).unwrap());
+
+        // An unsatisfied witness
+        let proof =
+            create_proof_from_assignments(&constraints, &params, r, s, vec![x], vec![x]).unwrap();
+        assert!(!verify_proof(&pvk, &proof, &[x]).unwrap());
+
+        assert!(
+            create_proof_from_assignments(&constraints, &params, r, s, vec![], vec![x]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_create_proof_with_shape() {
+        let params = generate_parameters_from_seed(Square(None), b"shape").unwrap();
+        let shape = CircuitConstraints::from_circuit(Square(None)).unwrap();
+
+        let x = Fr::from_str("4").unwrap();
+        let mut cs = WitnessCS::new();
+        Square(Some(x)).synthesize(&mut cs).unwrap();
+        assert_eq!(
+            cs.into_assignments(),
+            (vec![Fr::from_str("16").unwrap()], vec![x])
+        );
+
+        let (r, s) = (Fr::from_str("5").unwrap(), Fr::from_str("7").unwrap());
+        let proof = create_proof_with_shape(&shape, Square(Some(x)), &params, r, s).unwrap();
+        assert!(proof == create_proof(Square(Some(x)), &params, r, s).unwrap());
+        assert!(create_proof_with_shape(&shape, Square(None), &params, r, s).is_err());
+    }
+}
@@ -0,0 +1,201 @@
+//! Binary encoding of the witness of a circuit, so that witnesses can be generated on CPU nodes
+//! and shipped to the GPU provers, which prove them with `create_proof_from_assignments`.
+//!
+//! The encoding starts with a magic, a version and the identifier of the circuit (See
+//! `CircuitConstraints::circuit_id`), followed by the modulus of the field, the numbers of
+//! public inputs and of auxiliary variables, and the assignments in their canonical little
+//! endian representation.
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use ff::{PrimeField, PrimeFieldRepr};
+use paired::Engine;
+
+use std::io::{self, Read, Write};
+
+use super::{
+    create_proof_from_assignments, CircuitConstraints, ParameterSource, Proof, WitnessCS,
+    CIRCUIT_ID_LEN,
+};
+use crate::{Circuit, ConstraintSystem, SynthesisError};
+
+/// Magic bytes at the start of an encoded witness.
+pub const WITNESS_MAGIC: [u8; 8] = *b"BELLWTNS";
+/// Version of the witnesses written by `Witness::write`.
+pub const WITNESS_VERSION: u32 = 1;
+
+/// The assignments of the variables of a circuit.
+pub struct Witness<E: Engine> {
+    /// The public inputs, not counting the "one" input variable.
+    pub input_assignment: Vec<E::Fr>,
+    pub aux_assignment: Vec<E::Fr>,
+}
+
+// Not derived, which would require `E` to implement them
+impl<E: Engine> Clone for Witness<E> {
+    fn clone(&self) -> Self {
+        Witness {
+            input_assignment: self.input_assignment.clone(),
+            aux_assignment: self.aux_assignment.clone(),
+        }
+    }
+}
+
+impl<E: Engine> PartialEq for Witness<E> {
+    fn eq(&self, other: &Self) -> bool {
+        self.input_assignment == other.input_assignment
+            && self.aux_assignment == other.aux_assignment
+    }
+}
+
+fn invalid<E: Into<Box<dyn std::error::Error + Send + Sync>>>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+fn write_elements<F: PrimeField, W: Write>(values: &[F], writer: &mut W) -> io::Result<()> {
+    writer.write_u64::<BigEndian>(values.len() as u64)?;
+    for value in values {
+        value.into_repr().write_le(&mut *writer)?;
+    }
+    Ok(())
+}
+
+fn read_elements<F: PrimeField, R: Read>(reader: &mut R) -> io::Result<Vec<F>> {
+    let len = reader.read_u64::<BigEndian>()?;
+    (0..len)
+        .map(|_| {
+            let mut repr = F::Repr::default();
+            repr.read_le(&mut *reader)?;
+            F::from_repr(repr).map_err(invalid)
+        })
+        .collect()
+}
+
+impl<E: Engine> Witness<E> {
+    /// Synthesizes `circuit` with `WitnessCS`, which only computes the assignments.
+    pub fn from_circuit<C: Circuit<E>>(circuit: C) -> Result<Self, SynthesisError> {
+        let mut cs = WitnessCS::new();
+        circuit.synthesize(&mut cs)?;
+        let (input_assignment, aux_assignment) = cs.into_assignments();
+        Ok(Witness {
+            input_assignment,
+            aux_assignment,
+        })
+    }
+
+    /// Writes the witness, for the circuit identified by `circuit_id`.
+    pub fn write<W: Write>(
+        &self,
+        circuit_id: &[u8; CIRCUIT_ID_LEN],
+        mut writer: W,
+    ) -> io::Result<()> {
+        writer.write_all(&WITNESS_MAGIC)?;
+        writer.write_u32::<BigEndian>(WITNESS_VERSION)?;
+        writer.write_all(circuit_id)?;
+        E::Fr::char().write_le(&mut writer)?;
+        write_elements(&self.input_assignment, &mut writer)?;
+        write_elements(&self.aux_assignment, &mut writer)
+    }
+
+    /// Reads a witness, returning it with the identifier of its circuit.
+    pub fn read<R: Read>(mut reader: R) -> io::Result<(Self, [u8; CIRCUIT_ID_LEN])> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if magic != WITNESS_MAGIC {
+            return Err(invalid("not an encoded witness"));
+        }
+        let version = reader.read_u32::<BigEndian>()?;
+        if version != WITNESS_VERSION {
+            return Err(invalid(format!("unsupported witness version {}", version)));
+        }
+        let mut circuit_id = [0u8; CIRCUIT_ID_LEN];
+        reader.read_exact(&mut circuit_id)?;
+        let mut modulus = <E::Fr as PrimeField>::Repr::default();
+        modulus.read_le(&mut reader)?;
+        if modulus != E::Fr::char() {
+            return Err(invalid("the witness is over another field"));
+        }
+
+        let witness = Witness {
+            input_assignment: read_elements(&mut reader)?,
+            aux_assignment: read_elements(&mut reader)?,
+        };
+        Ok((witness, circuit_id))
+    }
+
+    /// Reads a witness, failing if it isn't a witness of the circuit identified by
+    /// `circuit_id`.
+    pub fn read_for<R: Read>(reader: R, circuit_id: &[u8; CIRCUIT_ID_LEN]) -> io::Result<Self> {
+        let (witness, id) = Self::read(reader)?;
+        if &id != circuit_id {
+            return Err(invalid("the witness is for another circuit"));
+        }
+        Ok(witness)
+    }
+
+    /// Same as `create_proof_from_assignments`, with the assignments of the witness.
+    pub fn prove<P: ParameterSource<E>>(
+        self,
+        constraints: &CircuitConstraints<E>,
+        params: P,
+        r: E::Fr,
+        s: E::Fr,
+    ) -> Result<Proof<E>, SynthesisError> {
+        create_proof_from_assignments(
+            constraints,
+            params,
+            r,
+            s,
+            self.input_assignment,
+            self.aux_assignment,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::groth16::generate_parameters_from_seed;
+    use crate::groth16::witness::tests::Square;
+    use crate::groth16::{create_proof, prepare_verifying_key, verify_proof};
+    use paired::bls12_381::{Bls12, Fr};
+
+    #[test]
+    fn test_witness_encoding() {
+        let params = generate_parameters_from_seed(Square(None), b"witness encoding").unwrap();
+        let pvk = prepare_verifying_key(&params.vk);
+        let constraints = CircuitConstraints::from_circuit(Square(None)).unwrap();
+        let circuit_id = constraints.circuit_id();
+
+        let x = Fr::from_str("3").unwrap();
+        let witness = Witness::<Bls12>::from_circuit(Square(Some(x))).unwrap();
+        assert_eq!(witness.input_assignment, vec![Fr::from_str("9").unwrap()]);
+        assert_eq!(witness.aux_assignment, vec![x]);
+
+        let mut encoded = vec![];
+        witness.write(&circuit_id, &mut encoded).unwrap();
+        assert!(encoded.starts_with(&WITNESS_MAGIC));
+        // The header, the modulus and two lengths, followed by two elements
+        assert_eq!(encoded.len(), 8 + 4 + 32 + 32 + 2 * 8 + 2 * 32);
+        let (read, id) = Witness::<Bls12>::read(&encoded[..]).unwrap();
+        assert!(read == witness);
+        assert_eq!(id, circuit_id);
+        assert!(Witness::<Bls12>::read_for(&encoded[..], &[0u8; 32]).is_err());
+        assert!(Witness::<Bls12>::read(&encoded[..encoded.len() - 1]).is_err());
+        let mut future = encoded.clone();
+        future[11] += 1;
+        assert!(Witness::<Bls12>::read(&future[..]).is_err());
+        // An element which isn't in its canonical representation
+        let mut non_canonical = encoded.clone();
+        let len = non_canonical.len();
+        for byte in &mut non_canonical[len - 32..] {
+            *byte = 0xff;
+        }
+        assert!(Witness::<Bls12>::read(&non_canonical[..]).is_err());
+
+        let read = Witness::<Bls12>::read_for(&encoded[..], &circuit_id).unwrap();
+        let (r, s) = (Fr::from_str("5").unwrap(), Fr::from_str("7").unwrap());
+        let proof = read.prove(&constraints, &params, r, s).unwrap();
+        assert!(verify_proof(&pvk, &proof, &witness.input_assignment).unwrap());
+        assert!(proof == create_proof(Square(Some(x)), &params, r, s).unwrap());
+    }
+}
@@ -0,0 +1,211 @@
+//! KZG10 polynomial commitments over the G1 and G2 groups of an engine, behind the `kzg`
+//! feature.
+//!
+//! The commitments and the opening proofs are multiexps of the crate, so they run on the GPU
+//! when a kernel is given, as the `groth16` prover does. The `UniversalParams` only depend on a
+//! maximum degree, which makes them reusable by any protocol (e.g. lookup arguments or data
+//! availability sampling) up to that degree.
+
+use ff::{Field, PrimeField};
+use futures::Future;
+use groupy::{CurveAffine, CurveProjective};
+use paired::{Engine, PairingCurveAffine};
+use rand_core::RngCore;
+use rayon::prelude::*;
+
+use std::sync::Arc;
+
+use crate::domain::{EvaluationDomain, Scalar};
+use crate::gpu::LockedMultiexpKernel;
+use crate::multicore::Worker;
+use crate::multiexp::{multiexp, FullDensity};
+use crate::SynthesisError;
+
+/// The powers of a secret `tau` in G1, and `tau` in G2, committing to polynomials of degree up
+/// to `max_degree`.
+#[derive(Clone, Debug)]
+pub struct UniversalParams<E: Engine> {
+    pub powers_of_tau_g1: Vec<E::G1Affine>,
+    pub g2: E::G2Affine,
+    pub tau_g2: E::G2Affine,
+}
+
+/// The evaluation of a committed polynomial at a point, with the proof of its correctness.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Opening<E: Engine> {
+    pub value: E::Fr,
+    pub proof: E::G1Affine,
+}
+
+impl<E: Engine> UniversalParams<E> {
+    /// Generates parameters with a random secret, which is only suitable for tests: production
+    /// parameters have to come from a powers of tau ceremony.
+    pub fn generate<R: RngCore>(max_degree: usize, rng: &mut R) -> Self {
+        let tau = E::Fr::random(rng);
+        let g1 = E::G1Affine::one();
+        let g2 = E::G2Affine::one();
+
+        let mut powers = Vec::with_capacity(max_degree + 1);
+        let mut acc = E::Fr::one();
+        for _ in 0..=max_degree {
+            powers.push(acc);
+            acc.mul_assign(&tau);
+        }
+        let powers_of_tau_g1 = powers
+            .par_iter()
+            .map(|p| g1.mul(p.into_repr()).into_affine())
+            .collect();
+
+        UniversalParams {
+            powers_of_tau_g1,
+            g2,
+            tau_g2: g2.mul(tau.into_repr()).into_affine(),
+        }
+    }
+
+    /// The largest degree of the polynomials these parameters can commit to.
+    pub fn max_degree(&self) -> usize {
+        self.powers_of_tau_g1.len().saturating_sub(1)
+    }
+}
+
+/// Commits to the polynomial of coefficients `coeffs`, lowest degree first. The multiexp runs
+/// on the GPU of `kern` if there is one.
+pub fn commit<E: Engine>(
+    params: &UniversalParams<E>,
+    worker: &Worker,
+    coeffs: &[E::Fr],
+    kern: &mut Option<LockedMultiexpKernel<E>>,
+) -> Result<E::G1Affine, SynthesisError> {
+    if coeffs.len() > params.powers_of_tau_g1.len() {
+        return Err(SynthesisError::PolynomialDegreeTooLarge);
+    }
+    if coeffs.is_empty() {
+        return Ok(E::G1Affine::zero());
+    }
+
+    let bases = Arc::new(params.powers_of_tau_g1[..coeffs.len()].to_vec());
+    let exps = Arc::new(coeffs.iter().map(|c| c.into_repr()).collect::<Vec<_>>());
+    Ok(multiexp(worker, (bases, 0), FullDensity, exps, kern)
+        .wait()?
+        .into_affine())
+}
+
+/// Evaluates the polynomial of coefficients `coeffs` at `point`, and proves the evaluation
+/// against its commitment.
+pub fn open<E: Engine>(
+    params: &UniversalParams<E>,
+    worker: &Worker,
+    coeffs: &[E::Fr],
+    point: E::Fr,
+    kern: &mut Option<LockedMultiexpKernel<E>>,
+) -> Result<Opening<E>, SynthesisError> {
+    // Coefficients of (p(X) - p(z)) / (X - z), the remainder of the division being p(z)
+    let mut quotient = vec![E::Fr::zero(); coeffs.len().saturating_sub(1)];
+    let mut value = E::Fr::zero();
+    for i in (0..coeffs.len()).rev() {
+        value.mul_assign(&point);
+        value.add_assign(&coeffs[i]);
+        if i > 0 {
+            quotient[i - 1] = value;
+        }
+    }
+
+    Ok(Opening {
+        value,
+        proof: commit(params, worker, &quotient, kern)?,
+    })
+}
+
+/// Checks that the polynomial of `commitment` evaluates to `opening.value` at `point`, i.e. that
+/// `e(C - value * G1, G2) = e(proof, (tau - point) * G2)`.
+pub fn verify<E: Engine>(
+    params: &UniversalParams<E>,
+    commitment: &E::G1Affine,
+    point: E::Fr,
+    opening: &Opening<E>,
+) -> bool {
+    // Rearranged as e(C - value * G1 + point * proof, G2) e(-proof, tau * G2) = 1, which needs a
+    // single final exponentiation
+    let mut lhs = opening.proof.mul(point.into_repr());
+    lhs.add_assign_mixed(commitment);
+    lhs.sub_assign(&E::G1Affine::one().mul(opening.value.into_repr()));
+    let mut neg_proof = opening.proof;
+    neg_proof.negate();
+
+    E::final_exponentiation(&E::miller_loop(
+        [
+            (&lhs.into_affine().prepare(), &params.g2.prepare()),
+            (&neg_proof.prepare(), &params.tau_g2.prepare()),
+        ]
+        .iter(),
+    ))
+    .map(|result| result == E::Fqk::one())
+    .unwrap_or(false)
+}
+
+/// The coefficients of the polynomial taking the values `evaluations` on the domain of their
+/// size, the powers of its root of unity. The number of evaluations must be a power of two.
+pub fn interpolate<E: Engine>(
+    worker: &Worker,
+    evaluations: Vec<E::Fr>,
+) -> Result<Vec<E::Fr>, SynthesisError> {
+    if !evaluations.len().is_power_of_two() {
+        return Err(SynthesisError::PolynomialDegreeTooLarge);
+    }
+
+    let mut domain = EvaluationDomain::<E, Scalar<E>>::from_coeffs(
+        evaluations.into_iter().map(Scalar).collect(),
+    )?;
+    domain.ifft(worker, &mut None)?;
+    Ok(domain.into_coeffs().into_iter().map(|s| s.0).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paired::bls12_381::{Bls12, Fr};
+
+    #[test]
+    fn test_kzg() {
+        let rng = &mut rand::thread_rng();
+        let worker = Worker::new();
+        let params = UniversalParams::<Bls12>::generate(16, rng);
+        assert_eq!(params.max_degree(), 16);
+
+        let coeffs = (0..8).map(|_| Fr::random(rng)).collect::<Vec<_>>();
+        let commitment = commit(&params, &worker, &coeffs, &mut None).unwrap();
+        let point = Fr::random(rng);
+        let opening = open(&params, &worker, &coeffs, point, &mut None).unwrap();
+        assert!(verify(&params, &commitment, point, &opening));
+
+        let mut wrong = opening.clone();
+        wrong.value.add_assign(&Fr::one());
+        assert!(!verify(&params, &commitment, point, &wrong));
+        assert!(!verify(&params, &commitment, Fr::random(rng), &opening));
+
+        let too_large = vec![Fr::one(); 18];
+        assert!(commit(&params, &worker, &too_large, &mut None).is_err());
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let rng = &mut rand::thread_rng();
+        let worker = Worker::new();
+        let evaluations = (0..8).map(|_| Fr::random(rng)).collect::<Vec<_>>();
+        let coeffs = interpolate::<Bls12>(&worker, evaluations.clone()).unwrap();
+
+        let mut domain = EvaluationDomain::<Bls12, Scalar<Bls12>>::from_coeffs(
+            coeffs.into_iter().map(Scalar).collect(),
+        )
+        .unwrap();
+        domain.fft(&worker, &mut None).unwrap();
+        let values = domain
+            .into_coeffs()
+            .into_iter()
+            .map(|s| s.0)
+            .collect::<Vec<_>>();
+        assert_eq!(values, evaluations);
+        assert!(interpolate::<Bls12>(&worker, vec![Fr::one(); 3]).is_err());
+    }
+}
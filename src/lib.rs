@@ -139,13 +139,21 @@
 #[macro_use]
 extern crate hex_literal;
 
+pub mod cancellation;
 pub mod domain;
 pub mod gadgets;
 pub mod gpu;
 #[cfg(feature = "groth16")]
 pub mod groth16;
+#[cfg(feature = "kzg")]
+pub mod kzg;
+pub mod merkle;
 pub mod multicore;
 pub mod multiexp;
+pub mod poseidon;
+#[cfg(feature = "remote-gpu")]
+pub mod remote;
+pub(crate) mod secret;
 
 pub mod util_cs;
 use ff::{Field, ScalarEngine};
@@ -338,12 +346,27 @@ pub enum SynthesisError {
     /// During verification, our verifying key was malformed.
     #[error("malformed verifying key")]
     MalformedVerifyingKey,
+    /// During batch verification, the number of proofs and of public inputs differed.
+    #[error("malformed proofs")]
+    MalformedProofs,
     /// During CRS generation, we observed an unconstrained auxiliary variable
     #[error("auxiliary variable was unconstrained")]
     UnconstrainedVariable,
+    /// The computation was cancelled through its `CancellationToken`.
+    #[error("cancelled")]
+    Cancelled,
     /// During GPU multiexp/fft, some GPU related error happened
     #[error("encountered a GPU error: {0}")]
-    GPUError(#[from] gpu::GPUError),
+    GPUError(gpu::GPUError),
+}
+
+impl From<gpu::GPUError> for SynthesisError {
+    fn from(e: gpu::GPUError) -> SynthesisError {
+        match e {
+            gpu::GPUError::Cancelled => SynthesisError::Cancelled,
+            e => SynthesisError::GPUError(e),
+        }
+    }
 }
 
 /// Represents a constraint system which can have new variables
@@ -0,0 +1,201 @@
+//! Merkle trees of field elements, of any power of two arity, with a pluggable hash.
+//!
+//! The paths of `MerkleTree::path` are verified in circuits by `gadgets::merkle`, with the
+//! circuit of the same `MerkleHasher`.
+
+use ff::PrimeField;
+use log::warn;
+use rayon::prelude::*;
+
+use crate::gpu;
+use crate::poseidon::PoseidonParams;
+
+/// A hash of `arity` field elements into one, hashing the nodes of a tree.
+pub trait MerkleHasher<F: PrimeField>: Sync {
+    fn arity(&self) -> usize;
+
+    fn hash(&self, children: &[F]) -> F;
+
+    /// Hashes each chunk of `arity` elements of `children`, i.e. a layer of a tree.
+    fn hash_many(&self, children: &[F]) -> Vec<F> {
+        children
+            .par_chunks(self.arity())
+            .map(|chunk| self.hash(chunk))
+            .collect()
+    }
+}
+
+impl<F: PrimeField> MerkleHasher<F> for PoseidonParams<F> {
+    fn arity(&self) -> usize {
+        PoseidonParams::arity(self)
+    }
+
+    fn hash(&self, children: &[F]) -> F {
+        PoseidonParams::hash(self, children)
+    }
+
+    fn hash_many(&self, children: &[F]) -> Vec<F> {
+        PoseidonParams::hash_many(self, children)
+    }
+}
+
+/// A tree whose number of leaves is a power of its arity, keeping all of its layers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MerkleTree<F: PrimeField> {
+    arity: usize,
+    /// The leaves first, the root last.
+    layers: Vec<Vec<F>>,
+}
+
+/// The siblings of the nodes on the path from a leaf to the root, leaf level first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MerklePath<F: PrimeField> {
+    pub index: usize,
+    /// `arity - 1` siblings per level, in the order of the children.
+    pub siblings: Vec<Vec<F>>,
+}
+
+impl<F: PrimeField> MerkleTree<F> {
+    /// Builds the tree of `leaves` on the CPU.
+    pub fn new<H: MerkleHasher<F>>(hasher: &H, leaves: Vec<F>) -> Self {
+        Self::build(hasher.arity(), leaves, |layer| hasher.hash_many(layer))
+    }
+
+    /// Builds the tree of `leaves` with the Poseidon hash of `params`, hashing the layers on the
+    /// GPU (See `gpu::PoseidonKernel`), or on the CPU if the kernel can't be created.
+    pub fn new_gpu(params: &PoseidonParams<F>, leaves: Vec<F>) -> Self {
+        let mut kernel = match gpu::PoseidonKernel::create(params) {
+            Ok(kernel) => Some(kernel),
+            Err(e) => {
+                warn!("Cannot instantiate GPU Poseidon kernel! Error: {}", e);
+                None
+            }
+        };
+
+        Self::build(params.arity(), leaves, |layer| {
+            if let Some(k) = kernel.as_mut() {
+                match k.hash_many(layer) {
+                    Ok(hashes) => return hashes,
+                    Err(e) => warn!("GPU Poseidon failed! Falling back to CPU... Error: {}", e),
+                }
+            }
+            params.hash_many(layer)
+        })
+    }
+
+    fn build<L>(arity: usize, leaves: Vec<F>, mut hash_layer: L) -> Self
+    where
+        L: FnMut(&[F]) -> Vec<F>,
+    {
+        assert!(
+            arity >= 2 && arity.is_power_of_two(),
+            "the arity must be a power of two"
+        );
+        let mut len = leaves.len();
+        while len > 1 && len % arity == 0 {
+            len /= arity;
+        }
+        assert_eq!(len, 1, "the number of leaves must be a power of the arity");
+
+        let mut layers = vec![leaves];
+        while layers.last().unwrap().len() > 1 {
+            let layer = hash_layer(layers.last().unwrap());
+            layers.push(layer);
+        }
+
+        MerkleTree { arity, layers }
+    }
+
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    pub fn root(&self) -> F {
+        self.layers.last().unwrap()[0]
+    }
+
+    pub fn leaves(&self) -> &[F] {
+        &self.layers[0]
+    }
+
+    /// The number of levels above the leaves, i.e. the length of the paths.
+    pub fn height(&self) -> usize {
+        self.layers.len() - 1
+    }
+
+    /// The path from the leaf `index` to the root.
+    pub fn path(&self, index: usize) -> MerklePath<F> {
+        assert!(index < self.leaves().len());
+
+        let mut siblings = Vec::with_capacity(self.height());
+        let mut i = index;
+        for layer in &self.layers[..self.height()] {
+            let first = i - i % self.arity;
+            siblings.push(
+                (first..first + self.arity)
+                    .filter(|j| *j != i)
+                    .map(|j| layer[j])
+                    .collect(),
+            );
+            i /= self.arity;
+        }
+
+        MerklePath { index, siblings }
+    }
+}
+
+impl<F: PrimeField> MerklePath<F> {
+    /// The root of the tree of `leaf`, if this is its path.
+    pub fn root<H: MerkleHasher<F>>(&self, hasher: &H, leaf: F) -> F {
+        let arity = hasher.arity();
+        let mut node = leaf;
+        let mut i = self.index;
+        for siblings in &self.siblings {
+            let mut children = siblings.clone();
+            children.insert(i % arity, node);
+            node = hasher.hash(&children);
+            i /= arity;
+        }
+        node
+    }
+
+    /// The bits of the index, least significant first, `log2(arity)` per level: the bits of the
+    /// position of the node among its siblings.
+    pub fn index_bits(&self, arity: usize) -> Vec<bool> {
+        let bits_per_level = arity.trailing_zeros() as usize;
+        (0..self.siblings.len() * bits_per_level)
+            .map(|b| (self.index >> b) & 1 == 1)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ff::Field;
+    use paired::bls12_381::Fr;
+
+    #[test]
+    fn test_merkle_tree() {
+        let rng = &mut rand::thread_rng();
+        for &(width, leaves) in &[(3, 16), (5, 64), (9, 64)] {
+            let params = PoseidonParams::<Fr>::new(width, 8, 57);
+            let leaves = (0..leaves).map(|_| Fr::random(rng)).collect::<Vec<_>>();
+            let tree = MerkleTree::new(&params, leaves.clone());
+            assert_eq!(
+                tree.height(),
+                (leaves.len().trailing_zeros() / params.arity().trailing_zeros()) as usize
+            );
+
+            for &index in &[0, 5, leaves.len() - 1] {
+                let path = tree.path(index);
+                assert_eq!(path.siblings.len(), tree.height());
+                assert_eq!(path.root(&params, leaves[index]), tree.root());
+                assert!(path.root(&params, leaves[index + 1 - 2 * (index % 2)]) != tree.root());
+            }
+
+            // Falls back to the CPU without a GPU
+            assert!(MerkleTree::new_gpu(&params, leaves) == tree);
+        }
+    }
+}
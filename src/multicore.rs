@@ -1,18 +1,21 @@
 //! An interface for dealing with the kinds of parallel computations involved in
 //! `bellperson`. It's currently just a thin wrapper around [`CpuPool`] and
-//! [`crossbeam`] but may be extended in the future to allow for various
-//! parallelism strategies.
+//! [`crossbeam`], or around a rayon `ThreadPool` provided by the application
+//! (See `Worker::with_thread_pool` and `set_thread_pool`).
 //!
 //! [`CpuPool`]: futures_cpupool::CpuPool
 
 #[cfg(feature = "multicore")]
 mod implementation {
-    use crossbeam::{self, thread::Scope};
-    use futures::{Future, IntoFuture, Poll};
+    use futures::sync::oneshot;
+    use futures::{Async, Future, IntoFuture, Poll};
     use futures_cpupool::{CpuFuture, CpuPool};
     use lazy_static::lazy_static;
     use num_cpus;
+    use std::cell::RefCell;
     use std::env;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::{Arc, Mutex, RwLock};
 
     lazy_static! {
         static ref NUM_CPUS: usize = if let Ok(num) = env::var("BELLMAN_NUM_CPUS") {
@@ -28,12 +31,65 @@ mod implementation {
             .num_threads(*NUM_CPUS)
             .build()
             .unwrap();
+        static ref GLOBAL_POOL: RwLock<Option<Arc<rayon::ThreadPool>>> = RwLock::new(None);
+    }
+
+    thread_local! {
+        static LOCAL_POOL: RefCell<Option<Arc<rayon::ThreadPool>>> = RefCell::new(None);
+    }
+
+    /// Makes `Worker::new` and the provers run on `pool` (Owned by the application) instead of
+    /// their own threads, so that they don't compete with the other users of the pool. `None`
+    /// restores the default threads.
+    pub fn set_thread_pool(pool: Option<Arc<rayon::ThreadPool>>) {
+        *GLOBAL_POOL.write().unwrap() = pool;
+    }
+
+    /// Runs `f` with `pool` as the thread pool of the `Worker`s and provers it creates (Taking
+    /// precedence over `set_thread_pool`), e.g. for limiting the parallelism of a single proving
+    /// task. Only affects the calling thread.
+    pub fn with_thread_pool<F, R>(pool: Arc<rayon::ThreadPool>, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        let prev = LOCAL_POOL.with(|p| p.replace(Some(pool)));
+        let res = panic::catch_unwind(AssertUnwindSafe(f));
+        LOCAL_POOL.with(|p| *p.borrow_mut() = prev);
+        res.unwrap_or_else(|e| panic::resume_unwind(e))
+    }
+
+    fn current_thread_pool() -> Option<Arc<rayon::ThreadPool>> {
+        LOCAL_POOL
+            .with(|p| p.borrow().clone())
+            .or_else(|| GLOBAL_POOL.read().unwrap().clone())
+    }
+
+    /// Runs `f` in the thread pool configured with `with_thread_pool` or `set_thread_pool`, and in
+    /// `THREAD_POOL` otherwise.
+    pub(crate) fn install<F, R>(f: F) -> R
+    where
+        F: FnOnce() -> R + Send,
+        R: Send,
+    {
+        match current_thread_pool() {
+            Some(pool) => {
+                let inner = pool.clone();
+                pool.install(move || with_thread_pool(inner, f))
+            }
+            None => THREAD_POOL.install(f),
+        }
+    }
+
+    #[derive(Clone)]
+    enum Pool {
+        CpuPool(CpuPool),
+        Rayon(Arc<rayon::ThreadPool>),
     }
 
     #[derive(Clone)]
     pub struct Worker {
         cpus: usize,
-        pool: CpuPool,
+        pool: Pool,
     }
 
     impl Worker {
@@ -43,12 +99,26 @@ mod implementation {
         pub(crate) fn new_with_cpus(cpus: usize) -> Worker {
             Worker {
                 cpus,
-                pool: CpuPool::new(cpus),
+                pool: Pool::CpuPool(CpuPool::new(cpus)),
             }
         }
 
+        /// Creates a worker running on the thread pool configured with `with_thread_pool` or
+        /// `set_thread_pool` if any, and on its own `BELLMAN_NUM_CPUS` threads otherwise.
         pub fn new() -> Worker {
-            Self::new_with_cpus(*NUM_CPUS)
+            match current_thread_pool() {
+                Some(pool) => Self::with_thread_pool(pool),
+                None => Self::new_with_cpus(*NUM_CPUS),
+            }
+        }
+
+        /// Creates a worker delegating all of its work to `pool`, with as much parallelism as
+        /// the pool has threads.
+        pub fn with_thread_pool(pool: Arc<rayon::ThreadPool>) -> Worker {
+            Worker {
+                cpus: pool.current_num_threads(),
+                pool: Pool::Rayon(pool),
+            }
         }
 
         pub fn log_num_cpus(&self) -> u32 {
@@ -63,14 +133,31 @@ mod implementation {
             R::Item: Send + 'static,
             R::Error: Send + 'static,
         {
-            WorkerFuture {
-                future: self.pool.spawn_fn(f),
-            }
+            let future = match self.pool {
+                Pool::CpuPool(ref pool) => WorkerFutureInner::CpuPool(pool.spawn_fn(f)),
+                Pool::Rayon(ref pool) => {
+                    let job: Job<R::Item, R::Error> = Box::new(move || f().into_future().wait());
+                    let job = Arc::new(Mutex::new(Some(job)));
+                    let (tx, rx) = oneshot::channel();
+                    let queued = job.clone();
+                    pool.spawn(move || {
+                        let job = queued.lock().unwrap().take();
+                        // A panic drops `tx`, which is reported by `WorkerFuture::poll`
+                        if let Some(Ok(res)) =
+                            job.map(|job| panic::catch_unwind(AssertUnwindSafe(job)))
+                        {
+                            let _ = tx.send(res);
+                        }
+                    });
+                    WorkerFutureInner::Rayon(job, rx)
+                }
+            };
+            WorkerFuture { future }
         }
 
         pub fn scope<'a, F, R>(&self, elements: usize, f: F) -> R
         where
-            F: FnOnce(&Scope<'a>, usize) -> R,
+            F: FnOnce(&Scope<'a, '_>, usize) -> R,
         {
             let chunk_size = if elements < self.cpus {
                 1
@@ -78,14 +165,54 @@ mod implementation {
                 elements / self.cpus
             };
 
-            // TODO: Handle case where threads fail
-            crossbeam::scope(|scope| f(scope, chunk_size))
-                .expect("Threads aren't allowed to fail yet")
+            match self.pool {
+                // TODO: Handle case where threads fail
+                Pool::CpuPool(_) => {
+                    crossbeam::scope(|scope| f(&Scope::Crossbeam(scope), chunk_size))
+                        .expect("Threads aren't allowed to fail yet")
+                }
+                Pool::Rayon(ref pool) => {
+                    pool.in_place_scope(|scope| f(&Scope::Rayon(scope), chunk_size))
+                }
+            }
+        }
+    }
+
+    /// Scope of `Worker::scope`, in which the chunks of the work are spawned
+    pub enum Scope<'a, 's> {
+        Crossbeam(&'s crossbeam::thread::Scope<'a>),
+        Rayon(&'s rayon::Scope<'a>),
+    }
+
+    impl<'a, 's> Scope<'a, 's> {
+        pub fn spawn<F>(&self, f: F)
+        where
+            F: FnOnce(&Scope<'a, '_>) + Send + 'a,
+        {
+            match self {
+                Scope::Crossbeam(scope) => {
+                    scope.spawn(move |scope| f(&Scope::Crossbeam(scope)));
+                }
+                Scope::Rayon(scope) => scope.spawn(move |scope| f(&Scope::Rayon(scope))),
+            }
         }
     }
 
+    type Job<T, E> = Box<dyn FnOnce() -> Result<T, E> + Send>;
+
+    enum WorkerFutureInner<T, E> {
+        CpuPool(CpuFuture<T, E>),
+        // The job is taken by the first of the thread pool and the thread polling the future.
+        // Running it on the polling thread when the pool hasn't started it yet ensures that
+        // waiting for the future from a thread of the pool can't deadlock.
+        Rayon(
+            Arc<Mutex<Option<Job<T, E>>>>,
+            oneshot::Receiver<Result<T, E>>,
+        ),
+    }
+
     pub struct WorkerFuture<T, E> {
-        future: CpuFuture<T, E>,
+        future: WorkerFutureInner<T, E>,
     }
 
     impl<T: Send + 'static, E: Send + 'static> Future for WorkerFuture<T, E> {
@@ -93,7 +220,20 @@ mod implementation {
         type Error = E;
 
         fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-            self.future.poll()
+            match self.future {
+                WorkerFutureInner::CpuPool(ref mut future) => future.poll(),
+                WorkerFutureInner::Rayon(ref job, ref mut rx) => {
+                    let job = job.lock().unwrap().take();
+                    if let Some(job) = job {
+                        return job().map(Async::Ready);
+                    }
+                    match rx.poll() {
+                        Ok(Async::Ready(res)) => res.map(Async::Ready),
+                        Ok(Async::NotReady) => Ok(Async::NotReady),
+                        Err(oneshot::Canceled) => panic!("Worker thread panicked!"),
+                    }
+                }
+            }
         }
     }
 
@@ -120,6 +260,39 @@ mod implementation {
         assert_eq!(log2_floor(7), 2);
         assert_eq!(log2_floor(8), 3);
     }
+
+    #[test]
+    fn test_rayon_worker() {
+        let pool = Arc::new(
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(1)
+                .build()
+                .unwrap(),
+        );
+        let worker = with_thread_pool(pool.clone(), Worker::new);
+        assert_eq!(worker.log_num_cpus(), 0);
+
+        // Waiting from the only thread of the pool mustn't deadlock
+        let sum = pool.install(|| {
+            let futures = (0..4u64)
+                .map(|i| worker.compute(move || Ok::<_, ()>(i)))
+                .collect::<Vec<_>>();
+            futures.into_iter().map(|f| f.wait().unwrap()).sum::<u64>()
+        });
+        assert_eq!(sum, 6);
+
+        let mut v = vec![0usize; 100];
+        worker.scope(v.len(), |scope, chunk| {
+            for (i, v) in v.chunks_mut(chunk).enumerate() {
+                scope.spawn(move |_| {
+                    for x in v.iter_mut() {
+                        *x = i + 1;
+                    }
+                });
+            }
+        });
+        assert!(v.iter().all(|&x| x == 1));
+    }
 }
 
 #[cfg(not(feature = "multicore"))]
@@ -130,6 +303,10 @@ mod implementation {
     pub struct Worker;
 
     impl Worker {
+        pub(crate) fn new_with_cpus(_: usize) -> Worker {
+            Worker
+        }
+
         pub fn new() -> Worker {
             Worker
         }
@@ -170,6 +347,27 @@ mod implementation {
         }
     }
 
+    /// Without the `multicore` feature the work is done on the calling thread, the thread pool
+    /// is ignored.
+    pub fn set_thread_pool(_: Option<std::sync::Arc<rayon::ThreadPool>>) {}
+
+    /// Without the `multicore` feature the work is done on the calling thread, the thread pool
+    /// is ignored.
+    pub fn with_thread_pool<F, R>(_: std::sync::Arc<rayon::ThreadPool>, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        f()
+    }
+
+    pub(crate) fn install<F, R>(f: F) -> R
+    where
+        F: FnOnce() -> R + Send,
+        R: Send,
+    {
+        f()
+    }
+
     pub struct DummyScope;
 
     impl DummyScope {
@@ -1,15 +1,21 @@
 use bit_vec::{self, BitVec};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use ff::{Field, PrimeField, PrimeFieldRepr, ScalarEngine};
-use futures::Future;
-use groupy::{CurveAffine, CurveProjective};
+use futures::{future, Future};
+use groupy::{CurveAffine, CurveProjective, EncodedPoint};
 use log::{info, warn};
-use std::io;
+use std::cmp;
+use std::env;
+use std::io::{self, Read, Write};
 use std::iter;
-use std::sync::Arc;
+use std::ops;
+use std::sync::{Arc, Mutex};
+use tracing::info_span;
 
 use super::multicore::Worker;
 use super::SynthesisError;
 use crate::gpu;
+use crate::secret;
 
 /// An object that builds a source of bases.
 pub trait SourceBuilder<G: CurveAffine>: Send + Sync + 'static + Clone {
@@ -27,6 +33,12 @@ pub trait Source<G: CurveAffine> {
         to: &mut <G as CurveAffine>::Projective,
     ) -> Result<(), SynthesisError>;
 
+    /// Like `add_assign_mixed`, but subtracts the element from `to`.
+    fn sub_assign_mixed(
+        &mut self,
+        to: &mut <G as CurveAffine>::Projective,
+    ) -> Result<(), SynthesisError>;
+
     /// Skips `amt` elements from the source, avoiding deserialization.
     fn skip(&mut self, amt: usize) -> Result<(), SynthesisError>;
 }
@@ -67,6 +79,31 @@ impl<G: CurveAffine> Source<G> for (Arc<Vec<G>>, usize) {
         Ok(())
     }
 
+    fn sub_assign_mixed(
+        &mut self,
+        to: &mut <G as CurveAffine>::Projective,
+    ) -> Result<(), SynthesisError> {
+        if self.0.len() <= self.1 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "expected more bases from source",
+            )
+            .into());
+        }
+
+        if self.0[self.1].is_zero() {
+            return Err(SynthesisError::UnexpectedIdentity);
+        }
+
+        let mut base = self.0[self.1];
+        base.negate();
+        to.add_assign_mixed(&base);
+
+        self.1 += 1;
+
+        Ok(())
+    }
+
     fn skip(&mut self, amt: usize) -> Result<(), SynthesisError> {
         if self.0.len() <= self.1 {
             return Err(io::Error::new(
@@ -85,8 +122,11 @@ impl<G: CurveAffine> Source<G> for (Arc<Vec<G>>, usize) {
 pub trait QueryDensity {
     /// Returns whether the base exists.
     type Iter: Iterator<Item = bool>;
+    /// Returns the indices of the existing bases, in increasing order.
+    type Indices: Iterator<Item = usize>;
 
     fn iter(self) -> Self::Iter;
+    fn indices(self) -> Self::Indices;
     fn get_query_size(self) -> Option<usize>;
 }
 
@@ -102,10 +142,16 @@ impl AsRef<FullDensity> for FullDensity {
 impl<'a> QueryDensity for &'a FullDensity {
     type Iter = iter::Repeat<bool>;
 
+    type Indices = ops::RangeFrom<usize>;
+
     fn iter(self) -> Self::Iter {
         iter::repeat(true)
     }
 
+    fn indices(self) -> Self::Indices {
+        0..
+    }
+
     fn get_query_size(self) -> Option<usize> {
         None
     }
@@ -119,11 +165,27 @@ pub struct DensityTracker {
 
 impl<'a> QueryDensity for &'a DensityTracker {
     type Iter = bit_vec::Iter<'a>;
+    type Indices =
+        iter::FilterMap<iter::Enumerate<bit_vec::Iter<'a>>, fn((usize, bool)) -> Option<usize>>;
 
     fn iter(self) -> Self::Iter {
         self.bv.iter()
     }
 
+    fn indices(self) -> Self::Indices {
+        fn index((i, d): (usize, bool)) -> Option<usize> {
+            if d {
+                Some(i)
+            } else {
+                None
+            }
+        }
+        self.bv
+            .iter()
+            .enumerate()
+            .filter_map(index as fn((usize, bool)) -> Option<usize>)
+    }
+
     fn get_query_size(self) -> Option<usize> {
         Some(self.bv.len())
     }
@@ -192,6 +254,201 @@ impl DensityTracker {
     }
 }
 
+/// Number of bases in each chunk of a `HybridDensity`.
+const HYBRID_CHUNK: usize = 1 << 12;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum DensityChunk {
+    // Offsets of the existing bases, for the chunks where they are few
+    Sparse(Vec<u16>),
+    // Bitmap of the existing bases
+    Dense(Vec<u64>),
+}
+
+/// A density map which stores each chunk of bases either as the offsets of its existing bases
+/// or as a bitmap, whichever is the smallest. Iterating over the indices of the existing bases
+/// then skips quickly through the sparse parts of the map, which speeds up the queries whose
+/// density is very skewed.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct HybridDensity {
+    len: usize,
+    total_density: usize,
+    chunks: Vec<DensityChunk>,
+}
+
+impl HybridDensity {
+    pub fn get_total_density(&self) -> usize {
+        self.total_density
+    }
+}
+
+impl<'a> From<&'a DensityTracker> for HybridDensity {
+    fn from(tracker: &'a DensityTracker) -> Self {
+        let len = tracker.bv.len();
+        let blocks = tracker.bv.storage();
+        let mut total_density = 0;
+
+        let chunks = (0..len)
+            .step_by(HYBRID_CHUNK)
+            .map(|start| {
+                let end = cmp::min(start + HYBRID_CHUNK, len);
+                let mut words = vec![0u64; (end - start + 63) / 64];
+                for (i, &block) in blocks[start / 32..(end + 31) / 32].iter().enumerate() {
+                    words[i / 2] |= u64::from(block) << (32 * (i % 2));
+                }
+                // The bits of the last block past the end of the tracker are unused
+                if end % 64 != 0 {
+                    *words.last_mut().unwrap() &= (1 << (end % 64)) - 1;
+                }
+
+                let count = words.iter().map(|w| w.count_ones() as usize).sum::<usize>();
+                total_density += count;
+                if count * 16 < words.len() * 64 {
+                    let mut offsets = Vec::with_capacity(count);
+                    for (i, &word) in words.iter().enumerate() {
+                        let mut word = word;
+                        while word != 0 {
+                            offsets.push((i * 64) as u16 + word.trailing_zeros() as u16);
+                            word &= word - 1;
+                        }
+                    }
+                    DensityChunk::Sparse(offsets)
+                } else {
+                    DensityChunk::Dense(words)
+                }
+            })
+            .collect();
+
+        HybridDensity {
+            len,
+            total_density,
+            chunks,
+        }
+    }
+}
+
+impl AsRef<HybridDensity> for HybridDensity {
+    fn as_ref(&self) -> &HybridDensity {
+        self
+    }
+}
+
+impl<'a> QueryDensity for &'a HybridDensity {
+    type Iter = HybridDensityIter<'a>;
+    type Indices = HybridDensityIndices<'a>;
+
+    fn iter(self) -> Self::Iter {
+        HybridDensityIter {
+            density: self,
+            pos: 0,
+            cursor: 0,
+        }
+    }
+
+    fn indices(self) -> Self::Indices {
+        HybridDensityIndices {
+            density: self,
+            chunk: 0,
+            cursor: 0,
+            word: 0,
+        }
+    }
+
+    fn get_query_size(self) -> Option<usize> {
+        Some(self.len)
+    }
+}
+
+/// Iterator over whether the bases of a `HybridDensity` exist.
+pub struct HybridDensityIter<'a> {
+    density: &'a HybridDensity,
+    pos: usize,
+    // Position in the offsets of the current sparse chunk
+    cursor: usize,
+}
+
+impl<'a> Iterator for HybridDensityIter<'a> {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<bool> {
+        if self.pos >= self.density.len {
+            return None;
+        }
+        let offset = self.pos % HYBRID_CHUNK;
+        if offset == 0 {
+            self.cursor = 0;
+        }
+        let exists = match self.density.chunks[self.pos / HYBRID_CHUNK] {
+            DensityChunk::Sparse(ref offsets) => {
+                if offsets.get(self.cursor) == Some(&(offset as u16)) {
+                    self.cursor += 1;
+                    true
+                } else {
+                    false
+                }
+            }
+            DensityChunk::Dense(ref words) => (words[offset / 64] >> (offset % 64)) & 1 == 1,
+        };
+        self.pos += 1;
+        Some(exists)
+    }
+}
+
+/// Iterator over the indices of the existing bases of a `HybridDensity`.
+pub struct HybridDensityIndices<'a> {
+    density: &'a HybridDensity,
+    chunk: usize,
+    // Position in the offsets of a sparse chunk, or index of the next word of a dense one
+    cursor: usize,
+    // Remaining bits of the current word of a dense chunk
+    word: u64,
+}
+
+impl<'a> Iterator for HybridDensityIndices<'a> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        while let Some(chunk) = self.density.chunks.get(self.chunk) {
+            let start = self.chunk * HYBRID_CHUNK;
+            match chunk {
+                DensityChunk::Sparse(offsets) => {
+                    if let Some(&offset) = offsets.get(self.cursor) {
+                        self.cursor += 1;
+                        return Some(start + offset as usize);
+                    }
+                }
+                DensityChunk::Dense(words) => loop {
+                    if self.word != 0 {
+                        let bit = self.word.trailing_zeros() as usize;
+                        self.word &= self.word - 1;
+                        return Some(start + (self.cursor - 1) * 64 + bit);
+                    }
+                    if self.cursor >= words.len() {
+                        break;
+                    }
+                    self.word = words[self.cursor];
+                    self.cursor += 1;
+                },
+            }
+            self.chunk += 1;
+            self.cursor = 0;
+            self.word = 0;
+        }
+        None
+    }
+}
+
+// The exponents of the existing bases of `density`
+fn dense_exponents<'a, Q, R>(density: &'a Q, exponents: &'a [R]) -> impl Iterator<Item = &'a R>
+where
+    &'a Q: QueryDensity,
+{
+    density
+        .indices()
+        .take_while(move |&i| i < exponents.len())
+        .map(move |i| &exponents[i])
+}
+
 fn multiexp_inner<Q, D, G, S>(
     pool: &Worker,
     bases: S,
@@ -227,26 +484,24 @@ where
             let one = <G::Engine as ScalarEngine>::Fr::one().into_repr();
 
             // Sort the bases into buckets
-            for (&exp, density) in exponents.iter().zip(density_map.as_ref().iter()) {
-                if density {
-                    if exp == zero {
+            for &exp in dense_exponents(density_map.as_ref(), &exponents[..]) {
+                if exp == zero {
+                    bases.skip(1)?;
+                } else if exp == one {
+                    if handle_trivial {
+                        bases.add_assign_mixed(&mut acc)?;
+                    } else {
                         bases.skip(1)?;
-                    } else if exp == one {
-                        if handle_trivial {
-                            bases.add_assign_mixed(&mut acc)?;
-                        } else {
-                            bases.skip(1)?;
-                        }
+                    }
+                } else {
+                    let mut exp = exp;
+                    exp.shr(skip);
+                    let exp = exp.as_ref()[0] % (1 << c);
+
+                    if exp != 0 {
+                        bases.add_assign_mixed(&mut buckets[(exp - 1) as usize])?;
                     } else {
-                        let mut exp = exp;
-                        exp.shr(skip);
-                        let exp = exp.as_ref()[0] % (1 << c);
-
-                        if exp != 0 {
-                            bases.add_assign_mixed(&mut buckets[(exp - 1) as usize])?;
-                        } else {
-                            bases.skip(1)?;
-                        }
+                        bases.skip(1)?;
                     }
                 }
             }
@@ -296,6 +551,146 @@ where
     }
 }
 
+// Whether `multiexp` uses the unsigned windows it had before the signed ones, for comparison.
+fn unsigned_multiexp() -> bool {
+    env::var("BELLMAN_UNSIGNED_MULTIEXP").is_ok()
+}
+
+// Adding `2^(c-1)` to each window of `c` bits but the last one (The first one ending past
+// `num_bits`) makes the addition propagate the carries of the signed representation, so that
+// every window of `exp + offset` minus `2^(c-1)` is a signed digit of `exp`. The sum can't
+// overflow, as both terms are below `2^num_bits` and the fields have spare bits in their reprs.
+fn signed_digits_offset<R: PrimeFieldRepr>(c: u32, num_bits: u32) -> R {
+    let mut offset = R::default();
+    let mut window = 0;
+    while (window + 1) * c <= num_bits {
+        let bit = window * c + c - 1;
+        offset.as_mut()[(bit / 64) as usize] |= 1 << (bit % 64);
+        window += 1;
+    }
+    offset
+}
+
+// Digit of the window `window` of `c` bits of an exponent, in its signed representation, from
+// `recoded`, the exponent plus `signed_digits_offset`. The digits are in `[-2^(c-1), 2^(c-1))`,
+// except for the last window `last`, which takes the final carry and is in `[0, 2^(c-1)]`.
+fn signed_window_digit(recoded: &[u64], window: u32, c: u32, last: bool) -> i64 {
+    let digit = window_digit(recoded, window * c, c) as i64;
+    if last {
+        digit
+    } else {
+        digit - (1 << (c - 1))
+    }
+}
+
+// Same as `multiexp_inner`, with signed digits: as the negative digits subtract their bases
+// from the buckets of the opposite digits, there are only `2^(c-1)` buckets per window, at the
+// cost of a carry between the windows.
+fn multiexp_inner_signed<Q, D, G, S>(
+    pool: &Worker,
+    bases: S,
+    density_map: D,
+    exponents: Arc<Vec<<<G::Engine as ScalarEngine>::Fr as PrimeField>::Repr>>,
+    window: u32,
+    c: u32,
+    handle_trivial: bool,
+) -> Box<dyn Future<Item = <G as CurveAffine>::Projective, Error = SynthesisError>>
+where
+    for<'a> &'a Q: QueryDensity,
+    D: Send + Sync + 'static + Clone + AsRef<Q>,
+    G: CurveAffine,
+    S: SourceBuilder<G>,
+{
+    // The last window can hold the final carry, as the exponents are below `2^NUM_BITS`.
+    let num_bits = <G::Engine as ScalarEngine>::Fr::NUM_BITS;
+    let last = (window + 1) * c > num_bits;
+
+    // Perform this region of the multiexp
+    let this = {
+        let bases = bases.clone();
+        let exponents = exponents.clone();
+        let density_map = density_map.clone();
+
+        pool.compute(move || {
+            // Accumulate the result
+            let mut acc = G::Projective::zero();
+
+            // Build a source for the bases
+            let mut bases = bases.new();
+
+            // Create space for the buckets
+            let mut buckets = vec![<G as CurveAffine>::Projective::zero(); 1 << (c - 1)];
+
+            let zero = <G::Engine as ScalarEngine>::Fr::zero().into_repr();
+            let one = <G::Engine as ScalarEngine>::Fr::one().into_repr();
+            let offset: <<G::Engine as ScalarEngine>::Fr as PrimeField>::Repr =
+                signed_digits_offset(c, num_bits);
+
+            // Sort the bases into buckets
+            for &exp in dense_exponents(density_map.as_ref(), &exponents[..]) {
+                if exp == zero {
+                    bases.skip(1)?;
+                } else if exp == one {
+                    if handle_trivial {
+                        bases.add_assign_mixed(&mut acc)?;
+                    } else {
+                        bases.skip(1)?;
+                    }
+                } else {
+                    let mut recoded = exp;
+                    recoded.add_nocarry(&offset);
+                    let digit = signed_window_digit(recoded.as_ref(), window, c, last);
+
+                    if digit > 0 {
+                        bases.add_assign_mixed(&mut buckets[(digit - 1) as usize])?;
+                    } else if digit < 0 {
+                        bases.sub_assign_mixed(&mut buckets[(-digit - 1) as usize])?;
+                    } else {
+                        bases.skip(1)?;
+                    }
+                }
+            }
+
+            // Summation by parts
+            let mut running_sum = G::Projective::zero();
+            for exp in buckets.into_iter().rev() {
+                running_sum.add_assign(&exp);
+                acc.add_assign(&running_sum);
+            }
+
+            Ok(acc)
+        })
+    };
+
+    if last {
+        // There isn't another region.
+        Box::new(this)
+    } else {
+        // There's another region more significant. Calculate and join it with
+        // this region recursively.
+        Box::new(
+            this.join(multiexp_inner_signed(
+                pool,
+                bases,
+                density_map,
+                exponents,
+                window + 1,
+                c,
+                false,
+            ))
+            .map(move |(this, mut higher)| {
+                for _ in 0..c {
+                    higher.double();
+                }
+
+                higher.add_assign(&this);
+
+                higher
+            }),
+        )
+    }
+}
+
 /// Perform multi-exponentiation. The caller is responsible for ensuring the
 /// query size is the same as the number of exponents.
 pub fn multiexp<Q, D, G, S>(
@@ -312,21 +707,65 @@ where
     G::Engine: paired::Engine,
     S: SourceBuilder<G>,
 {
-    if let Some(ref mut kern) = kern {
-        if let Ok(p) = kern.with(|k: &mut gpu::MultiexpKernel<G::Engine>| {
-            let mut exps = vec![exponents[0]; exponents.len()];
-            let mut n = 0;
-            for (&e, d) in exponents.iter().zip(density_map.as_ref().iter()) {
-                if d {
+    multiexp_placed(pool, bases, density_map, exponents, kern).0
+}
+
+/// Same as `multiexp`, also returning whether the multiexp ran on the GPU.
+pub(crate) fn multiexp_placed<Q, D, G, S>(
+    pool: &Worker,
+    bases: S,
+    density_map: D,
+    exponents: Arc<Vec<<<G::Engine as ScalarEngine>::Fr as PrimeField>::Repr>>,
+    kern: &mut Option<gpu::LockedMultiexpKernel<G::Engine>>,
+) -> (
+    Box<dyn Future<Item = <G as CurveAffine>::Projective, Error = SynthesisError>>,
+    bool,
+)
+where
+    for<'a> &'a Q: QueryDensity,
+    D: Send + Sync + 'static + Clone + AsRef<Q>,
+    G: CurveAffine,
+    G::Engine: paired::Engine,
+    S: SourceBuilder<G>,
+{
+    let span = info_span!("multiexp", n = exponents.len());
+    let _enter = span.enter();
+
+    // An empty multiexp is left to the CPU, there is no exponent to fill the dense copy with
+    if let Some(kern) = kern.as_mut().filter(|_| !exponents.is_empty()) {
+        // The dense copy of the exponents is built by the first attempt that reaches the GPU,
+        // and kept across retries, along with the progress, so that a multiexp interrupted by a
+        // high priority process resumes from the chunks it already completed.
+        let mut dense: Option<(Arc<Vec<_>>, usize)> = None;
+        let mut progress = gpu::MultiexpProgress::new();
+        let result = kern.with(|k: &mut gpu::MultiexpKernel<G::Engine>| {
+            let (exps, n) = dense.get_or_insert_with(|| {
+                let mut exps = vec![exponents[0]; exponents.len()];
+                let mut n = 0;
+                for &e in dense_exponents(density_map.as_ref(), &exponents[..]) {
                     exps[n] = e;
                     n += 1;
                 }
-            }
-
+                (Arc::new(exps), n)
+            });
             let (bss, skip) = bases.clone().get();
-            k.multiexp(pool, bss, Arc::new(exps.clone()), skip, n)
-        }) {
-            return Box::new(pool.compute(move || Ok(p)));
+            if bss.len() < skip + *n {
+                return Err(gpu::GPUError::Simple("Not enough bases for the multiexp!"));
+            }
+            k.multiexp_from(pool, bss, exps.clone(), skip, *n, &mut progress)
+        });
+        // The kernel is done with the dense copy of the exponents
+        if let Some((mut exps, _)) = dense {
+            secret::zeroize_shared(&mut exps);
+        }
+        match result {
+            Ok(p) => return (Box::new(pool.compute(move || Ok(p))), true),
+            // A cancelled multiexp isn't resumed on the CPU
+            Err(gpu::GPUError::Cancelled) => {
+                let cancelled = Err::<<G as CurveAffine>::Projective, _>(SynthesisError::Cancelled);
+                return (Box::new(pool.compute(move || cancelled)), true);
+            }
+            Err(_) => (),
         }
     }
 
@@ -342,17 +781,362 @@ where
         assert!(query_size == exponents.len());
     }
 
-    let future = multiexp_inner(pool, bases, density_map, exponents, 0, c, true);
+    let future = if unsigned_multiexp() {
+        multiexp_inner(pool, bases, density_map, exponents, 0, c, true)
+    } else {
+        multiexp_inner_signed(pool, bases, density_map, exponents, 0, c, true)
+    };
     #[cfg(feature = "gpu")]
     {
         // Do not give the control back to the caller till the
         // multiexp is done. We may want to reacquire the GPU again
         // between the multiexps.
         let result = future.wait();
-        Box::new(pool.compute(move || result))
+        (Box::new(pool.compute(move || result)), false)
     }
     #[cfg(not(feature = "gpu"))]
-    future
+    (future, false)
+}
+
+/// Same as `multiexp`, on the CPU, with the same work for any exponents of the same length,
+/// for the provers whose timing mustn't depend on the witness (See
+/// `ProverConfig::constant_time`): there are no shortcuts for the exponents 0 and 1, and each
+/// base is added to a bucket in every window, the bases of the zero digits going to a bucket
+/// which is discarded.
+///
+/// The density map is used as is, as it depends on the constraints and not on the assignment.
+/// The buckets which are accessed still depend on the exponents, which may leak through the
+/// caches, and the additions of points aren't constant time.
+pub fn multiexp_constant_time<Q, D, G, S>(
+    pool: &Worker,
+    bases: S,
+    density_map: D,
+    exponents: Arc<Vec<<<G::Engine as ScalarEngine>::Fr as PrimeField>::Repr>>,
+) -> Box<dyn Future<Item = <G as CurveAffine>::Projective, Error = SynthesisError>>
+where
+    for<'a> &'a Q: QueryDensity,
+    D: Send + Sync + 'static + Clone + AsRef<Q>,
+    G: CurveAffine,
+    S: SourceBuilder<G>,
+{
+    let span = info_span!("multiexp_constant_time", n = exponents.len());
+    let _enter = span.enter();
+
+    let c = if exponents.len() < 32 {
+        3u32
+    } else {
+        (f64::from(exponents.len() as u32)).ln().ceil() as u32
+    };
+
+    if let Some(query_size) = density_map.as_ref().get_query_size() {
+        assert!(query_size == exponents.len());
+    }
+
+    let num_windows = (<G::Engine as ScalarEngine>::Fr::NUM_BITS + c - 1) / c;
+    let windows = (0..num_windows)
+        .map(|window| {
+            let bases = bases.clone();
+            let exponents = exponents.clone();
+            let density_map = density_map.clone();
+
+            pool.compute(move || {
+                let mut bases = bases.new();
+                // The bucket of the zero digit is discarded
+                let mut buckets = vec![<G as CurveAffine>::Projective::zero(); 1 << c];
+                for exp in dense_exponents(density_map.as_ref(), &exponents[..]) {
+                    let digit = window_digit(exp.as_ref(), window * c, c);
+                    bases.add_assign_mixed(&mut buckets[digit])?;
+                }
+
+                // Summation by parts, as in `multiexp_inner`
+                let mut acc = G::Projective::zero();
+                let mut running_sum = G::Projective::zero();
+                for exp in buckets.into_iter().skip(1).rev() {
+                    running_sum.add_assign(&exp);
+                    acc.add_assign(&running_sum);
+                }
+
+                Ok(acc)
+            })
+        })
+        .collect::<Vec<_>>();
+
+    Box::new(future::join_all(windows).map(move |windows| {
+        let mut acc = G::Projective::zero();
+        for window in windows.into_iter().rev() {
+            for _ in 0..c {
+                acc.double();
+            }
+            acc.add_assign(&window);
+        }
+        acc
+    }))
+}
+
+/// Perform multi-exponentiation over the `(base, exponent)` pairs of `pairs`, without holding
+/// them all in memory: they are read and multiexp'd by chunks of `chunk_size` pairs, the next
+/// chunk being read while the current one is computed. This makes it possible to stream the
+/// bases from e.g. a memory-mapped parameter file, keeping at most two chunks in memory.
+pub fn multiexp_stream<G, I>(
+    pool: &Worker,
+    pairs: I,
+    chunk_size: usize,
+    kern: &mut Option<gpu::LockedMultiexpKernel<G::Engine>>,
+) -> Result<<G as CurveAffine>::Projective, SynthesisError>
+where
+    G: CurveAffine,
+    G::Engine: paired::Engine,
+    I: IntoIterator<Item = (G, <G::Engine as ScalarEngine>::Fr)>,
+{
+    assert!(chunk_size > 0);
+    let mut pairs = pairs.into_iter();
+    let mut acc = G::Projective::zero();
+    let mut pending: Option<
+        Box<dyn Future<Item = <G as CurveAffine>::Projective, Error = SynthesisError>>,
+    > = None;
+
+    loop {
+        let (bases, exps): (Vec<_>, Vec<_>) = pairs
+            .by_ref()
+            .take(chunk_size)
+            .map(|(base, exp)| (base, exp.into_repr()))
+            .unzip();
+        if bases.is_empty() {
+            break;
+        }
+
+        let future = multiexp(
+            pool,
+            (Arc::new(bases), 0),
+            FullDensity,
+            Arc::new(exps),
+            kern,
+        );
+        if let Some(pending) = pending.take() {
+            acc.add_assign(&pending.wait()?);
+        }
+        pending = Some(future);
+    }
+
+    if let Some(pending) = pending {
+        acc.add_assign(&pending.wait()?);
+    }
+    Ok(acc)
+}
+
+/// Largest window of `PrecomputedBases`, which bounds the memory of the buckets.
+pub const MAX_PRECOMPUTED_WINDOW: u32 = 16;
+
+/// Tables of the multiples `2^(window * j) * base` of every base of a vector, for each window
+/// `j` of the exponents.
+///
+/// A multiexp against these tables sorts the digits of all of the windows into a single set of
+/// buckets, so it needs neither doublings nor a summation per window, and can afford larger
+/// windows than `multiexp`. It costs `ceil(NUM_BITS / window)` points of memory per base, so it
+/// pays off when many multiexps are run against the same bases, e.g. when proving many
+/// instances with the same parameters.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PrecomputedBases<G: CurveAffine> {
+    window: u32,
+    windows: usize,
+    tables: Arc<Vec<G>>,
+}
+
+impl<G: CurveAffine> PrecomputedBases<G> {
+    /// Builds the tables of `bases` for windows of `window` bits.
+    pub fn new(pool: &Worker, bases: &[G], window: u32) -> Self {
+        assert!(window > 0 && window <= MAX_PRECOMPUTED_WINDOW);
+        let windows = Self::num_windows(window);
+        let mut tables = vec![G::Projective::zero(); bases.len() * windows];
+
+        if bases.is_empty() {
+            return PrecomputedBases {
+                window,
+                windows,
+                tables: Arc::new(vec![]),
+            };
+        }
+
+        pool.scope(bases.len(), |scope, chunk| {
+            for (tables, bases) in tables.chunks_mut(chunk * windows).zip(bases.chunks(chunk)) {
+                scope.spawn(move |_scope| {
+                    for (row, base) in tables.chunks_mut(windows).zip(bases.iter()) {
+                        let mut multiple = base.into_projective();
+                        for entry in row.iter_mut() {
+                            *entry = multiple;
+                            for _ in 0..window {
+                                multiple.double();
+                            }
+                        }
+                    }
+                    G::Projective::batch_normalization(tables);
+                });
+            }
+        });
+
+        PrecomputedBases {
+            window,
+            windows,
+            tables: Arc::new(tables.into_iter().map(|p| p.into_affine()).collect()),
+        }
+    }
+
+    /// The window which minimizes the number of additions of a multiexp of `len` elements.
+    pub fn default_window(len: usize) -> u32 {
+        (1..=MAX_PRECOMPUTED_WINDOW)
+            .min_by_key(|&c| len * Self::num_windows(c) + (1 << c))
+            .unwrap()
+    }
+
+    fn num_windows(window: u32) -> usize {
+        let bits = <G::Engine as ScalarEngine>::Fr::NUM_BITS;
+        ((bits + window - 1) / window) as usize
+    }
+
+    pub fn window(&self) -> u32 {
+        self.window
+    }
+
+    /// Number of bases in the tables.
+    pub fn len(&self) -> usize {
+        self.tables.len() / self.windows
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_u32::<BigEndian>(self.window)?;
+        writer.write_u32::<BigEndian>(self.len() as u32)?;
+        for g in &self.tables[..] {
+            writer.write_all(g.into_uncompressed().as_ref())?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads tables written by `write`. When `checked` is set, the points are checked to be on
+    /// the curve and in the right subgroup.
+    pub fn read<R: Read>(mut reader: R, checked: bool) -> io::Result<Self> {
+        let window = reader.read_u32::<BigEndian>()?;
+        if window == 0 || window > MAX_PRECOMPUTED_WINDOW {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid window of precomputed bases",
+            ));
+        }
+        let windows = Self::num_windows(window);
+        let len = reader.read_u32::<BigEndian>()? as usize;
+
+        let mut tables = Vec::with_capacity(len * windows);
+        for _ in 0..len * windows {
+            let mut repr = G::Uncompressed::empty();
+            reader.read_exact(repr.as_mut())?;
+            let g = if checked {
+                repr.into_affine()
+            } else {
+                repr.into_affine_unchecked()
+            }
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            tables.push(g);
+        }
+
+        Ok(PrecomputedBases {
+            window,
+            windows,
+            tables: Arc::new(tables),
+        })
+    }
+}
+
+// The `c` bits of `exp` starting at bit `offset`
+fn window_digit(exp: &[u64], offset: u32, c: u32) -> usize {
+    let limb = (offset / 64) as usize;
+    let shift = offset % 64;
+    if limb >= exp.len() {
+        return 0;
+    }
+    let mut digit = exp[limb] >> shift;
+    if shift + c > 64 && limb + 1 < exp.len() {
+        digit |= exp[limb + 1] << (64 - shift);
+    }
+    (digit & ((1 << c) - 1)) as usize
+}
+
+/// Perform multi-exponentiation against precomputed bases, starting from the base of index
+/// `skip` like the `(Arc<Vec<G>>, usize)` source of `multiexp`. The caller is responsible for
+/// ensuring the query size is the same as the number of exponents.
+pub fn multiexp_precomputed<Q, D, G>(
+    pool: &Worker,
+    bases: &PrecomputedBases<G>,
+    skip: usize,
+    density_map: D,
+    exponents: Arc<Vec<<<G::Engine as ScalarEngine>::Fr as PrimeField>::Repr>>,
+) -> Box<dyn Future<Item = <G as CurveAffine>::Projective, Error = SynthesisError>>
+where
+    for<'a> &'a Q: QueryDensity,
+    D: Send + Sync + 'static + Clone + AsRef<Q>,
+    G: CurveAffine,
+{
+    let span = info_span!("multiexp_precomputed", n = exponents.len());
+    let _enter = span.enter();
+
+    if let Some(query_size) = density_map.as_ref().get_query_size() {
+        assert!(query_size == exponents.len());
+    }
+
+    let mut exps = dense_exponents(density_map.as_ref(), &exponents[..])
+        .cloned()
+        .collect::<Vec<_>>();
+
+    if bases.len() < skip + exps.len() {
+        let err: SynthesisError = io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "expected more bases from source",
+        )
+        .into();
+        return Box::new(pool.compute(move || Err(err)));
+    }
+
+    if exps.is_empty() {
+        return Box::new(pool.compute(|| Ok(G::Projective::zero())));
+    }
+
+    let (c, windows) = (bases.window, bases.windows);
+    let tables = &bases.tables[skip * windows..(skip + exps.len()) * windows];
+    let acc = Mutex::new(G::Projective::zero());
+
+    pool.scope(exps.len(), |scope, chunk| {
+        for (exps, tables) in exps.chunks(chunk).zip(tables.chunks(chunk * windows)) {
+            let acc = &acc;
+            scope.spawn(move |_scope| {
+                let mut buckets = vec![<G as CurveAffine>::Projective::zero(); (1 << c) - 1];
+                for (exp, row) in exps.iter().zip(tables.chunks(windows)) {
+                    for (j, base) in row.iter().enumerate() {
+                        let digit = window_digit(exp.as_ref(), j as u32 * c, c);
+                        if digit != 0 {
+                            buckets[digit - 1].add_assign_mixed(base);
+                        }
+                    }
+                }
+
+                // Summation by parts, as in `multiexp_inner`
+                let mut sum = G::Projective::zero();
+                let mut running_sum = G::Projective::zero();
+                for exp in buckets.into_iter().rev() {
+                    running_sum.add_assign(&exp);
+                    sum.add_assign(&running_sum);
+                }
+
+                acc.lock().unwrap().add_assign(&sum);
+            });
+        }
+    });
+    secret::zeroize(&mut exps);
+
+    let acc = acc.into_inner().unwrap();
+    Box::new(pool.compute(move || Ok(acc)))
 }
 
 #[cfg(feature = "pairing")]
@@ -399,7 +1183,10 @@ fn test_with_bls12() {
     assert_eq!(naive, fast);
 }
 
-pub fn create_multiexp_kernel<E>(_log_d: usize, priority: bool) -> Option<gpu::MultiexpKernel<E>>
+pub fn create_multiexp_kernel<E>(
+    _log_d: usize,
+    priority: gpu::Priority,
+) -> Option<gpu::MultiexpKernel<E>>
 where
     E: paired::Engine,
 {
@@ -426,7 +1213,10 @@ pub fn gpu_multiexp_consistency() {
 
     const MAX_LOG_D: usize = 20;
     const START_LOG_D: usize = 10;
-    let mut kern = Some(gpu::LockedMultiexpKernel::<Bls12>::new(MAX_LOG_D, false));
+    let mut kern = Some(gpu::LockedMultiexpKernel::<Bls12>::new(
+        MAX_LOG_D,
+        gpu::Priority::Low,
+    ));
     let pool = Worker::new();
 
     let rng = &mut rand::thread_rng();
@@ -474,6 +1264,43 @@ pub fn gpu_multiexp_consistency() {
     }
 }
 
+#[cfg(feature = "gpu")]
+#[test]
+pub fn gpu_multiexp_g2_consistency() {
+    use paired::bls12_381::Bls12;
+
+    let _ = env_logger::try_init();
+
+    const LOG_D: usize = 16;
+    let mut kern = Some(gpu::LockedMultiexpKernel::<Bls12>::new(
+        LOG_D,
+        gpu::Priority::Low,
+    ));
+    let pool = Worker::new();
+
+    let rng = &mut rand::thread_rng();
+
+    let g = Arc::new(
+        (0..(1 << LOG_D))
+            .map(|_| <Bls12 as paired::Engine>::G2::random(rng).into_affine())
+            .collect::<Vec<_>>(),
+    );
+    let v = Arc::new(
+        (0..(1 << LOG_D))
+            .map(|_| <Bls12 as ScalarEngine>::Fr::random(rng).into_repr())
+            .collect::<Vec<_>>(),
+    );
+
+    let gpu = multiexp(&pool, (g.clone(), 0), FullDensity, v.clone(), &mut kern)
+        .wait()
+        .unwrap();
+    let cpu = multiexp(&pool, (g, 0), FullDensity, v, &mut None)
+        .wait()
+        .unwrap();
+
+    assert_eq!(cpu, gpu);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -482,6 +1309,234 @@ mod tests {
     use rand_core::SeedableRng;
     use rand_xorshift::XorShiftRng;
 
+    #[test]
+    fn test_signed_multiexp() {
+        use paired::bls12_381::{Bls12, Fr};
+        use paired::Engine;
+
+        let mut rng = XorShiftRng::from_seed([
+            0x59, 0x62, 0xbe, 0x5d, 0x76, 0x3d, 0x31, 0x8d, 0x17, 0xdb, 0x37, 0x32, 0x54, 0x06,
+            0xbc, 0xe5,
+        ]);
+        const SAMPLES: usize = 1 << 8;
+
+        let bases = Arc::new(
+            (0..SAMPLES)
+                .map(|_| <Bls12 as Engine>::G1::random(&mut rng).into_affine())
+                .collect::<Vec<_>>(),
+        );
+        let mut exps = (0..SAMPLES)
+            .map(|_| Fr::random(&mut rng).into_repr())
+            .collect::<Vec<_>>();
+        let mut minus_one = Fr::one();
+        minus_one.negate();
+        exps[0] = Fr::zero().into_repr();
+        exps[1] = Fr::one().into_repr();
+        exps[2] = minus_one.into_repr();
+        let exps = Arc::new(exps);
+
+        let mut expected = <Bls12 as Engine>::G1::zero();
+        for (base, exp) in bases.iter().zip(exps.iter()) {
+            expected.add_assign(&base.mul(*exp));
+        }
+
+        let pool = Worker::new();
+        for &c in &[3, 5, 8, 13] {
+            let unsigned = multiexp_inner(
+                &pool,
+                (bases.clone(), 0),
+                FullDensity,
+                exps.clone(),
+                0,
+                c,
+                true,
+            )
+            .wait()
+            .unwrap();
+            let signed = multiexp_inner_signed(
+                &pool,
+                (bases.clone(), 0),
+                FullDensity,
+                exps.clone(),
+                0,
+                c,
+                true,
+            )
+            .wait()
+            .unwrap();
+            assert_eq!(unsigned, expected, "Unsigned mismatch for c = {}", c);
+            assert_eq!(signed, expected, "Signed mismatch for c = {}", c);
+        }
+    }
+
+    #[test]
+    fn test_multiexp_constant_time() {
+        use paired::bls12_381::{Bls12, Fr};
+        use paired::Engine;
+
+        let mut rng = XorShiftRng::from_seed([
+            0x59, 0x62, 0xbe, 0x5d, 0x76, 0x3d, 0x31, 0x8d, 0x17, 0xdb, 0x37, 0x32, 0x54, 0x06,
+            0xbc, 0xe5,
+        ]);
+        const SAMPLES: usize = 1 << 8;
+
+        let mut exps = (0..SAMPLES)
+            .map(|_| Fr::random(&mut rng).into_repr())
+            .collect::<Vec<_>>();
+        let mut minus_one = Fr::one();
+        minus_one.negate();
+        exps[0] = Fr::zero().into_repr();
+        exps[1] = Fr::one().into_repr();
+        exps[2] = minus_one.into_repr();
+        let exps = Arc::new(exps);
+
+        // Two exponents out of three are in the query
+        let mut density = DensityTracker::new();
+        for i in 0..SAMPLES {
+            density.add_element();
+            if i % 3 != 2 {
+                density.inc(i);
+            }
+        }
+        let num_bases = density.get_total_density();
+        let bases = Arc::new(
+            (0..num_bases)
+                .map(|_| <Bls12 as Engine>::G1::random(&mut rng).into_affine())
+                .collect::<Vec<_>>(),
+        );
+
+        let mut expected = <Bls12 as Engine>::G1::zero();
+        let dense = exps.iter().enumerate().filter(|&(i, _)| i % 3 != 2);
+        for (base, (_, exp)) in bases.iter().zip(dense) {
+            expected.add_assign(&base.mul(*exp));
+        }
+
+        let pool = Worker::new();
+        let result = multiexp_constant_time(&pool, (bases, 0), Arc::new(density), exps)
+            .wait()
+            .unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_multiexp_stream() {
+        use paired::bls12_381::{Bls12, Fr, G1Affine};
+        use paired::Engine;
+
+        let mut rng = XorShiftRng::from_seed([
+            0x59, 0x62, 0xbe, 0x5d, 0x76, 0x3d, 0x31, 0x8d, 0x17, 0xdb, 0x37, 0x32, 0x54, 0x06,
+            0xbc, 0xe5,
+        ]);
+        const SAMPLES: usize = 1000;
+
+        let pairs = (0..SAMPLES)
+            .map(|_| {
+                (
+                    <Bls12 as Engine>::G1::random(&mut rng).into_affine(),
+                    Fr::random(&mut rng),
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut expected = <Bls12 as Engine>::G1::zero();
+        for (base, exp) in pairs.iter() {
+            expected.add_assign(&base.mul(*exp));
+        }
+
+        let pool = Worker::new();
+        for &chunk_size in &[1, 64, 300, SAMPLES, 2 * SAMPLES] {
+            let result =
+                multiexp_stream::<G1Affine, _>(&pool, pairs.iter().cloned(), chunk_size, &mut None)
+                    .unwrap();
+            assert_eq!(result, expected, "Mismatch for chunks of {}", chunk_size);
+        }
+        assert_eq!(
+            multiexp_stream::<G1Affine, _>(&pool, vec![], 64, &mut None).unwrap(),
+            <Bls12 as Engine>::G1::zero()
+        );
+    }
+
+    #[test]
+    fn test_precomputed_multiexp() {
+        use paired::bls12_381::{Bls12, Fr};
+        use paired::Engine;
+
+        let mut rng = XorShiftRng::from_seed([
+            0x59, 0x62, 0xbe, 0x5d, 0x76, 0x3d, 0x31, 0x8d, 0x17, 0xdb, 0x37, 0x32, 0x54, 0x06,
+            0xbc, 0xe5,
+        ]);
+        const SAMPLES: usize = 1 << 8;
+
+        let bases = (0..SAMPLES + 3)
+            .map(|_| <Bls12 as Engine>::G1::random(&mut rng).into_affine())
+            .collect::<Vec<_>>();
+        let mut exps = (0..SAMPLES)
+            .map(|_| Fr::random(&mut rng).into_repr())
+            .collect::<Vec<_>>();
+        exps[0] = Fr::zero().into_repr();
+        exps[1] = Fr::one().into_repr();
+        let mut density = DensityTracker::new();
+        for i in 0..SAMPLES {
+            density.add_element();
+            if rng.gen() {
+                density.inc(i);
+            }
+        }
+
+        let mut expected = <Bls12 as Engine>::G1::zero();
+        let mut base = bases[3..].iter();
+        for (exp, d) in exps.iter().zip(density.bv.iter()) {
+            if d {
+                expected.add_assign(&base.next().unwrap().mul(*exp));
+            }
+        }
+
+        let pool = Worker::new();
+        let exps = Arc::new(exps);
+        let density = Arc::new(density);
+        for &window in &[
+            1,
+            5,
+            PrecomputedBases::<<Bls12 as Engine>::G1Affine>::default_window(SAMPLES),
+        ] {
+            let precomputed = PrecomputedBases::new(&pool, &bases, window);
+            let mut buf = vec![];
+            precomputed.write(&mut buf).unwrap();
+            assert_eq!(PrecomputedBases::read(&buf[..], true).unwrap(), precomputed);
+
+            let result =
+                multiexp_precomputed(&pool, &precomputed, 3, density.clone(), exps.clone())
+                    .wait()
+                    .unwrap();
+            assert_eq!(result, expected, "Mismatch for window {}", window);
+        }
+    }
+
+    #[test]
+    fn test_hybrid_density() {
+        let mut rng = XorShiftRng::from_seed([
+            0x59, 0x62, 0xbe, 0x5d, 0x76, 0x3d, 0x31, 0x8d, 0x17, 0xdb, 0x37, 0x32, 0x54, 0x06,
+            0xbc, 0xe5,
+        ]);
+
+        for &len in &[0, 1, 100, HYBRID_CHUNK, 3 * HYBRID_CHUNK + 77] {
+            let mut tracker = DensityTracker::new();
+            for i in 0..len {
+                tracker.add_element();
+                // Sparse first half, dense second half
+                let p = if i < len / 2 { 0.01 } else { 0.9 };
+                if rng.gen_bool(p) {
+                    tracker.inc(i);
+                }
+            }
+
+            let hybrid = HybridDensity::from(&tracker);
+            assert_eq!(hybrid.get_total_density(), tracker.get_total_density());
+            assert_eq!(hybrid.get_query_size(), tracker.get_query_size());
+            assert!(hybrid.iter().eq(tracker.iter()));
+            assert!(hybrid.indices().eq(tracker.indices()));
+        }
+    }
+
     #[test]
     fn test_extend_density_regular() {
         let mut rng = XorShiftRng::from_seed([
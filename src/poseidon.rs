@@ -0,0 +1,202 @@
+//! The Poseidon hash function over a prime field, with the `x^5` S-box.
+//!
+//! `PoseidonParams` holds the round constants and the MDS matrix of a permutation of `width`
+//! elements, derived deterministically from the width and the numbers of rounds, so that the
+//! native hash, the circuit gadget (`gadgets::poseidon`) and the GPU kernel
+//! (`gpu::PoseidonKernel`) all agree on them. The numbers of rounds have to be chosen for the
+//! field and the width following the Poseidon paper; the S-box requires `gcd(5, p - 1) = 1`,
+//! which holds for the scalar field of BLS12-381.
+
+use blake2b_simd::Params as Blake2bParams;
+use ff::{Field, PrimeField};
+use rand_chacha::ChaChaRng;
+use rand_core::SeedableRng;
+use rayon::prelude::*;
+
+/// Domain separation tag of the derivation of the round constants.
+pub const POSEIDON_CONSTANTS_DOMAIN: &[u8] = b"bellperson/poseidon/constants";
+
+/// The constants of a Poseidon permutation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PoseidonParams<F: PrimeField> {
+    width: usize,
+    full_rounds: usize,
+    partial_rounds: usize,
+    /// `width` constants per round, added to the state at the start of the round.
+    round_constants: Vec<F>,
+    /// The `width` x `width` MDS matrix mixing the state at the end of each round, row-major.
+    mds: Vec<F>,
+}
+
+impl<F: PrimeField> PoseidonParams<F> {
+    /// Generates the constants of a permutation of `width` elements, with `full_rounds` rounds
+    /// (Half of them before the partial rounds, half after) applying the S-box to the whole
+    /// state and `partial_rounds` rounds applying it to the first element only.
+    pub fn new(width: usize, full_rounds: usize, partial_rounds: usize) -> Self {
+        assert!(width >= 2, "the width must be at least 2");
+        assert!(
+            full_rounds % 2 == 0,
+            "the number of full rounds must be even"
+        );
+
+        let mut seed = Blake2bParams::new().hash_length(32).to_state();
+        seed.update(POSEIDON_CONSTANTS_DOMAIN);
+        seed.update(&(F::NUM_BITS as u64).to_le_bytes());
+        seed.update(&(width as u64).to_le_bytes());
+        seed.update(&(full_rounds as u64).to_le_bytes());
+        seed.update(&(partial_rounds as u64).to_le_bytes());
+        let mut rng_seed = [0u8; 32];
+        rng_seed.copy_from_slice(seed.finalize().as_bytes());
+        let mut rng = ChaChaRng::from_seed(rng_seed);
+
+        let round_constants = (0..width * (full_rounds + partial_rounds))
+            .map(|_| F::random(&mut rng))
+            .collect();
+
+        // Cauchy matrix 1 / (x_i + y_j) with x_i = i and y_j = width + j, whose sums are distinct
+        // and non-zero, which makes it MDS
+        let mut mds = Vec::with_capacity(width * width);
+        for i in 0..width {
+            for j in 0..width {
+                let sum = F::from_str(&(i + width + j).to_string()).unwrap();
+                mds.push(sum.inverse().unwrap());
+            }
+        }
+
+        PoseidonParams {
+            width,
+            full_rounds,
+            partial_rounds,
+            round_constants,
+            mds,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The number of elements hashed by `hash`, the other one being the capacity.
+    pub fn arity(&self) -> usize {
+        self.width - 1
+    }
+
+    pub fn full_rounds(&self) -> usize {
+        self.full_rounds
+    }
+
+    pub fn partial_rounds(&self) -> usize {
+        self.partial_rounds
+    }
+
+    pub fn round_constants(&self) -> &[F] {
+        &self.round_constants
+    }
+
+    pub fn mds(&self) -> &[F] {
+        &self.mds
+    }
+
+    /// Whether the S-box of the round `round` applies to the whole state.
+    pub fn is_full_round(&self, round: usize) -> bool {
+        let half = self.full_rounds / 2;
+        round < half || round >= half + self.partial_rounds
+    }
+
+    /// The initial value of the capacity element, which separates the hashes of different
+    /// arities.
+    pub fn domain_tag(&self) -> F {
+        let mut tag = F::zero();
+        let one = F::one();
+        for _ in 0..self.arity() {
+            tag.double();
+            tag.add_assign(&one);
+        }
+        tag
+    }
+
+    /// Applies the permutation to `state`, whose length must be the width.
+    pub fn permute(&self, state: &mut [F]) {
+        assert_eq!(state.len(), self.width);
+
+        let mut mixed = vec![F::zero(); self.width];
+        for round in 0..self.full_rounds + self.partial_rounds {
+            let constants = &self.round_constants[round * self.width..][..self.width];
+            for (s, c) in state.iter_mut().zip(constants) {
+                s.add_assign(c);
+            }
+
+            if self.is_full_round(round) {
+                state.iter_mut().for_each(sbox);
+            } else {
+                sbox(&mut state[0]);
+            }
+
+            for (i, m) in mixed.iter_mut().enumerate() {
+                *m = F::zero();
+                for (s, c) in state.iter().zip(&self.mds[i * self.width..][..self.width]) {
+                    let mut tmp = *s;
+                    tmp.mul_assign(c);
+                    m.add_assign(&tmp);
+                }
+            }
+            state.copy_from_slice(&mixed);
+        }
+    }
+
+    /// Hashes `arity` elements.
+    pub fn hash(&self, inputs: &[F]) -> F {
+        assert_eq!(inputs.len(), self.arity());
+
+        let mut state = Vec::with_capacity(self.width);
+        state.push(self.domain_tag());
+        state.extend_from_slice(inputs);
+        self.permute(&mut state);
+        state[1]
+    }
+
+    /// Hashes each chunk of `arity` elements of `inputs` in parallel, e.g. the leaves of a tree.
+    pub fn hash_many(&self, inputs: &[F]) -> Vec<F> {
+        assert_eq!(inputs.len() % self.arity(), 0);
+
+        inputs
+            .par_chunks(self.arity())
+            .map(|chunk| self.hash(chunk))
+            .collect()
+    }
+}
+
+fn sbox<F: Field>(x: &mut F) {
+    let mut x4 = *x;
+    x4.square();
+    x4.square();
+    x.mul_assign(&x4);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paired::bls12_381::Fr;
+
+    #[test]
+    fn test_poseidon() {
+        let rng = &mut rand::thread_rng();
+        let params = PoseidonParams::<Fr>::new(3, 8, 57);
+        assert!(params == PoseidonParams::new(3, 8, 57));
+        assert!(params != PoseidonParams::new(3, 8, 56));
+        assert_eq!(params.round_constants().len(), 3 * 65);
+        assert_eq!((0..65).filter(|r| params.is_full_round(*r)).count(), 8);
+        assert_eq!(params.domain_tag(), Fr::from_str("3").unwrap());
+
+        let inputs = (0..8).map(|_| Fr::random(rng)).collect::<Vec<_>>();
+        let hash = params.hash(&inputs[..2]);
+        assert_eq!(hash, params.hash(&inputs[..2]));
+        assert!(hash != params.hash(&[inputs[1], inputs[0]]));
+        assert!(hash != PoseidonParams::new(3, 8, 56).hash(&inputs[..2]));
+
+        let hashes = params.hash_many(&inputs);
+        assert_eq!(hashes.len(), 4);
+        assert_eq!(hashes[0], hash);
+        assert_eq!(hashes[3], params.hash(&inputs[6..]));
+    }
+}
@@ -0,0 +1,453 @@
+//! Offloading of FFTs and multiexps to a GPU worker process over the network.
+//!
+//! A machine with GPUs runs a [`RemoteWorker`] (See the `gpu_worker` example), and proving nodes
+//! without GPUs send it the heavy stages of the proof with a [`RemoteClient`]. Transport is any
+//! reliable byte stream, typically a TCP connection on the LAN or a Unix socket.
+//!
+//! Every message is a frame: its length as a big-endian `u64` followed by the payload. The first
+//! byte of a request payload is the kind of the job:
+//!
+//! - FFT: `lgn` (`u32`), `omega`, then the 2^`lgn` elements.
+//! - G1 / G2 multiexp: `n` (`u64`), the `n` bases (Uncompressed), then the `n` exponents.
+//!
+//! Field elements and exponents are in little-endian representation. The first byte of a
+//! response payload is `0` on success, followed by the resulting elements or point, and `1` on
+//! failure, followed by an UTF-8 error message.
+//!
+//! NOTE: Bases are decoded without subgroup checks, a client sending invalid points only gets
+//! wrong results back.
+//!
+//! [`RemoteWorker`]: struct.RemoteWorker.html
+//! [`RemoteClient`]: struct.RemoteClient.html
+
+use crate::domain::{best_fft, Scalar};
+use crate::gpu::{LockedFFTKernel, LockedMultiexpKernel, Priority};
+use crate::multicore::Worker;
+use crate::multiexp::{multiexp, FullDensity};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use ff::{PrimeField, PrimeFieldRepr, ScalarEngine};
+use futures::Future;
+use groupy::{CurveAffine, CurveProjective, EncodedPoint};
+use log::{error, info};
+use paired::Engine;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+
+// Larger frames are rejected instead of being allocated
+const MAX_FRAME_LEN: u64 = 1 << 36;
+
+const JOB_FFT: u8 = 0;
+const JOB_MULTIEXP_G1: u8 = 1;
+const JOB_MULTIEXP_G2: u8 = 2;
+
+const STATUS_OK: u8 = 0;
+const STATUS_ERROR: u8 = 1;
+
+fn invalid_data<E: ToString>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+fn write_frame<W: Write>(w: &mut W, payload: &[u8]) -> io::Result<()> {
+    w.write_u64::<BigEndian>(payload.len() as u64)?;
+    w.write_all(payload)?;
+    w.flush()
+}
+
+fn read_frame<R: Read>(r: &mut R) -> io::Result<Vec<u8>> {
+    let len = r.read_u64::<BigEndian>()?;
+    if len > MAX_FRAME_LEN {
+        return Err(invalid_data("frame is too large"));
+    }
+    // The payload grows as it arrives, so that a bogus length can't allocate more than was sent
+    let mut payload = Vec::new();
+    r.by_ref().take(len).read_to_end(&mut payload)?;
+    if payload.len() as u64 != len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "truncated frame",
+        ));
+    }
+    Ok(payload)
+}
+
+fn write_scalar<F: PrimeField, W: Write>(w: &mut W, e: &F) -> io::Result<()> {
+    e.into_repr().write_le(w)
+}
+
+fn read_scalar<F: PrimeField, R: Read>(r: &mut R) -> io::Result<F> {
+    let mut repr = F::Repr::default();
+    repr.read_le(r)?;
+    F::from_repr(repr).map_err(invalid_data)
+}
+
+fn write_point<G: CurveAffine, W: Write>(w: &mut W, p: &G) -> io::Result<()> {
+    w.write_all(p.into_uncompressed().as_ref())
+}
+
+fn read_point<G: CurveAffine, R: Read>(r: &mut R) -> io::Result<G> {
+    let mut encoded = G::Uncompressed::empty();
+    r.read_exact(encoded.as_mut())?;
+    encoded.into_affine_unchecked().map_err(invalid_data)
+}
+
+/// Serves FFT and multiexp jobs, on the GPU when there is one and on the CPU otherwise.
+pub struct RemoteWorker<E>
+where
+    E: Engine,
+{
+    pool: Worker,
+    priority: Priority,
+    // The FFT kernel is re-created whenever a larger FFT is requested
+    fft_log_d: usize,
+    fft_kern: Option<LockedFFTKernel<E>>,
+    multiexp_kern: Option<LockedMultiexpKernel<E>>,
+}
+
+impl<E> RemoteWorker<E>
+where
+    E: Engine,
+{
+    pub fn new(priority: Priority) -> RemoteWorker<E> {
+        RemoteWorker {
+            pool: Worker::new(),
+            priority,
+            fft_log_d: 0,
+            fft_kern: None,
+            multiexp_kern: Some(LockedMultiexpKernel::<E>::new(0, priority)),
+        }
+    }
+
+    /// Answers the requests sent on `stream` until the client disconnects.
+    pub fn serve<S: Read + Write>(&mut self, stream: &mut S) -> io::Result<()> {
+        loop {
+            let request = match read_frame(stream) {
+                Ok(request) => request,
+                Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            let mut response = Vec::new();
+            match self.handle(&request, &mut response) {
+                Ok(()) => {}
+                Err(e) => {
+                    response.clear();
+                    response.push(STATUS_ERROR);
+                    response.extend_from_slice(e.to_string().as_bytes());
+                }
+            }
+            write_frame(stream, &response)?;
+        }
+    }
+
+    fn handle(&mut self, mut request: &[u8], response: &mut Vec<u8>) -> io::Result<()> {
+        match request.read_u8()? {
+            JOB_FFT => self.fft(request, response),
+            JOB_MULTIEXP_G1 => self.multiexp::<E::G1Affine>(request, response),
+            JOB_MULTIEXP_G2 => self.multiexp::<E::G2Affine>(request, response),
+            _ => Err(invalid_data("unknown job")),
+        }
+    }
+
+    fn fft(&mut self, mut request: &[u8], response: &mut Vec<u8>) -> io::Result<()> {
+        let lgn = request.read_u32::<BigEndian>()?;
+        if lgn >= E::Fr::S {
+            return Err(invalid_data("FFT is too large for the field"));
+        }
+        let repr_size = std::mem::size_of::<<E::Fr as PrimeField>::Repr>();
+        if request.len() != ((1usize << lgn) + 1) * repr_size {
+            return Err(invalid_data("FFT input doesn't have 2^lgn elements"));
+        }
+        let omega = read_scalar::<E::Fr, _>(&mut request)?;
+        let mut a = (0..1usize << lgn)
+            .map(|_| read_scalar::<E::Fr, _>(&mut request).map(Scalar::<E>))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        // Without the `gpu` feature the FFTs are done on the CPU directly
+        let grow = self.fft_kern.is_none() || (lgn as usize) > self.fft_log_d;
+        if cfg!(feature = "gpu") && grow {
+            // Free the previous kernel before allocating a larger one
+            self.fft_kern = None;
+            self.fft_log_d = lgn as usize;
+            self.fft_kern = Some(LockedFFTKernel::<E>::new(self.fft_log_d, self.priority));
+        }
+        best_fft(&mut self.fft_kern, &mut a, &self.pool, &omega, lgn).map_err(invalid_data)?;
+
+        response.push(STATUS_OK);
+        for e in a.iter() {
+            write_scalar(response, &e.0)?;
+        }
+        Ok(())
+    }
+
+    fn multiexp<G>(&mut self, mut request: &[u8], response: &mut Vec<u8>) -> io::Result<()>
+    where
+        G: CurveAffine<Engine = E>,
+    {
+        let n = request.read_u64::<BigEndian>()? as usize;
+        if n == 0 {
+            return Err(invalid_data("no bases or exponents"));
+        }
+        let elem = G::Uncompressed::size() + std::mem::size_of::<<E::Fr as PrimeField>::Repr>();
+        if n.checked_mul(elem) != Some(request.len()) {
+            return Err(invalid_data("wrong number of bases or exponents"));
+        }
+        let bases = (0..n)
+            .map(|_| read_point::<G, _>(&mut request))
+            .collect::<io::Result<Vec<_>>>()?;
+        let exps = (0..n)
+            .map(|_| {
+                let mut repr = <E::Fr as PrimeField>::Repr::default();
+                repr.read_le(&mut request).map(|_| repr)
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let result = multiexp(
+            &self.pool,
+            (Arc::new(bases), 0),
+            FullDensity,
+            Arc::new(exps),
+            &mut self.multiexp_kern,
+        )
+        .wait()
+        .map_err(invalid_data)?;
+
+        response.push(STATUS_OK);
+        write_point(response, &result.into_affine())
+    }
+}
+
+/// Accepts connections on `addr` and serves them one after the other, as they would compete for
+/// the same GPUs anyway.
+pub fn serve_tcp<E, A>(addr: A, priority: Priority) -> io::Result<()>
+where
+    E: Engine,
+    A: ToSocketAddrs,
+{
+    let listener = TcpListener::bind(addr)?;
+    info!("GPU worker listening on {}.", listener.local_addr()?);
+    let mut worker = RemoteWorker::<E>::new(priority);
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        let peer = stream.peer_addr()?;
+        info!("Serving {}...", peer);
+        if let Err(e) = worker.serve(&mut stream) {
+            error!("Connection to {} failed! Error: {}", peer, e);
+        }
+    }
+    Ok(())
+}
+
+/// Sends FFT and multiexp jobs to a `RemoteWorker`.
+pub struct RemoteClient<S>
+where
+    S: Read + Write,
+{
+    stream: S,
+}
+
+impl RemoteClient<TcpStream> {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<RemoteClient<TcpStream>> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        Ok(RemoteClient::new(stream))
+    }
+}
+
+#[cfg(unix)]
+impl RemoteClient<std::os::unix::net::UnixStream> {
+    pub fn connect_unix<P: AsRef<std::path::Path>>(
+        path: P,
+    ) -> io::Result<RemoteClient<std::os::unix::net::UnixStream>> {
+        Ok(RemoteClient::new(std::os::unix::net::UnixStream::connect(
+            path,
+        )?))
+    }
+}
+
+impl<S> RemoteClient<S>
+where
+    S: Read + Write,
+{
+    pub fn new(stream: S) -> RemoteClient<S> {
+        RemoteClient { stream }
+    }
+
+    fn call(&mut self, request: &[u8]) -> io::Result<Vec<u8>> {
+        write_frame(&mut self.stream, request)?;
+        let mut response = read_frame(&mut self.stream)?;
+        match response.first() {
+            Some(&STATUS_OK) => Ok(response.split_off(1)),
+            Some(&STATUS_ERROR) => Err(io::Error::new(
+                io::ErrorKind::Other,
+                String::from_utf8_lossy(&response[1..]).into_owned(),
+            )),
+            _ => Err(invalid_data("invalid response")),
+        }
+    }
+
+    /// Performs FFT on `a`, which has 2^`lgn` elements, on the worker.
+    pub fn fft<F: PrimeField>(&mut self, a: &mut [F], omega: &F, lgn: u32) -> io::Result<()> {
+        if a.len() != 1 << lgn {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "FFT input doesn't have 2^lgn elements",
+            ));
+        }
+        let mut request = vec![JOB_FFT];
+        request.write_u32::<BigEndian>(lgn)?;
+        write_scalar(&mut request, omega)?;
+        for e in a.iter() {
+            write_scalar(&mut request, e)?;
+        }
+
+        let response = self.call(&request)?;
+        let mut response = &response[..];
+        for e in a.iter_mut() {
+            *e = read_scalar(&mut response)?;
+        }
+        Ok(())
+    }
+
+    /// Multiexp of `bases` with `exps` in G1 on the worker.
+    pub fn multiexp_g1<E: Engine>(
+        &mut self,
+        bases: &[E::G1Affine],
+        exps: &[<E::Fr as PrimeField>::Repr],
+    ) -> io::Result<E::G1> {
+        self.multiexp(JOB_MULTIEXP_G1, bases, exps)
+    }
+
+    /// Multiexp of `bases` with `exps` in G2 on the worker.
+    pub fn multiexp_g2<E: Engine>(
+        &mut self,
+        bases: &[E::G2Affine],
+        exps: &[<E::Fr as PrimeField>::Repr],
+    ) -> io::Result<E::G2> {
+        self.multiexp(JOB_MULTIEXP_G2, bases, exps)
+    }
+
+    fn multiexp<G: CurveAffine>(
+        &mut self,
+        job: u8,
+        bases: &[G],
+        exps: &[<<G::Engine as ScalarEngine>::Fr as PrimeField>::Repr],
+    ) -> io::Result<G::Projective> {
+        if bases.len() != exps.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "number of bases and exponents don't match",
+            ));
+        }
+        let mut request = vec![job];
+        request.write_u64::<BigEndian>(bases.len() as u64)?;
+        for p in bases.iter() {
+            write_point(&mut request, p)?;
+        }
+        for e in exps.iter() {
+            e.write_le(&mut request)?;
+        }
+
+        let response = self.call(&request)?;
+        Ok(read_point::<G, _>(&mut &response[..])?.into_projective())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::serial_fft;
+    use ff::Field;
+    use paired::bls12_381::{Bls12, Fr, G1Affine, G1, G2};
+    use std::thread;
+
+    #[test]
+    fn test_remote_worker() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut stream = listener.incoming().next().unwrap().unwrap();
+            RemoteWorker::<Bls12>::new(Priority::Low)
+                .serve(&mut stream)
+                .unwrap();
+        });
+
+        let rng = &mut rand::thread_rng();
+        let pool = Worker::new();
+        let mut client = RemoteClient::connect(addr).unwrap();
+
+        const LOG_N: u32 = 10;
+        let mut omega = Fr::root_of_unity();
+        for _ in LOG_N..Fr::S {
+            omega.square();
+        }
+        let mut a = (0..1 << LOG_N).map(|_| Fr::random(rng)).collect::<Vec<_>>();
+        let mut expected = a.iter().map(|&e| Scalar::<Bls12>(e)).collect::<Vec<_>>();
+        client.fft(&mut a, &omega, LOG_N).unwrap();
+        serial_fft::<Bls12, _>(&mut expected, &omega, LOG_N);
+        assert!(a.iter().eq(expected.iter().map(|s| &s.0)));
+
+        let exps = (0..1 << LOG_N)
+            .map(|_| Fr::random(rng).into_repr())
+            .collect::<Vec<_>>();
+        let g1 = (0..1 << LOG_N)
+            .map(|_| G1::random(rng).into_affine())
+            .collect::<Vec<_>>();
+        let g2 = (0..1 << LOG_N)
+            .map(|_| G2::random(rng).into_affine())
+            .collect::<Vec<_>>();
+        let expected_g1 = multiexp(
+            &pool,
+            (Arc::new(g1.clone()), 0),
+            FullDensity,
+            Arc::new(exps.clone()),
+            &mut None,
+        )
+        .wait()
+        .unwrap();
+        let expected_g2 = multiexp(
+            &pool,
+            (Arc::new(g2.clone()), 0),
+            FullDensity,
+            Arc::new(exps.clone()),
+            &mut None,
+        )
+        .wait()
+        .unwrap();
+        assert_eq!(
+            client.multiexp_g1::<Bls12>(&g1, &exps).unwrap(),
+            expected_g1
+        );
+        assert_eq!(
+            client.multiexp_g2::<Bls12>(&g2, &exps).unwrap(),
+            expected_g2
+        );
+
+        // Errors are reported without closing the connection
+        assert!(client.call(&[42]).is_err());
+        let mut b = vec![Fr::one(); 4];
+        client.fft(&mut b, &omega, 2).unwrap();
+
+        drop(client);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_malformed_requests() {
+        // A frame announcing more bytes than are sent fails without allocating them
+        let mut frame = vec![];
+        frame.write_u64::<BigEndian>(MAX_FRAME_LEN).unwrap();
+        frame.extend_from_slice(&[1, 2, 3]);
+        let err = read_frame(&mut &frame[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut worker = RemoteWorker::<Bls12>::new(Priority::Low);
+        for &n in &[0u64, u64::max_value()] {
+            let mut request = vec![];
+            request.write_u64::<BigEndian>(n).unwrap();
+            let mut response = vec![];
+            assert!(worker
+                .multiexp::<G1Affine>(&request, &mut response)
+                .is_err());
+        }
+    }
+}
@@ -0,0 +1,58 @@
+//! Zeroization of the secret values of the prover, behind the `zeroize` feature.
+//!
+//! The witness, the randomness `r` and `s` of the proofs, the evaluations of the polynomials and
+//! the host buffers of the FFTs are overwritten with zeros before they are freed, so that they
+//! don't linger in the freed heap pages of long-running proving services. Without the feature,
+//! the functions do nothing. This is best effort: the buffers freed while a vector grows and the
+//! copies in registers and on the stack aren't cleared.
+
+use std::sync::Arc;
+
+/// Overwrites `values` with zeros, with writes the compiler can't elide. The all-zero bytes must
+/// be a valid `T`, as they are for the field elements, their representations and their GPU
+/// wrappers.
+#[cfg(feature = "zeroize")]
+pub(crate) fn zeroize<T: Copy>(values: &mut [T]) {
+    use ::zeroize::Zeroize;
+    use std::{mem, slice};
+
+    // Safety: the values are initialized, and `T` is `Copy` so it owns no other memory
+    let bytes = unsafe {
+        slice::from_raw_parts_mut(values.as_mut_ptr() as *mut u8, mem::size_of_val(values))
+    };
+    bytes.zeroize();
+}
+
+#[cfg(not(feature = "zeroize"))]
+pub(crate) fn zeroize<T: Copy>(_values: &mut [T]) {}
+
+/// `zeroize` for the exponents shared with the futures of the multiexps, which is skipped if
+/// they are still shared.
+pub(crate) fn zeroize_shared<T: Copy>(values: &mut Arc<Vec<T>>) {
+    if let Some(values) = Arc::get_mut(values) {
+        zeroize(values);
+    }
+}
+
+#[cfg(all(test, feature = "zeroize"))]
+mod tests {
+    use super::*;
+    use ff::{Field, PrimeField, PrimeFieldRepr};
+    use paired::bls12_381::Fr;
+
+    #[test]
+    fn test_zeroize() {
+        let rng = &mut rand::thread_rng();
+        let mut values = (0..8).map(|_| Fr::random(rng)).collect::<Vec<_>>();
+        zeroize(&mut values);
+        assert!(values.iter().all(|v| v.is_zero()));
+
+        let mut shared = Arc::new(vec![Fr::one().into_repr(); 4]);
+        let other = shared.clone();
+        zeroize_shared(&mut shared);
+        assert!(shared.iter().all(|v| !v.is_zero()));
+        drop(other);
+        zeroize_shared(&mut shared);
+        assert!(shared.iter().all(|v| v.is_zero()));
+    }
+}
@@ -0,0 +1,272 @@
+use std::fmt;
+
+use crate::{Circuit, ConstraintSystem, Index, LinearCombination, SynthesisError, Variable};
+use ff::Field;
+use paired::Engine;
+
+/// A constraint which isn't satisfied by the witness, with the evaluations of its linear
+/// combinations.
+pub struct UnsatisfiedConstraint<E: Engine> {
+    /// Path of the namespace of the constraint, e.g. `"hash/round 3"`.
+    pub namespace: String,
+    pub name: String,
+    /// Index of the constraint, in the order they were enforced.
+    pub index: usize,
+    pub a: E::Fr,
+    pub b: E::Fr,
+    pub c: E::Fr,
+}
+
+// Not derived, which would require `E` to implement them
+impl<E: Engine> Clone for UnsatisfiedConstraint<E> {
+    fn clone(&self) -> Self {
+        UnsatisfiedConstraint {
+            namespace: self.namespace.clone(),
+            name: self.name.clone(),
+            index: self.index,
+            a: self.a,
+            b: self.b,
+            c: self.c,
+        }
+    }
+}
+
+impl<E: Engine> fmt::Debug for UnsatisfiedConstraint<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("UnsatisfiedConstraint")
+            .field("namespace", &self.namespace)
+            .field("name", &self.name)
+            .field("index", &self.index)
+            .field("a", &self.a)
+            .field("b", &self.b)
+            .field("c", &self.c)
+            .finish()
+    }
+}
+
+impl<E: Engine> fmt::Display for UnsatisfiedConstraint<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path = if self.namespace.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{}/{}", self.namespace, self.name)
+        };
+        write!(
+            f,
+            "constraint {} `{}` is unsatisfied: a = {}, b = {}, a * b != c = {}",
+            self.index, path, self.a, self.b, self.c
+        )
+    }
+}
+
+/// Constraint system checking each constraint against the witness as soon as it is enforced,
+/// and keeping the first one which isn't satisfied (Unlike `TestConstraintSystem`, the
+/// constraints aren't kept).
+pub struct DebugConstraintSystem<E: Engine> {
+    current_namespace: Vec<String>,
+    inputs: Vec<E::Fr>,
+    aux: Vec<E::Fr>,
+    num_constraints: usize,
+    num_unsatisfied: usize,
+    first_unsatisfied: Option<UnsatisfiedConstraint<E>>,
+}
+
+impl<E: Engine> DebugConstraintSystem<E> {
+    pub fn new() -> Self {
+        DebugConstraintSystem::default()
+    }
+
+    pub fn is_satisfied(&self) -> bool {
+        self.first_unsatisfied.is_none()
+    }
+
+    pub fn first_unsatisfied(&self) -> Option<&UnsatisfiedConstraint<E>> {
+        self.first_unsatisfied.as_ref()
+    }
+
+    pub fn num_unsatisfied(&self) -> usize {
+        self.num_unsatisfied
+    }
+
+    pub fn num_constraints(&self) -> usize {
+        self.num_constraints
+    }
+
+    /// The assignments of the public inputs, not including the "one" input variable.
+    pub fn public_inputs(&self) -> &[E::Fr] {
+        &self.inputs[1..]
+    }
+
+    fn eval(&self, lc: &LinearCombination<E>) -> E::Fr {
+        let mut acc = E::Fr::zero();
+        for (var, coeff) in lc.0.iter() {
+            let mut tmp = match var.get_unchecked() {
+                Index::Input(index) => self.inputs[index],
+                Index::Aux(index) => self.aux[index],
+            };
+            tmp.mul_assign(coeff);
+            acc.add_assign(&tmp);
+        }
+        acc
+    }
+}
+
+impl<E: Engine> Default for DebugConstraintSystem<E> {
+    fn default() -> Self {
+        DebugConstraintSystem {
+            current_namespace: vec![],
+            inputs: vec![E::Fr::one()],
+            aux: vec![],
+            num_constraints: 0,
+            num_unsatisfied: 0,
+            first_unsatisfied: None,
+        }
+    }
+}
+
+impl<E: Engine> ConstraintSystem<E> for DebugConstraintSystem<E> {
+    type Root = Self;
+
+    fn new() -> Self {
+        DebugConstraintSystem::default()
+    }
+
+    fn alloc<F, A, AR>(&mut self, _: A, f: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<E::Fr, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.aux.push(f()?);
+
+        Ok(Variable::new_unchecked(Index::Aux(self.aux.len() - 1)))
+    }
+
+    fn alloc_input<F, A, AR>(&mut self, _: A, f: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<E::Fr, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.inputs.push(f()?);
+
+        Ok(Variable::new_unchecked(Index::Input(self.inputs.len() - 1)))
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, annotation: A, a: LA, b: LB, c: LC)
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+        LA: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+        LB: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+        LC: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+    {
+        let a = self.eval(&a(LinearCombination::zero()));
+        let b = self.eval(&b(LinearCombination::zero()));
+        let c = self.eval(&c(LinearCombination::zero()));
+        let index = self.num_constraints;
+        self.num_constraints += 1;
+
+        let mut ab = a;
+        ab.mul_assign(&b);
+        if ab == c {
+            return;
+        }
+        self.num_unsatisfied += 1;
+        if self.first_unsatisfied.is_none() {
+            self.first_unsatisfied = Some(UnsatisfiedConstraint {
+                namespace: self.current_namespace.join("/"),
+                name: annotation().into(),
+                index,
+                a,
+                b,
+                c,
+            });
+        }
+    }
+
+    fn push_namespace<NR, N>(&mut self, name_fn: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+        self.current_namespace.push(name_fn().into());
+    }
+
+    fn pop_namespace(&mut self) {
+        assert!(self.current_namespace.pop().is_some());
+    }
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+}
+
+/// Synthesizes `circuit` with its witness, returning the first constraint it doesn't satisfy if
+/// any, e.g. to find out why its proofs don't verify.
+pub fn first_unsatisfied<E, C>(
+    circuit: C,
+) -> Result<Option<UnsatisfiedConstraint<E>>, SynthesisError>
+where
+    E: Engine,
+    C: Circuit<E>,
+{
+    let mut cs = DebugConstraintSystem::new();
+    circuit.synthesize(&mut cs)?;
+    Ok(cs.first_unsatisfied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ff::PrimeField;
+    use paired::bls12_381::{Bls12, Fr};
+
+    // Enforces `x * x = y` in the namespace "square"
+    struct Square(Fr, Fr);
+
+    impl Circuit<Bls12> for Square {
+        fn synthesize<CS: ConstraintSystem<Bls12>>(
+            self,
+            cs: &mut CS,
+        ) -> Result<(), SynthesisError> {
+            let y = cs.alloc_input(|| "y", || Ok(self.1))?;
+            let mut cs = cs.namespace(|| "square");
+            let x = cs.alloc(|| "x", || Ok(self.0))?;
+            cs.enforce(|| "x * x = y", |lc| lc + x, |lc| lc + x, |lc| lc + y);
+            cs.enforce(
+                || "y * 1 = y",
+                |lc| lc + y,
+                |lc| lc + CS::one(),
+                |lc| lc + y,
+            );
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_debug_cs() {
+        let three = Fr::from_str("3").unwrap();
+        let nine = Fr::from_str("9").unwrap();
+        assert!(first_unsatisfied(Square(three, nine)).unwrap().is_none());
+
+        let unsatisfied = first_unsatisfied(Square(three, three)).unwrap().unwrap();
+        assert_eq!(unsatisfied.namespace, "square");
+        assert_eq!(unsatisfied.name, "x * x = y");
+        assert_eq!(unsatisfied.index, 0);
+        assert_eq!(
+            (unsatisfied.a, unsatisfied.b, unsatisfied.c),
+            (three, three, three)
+        );
+        assert!(unsatisfied
+            .to_string()
+            .starts_with("constraint 0 `square/x * x = y` is unsatisfied"));
+
+        let mut cs = DebugConstraintSystem::new();
+        Square(three, three).synthesize(&mut cs).unwrap();
+        assert!(!cs.is_satisfied());
+        assert_eq!(cs.num_constraints(), 2);
+        assert_eq!(cs.num_unsatisfied(), 1);
+    }
+}
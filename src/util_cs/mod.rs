@@ -1,3 +1,5 @@
 pub mod bench_cs;
+pub mod debug_cs;
 pub mod metric_cs;
+pub mod profiling_cs;
 pub mod test_cs;
@@ -0,0 +1,247 @@
+use std::fmt;
+
+use crate::{ConstraintSystem, LinearCombination, SynthesisError, Variable};
+use ff::Field;
+use paired::Engine;
+
+/// Constraints, variables and non-zero coefficients of a namespace, including the ones of its
+/// children.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProfileNode {
+    pub name: String,
+    pub constraints: usize,
+    pub inputs: usize,
+    pub aux: usize,
+    /// Non-zero coefficients of the A, B and C linear combinations of the constraints.
+    pub nonzero_coefficients: usize,
+    /// The child namespaces, in the order they were entered.
+    pub children: Vec<ProfileNode>,
+}
+
+impl ProfileNode {
+    fn new(name: String) -> Self {
+        ProfileNode {
+            name,
+            ..ProfileNode::default()
+        }
+    }
+
+    fn add_child(&mut self, child: ProfileNode) {
+        self.constraints += child.constraints;
+        self.inputs += child.inputs;
+        self.aux += child.aux;
+        self.nonzero_coefficients += child.nonzero_coefficients;
+        self.children.push(child);
+    }
+
+    /// The node of the namespace at `path` (Relative to this node, e.g. `"hash/round 3"`), or the
+    /// first one if the path was entered several times.
+    pub fn get(&self, path: &str) -> Option<&ProfileNode> {
+        path.split('/').try_fold(self, |node, name| {
+            node.children.iter().find(|child| child.name == name)
+        })
+    }
+
+    fn fmt_indented(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        writeln!(
+            f,
+            "{:indent$}{}: {} constraints, {} inputs, {} aux, {} non-zero coefficients",
+            "",
+            self.name,
+            self.constraints,
+            self.inputs,
+            self.aux,
+            self.nonzero_coefficients,
+            indent = 2 * depth
+        )?;
+        for child in self.children.iter() {
+            child.fmt_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints the tree of the namespaces, one per line.
+impl fmt::Display for ProfileNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+/// Wraps a root constraint system, counting the constraints, variables and non-zero coefficients
+/// synthesized in each namespace, so that the gadgets responsible for most of the constraints of
+/// a circuit can be found. Wrapping a `BenchCS` only counts them.
+pub struct ProfilingConstraintSystem<CS> {
+    inner: CS,
+    // The root and the namespaces entered, the last one being the current one
+    stack: Vec<ProfileNode>,
+}
+
+impl<CS> ProfilingConstraintSystem<CS> {
+    pub fn new(inner: CS) -> Self {
+        ProfilingConstraintSystem {
+            inner,
+            stack: vec![ProfileNode::new(String::new())],
+        }
+    }
+
+    /// The report of the namespaces of the constraint system, the root node having an empty
+    /// name. Namespaces which haven't been left yet are included.
+    pub fn report(&self) -> ProfileNode {
+        let mut stack = self.stack.clone();
+        while stack.len() > 1 {
+            let child = stack.pop().unwrap();
+            stack.last_mut().unwrap().add_child(child);
+        }
+        stack.pop().unwrap()
+    }
+
+    pub fn inner(&self) -> &CS {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> CS {
+        self.inner
+    }
+
+    fn current(&mut self) -> &mut ProfileNode {
+        self.stack.last_mut().unwrap()
+    }
+}
+
+impl<E: Engine, CS: ConstraintSystem<E>> ConstraintSystem<E> for ProfilingConstraintSystem<CS> {
+    type Root = Self;
+
+    fn one() -> Variable {
+        CS::one()
+    }
+
+    fn alloc<F, A, AR>(&mut self, annotation: A, f: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<E::Fr, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        let var = self.inner.alloc(annotation, f)?;
+        self.current().aux += 1;
+        Ok(var)
+    }
+
+    fn alloc_input<F, A, AR>(&mut self, annotation: A, f: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<E::Fr, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        let var = self.inner.alloc_input(annotation, f)?;
+        self.current().inputs += 1;
+        Ok(var)
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, annotation: A, a: LA, b: LB, c: LC)
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+        LA: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+        LB: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+        LC: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+    {
+        let a = a(LinearCombination::zero());
+        let b = b(LinearCombination::zero());
+        let c = c(LinearCombination::zero());
+        let nonzero = [&a, &b, &c]
+            .iter()
+            .map(|lc| lc.0.values().filter(|coeff| !coeff.is_zero()).count())
+            .sum::<usize>();
+
+        let current = self.current();
+        current.constraints += 1;
+        current.nonzero_coefficients += nonzero;
+        self.inner
+            .enforce(annotation, |lc| lc + &a, |lc| lc + &b, |lc| lc + &c);
+    }
+
+    fn push_namespace<NR, N>(&mut self, name_fn: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+        let name = name_fn().into();
+        self.inner.push_namespace(|| name.clone());
+        self.stack.push(ProfileNode::new(name));
+    }
+
+    fn pop_namespace(&mut self) {
+        self.inner.pop_namespace();
+        assert!(self.stack.len() > 1, "no namespace to pop");
+        let child = self.stack.pop().unwrap();
+        self.current().add_child(child);
+    }
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util_cs::bench_cs::BenchCS;
+    use crate::util_cs::test_cs::TestConstraintSystem;
+    use ff::PrimeField;
+    use paired::bls12_381::{Bls12, Fr};
+
+    fn synthesize<CS: ConstraintSystem<Bls12>>(cs: &mut CS) {
+        let x = cs
+            .alloc_input(|| "x", || Ok(Fr::from_str("3").unwrap()))
+            .unwrap();
+        let mut y = x;
+        for i in 0..3 {
+            let mut cs = cs.namespace(|| format!("square {}", i));
+            let z = cs.alloc(|| "z", || Ok(Fr::from_str("3").unwrap())).unwrap();
+            cs.enforce(
+                || "y * 1 = z",
+                |lc| lc + y,
+                |lc| lc + CS::one(),
+                |lc| lc + z,
+            );
+            y = z;
+        }
+        let mut cs = cs.namespace(|| "output");
+        let mut cs = cs.namespace(|| "check");
+        cs.enforce(
+            || "x + y = 2 * x",
+            |lc| lc + x + y,
+            |lc| lc + CS::one(),
+            |lc| lc + (Fr::from_str("2").unwrap(), x),
+        );
+    }
+
+    #[test]
+    fn test_profiling_cs() {
+        let mut cs = ProfilingConstraintSystem::new(TestConstraintSystem::<Bls12>::new());
+        synthesize(&mut cs);
+        assert!(cs.inner().is_satisfied());
+        assert_eq!(cs.inner().num_constraints(), 4);
+
+        let report = cs.report();
+        assert_eq!(report.constraints, 4);
+        assert_eq!(report.inputs, 1);
+        assert_eq!(report.aux, 3);
+        assert_eq!(report.nonzero_coefficients, 3 * 3 + 4);
+        assert_eq!(report.children.len(), 4);
+
+        let square = report.get("square 1").unwrap();
+        assert_eq!((square.constraints, square.aux), (1, 1));
+        assert!(square.children.is_empty());
+        let check = report.get("output/check").unwrap();
+        assert_eq!(check.constraints, 1);
+        assert_eq!(report.get("output").unwrap().constraints, 1);
+        assert!(report.get("output/missing").is_none());
+        assert!(report.to_string().contains("\n    check: 1 constraints"));
+
+        let mut cs = ProfilingConstraintSystem::new(BenchCS::<Bls12>::new());
+        synthesize(&mut cs);
+        assert_eq!(cs.report(), report);
+    }
+}